@@ -0,0 +1,200 @@
+//! # Pico Display Buffered Console Example
+//!
+//! Echoes the data received over USB serial back to the host and shows it on the
+//! Pico Display, through a terminal drawing into a framebuffer. The rows that changed,
+//! those moved by a scroll included, go to the panel with DMA while the USB device is
+//! polled.
+//!
+//! The lines also go to a shell, whose `bench scroll` compares drawing the rows again
+//! with moving their pixels when the terminal scrolls.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+use core::fmt::Write;
+use rp2040_test::bench::{self, BenchResult};
+use rp2040_test::board::Board;
+use rp2040_test::display::Display;
+use rp2040_test::dma::PanelDma;
+use rp2040_test::dual_display::VISIBLE_OFFSET;
+use rp2040_test::shared::Shared;
+use rp2040_test::shell::{Args, Command, LineBuffer, Shell, ShellContext};
+use rp2040_test::{hal, PicoBufferedTerminal, PicoUsbConsole};
+
+/// DMA channel writing to the panel
+const PANEL_DMA_CHANNEL: usize = 0;
+
+/// Terminal drawing into the framebuffer, in a static as the framebuffer takes 64kB
+static TERMINAL: Shared<PicoBufferedTerminal<'static>> = Shared::new();
+
+/// What the commands of the shell reach
+struct Console<'a> {
+    console: PicoUsbConsole<'a>,
+    display: Display,
+    dma: PanelDma,
+    timer: hal::timer::Timer,
+    /// Time of the current iteration, in microseconds
+    now: u64,
+}
+
+impl ShellContext for Console<'_> {
+    fn write(&mut self, data: &[u8]) {
+        let _ = self.console.write(data, self.now);
+    }
+
+    fn uptime_us(&self) -> u64 {
+        self.now
+    }
+}
+
+/// Entry point to our bare-metal application.
+///
+/// The function sets up the buffered terminal, then polls the USB device and
+/// flushes the framebuffer after each write in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the pins, the display and the USB serial port
+    let mut board = Board::take().unwrap();
+    let (terminal, display) = board.buffered_terminal().unwrap();
+    TERMINAL.put(terminal);
+    let mut usb_dev = board.usb_device();
+    let mut context = Console {
+        console: board.serial(),
+        display,
+        dma: PanelDma::new(PANEL_DMA_CHANNEL, &mut board.resets),
+        timer: board.timer,
+        now: 0,
+    };
+
+    let mut shell: Shell<Console, 1> = Shell::new();
+    let _ = shell.register(Command {
+        name: "bench",
+        help: "draw lines and show the speed, or `scroll` to compare both ways of scrolling",
+        handler: bench_command,
+    });
+    let mut line_buffer = LineBuffer::new();
+
+    loop {
+        context.now = context.timer.get_counter();
+        usb_dev.poll(&mut [context.console.port()]);
+        let _ = context.console.poll(context.now);
+
+        let mut buf = [0u8; 64];
+        let count = match context.console.read(&mut buf) {
+            Ok(count) if count > 0 => count,
+            _ => continue,
+        };
+        let _ = context.console.write(&buf[..count], context.now);
+
+        // A scroll marks the whole terminal, so its rows go out as one transfer. The USB
+        // device is served meanwhile, and the next data waits in its buffers
+        let Console {
+            console,
+            display,
+            dma,
+            ..
+        } = &mut context;
+        TERMINAL.lock(|terminal| {
+            terminal.write(&buf[..count]);
+            flush(terminal, display, dma, || {
+                usb_dev.poll(&mut [console.port()]);
+            });
+        });
+
+        // Text that isn't a command is only shown
+        for &byte in &buf[..count] {
+            if let Some(line) = line_buffer.push(byte) {
+                shell.run(&mut context, &line);
+            }
+        }
+    }
+}
+
+/// Send the rows of the framebuffer changed since the last flush, calling `idle` until the DMA
+/// channel is done
+fn flush<F: FnMut()>(
+    terminal: &mut PicoBufferedTerminal<'static>,
+    display: &mut Display,
+    dma: &mut PanelDma,
+    mut idle: F,
+) {
+    if let Some(transfer) = terminal
+        .screen_mut()
+        .flush_dma(dma, display, VISIBLE_OFFSET)
+    {
+        while !transfer.is_done() {
+            idle();
+        }
+    }
+}
+
+/// Draw lines on the terminal and show the speed, flushing after each line
+///
+/// `bench scroll` runs it twice, with the rows drawn again on each scroll and then with their
+/// pixels moved, see `bench::compare_scroll`.
+fn bench_command(context: &mut Console, mut args: Args) {
+    let mut word = args.next();
+    let scroll = word == Some("scroll");
+    if scroll {
+        word = args.next();
+    }
+    let lines = match (word.map(str::parse), args.next()) {
+        (None, None) => bench::DEFAULT_LINES,
+        (Some(Ok(lines)), None) => lines,
+        _ => {
+            context.write(b"usage: bench [scroll] [lines]\r\n");
+            return;
+        }
+    };
+
+    let Console {
+        display,
+        dma,
+        timer,
+        ..
+    } = &mut *context;
+    let write = |data: &[u8]| {
+        TERMINAL.lock(|terminal| {
+            terminal.write(data);
+            flush(terminal, display, dma, || ());
+        });
+    };
+    let now_us = || timer.get_counter();
+    if scroll {
+        let (redraw, moved) = bench::compare_scroll(lines, write, now_us, |enabled| {
+            TERMINAL.lock(|terminal| terminal.set_pixel_scroll(enabled));
+        });
+        show_result(context, "redrawn", &redraw);
+        show_result(context, "moved", &moved);
+    } else {
+        let result = bench::run(lines, write, now_us);
+        show_result(context, "bench", &result);
+    }
+}
+
+fn show_result(context: &mut Console, name: &str, result: &BenchResult) {
+    let mut text: heapless::String<128> = heapless::String::new();
+    let _ = write!(
+        text,
+        "{} {} chars in {} ms: {} chars/s, {} frames, {} SPI bytes\r\n",
+        name,
+        result.chars,
+        result.elapsed_us / 1000,
+        result.chars_per_second(),
+        result.panel.ram_writes,
+        result.panel.spi_bytes
+    );
+    context.write(text.as_bytes());
+}
+
+// End of file
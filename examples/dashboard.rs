@@ -0,0 +1,107 @@
+//! # Pico Display Dashboard Example
+//!
+//! Shows the uptime and the chip temperature of the RP2040 in large 7-segment
+//! digits on the Pico Display, refreshed every second.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::{Rgb565, RgbColor},
+    prelude::*,
+    text::Text,
+};
+
+// Reading the ADC
+use embedded_hal::adc::OneShot;
+
+// Formatting into fixed-size strings
+use core::fmt::Write;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use rp2040_test::hal;
+
+use rp2040_test::board::Board;
+use rp2040_test::seven_segment::{SevenSegment, SevenSegmentStyle};
+
+/// Entry point to our bare-metal application.
+///
+/// The function configures the RP2040 peripherals, then redraws the dashboard
+/// every second in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the pins and the display
+    let mut board = Board::take().unwrap();
+    let mut screen = board.display().unwrap();
+
+    // The temperature sensor is read through the ADC
+    let mut adc = hal::adc::Adc::new(board.adc, &mut board.resets);
+    let mut temp_sense = adc.enable_temp_sensor();
+
+    let label_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CYAN);
+    // The background erases the previous values
+    let value_style = SevenSegmentStyle {
+        digit_size: Size::new(20, 36),
+        thickness: 4,
+        spacing: 4,
+        color: Rgb565::WHITE,
+        background_color: Rgb565::BLACK,
+    };
+
+    Text::new("Uptime", Point::new(50, 70), label_style)
+        .draw(&mut screen)
+        .unwrap();
+    Text::new("Temperature", Point::new(50, 130), label_style)
+        .draw(&mut screen)
+        .unwrap();
+
+    loop {
+        let uptime = (board.timer.get_counter() / 1_000_000) as u32;
+        let mut text: heapless::String<16> = heapless::String::new();
+        let _ = write!(
+            text,
+            "{:02}:{:02}:{:02}",
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60
+        );
+        SevenSegment::new(&text, Point::new(50, 76), value_style)
+            .draw(&mut screen)
+            .unwrap();
+
+        text.clear();
+        match adc.read(&mut temp_sense) {
+            Ok(raw) => {
+                let temperature = rp2040_test::temperature_from_adc(raw);
+                let _ = write!(
+                    text,
+                    "{:>3}.{}C",
+                    temperature / 10,
+                    (temperature % 10).abs()
+                );
+            }
+            Err(_) => {
+                let _ = text.push_str(" --.-C");
+            }
+        }
+        SevenSegment::new(&text, Point::new(50, 136), value_style)
+            .draw(&mut screen)
+            .unwrap();
+
+        board.delay.delay_ms(1000);
+    }
+}
+
+// End of file
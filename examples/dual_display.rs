@@ -0,0 +1,179 @@
+//! # Two Display Example
+//!
+//! Drives the Pico Display and a second ST7789 panel on SPI1. Holding A shows a
+//! banner across both panels as one wide screen, otherwise each panel shows its
+//! own content: the uptime on the left one and the chip temperature on the
+//! right one.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
+    pixelcolor::{Rgb565, RgbColor},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+// GPIO traits
+use embedded_hal::digital::v2::InputPin;
+
+// Reading the ADC
+use embedded_hal::adc::OneShot;
+
+// Time handling traits
+use embedded_time::rate::*;
+
+// Formatting into fixed-size strings
+use core::fmt::Write;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use rp2040_test::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use rp2040_test::hal;
+
+use rp2040_test::display;
+use rp2040_test::dual_display::{self, WideScreen, VISIBLE_OFFSET};
+
+/// Text of the banner shown across both panels.
+static BANNER: &str = "Two panels, one screen";
+
+/// Entry point to our bare-metal application.
+///
+/// The function configures the RP2040 peripherals, then redraws the panels
+/// every 100ms in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::watchdog::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    let clocks = hal::clocks::init_clocks_and_plls(
+        rp2040_test::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The delay object lets us wait for specified amounts of time (in
+    // milliseconds)
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().integer());
+
+    // Set the pins up according to their function on this particular board
+    let sio = hal::sio::Sio::new(pac.SIO);
+    let pins = rp2040_test::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Configure both panels
+    let left = display::init(
+        pac.SPI0,
+        pins.lcd_dc.into_push_pull_output(),
+        pins.lcd_cs.into_push_pull_output(),
+        pins.spi_sclk.into_mode(),
+        pins.spi_mosi.into_mode(),
+        pins.gpio0.into_mode(),
+        &mut pac.RESETS,
+        &mut delay,
+    );
+    let right = dual_display::init_second(
+        pac.SPI1,
+        pins.gpio21.into_push_pull_output(),
+        pins.gpio9.into_push_pull_output(),
+        pins.gpio10.into_mode(),
+        pins.gpio11.into_mode(),
+        &mut pac.RESETS,
+        &mut delay,
+    );
+    let mut screen = WideScreen::new(left, right);
+
+    let btn_a = pins.btn_a.into_pull_up_input();
+
+    // The temperature sensor is read through the ADC
+    let mut adc = hal::adc::Adc::new(pac.ADC, &mut pac.RESETS);
+    let mut temp_sense = adc.enable_temp_sensor();
+
+    let timer = hal::timer::Timer::new(pac.TIMER, &mut pac.RESETS);
+
+    // The background erases the previous values
+    let style = MonoTextStyleBuilder::new()
+        .font(&FONT_10X20)
+        .text_color(Rgb565::WHITE)
+        .background_color(Rgb565::BLACK)
+        .build();
+
+    let mut wide = false;
+    loop {
+        let pressed = btn_a.is_low().unwrap();
+        if pressed != wide {
+            wide = pressed;
+            screen.clear(Rgb565::BLACK).unwrap();
+        }
+
+        if wide {
+            // Centered on the wide screen, the banner would be cut by the bezels
+            let size = Size::new(BANNER.len() as u32 * 10, 20);
+            let centered = Rectangle::with_center(screen.bounding_box().center(), size);
+            let area = dual_display::avoid_seam(centered);
+            let width = screen.size().width;
+            Rectangle::new(Point::new(0, area.top_left.y - 4), Size::new(width, 28))
+                .into_styled(PrimitiveStyle::with_fill(Rgb565::BLUE))
+                .draw(&mut screen)
+                .unwrap();
+            Text::with_baseline(BANNER, area.top_left, style, Baseline::Top)
+                .draw(&mut screen)
+                .unwrap();
+        } else {
+            let mut text: heapless::String<16> = heapless::String::new();
+            let uptime = (timer.get_counter() / 1_000_000) as u32;
+            let _ = write!(text, "Up {}s", uptime);
+            Text::with_baseline(&text, VISIBLE_OFFSET + Point::new(10, 10), style, Baseline::Top)
+                .draw(screen.left_mut())
+                .unwrap();
+
+            text.clear();
+            if let Ok(raw) = adc.read(&mut temp_sense) {
+                let temperature = rp2040_test::temperature_from_adc(raw);
+                let _ = write!(
+                    text,
+                    "{}.{}C",
+                    temperature / 10,
+                    (temperature % 10).abs()
+                );
+            }
+            Text::with_baseline(&text, VISIBLE_OFFSET + Point::new(10, 10), style, Baseline::Top)
+                .draw(screen.right_mut())
+                .unwrap();
+        }
+
+        delay.delay_ms(100);
+    }
+}
+
+// End of file
@@ -0,0 +1,125 @@
+//! # Pico Display Embassy Echo Example
+//!
+//! Echoes the data received over USB serial back to the host and onto the
+//! terminal, with the terminal, the echo and the blinking LED as async tasks
+//! on the embassy executor. The USB device is still polled by its interrupt,
+//! which passes the data to the tasks through a pipe.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+use embassy_executor::{Executor, Spawner};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::{Instant, Timer};
+use embedded_hal::digital::v2::OutputPin;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+use rp2040_test::board::{Board, LedPin};
+use rp2040_test::hal::pac::{self, interrupt};
+use rp2040_test::shared::Core0Shared;
+use rp2040_test::{time_driver, PicoDisplayTerminal, PicoUsbConsole};
+use usb_device::prelude::*;
+
+// Note (safety): the executor and the USB interrupt both run on core 0, and core 1 isn't started
+static USB_DEVICE: Core0Shared<UsbDevice<rp2040_test::hal::usb::UsbBus>> =
+    unsafe { Core0Shared::new() };
+static USB_CONSOLE: Core0Shared<PicoUsbConsole> = unsafe { Core0Shared::new() };
+
+/// Data received from the host, written by the USB interrupt
+static RECEIVED: Pipe<CriticalSectionRawMutex, 256> = Pipe::new();
+/// Data for the terminal task
+static TO_TERMINAL: Pipe<CriticalSectionRawMutex, 512> = Pipe::new();
+
+/// Entry point to our bare-metal application.
+///
+/// The function sets up the board and the USB interrupt, then runs the tasks
+/// on the executor forever.
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the pins, the display and the USB serial port
+    let mut board = Board::take().unwrap();
+    time_driver::init();
+    let terminal = board.terminal().unwrap();
+    let led = board.led().unwrap();
+    USB_CONSOLE.put(board.serial());
+    USB_DEVICE.put(board.usb_device());
+
+    // Note (safety): the USB objects are in place before the interrupt starts
+    unsafe { pac::NVIC::unmask(pac::Interrupt::USBCTRL_IRQ) };
+
+    let executor: &'static mut Executor =
+        cortex_m::singleton!(: Executor = Executor::new()).unwrap();
+    executor.run(|spawner: Spawner| {
+        spawner.spawn(draw(terminal)).unwrap();
+        spawner.spawn(echo()).unwrap();
+        spawner.spawn(blink(led)).unwrap();
+    })
+}
+
+/// Draw the data for the terminal as it comes
+#[embassy_executor::task]
+async fn draw(mut terminal: PicoDisplayTerminal<'static>) {
+    let mut buf = [0; 64];
+    loop {
+        let count = TO_TERMINAL.read(&mut buf).await;
+        terminal.write(&buf[..count]);
+    }
+}
+
+/// Send the data received back to the host, and to the terminal
+#[embassy_executor::task]
+async fn echo() {
+    let mut buf = [0; 64];
+    loop {
+        let count = RECEIVED.read(&mut buf).await;
+        // The USB interrupt may have left data behind while the pipe was full
+        pac::NVIC::pend(pac::Interrupt::USBCTRL_IRQ);
+        let now = Instant::now().as_micros();
+        // Dropped while no host is reading
+        USB_CONSOLE.lock(|console| console.write(&buf[..count], now));
+        TO_TERMINAL.write_all(&buf[..count]).await;
+    }
+}
+
+/// Blink the LED, to show the executor runs
+#[embassy_executor::task]
+async fn blink(mut led: LedPin) {
+    loop {
+        led.set_high().unwrap();
+        Timer::after_millis(500).await;
+        led.set_low().unwrap();
+        Timer::after_millis(500).await;
+    }
+}
+
+/// Poll the USB device, and pass the data received to the echo task
+#[allow(non_snake_case)]
+#[interrupt]
+fn USBCTRL_IRQ() {
+    cortex_m::interrupt::free(|cs| {
+        let (mut usb_dev, mut console) = (USB_DEVICE.borrow(cs), USB_CONSOLE.borrow(cs));
+        if let (Some(usb_dev), Some(console)) = (usb_dev.as_mut(), console.as_mut()) {
+            if usb_dev.poll(&mut [console.port()]) {
+                // Only read what the pipe has room for, the rest waits in the USB buffers
+                let mut buf = [0; 64];
+                let room = RECEIVED.free_capacity().min(buf.len());
+                if let Ok(count) = console.read(&mut buf[..room]) {
+                    let _ = RECEIVED.try_write(&buf[..count]);
+                }
+            }
+            let _ = console.poll(Instant::now().as_micros());
+        }
+    });
+}
+
+// End of file
@@ -0,0 +1,199 @@
+//! # Pico HID Macro Pad Example
+//!
+//! Turns the A, B, X and Y buttons of the Pico Display into a macro pad, sending a key
+//! combination to the host through a USB HID keyboard while a button is held. The same USB
+//! device has a serial console, whose `type <text>` command types the text on the keyboard.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+// GPIO traits
+use embedded_hal::digital::v2::InputPin;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use rp2040_test::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use rp2040_test::hal;
+
+use rp2040_test::buttons::Debouncer;
+use rp2040_test::keyboard::{modifier, KeyPress};
+use rp2040_test::shell::{self, Command, LineBuffer, Shell, ShellContext};
+use rp2040_test::{PicoUsbConsole, PicoUsbKeyboard};
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use usbd_serial::SerialPort;
+
+/// Key combinations sent by the A, B, X and Y buttons.
+const MACROS: [KeyPress; 4] = [
+    // Ctrl+C
+    KeyPress::new(modifier::CTRL, 0x06),
+    // Ctrl+V
+    KeyPress::new(modifier::CTRL, 0x19),
+    // Ctrl+Z
+    KeyPress::new(modifier::CTRL, 0x1d),
+    // Ctrl+Shift+Z
+    KeyPress::new(modifier::CTRL | modifier::SHIFT, 0x1d),
+];
+
+/// What the commands of the console act on
+struct MacroPad<'a> {
+    console: PicoUsbConsole<'a>,
+    keyboard: PicoUsbKeyboard<'a>,
+    /// Time of the current iteration, in microseconds
+    now: u64,
+}
+
+impl ShellContext for MacroPad<'_> {
+    fn write(&mut self, data: &[u8]) {
+        let _ = self.console.write(data, self.now);
+    }
+
+    fn uptime_us(&self) -> u64 {
+        self.now
+    }
+}
+
+/// Entry point to our bare-metal application.
+///
+/// The function configures the RP2040 peripherals, then polls the USB device,
+/// the console and the buttons in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::watchdog::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    let clocks = hal::clocks::init_clocks_and_plls(
+        rp2040_test::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+    let mut pad = MacroPad {
+        console: PicoUsbConsole::new(SerialPort::new(&usb_bus)),
+        keyboard: PicoUsbKeyboard::new(&usb_bus),
+        now: 0,
+    };
+
+    // Create a USB device with a fake VID and PID. It has several interfaces, which the
+    // miscellaneous class groups with interface associations
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("Fake company")
+        .product("Macro pad")
+        .serial_number("TEST")
+        .device_class(0xEF) // from: https://www.usb.org/defined-class-codes
+        .device_sub_class(0x02)
+        .device_protocol(0x01)
+        .build();
+
+    let mut shell: Shell<MacroPad, 1> = Shell::new();
+    let _ = shell.register(Command {
+        name: "type",
+        help: "type the words on the keyboard",
+        handler: |pad, words| {
+            for (index, word) in words.enumerate() {
+                if index > 0 {
+                    pad.keyboard.type_text(b" ");
+                }
+                pad.keyboard.type_text(word.as_bytes());
+            }
+            pad.keyboard.type_text(b"\n");
+        },
+    });
+    let mut line_buffer = LineBuffer::new();
+
+    // Set the pins up according to their function on this particular board
+    let sio = hal::sio::Sio::new(pac.SIO);
+    let pins = rp2040_test::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // The buttons pull their pin low when pressed
+    let btn_a = pins.btn_a.into_pull_up_input();
+    let btn_b = pins.btn_b.into_pull_up_input();
+    let btn_x = pins.btn_x.into_pull_up_input();
+    let btn_y = pins.btn_y.into_pull_up_input();
+
+    // A bouncing button would otherwise send its macro more than once
+    let timer = hal::timer::Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut debouncer = Debouncer::new();
+
+    loop {
+        pad.now = timer.get_counter();
+        if usb_dev.poll(&mut [pad.console.port(), pad.keyboard.class()]) {
+            let mut buf = [0u8; 64];
+            let count = pad.console.read(&mut buf).unwrap_or(0);
+            for &byte in &buf[..count] {
+                let mut echo: heapless::Vec<u8, 8> = heapless::Vec::new();
+                shell::push_echo(&mut echo, byte);
+                pad.write(&echo);
+                if let Some(line) = line_buffer.push(byte) {
+                    if !shell.run(&mut pad, &line) {
+                        pad.write(b"unknown command, see help\r\n");
+                    }
+                    pad.write(shell::PROMPT);
+                }
+            }
+        }
+        let _ = pad.console.poll(pad.now);
+
+        let raw = [
+            btn_a.is_low().unwrap(),
+            btn_b.is_low().unwrap(),
+            btn_x.is_low().unwrap(),
+            btn_y.is_low().unwrap(),
+        ];
+        debouncer.update(raw, (pad.now / 1000) as u32, |_| ());
+        let pressed = debouncer.pressed();
+        // Only one macro at a time, the first pressed button wins
+        let key = pressed
+            .iter()
+            .zip(MACROS.iter())
+            .find(|(pressed, _)| **pressed)
+            .map(|(_, key)| *key);
+        match key {
+            Some(key) => pad.keyboard.press(key),
+            None => pad.keyboard.release(),
+        }
+        // Sent again on the next iteration if the host hasn't read the previous report
+        let _ = pad.keyboard.poll();
+    }
+}
+
+// End of file
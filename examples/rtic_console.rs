@@ -0,0 +1,179 @@
+//! # Pico Display RTIC Console Example
+//!
+//! Echoes the data received over USB serial back to the host and onto the
+//! terminal, as an RTIC app: the USB interrupt, the button scan on a timer
+//! alarm and the terminal rendering are tasks, and the resources they share
+//! are locked by RTIC instead of living in `static mut` globals.
+//!
+//! A press on a button is reported to the host, and A clears the terminal.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+use rp2040_test::hal::pac;
+
+/// Time between two scans of the buttons, in microseconds
+const SCAN_US: u32 = 5_000;
+
+/// Bytes received and not drawn yet
+const QUEUE_SIZE: usize = 512;
+
+/// Time since boot, in microseconds
+fn now_us() -> u64 {
+    // Note (safety): the counter is only read
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    // The high word is read again, in case the low word wrapped in between
+    loop {
+        let high = timer.timerawh.read().bits();
+        let low = timer.timerawl.read().bits();
+        if timer.timerawh.read().bits() == high {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
+
+/// Run `scan_buttons` again in `SCAN_US`
+fn arm_scan() {
+    // Note (safety): alarm 0 is only used by `scan_buttons`
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    let at = timer.timerawl.read().bits().wrapping_add(SCAN_US);
+    timer.alarm0.write(|w| unsafe { w.bits(at) });
+}
+
+#[rtic::app(device = rp2040_test::hal::pac, peripherals = true, dispatchers = [TIMER_IRQ_1])]
+mod app {
+    use super::{arm_scan, now_us, QUEUE_SIZE};
+    use core::fmt::Write;
+    use heapless::spsc::{Consumer, Producer, Queue};
+    use rp2040_test::board::{Board, Buttons};
+    use rp2040_test::buttons::{Button, ButtonEvent, Debouncer};
+    use rp2040_test::hal;
+    use rp2040_test::{PicoDisplayTerminal, PicoUsbConsole};
+    use usb_device::prelude::*;
+
+    #[shared]
+    struct Shared {
+        console: PicoUsbConsole<'static>,
+        terminal: PicoDisplayTerminal<'static>,
+    }
+
+    #[local]
+    struct Local {
+        usb_dev: UsbDevice<'static, hal::usb::UsbBus>,
+        received: Producer<'static, u8, QUEUE_SIZE>,
+        to_draw: Consumer<'static, u8, QUEUE_SIZE>,
+        buttons: Buttons,
+        debouncer: Debouncer,
+    }
+
+    #[init(local = [queue: Queue<u8, QUEUE_SIZE> = Queue::new()])]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        // Set up the clocks, the pins, the display and the USB serial port
+        let mut board = Board::new(cx.device, cx.core.SYST).unwrap();
+        let terminal = board.terminal().unwrap();
+        let buttons = board.buttons().unwrap();
+        let console = board.serial();
+        let usb_dev = board.usb_device();
+
+        // Note (safety): alarm 0 only raises the interrupt of `scan_buttons`
+        let timer = unsafe { &*hal::pac::TIMER::ptr() };
+        timer.inte.modify(|_, w| w.alarm_0().set_bit());
+        arm_scan();
+
+        let (received, to_draw) = cx.local.queue.split();
+        (
+            Shared { console, terminal },
+            Local {
+                usb_dev,
+                received,
+                to_draw,
+                buttons,
+                debouncer: Debouncer::new(),
+            },
+            init::Monotonics(),
+        )
+    }
+
+    /// Poll the USB device, echo the data received and queue it for the terminal
+    #[task(binds = USBCTRL_IRQ, priority = 2, shared = [console], local = [usb_dev, received])]
+    fn usb(mut cx: usb::Context) {
+        let usb_dev = cx.local.usb_dev;
+        let received = cx.local.received;
+        cx.shared.console.lock(|console| {
+            if usb_dev.poll(&mut [console.port()]) {
+                // Only read what the queue has room for, the rest waits in the USB buffers
+                let mut buf = [0; 64];
+                let room = (received.capacity() - received.len()).min(buf.len());
+                if let Ok(count) = console.read(&mut buf[..room]) {
+                    // Dropped while no host is reading
+                    let _ = console.write(&buf[..count], now_us());
+                    for &byte in &buf[..count] {
+                        let _ = received.enqueue(byte);
+                    }
+                }
+            }
+            let _ = console.poll(now_us());
+        });
+        if received.len() > 0 {
+            let _ = render::spawn();
+        }
+    }
+
+    /// Draw the queued data on the terminal, below the USB and the buttons
+    #[task(priority = 1, shared = [terminal], local = [to_draw])]
+    fn render(mut cx: render::Context) {
+        let to_draw = cx.local.to_draw;
+        while to_draw.ready() {
+            // A few bytes per lock, so a button press isn't kept waiting by a long redraw
+            cx.shared.terminal.lock(|terminal| {
+                for _ in 0..16 {
+                    match to_draw.dequeue() {
+                        Some(byte) => terminal.write_char(byte),
+                        None => break,
+                    }
+                }
+            });
+        }
+        // The USB interrupt may have left data behind while the queue was full
+        rtic::pend(hal::pac::Interrupt::USBCTRL_IRQ);
+    }
+
+    /// Debounce the buttons, report the presses to the host and clear the terminal on A
+    #[task(
+        binds = TIMER_IRQ_0,
+        priority = 2,
+        shared = [console, terminal],
+        local = [buttons, debouncer]
+    )]
+    fn scan_buttons(cx: scan_buttons::Context) {
+        // Note (safety): only the interrupt flag of alarm 0 is cleared
+        let timer = unsafe { &*hal::pac::TIMER::ptr() };
+        timer.intr.write(|w| w.alarm_0().set_bit());
+        arm_scan();
+
+        let now = now_us();
+        let levels = cx.local.buttons.pressed();
+        let (mut console, mut terminal) = (cx.shared.console, cx.shared.terminal);
+        cx.local.debouncer.update(levels, (now / 1000) as u32, |event| {
+            if let ButtonEvent::Pressed(button) = event {
+                let mut line: heapless::String<16> = heapless::String::new();
+                let _ = write!(line, "button {:?}\r\n", button);
+                console.lock(|console| {
+                    let _ = console.write(line.as_bytes(), now);
+                });
+                if button == Button::A {
+                    terminal.lock(|terminal| terminal.write(b"\x1b[2J\x1b[H"));
+                }
+            }
+        });
+    }
+}
+
+// End of file
@@ -0,0 +1,1879 @@
+//! Commands of the `serial_echo` shell
+//!
+//! Each command is a handler registered in `build_shell`, replying to the host. Commands that
+//! reboot the device or erase the settings ask for a confirmation first.
+
+use super::pages::{switches_menu, KeyboardPage, Page, SearchPage, SettingsPage, TypingPage};
+use super::*;
+
+/// Returns true if the command only reads the state of the device, or changes what is neither
+/// kept in flash nor wired to the GPIOs or the HID payloads
+///
+/// Every other command is refused while the lock is closed, so a new command is protected until
+/// it is added here.
+fn is_exempt(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (
+            Some(
+                "help" | "clear" | "uptime" | "version" | "time" | "temp" | "info" | "pins"
+                | "glyphs" | "hash" | "assets" | "stats" | "errors" | "events" | "latency"
+                | "output" | "pager" | "zoom" | "coalesce" | "mux" | "mirror" | "logger" | "plot"
+                | "draw" | "bench" | "buffer" | "search" | "typing" | "page",
+            ),
+            _,
+        ) => true,
+        // The lock itself
+        (Some("unlock"), Some(_)) | (Some("lock"), None) => true,
+        // Showing what the other commands change
+        (
+            Some(
+                "led" | "backlight" | "ferris" | "status" | "config" | "heartbeat" | "fault"
+                | "crash" | "route",
+            ),
+            None,
+        )
+        | (Some("trigger" | "switch" | "glyph"), Some("list"))
+        | (Some("log" | "crash"), Some("dump"))
+        | (Some("fault"), Some("codes")) => true,
+        (Some("led"), Some("pattern")) => words.next().is_none(),
+        _ => false,
+    }
+}
+
+/// Returns true if the line is a command refused while the lock is closed
+///
+/// Lines that aren't commands are data for the echo, and go through.
+fn is_protected(line: &str, shell: &Shell<CommandState, SHELL_COMMANDS>) -> bool {
+    let is_command = line
+        .split_whitespace()
+        .next()
+        .map_or(false, |name| shell.has_command(name))
+        || Disruptive::parse(line).is_some();
+    is_command && !is_exempt(line)
+}
+
+/// Commands registered in the shell, besides the built-in ones
+const SHELL_COMMANDS: usize = 49;
+
+/// Build the shell, with the commands of this example
+pub(crate) fn build_shell() -> Shell<CommandState, SHELL_COMMANDS> {
+    let mut shell = Shell::new();
+    let _ = shell.register(Command {
+        name: "version",
+        help: "show the firmware version",
+        handler: |state, _args| {
+            state.write(concat!("version ", env!("CARGO_PKG_VERSION"), "\r\n").as_bytes())
+        },
+    });
+    let _ = shell.register(Command {
+        name: "bench",
+        help: "draw lines on the terminal and show the speed",
+        handler: |state, mut args| {
+            let lines = match args.next().map(str::parse) {
+                None => bench::DEFAULT_LINES,
+                Some(Ok(lines)) => lines,
+                Some(Err(_)) => {
+                    state.write(b"usage: bench [lines]\r\n");
+                    return;
+                }
+            };
+            let result = bench::run(lines, write_to_terminal, now_us);
+            let mut text: heapless::String<128> = heapless::String::new();
+            let _ = write!(
+                text,
+                "bench {} chars in {} ms: {} chars/s, {} frames, {} SPI bytes\r\n",
+                result.chars,
+                result.elapsed_us / 1000,
+                result.chars_per_second(),
+                result.panel.ram_writes,
+                result.panel.spi_bytes
+            );
+            state.write(text.as_bytes());
+        },
+    });
+    for command in [
+        Command {
+            name: "time",
+            help: "show the time, or `sync <unix_ms>` to set it",
+            handler: time_command,
+        },
+        Command {
+            name: "temp",
+            help: "show the temperature of the chip",
+            handler: temp_command,
+        },
+        Command {
+            name: "info",
+            help: "show the name, unique ID and version",
+            handler: info_command,
+        },
+        Command {
+            name: "rgb",
+            help: "set the RGB LED: <rrggbb>, off, brightness, solid, breathe, blink",
+            handler: rgb_command,
+        },
+        Command {
+            name: "identify",
+            help: "flash the screen and the LED for 5 seconds",
+            handler: identify_command,
+        },
+        Command {
+            name: "led",
+            help: "show or set the blink `pattern` of the LED",
+            handler: led_command,
+        },
+        Command {
+            name: "backlight",
+            help: "show or set the brightness, and the `idle` timeout",
+            handler: backlight_command,
+        },
+        Command {
+            name: "ferris",
+            help: "show the animations of Ferris, or set the `idle` timeout",
+            handler: ferris_command,
+        },
+        Command {
+            name: "name",
+            help: "`set` or `clear` the name of the device",
+            handler: name_command,
+        },
+        Command {
+            name: "status",
+            help: "show the segments of the status bar, `set` them or `reset` them",
+            handler: status_command,
+        },
+        Command {
+            name: "config",
+            help: "show the settings records, or `save` them",
+            handler: config_command,
+        },
+        Command {
+            name: "stats",
+            help: "show the metrics, `reset` them or stream them `every` seconds",
+            handler: stats_command,
+        },
+        Command {
+            name: "latency",
+            help: "turn the display latency test on or off",
+            handler: latency_command,
+        },
+        Command {
+            name: "events",
+            help: "turn the !EVT lines on or off, or set their `mask`",
+            handler: events_command,
+        },
+        Command {
+            name: "errors",
+            help: "show the bytes lost, or `clear` the counters",
+            handler: errors_command,
+        },
+        Command {
+            name: "plot",
+            help: "add a sample to the plot, or line, bars, clear",
+            handler: plot_command,
+        },
+        Command {
+            name: "trigger",
+            help: "add, remove, clear or list the triggers",
+            handler: trigger_command,
+        },
+        Command {
+            name: "log",
+            help: "start, stop or dump the temperature log",
+            handler: log_command,
+        },
+        Command {
+            name: "unlock",
+            help: "open the lock with its PIN",
+            handler: unlock_command,
+        },
+        Command {
+            name: "lock",
+            help: "lock the device, or `set` or `clear` the PIN",
+            handler: lock_command,
+        },
+        Command {
+            name: "heartbeat",
+            help: "beat, or watch the heartbeats `every` seconds, or off",
+            handler: heartbeat_command,
+        },
+        Command {
+            name: "fault",
+            help: "show the fault, its `codes`, or `clear` it",
+            handler: fault_command,
+        },
+        Command {
+            name: "screen",
+            help: "set the panel mode, or the `check` of its ID",
+            handler: screen_command,
+        },
+        Command {
+            name: "pins",
+            help: "show how the pins are set up",
+            handler: pins_command,
+        },
+        Command {
+            name: "switch",
+            help: "list the switches, or set one on, off, toggle, schedule, boot",
+            handler: switch_command,
+        },
+        Command {
+            name: "mux",
+            help: "turn the channels on or off",
+            handler: mux_command,
+        },
+        Command {
+            name: "mirror",
+            help: "send the screen on the data channel, on or off",
+            handler: mirror_command,
+        },
+        Command {
+            name: "hash",
+            help: "hash an area of the flash with crc32 or sha256",
+            handler: hash_command,
+        },
+        Command {
+            name: "zoom",
+            help: "scale the terminal text, 1 or 2",
+            handler: zoom_command,
+        },
+        Command {
+            name: "output",
+            help: "reply in json or text",
+            handler: output_command,
+        },
+        Command {
+            name: "pager",
+            help: "pause the output after a number of lines, or off",
+            handler: pager_command,
+        },
+        Command {
+            name: "glyphs",
+            help: "show the glyph cache hits",
+            handler: glyphs_command,
+        },
+        Command {
+            name: "logger",
+            help: "send the logs to display, usb, both or off, or set the level",
+            handler: logger_command,
+        },
+        Command {
+            name: "route",
+            help: "show the routes, or turn <source> <sink> on or off",
+            handler: route_command,
+        },
+        Command {
+            name: "buffer",
+            help: "send the data of the log buffer, or `clear` it",
+            handler: buffer_command,
+        },
+        Command {
+            name: "glyph",
+            help: "set, clear or list the custom glyphs",
+            handler: glyph_command,
+        },
+        Command {
+            name: "draw",
+            help: "apply cell and rect updates to the screen",
+            handler: draw_command,
+        },
+        Command {
+            name: "coalesce",
+            help: "hold back small writes: off, line or a number of bytes",
+            handler: coalesce_command,
+        },
+        Command {
+            name: "crash",
+            help: "show, dump or clear the screen of the last crash",
+            handler: crash_command,
+        },
+        Command {
+            name: "pulses",
+            help: "record the pulses on the capture pin",
+            handler: pulses_command,
+        },
+        Command {
+            name: "assets",
+            help: "show the uploaded splash and notes",
+            handler: assets_command,
+        },
+        Command {
+            name: "backup",
+            help: "send the configuration and assets on the data channel",
+            handler: backup_command,
+        },
+        Command {
+            name: "restore",
+            help: "write back a backup from the data channel",
+            handler: restore_command,
+        },
+        Command {
+            name: "confirm",
+            help: "run the command waiting for this token",
+            handler: confirm_command,
+        },
+        Command {
+            name: "search",
+            help: "search the terminal and its history",
+            handler: search_command,
+        },
+        Command {
+            name: "typing",
+            help: "open the typing speed test",
+            handler: typing_command,
+        },
+        Command {
+            name: "page",
+            help: "open the settings or switches page",
+            handler: page_command,
+        },
+    ] {
+        let _ = shell.register(command);
+    }
+    shell
+}
+
+impl ShellContext for CommandState {
+    fn write(&mut self, data: &[u8]) {
+        send_to_host(data);
+    }
+
+    fn uptime_us(&self) -> u64 {
+        now_us()
+    }
+
+    fn clear_screen(&mut self) {
+        write_to_terminal(b"\x1b[2J\x1b[H");
+    }
+
+    /// `reboot` and `reboot into <target>` wait for a token or the X button instead
+    fn reboot(&mut self, mut args: Args<'_>) -> bool {
+        let action = match (args.next(), args.next(), args.next()) {
+            (None, None, None) => Some(Disruptive::Reboot(None)),
+            (Some("into"), Some(target), None) => {
+                RebootTarget::parse(target).map(|target| Disruptive::Reboot(Some(target)))
+            }
+            _ => None,
+        };
+        match action {
+            Some(action) => request_disruptive(action, self),
+            None => send_to_host(b"reboot: use `reboot`, or `reboot into <target>`\r\n"),
+        }
+        true
+    }
+}
+
+/// Run a command line received from the host, then show the prompt
+///
+/// The commands of this example are registered in the shell, see `build_shell`. Lines that
+/// aren't commands are ignored, as they are also regular data for the echo.
+pub(crate) fn run_command(
+    line: &str,
+    state: &mut CommandState,
+    shell: &Shell<CommandState, SHELL_COMMANDS>,
+) {
+    diag_debug!("command: {}", line.trim());
+    COMMANDS.add(1);
+
+    // Each command output starts on a new page
+    state.pager.reset();
+
+    // A trailing `--json` asks for a JSON reply to this command only
+    let (line, json) = match line.trim_end().strip_suffix("--json") {
+        Some(line) => (line, true),
+        None => (line, state.json_output),
+    };
+    state.json = json;
+
+    if state.lock.is_locked() && is_protected(line, shell) {
+        send_to_host(b"locked: send `unlock <pin>` first\r\n");
+    } else if let Some(action) = Disruptive::parse(line) {
+        request_disruptive(action, state);
+    } else {
+        shell.run(state, line);
+    }
+    send_to_host(shell::PROMPT);
+}
+
+/// Command taking the device offline, which has to be confirmed, see `ConfirmMode`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Disruptive {
+    /// Reboot, into a page or mode with a target
+    Reboot(Option<RebootTarget>),
+    /// Reboot into the USB bootloader
+    Bootsel,
+    /// Go back to the default settings
+    SettingsReset,
+}
+
+impl Disruptive {
+    /// `bootsel` and `settings reset`, which aren't commands of the shell
+    ///
+    /// `reboot` is a built-in command of the shell, which hands it to `CommandState::reboot`.
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("bootsel" | "bootloader"), None, None) => Some(Disruptive::Bootsel),
+            (Some("settings" | "config"), Some("reset"), None) => Some(Disruptive::SettingsReset),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Disruptive::Reboot(_) => "reboot",
+            Disruptive::Bootsel => "bootsel",
+            Disruptive::SettingsReset => "settings reset",
+        }
+    }
+}
+
+/// Page or mode `reboot into` comes back up into, kept across the reset by `boot_mode`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RebootTarget {
+    /// The terminal, without the splash and the greeting
+    Terminal,
+    Settings,
+    Switches,
+    Keyboard,
+    Typing,
+    /// USB serial only, like holding X at power on
+    Headless,
+}
+
+/// Every target, in the order of their IDs
+const REBOOT_TARGETS: [RebootTarget; 6] = [
+    RebootTarget::Terminal,
+    RebootTarget::Settings,
+    RebootTarget::Switches,
+    RebootTarget::Keyboard,
+    RebootTarget::Typing,
+    RebootTarget::Headless,
+];
+
+impl RebootTarget {
+    fn name(self) -> &'static str {
+        match self {
+            RebootTarget::Terminal => "terminal",
+            RebootTarget::Settings => "settings",
+            RebootTarget::Switches => "switches",
+            RebootTarget::Keyboard => "keyboard",
+            RebootTarget::Typing => "typing",
+            RebootTarget::Headless => "headless",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        REBOOT_TARGETS.iter().copied().find(|target| target.name() == name)
+    }
+
+    fn id(self) -> u8 {
+        REBOOT_TARGETS.iter().position(|&target| target == self).unwrap() as u8
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        REBOOT_TARGETS.get(id as usize).copied()
+    }
+
+    /// Page to open once the firmware is up
+    pub(crate) fn page(self) -> Option<Page> {
+        match self {
+            RebootTarget::Settings => Some(Page::Settings(SettingsPage::new())),
+            RebootTarget::Switches => Some(Page::Switches(switches_menu())),
+            RebootTarget::Keyboard => Some(Page::Keyboard(KeyboardPage::new())),
+            RebootTarget::Typing => Some(Page::Typing(TypingPage::new())),
+            RebootTarget::Terminal | RebootTarget::Headless => None,
+        }
+    }
+}
+
+/// Run `action` right away if the settings don't ask for a confirmation, or wait for it
+fn request_disruptive(action: Disruptive, state: &mut CommandState) {
+    if state.settings.confirm == ConfirmMode::Off {
+        run_disruptive(action, state);
+    } else {
+        ask_confirmation(action, state);
+    }
+}
+
+/// Hold `action` until it is confirmed, telling the host and the screen how
+pub(crate) fn ask_confirmation(action: Disruptive, state: &mut CommandState) {
+    let token = state.confirm.ask(action, &mut state.rng, (now_us() / 1000) as u32);
+    let mut line: heapless::String<80> = heapless::String::new();
+    let _ = match state.settings.confirm {
+        ConfirmMode::Button => write!(line, "{}: press X on the device to confirm", action.name()),
+        _ => write!(
+            line,
+            "{}: send `confirm {:04x}` or press X on the device",
+            action.name(),
+            token
+        ),
+    };
+    let _ = write!(line, " within {}s\r\n", confirm::CONFIRM_MS / 1000);
+    send_to_host(line.as_bytes());
+
+    let mut prompt: heapless::String<40> = heapless::String::new();
+    let _ = write!(prompt, "\n{}? X: yes, other: no\n", action.name());
+    write_to_terminal(prompt.as_bytes());
+}
+
+/// Run a command taking the device offline, once confirmed
+pub(crate) fn run_disruptive(action: Disruptive, state: &mut CommandState) {
+    diag_info!("confirmed: {}", action.name());
+    match action {
+        Disruptive::Reboot(None) => cortex_m::peripheral::SCB::sys_reset(),
+        Disruptive::Reboot(Some(target)) => boot_mode::reboot_into(target.id()),
+        Disruptive::Bootsel => hal::rom_data::reset_to_usb_boot(0, 0),
+        Disruptive::SettingsReset => {
+            state.settings = Settings::default();
+            state.settings.save();
+            apply_settings(&state.settings);
+            DEVICE_NAME.put(DeviceName::from_unique_id(&flash::unique_id()));
+            send_to_host(b"settings reset\r\n");
+            write_to_terminal(b"Settings reset\n");
+        }
+    }
+}
+
+/// Show the time, or set it in milliseconds since the Unix epoch
+fn time_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    match (args.next(), args.next()) {
+        (Some("sync"), Some(unix_ms)) => match unix_ms.parse() {
+            Ok(unix_ms) => {
+                CLOCK.sync(unix_ms, now_us());
+                send_to_host(b"time synced\r\n");
+            }
+            Err(_) => send_to_host(b"time: invalid timestamp\r\n"),
+        },
+        (None, None) => {
+            let mut text: heapless::String<64> = heapless::String::new();
+            let timestamp = CLOCK.timestamp(now_us());
+            let result = if json {
+                let (name, ms) = match timestamp {
+                    Timestamp::Unix(unix_ms) => ("unix_ms", unix_ms),
+                    Timestamp::Uptime(uptime_ms) => ("uptime_ms", uptime_ms),
+                };
+                JsonLine::new(&mut text).number(name, ms).finish()
+            } else {
+                write!(text, "time {}\r\n", timestamp)
+            };
+            if result.is_ok() {
+                send_to_host(text.as_bytes());
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Show the latest temperature of the chip
+fn temp_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    if args.next().is_some() {
+        return;
+    }
+    let mut text: heapless::String<40> = heapless::String::new();
+    let _ = match (state.temperature, json) {
+        (Some(temp), true) => JsonLine::new(&mut text).number("temp_dc", temp).finish(),
+        (None, true) => JsonLine::new(&mut text).null("temp_dc").finish(),
+        (Some(temp), false) => {
+            write!(text, "temp {}.{}C\r\n", temp / 10, (temp % 10).abs())
+        }
+        (None, false) => write!(text, "temp: not read yet\r\n"),
+    };
+    send_to_host(text.as_bytes());
+}
+
+/// Show what tells this board apart from the others
+fn info_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    if args.next().is_some() {
+        return;
+    }
+    let unique_id = flash::unique_id();
+    let name = DEVICE_NAME
+        .lock(|name| *name)
+        .unwrap_or_else(|| DeviceName::from_unique_id(&unique_id));
+    let mut id: heapless::String<16> = heapless::String::new();
+    for byte in unique_id.iter() {
+        let _ = write!(id, "{:02x}", byte);
+    }
+    let mut text: heapless::String<96> = heapless::String::new();
+    let _ = if json {
+        JsonLine::new(&mut text)
+            .string("name", name.as_str())
+            .string("unique_id", &id)
+            .string("version", env!("CARGO_PKG_VERSION"))
+            .finish()
+    } else {
+        write!(
+            text,
+            "name {}\r\nunique id {}\r\nversion {}\r\n",
+            name,
+            id,
+            env!("CARGO_PKG_VERSION")
+        )
+    };
+    send_to_host(text.as_bytes());
+}
+
+/// Color, brightness and effect of the RGB LED
+fn rgb_command(state: &mut CommandState, mut args: Args) {
+    let rgb_led = match state.rgb_led.as_mut() {
+        Some(rgb_led) => rgb_led,
+        None => {
+            send_to_host(b"rgb: off in safe mode\r\n");
+            return;
+        }
+    };
+    match (args.next(), args.next()) {
+        (Some("off"), None) => rgb_led.set_brightness(0),
+        (Some("brightness"), Some(value)) => match value.parse() {
+            Ok(brightness) => rgb_led.set_brightness(brightness),
+            Err(_) => send_to_host(b"rgb brightness: use 0 to 255\r\n"),
+        },
+        (Some("solid"), None) => rgb_led.set_effect(Effect::Solid),
+        (Some("breathe"), None) => rgb_led.set_effect(Effect::Breathe { period_ms: 3000 }),
+        (Some("blink"), None) => rgb_led.set_effect(Effect::Blink { period_ms: 1000 }),
+        (Some(hex), None) => match u32::from_str_radix(hex, 16) {
+            Ok(rgb) if hex.len() == 6 => {
+                let [_, r, g, b] = rgb.to_be_bytes();
+                rgb_led.set_rgb888(Rgb888::new(r, g, b));
+                if rgb_led.brightness() == 0 {
+                    rgb_led.set_brightness(255);
+                }
+            }
+            _ => send_to_host(b"rgb: use <rrggbb>, off, brightness, solid, breathe or blink\r\n"),
+        },
+        _ => (),
+    }
+}
+
+/// Show which board this is, by flashing its screen and LED for 5 seconds
+fn identify_command(state: &mut CommandState, mut args: Args) {
+    if args.next().is_some() {
+        return;
+    }
+    state.identify = 500;
+    let name = DEVICE_NAME.lock(|name| *name);
+    let mut text: heapless::String<64> = heapless::String::new();
+    let _ = match name {
+        Some(name) => write!(text, "identify {}\r\n", name),
+        None => write!(text, "identify\r\n"),
+    };
+    send_to_host(text.as_bytes());
+}
+
+/// Blink signature of the LED, until the next boot
+fn led_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    match (args.next(), args.next()) {
+        (Some("pattern"), None) => {
+            let pattern = state.blink.as_ref().map(BlinkPattern::as_str);
+            let mut text: heapless::String<48> = heapless::String::new();
+            let _ = match (pattern, json) {
+                (Some(pattern), true) => {
+                    JsonLine::new(&mut text).string("pattern", pattern).finish()
+                }
+                (None, true) => JsonLine::new(&mut text).null("pattern").finish(),
+                (pattern, false) => write!(text, "led pattern {}\r\n", pattern.unwrap_or("off")),
+            };
+            send_to_host(text.as_bytes());
+        }
+        (Some("pattern"), Some("off")) => {
+            state.blink = None;
+            send_to_host(b"led pattern off\r\n");
+        }
+        (Some("pattern"), Some(pattern)) => match BlinkPattern::parse(pattern) {
+            Some(pattern) => {
+                state.blink = Some(pattern);
+                send_to_host(b"led pattern set\r\n");
+            }
+            None => send_to_host(b"led pattern: use up to 12 dots and dashes, such as ..-\r\n"),
+        },
+        _ => (),
+    }
+}
+
+/// Brightness and idle timeout of the backlight
+fn backlight_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    match (args.next(), args.next()) {
+        (None, None) => {
+            let (brightness, idle_timeout) = BACKLIGHT
+                .lock(|backlight| (backlight.brightness(), backlight.idle_timeout()))
+                .unwrap_or((0, None));
+            let mut text: heapless::String<64> = heapless::String::new();
+            let _ = match (idle_timeout, json) {
+                (Some(seconds), true) => JsonLine::new(&mut text)
+                    .number("brightness", brightness)
+                    .number("idle", seconds)
+                    .finish(),
+                (None, true) => JsonLine::new(&mut text)
+                    .number("brightness", brightness)
+                    .null("idle")
+                    .finish(),
+                (Some(seconds), false) => {
+                    write!(
+                        text,
+                        "backlight {}, dims after {}s\r\n",
+                        brightness, seconds
+                    )
+                }
+                (None, false) => write!(text, "backlight {}, never dims\r\n", brightness),
+            };
+            send_to_host(text.as_bytes());
+        }
+        (Some("idle"), Some(seconds)) => {
+            let timeout = match seconds {
+                "off" => Ok(None),
+                seconds => seconds.parse().map(Some),
+            };
+            match timeout {
+                Ok(timeout) => {
+                    let now_ms = (now_us() / 1000) as u32;
+                    BACKLIGHT.lock(|backlight| backlight.set_idle_timeout(timeout, now_ms));
+                    send_to_host(b"backlight idle set\r\n");
+                }
+                Err(_) => send_to_host(b"backlight idle: use a number of seconds, or off\r\n"),
+            }
+        }
+        (Some(value), None) => match value.parse() {
+            Ok(brightness) => {
+                BACKLIGHT.lock(|backlight| backlight.set_brightness(brightness));
+                send_to_host(b"backlight set\r\n");
+            }
+            Err(_) => send_to_host(b"backlight: use 0 to 255\r\n"),
+        },
+        _ => (),
+    }
+}
+
+/// Idle timeout and animations of Ferris
+fn ferris_command(_state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (None, None) => {
+            let mut text: heapless::String<96> = heapless::String::new();
+            let _ = IDLE.lock(|idle| {
+                match idle.idle_after() {
+                    Some(seconds) => write!(text, "ferris after {}s:", seconds)?,
+                    None => write!(text, "ferris off:")?,
+                }
+                for animation in idle.animations() {
+                    write!(text, " {}", animation.name)?;
+                }
+                write!(text, "\r\n")
+            });
+            send_to_host(text.as_bytes());
+        }
+        (Some("idle"), Some(seconds)) => {
+            let timeout = match seconds {
+                "off" => Ok(None),
+                seconds => seconds.parse().map(Some),
+            };
+            match timeout {
+                Ok(timeout) => {
+                    let now_ms = (now_us() / 1000) as u32;
+                    if IDLE.lock(|idle| idle.set_idle_after(timeout, now_ms)) == Some(true) {
+                        TERMINAL.lock(|terminal| terminal.refresh());
+                    }
+                    send_to_host(b"ferris idle set\r\n");
+                }
+                Err(_) => send_to_host(b"ferris idle: use a number of seconds, or off\r\n"),
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Replace the name of the device, which the USB serial number shows from the next boot
+fn name_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("set"), Some(name)) => match DeviceName::parse(name.as_bytes()) {
+            Some(name) => {
+                state.settings.name = Some(name);
+                state.settings.save();
+                DEVICE_NAME.put(name);
+                send_to_host(b"name set, the USB serial number changes on the next boot\r\n");
+            }
+            None => send_to_host(b"name: use up to 24 lower case letters, digits or dashes\r\n"),
+        },
+        (Some("clear"), None) => {
+            state.settings.name = None;
+            state.settings.save();
+            DEVICE_NAME.put(DeviceName::from_unique_id(&flash::unique_id()));
+            send_to_host(b"name cleared\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Show the segments of the status bar, set them from a comma separated list, or reset them
+fn status_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next(), args.next()) {
+        (None, None, None) => {
+            let mut text: heapless::String<64> = heapless::String::new();
+            let _ = text.push_str("status:");
+            for segment in state.settings.status.segments.iter().flatten() {
+                let _ = write!(text, " {}", segment.name());
+            }
+            let _ = text.push_str("\r\n");
+            send_to_host(text.as_bytes());
+        }
+        (Some("set"), Some(names), None) => match StatusLayout::parse(names) {
+            Some(layout) => {
+                state.settings.status = layout;
+                state.settings.save();
+                send_to_host(b"status set\r\n");
+            }
+            None => send_to_host(
+                b"status: use up to 6 of clock, temp, usb, rx, baud, errors or search, \
+                separated by commas\r\n",
+            ),
+        },
+        (Some("reset"), None, None) => {
+            state.settings.status = StatusLayout::default();
+            state.settings.save();
+            send_to_host(b"status reset\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Save the settings now, and show how much of the current configuration sector is used
+fn config_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("save"), None) => {
+            let used = state.settings.save();
+            let mut text: heapless::String<48> = heapless::String::new();
+            let _ = write!(
+                text,
+                "config saved, record {} of {}\r\n",
+                used,
+                config::RECORDS
+            );
+            send_to_host(text.as_bytes());
+        }
+        (None, None) => {
+            let mut text: heapless::String<64> = heapless::String::new();
+            let _ = write!(
+                text,
+                "config: {} of {} records used{}\r\n",
+                config::used(),
+                config::RECORDS,
+                if config::is_corrupt() {
+                    ", newest corrupt"
+                } else {
+                    ""
+                }
+            );
+            send_to_host(text.as_bytes());
+        }
+        _ => (),
+    }
+}
+
+/// Show the metrics, or send them every few seconds
+fn stats_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    match (args.next(), args.next()) {
+        (None, None) => send_stats(json),
+        (Some("reset"), None) => {
+            METRICS.reset();
+            send_to_host(b"stats reset\r\n");
+        }
+        (Some("every"), Some(seconds)) => match seconds.parse() {
+            Ok(seconds) if seconds > 0 => {
+                state.stats_every = Some(seconds);
+                send_to_host(b"stats streaming\r\n");
+            }
+            _ => send_to_host(b"stats: use a number of seconds\r\n"),
+        },
+        (Some("off"), None) => {
+            state.stats_every = None;
+            send_to_host(b"stats stopped\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Report the latency markers of the host once drawn
+fn latency_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("on"), None) => {
+            // Both counts of the markers start over together
+            cortex_m::interrupt::free(|_| {
+                MARKER_STAMPS.put(MarkerStamps::new());
+                state.latency = Some(LatencyProbe::new());
+                LATENCY_TEST.store(true, Ordering::Relaxed);
+            });
+            send_to_host(b"latency test on\r\n");
+        }
+        (Some("off"), None) => {
+            LATENCY_TEST.store(false, Ordering::Relaxed);
+            state.latency = None;
+            send_to_host(b"latency test off\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Turn the `!EVT` notifications on or off, or choose the kinds of events sent
+fn events_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (None, None) => {
+            let mut kinds: heapless::String<32> = heapless::String::new();
+            for kind in EventKind::ALL {
+                if EVENTS.mask() & kind.mask() != 0 {
+                    if !kinds.is_empty() {
+                        let _ = kinds.push(',');
+                    }
+                    let _ = kinds.push_str(kind.name());
+                }
+            }
+            let mut text: heapless::String<64> = heapless::String::new();
+            let _ = if state.json {
+                JsonLine::new(&mut text)
+                    .boolean("enabled", EVENTS.is_enabled())
+                    .string("kinds", &kinds)
+                    .finish()
+            } else {
+                let enabled = if EVENTS.is_enabled() { "on" } else { "off" };
+                write!(text, "events {}: {}\r\n", enabled, kinds)
+            };
+            send_to_host(text.as_bytes());
+        }
+        (Some(enabled @ ("on" | "off")), None) => {
+            EVENTS.set_enabled(enabled == "on");
+            send_to_host(b"events set\r\n");
+        }
+        (Some("mask"), Some(kinds)) => match EventKind::parse_mask(kinds) {
+            Some(mask) => {
+                EVENTS.set_mask(mask);
+                send_to_host(b"events mask set\r\n");
+            }
+            None => send_to_host(b"events: use all, or button,temp,input,display\r\n"),
+        },
+        _ => (),
+    }
+}
+
+/// Show the bytes lost since boot or the last clear, and clear them with `errors clear`
+fn errors_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    if let (arg @ (None | Some("clear")), None) = (args.next(), args.next()) {
+        let errors = match arg {
+            Some(_) => ERRORS.take(),
+            None => ERRORS.snapshot(),
+        };
+        let mut text: heapless::String<80> = heapless::String::new();
+        let _ = if json {
+            JsonLine::new(&mut text)
+                .number("rx_overflow", errors.rx_overflow)
+                .number("tx_drop", errors.tx_drop)
+                .number("queue_overrun", errors.queue_overrun)
+                .finish()
+        } else {
+            write!(
+                text,
+                "errors: {} rx overflow, {} tx drop, {} queue overrun\r\n",
+                errors.rx_overflow, errors.tx_drop, errors.queue_overrun
+            )
+        };
+        send_to_host(text.as_bytes());
+    }
+}
+
+/// Add a sample to the plot, or change how it is drawn
+fn plot_command(state: &mut CommandState, mut args: Args) {
+    if let (Some(arg), None) = (args.next(), args.next()) {
+        match arg {
+            "line" => state.plot.set_mode(PlotMode::Sparkline),
+            "bars" => state.plot.set_mode(PlotMode::Bars),
+            "clear" => state.plot.clear(),
+            _ => match arg.parse() {
+                Ok(sample) => state.plot.push(sample),
+                Err(_) => {
+                    send_to_host(b"plot: invalid sample\r\n");
+                    return;
+                }
+            },
+        }
+        draw_on_screen(|screen| {
+            let _ = state.plot.draw(screen);
+        });
+    }
+}
+
+/// Watch the received data for a pattern
+fn trigger_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    match (args.next(), args.next()) {
+        (Some("add"), Some(pattern)) => match args.next().and_then(TriggerAction::from_name) {
+            Some(action) => {
+                let added = TRIGGERS
+                    .lock(|triggers| {
+                        triggers.add(pattern, action) && {
+                            triggers.save();
+                            true
+                        }
+                    })
+                    .unwrap_or(false);
+                if added {
+                    send_to_host(b"trigger added\r\n");
+                } else {
+                    send_to_host(b"trigger: too many triggers or pattern too long\r\n");
+                }
+            }
+            None => send_to_host(b"trigger: action must be highlight, led or marker\r\n"),
+        },
+        (Some("remove"), Some(index)) => {
+            let removed = TRIGGERS
+                .lock(|triggers| {
+                    match index.parse().ok().and_then(|index| triggers.remove(index)) {
+                        Some(_) => {
+                            triggers.save();
+                            true
+                        }
+                        None => false,
+                    }
+                })
+                .unwrap_or(false);
+            if removed {
+                send_to_host(b"trigger removed\r\n");
+            } else {
+                send_to_host(b"trigger: no such trigger\r\n");
+            }
+        }
+        (Some("clear"), None) => {
+            TRIGGERS.lock(|triggers| {
+                triggers.clear();
+                triggers.save();
+            });
+            send_to_host(b"triggers cleared\r\n");
+        }
+        (Some("list"), None) => {
+            TRIGGERS.lock(|triggers| {
+                for (index, trigger) in triggers.iter().enumerate() {
+                    let mut text: heapless::String<96> = heapless::String::new();
+                    let result = if json {
+                        JsonLine::new(&mut text)
+                            .number("index", index)
+                            .string("pattern", &trigger.pattern)
+                            .string("action", trigger.action.name())
+                            .finish()
+                    } else {
+                        write!(
+                            text,
+                            "{}: {} {}\r\n",
+                            index,
+                            trigger.pattern,
+                            trigger.action.name()
+                        )
+                    };
+                    if result.is_ok() {
+                        send_to_host(text.as_bytes());
+                    }
+                }
+            });
+        }
+        _ => (),
+    }
+}
+
+/// Log the temperature every second
+fn log_command(state: &mut CommandState, mut args: Args) {
+    let data_log = match state.data_log.as_mut() {
+        Some(data_log) => data_log,
+        None => {
+            send_to_host(b"log: off in safe mode\r\n");
+            return;
+        }
+    };
+    match (args.next(), args.next()) {
+        (Some("start"), None) => {
+            data_log.start();
+            send_to_host(b"log started\r\n");
+        }
+        (Some("stop"), None) => {
+            data_log.stop();
+            send_to_host(b"log stopped\r\n");
+        }
+        (Some("dump"), None) => {
+            // Include the latest lines
+            data_log.flush();
+            send_to_host(b"time_ms,temperature_c\r\n");
+            state.log_dump = Some(data_log.dump());
+        }
+        _ => (),
+    }
+}
+
+/// Open the lock with its PIN
+fn unlock_command(state: &mut CommandState, mut args: Args) {
+    if let (Some(pin), None) = (args.next(), args.next()) {
+        match state.lock.unlock(pin, now_us()) {
+            Ok(()) => send_to_host(b"unlocked\r\n"),
+            Err(LockError::Backoff(seconds)) => {
+                let mut text: heapless::String<48> = heapless::String::new();
+                if write!(text, "unlock: try again in {}s\r\n", seconds).is_ok() {
+                    send_to_host(text.as_bytes());
+                }
+            }
+            Err(_) => send_to_host(b"unlock: wrong PIN\r\n"),
+        }
+    }
+}
+
+/// Lock the commands that change the device, or change the PIN
+fn lock_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (None, None) => {
+            state.lock.lock();
+            send_to_host(if state.lock.is_set() {
+                b"locked\r\n"
+            } else {
+                b"lock: no PIN set\r\n"
+            });
+        }
+        (Some("set"), Some(pin)) => match state.lock.set_pin(Some(pin), &mut state.rng) {
+            Ok(()) => send_to_host(b"PIN set\r\n"),
+            Err(_) => send_to_host(b"lock: PIN must be 4 to 8 digits from 1 to 4\r\n"),
+        },
+        (Some("clear"), None) => {
+            let _ = state.lock.set_pin(None, &mut state.rng);
+            send_to_host(b"PIN removed\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Watch the heartbeats of the host
+fn heartbeat_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (None, None) => {
+            if let Some(event) = state.heartbeat.beat(now_us()) {
+                show_heartbeat(event);
+            }
+        }
+        (Some("every"), Some(seconds)) => match seconds.parse() {
+            Ok(seconds) if seconds > 0 => {
+                state.heartbeat.enable(seconds, now_us());
+                state.stall_pin = args.next() == Some("pin");
+                send_to_host(b"heartbeat enabled\r\n");
+            }
+            _ => send_to_host(b"heartbeat: invalid period\r\n"),
+        },
+        (Some("off"), None) => {
+            if let Some(event) = state.heartbeat.disable() {
+                show_heartbeat(event);
+            }
+            send_to_host(b"heartbeat disabled\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// First failure found, and the codes blinked by the LED
+fn fault_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (None, None) => {
+            let mut text: heapless::String<48> = heapless::String::new();
+            let _ = match state.fault {
+                Some(fault) => write!(text, "fault {}: {}\r\n", fault.code(), fault.name()),
+                None => write!(text, "no fault\r\n"),
+            };
+            send_to_host(text.as_bytes());
+        }
+        (Some("codes"), None) => {
+            for fault in FAULTS.iter() {
+                let mut text: heapless::String<48> = heapless::String::new();
+                let pattern = fault.pattern();
+                let _ = write!(
+                    text,
+                    "{:<2} {:<12} {}\r\n",
+                    fault.code(),
+                    pattern.as_str(),
+                    fault.name()
+                );
+                send_to_host(text.as_bytes());
+            }
+        }
+        (Some("clear"), None) => {
+            state.fault = None;
+            send_to_host(b"fault cleared\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Cut the power drawn by the panel
+fn screen_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("check"), Some(check)) => match check {
+            "on" => {
+                let mut id = None;
+                draw_on_screen(|screen| id = display::read_id(screen));
+                match id {
+                    Some(_) => state.panel_id = id,
+                    None => send_to_host(b"screen: the panel isn't answering on GPIO0\r\n"),
+                }
+            }
+            "off" => state.panel_id = None,
+            _ => send_to_host(b"screen: check must be on or off\r\n"),
+        },
+        (Some(mode), start) => {
+            let mode = match (mode, start, args.next()) {
+                ("normal", None, None) => PanelMode::Normal,
+                ("idle", None, None) => PanelMode::Idle,
+                ("band", Some(start), Some(end)) => match (start.parse(), end.parse()) {
+                    (Ok(start), Ok(end)) if start <= end => PanelMode::Partial { start, end },
+                    _ => {
+                        send_to_host(b"screen: invalid band\r\n");
+                        return;
+                    }
+                },
+                _ => {
+                    send_to_host(b"screen: mode must be normal, idle or band <start> <end>\r\n");
+                    return;
+                }
+            };
+            draw_on_screen(|screen| display::set_mode(screen, mode));
+        }
+        _ => (),
+    }
+}
+
+/// Show how the pins are set up
+fn pins_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    if args.next().is_some() {
+        return;
+    }
+    if !json {
+        send_to_host(pin_info::HEADER.as_bytes());
+    }
+    state.pin_dump = Some(0);
+}
+
+/// Drive the relay outputs, numbered from 1
+fn switch_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("list"), None) => {
+            for index in 0..MAX_SWITCHES {
+                let mut text: heapless::String<48> = heapless::String::new();
+                let _ = write!(
+                    text,
+                    "{}: {}",
+                    index + 1,
+                    if state.switches.is_on(index) {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                );
+                if let Some(schedule) = state.switches.schedule(index) {
+                    let _ = write!(text, " {}", schedule);
+                }
+                if state.switches.restores(index) {
+                    let _ = write!(text, " restore");
+                }
+                let _ = write!(text, "\r\n");
+                send_to_host(text.as_bytes());
+            }
+        }
+        (Some(index), Some(arg)) => {
+            let index = match index.parse::<usize>() {
+                Ok(index) if (1..=MAX_SWITCHES).contains(&index) => index - 1,
+                _ => {
+                    send_to_host(b"switch: no such switch\r\n");
+                    return;
+                }
+            };
+            let switches = &mut state.switches;
+            let done = match (arg, args.next(), args.next()) {
+                ("on", None, None) => switches.set(index, true),
+                ("off", None, None) => switches.set(index, false),
+                ("toggle", None, None) => switches.toggle(index),
+                ("schedule", Some("off"), None) => switches.set_schedule(index, None),
+                ("schedule", Some(on), Some(off)) => {
+                    match (Schedule::parse_time(on), Schedule::parse_time(off)) {
+                        (Some(on), Some(off)) => {
+                            switches.set_schedule(index, Some(Schedule { on, off }))
+                        }
+                        _ => false,
+                    }
+                }
+                ("boot", Some("off"), None) => switches.set_restore(index, false),
+                ("boot", Some("restore"), None) => switches.set_restore(index, true),
+                _ => false,
+            };
+            if done {
+                switches.save();
+                send_to_host(b"ok\r\n");
+            } else {
+                send_to_host(b"switch: invalid command\r\n");
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Frame the serial data into channels, or go back to plain data
+fn mux_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("on"), None) => {
+            send_to_host(b"mux on\r\n");
+            MUX_ENABLED.store(true, Ordering::Relaxed);
+        }
+        (Some("off"), None) => {
+            MUX_ENABLED.store(false, Ordering::Relaxed);
+            // The frames of the mirror would end up in the console
+            state.mirror = None;
+            send_to_host(b"mux off\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Send the screen to the host on the data channel, see `mirror::Mirror`
+fn mirror_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("on"), None) => {
+            if MUX_ENABLED.load(Ordering::Relaxed) {
+                match state.mirror.as_mut() {
+                    // A new viewer gets the whole screen
+                    Some(mirror) => mirror.reset(),
+                    None => state.mirror = Some(Mirror::new()),
+                }
+                send_to_host(b"mirror on\r\n");
+            } else {
+                send_to_host(b"mirror needs `mux on` first\r\n");
+            }
+        }
+        (Some("off"), None) => {
+            state.mirror = None;
+            send_to_host(b"mirror off\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Hash an area of the flash, to compare it with a checksum computed on the host
+fn hash_command(_state: &mut CommandState, mut args: Args) {
+    if let (Some(algorithm), Some(area)) = (args.next(), args.next()) {
+        let (offset, len) = match (area, args.next()) {
+            ("log", None) => (LOG_OFFSET, LOG_SIZE),
+            (offset, Some(len)) => match (parse_number(offset), parse_number(len)) {
+                (Some(offset), Some(len))
+                    if offset
+                        .checked_add(len)
+                        .map_or(false, |end| end <= FLASH_SIZE) =>
+                {
+                    (offset, len)
+                }
+                _ => {
+                    send_to_host(b"hash: area out of the flash\r\n");
+                    return;
+                }
+            },
+            _ => {
+                send_to_host(b"hash: area must be log or <offset> <len>\r\n");
+                return;
+            }
+        };
+        let data = flash::read(offset, len as usize);
+        let mut text: heapless::String<80> = heapless::String::new();
+        match algorithm {
+            "crc32" => {
+                let mut crc = Crc32::new();
+                crc.update(data);
+                let _ = write!(text, "crc32 {:08x}", crc.finish());
+            }
+            "sha256" => {
+                let mut sha = Sha256::new();
+                sha.update(data);
+                let _ = write!(text, "sha256 ");
+                for byte in sha.finish().iter() {
+                    let _ = write!(text, "{:02x}", byte);
+                }
+            }
+            _ => {
+                send_to_host(b"hash: algorithm must be crc32 or sha256\r\n");
+                return;
+            }
+        }
+        let _ = write!(text, "\r\n");
+        send_to_host(text.as_bytes());
+    }
+}
+
+/// Scale the terminal text, for readability at a distance
+fn zoom_command(_state: &mut CommandState, mut args: Args) {
+    if let (Some(zoom), None) = (args.next(), args.next()) {
+        match zoom {
+            "1" | "2" => {
+                let zoom = if zoom == "2" { 2 } else { 1 };
+                TERMINAL.lock(|terminal| terminal.set_zoom(zoom));
+            }
+            _ => send_to_host(b"zoom: must be 1 or 2\r\n"),
+        }
+    }
+}
+
+/// Reply to the commands in JSON lines, for host automation
+fn output_command(state: &mut CommandState, mut args: Args) {
+    if let (Some(format), None) = (args.next(), args.next()) {
+        match format {
+            "json" => state.json_output = true,
+            "text" => state.json_output = false,
+            _ => send_to_host(b"output: must be json or text\r\n"),
+        }
+    }
+}
+
+/// Pause long output after this many lines
+fn pager_command(state: &mut CommandState, mut args: Args) {
+    if let (Some(rows), None) = (args.next(), args.next()) {
+        match rows {
+            "off" => state.pager.set_rows(None),
+            rows => match rows.parse() {
+                Ok(rows) if rows > 0 => state.pager.set_rows(Some(rows)),
+                _ => send_to_host(b"pager: must be off or a number of lines\r\n"),
+            },
+        }
+    }
+}
+
+/// Show how often the glyphs of the terminal are found in the cache
+fn glyphs_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    if args.next().is_some() {
+        return;
+    }
+    let stats = TERMINAL.lock(|terminal| terminal.glyph_stats());
+    if let Some(stats) = stats {
+        let mut text: heapless::String<64> = heapless::String::new();
+        let _ = if json {
+            JsonLine::new(&mut text)
+                .number("hits", stats.hits)
+                .number("misses", stats.misses)
+                .finish()
+        } else {
+            write!(
+                text,
+                "glyphs: {} hits, {} misses, {}%\r\n",
+                stats.hits,
+                stats.misses,
+                stats.hit_rate()
+            )
+        };
+        send_to_host(text.as_bytes());
+    }
+}
+
+/// Send the records of the logger to the terminal, the host, both or nowhere
+fn logger_command(_state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some(sinks @ ("display" | "usb" | "both" | "off")), None) => {
+            ROUTES.set(
+                Source::Log,
+                Sink::Display,
+                matches!(sinks, "display" | "both"),
+            );
+            ROUTES.set(Source::Log, Sink::Usb, matches!(sinks, "usb" | "both"));
+            send_to_host(b"logger set\r\n");
+        }
+        (Some("level"), Some(level)) => match level.parse::<LevelFilter>() {
+            Ok(level) => {
+                logger::set_level(level);
+                send_to_host(b"logger level set\r\n");
+            }
+            Err(_) => send_to_host(b"logger: use off, error, warn, info, debug or trace\r\n"),
+        },
+        (None, None) => {
+            let sinks = match (
+                ROUTES.is_routed(Source::Log, Sink::Display),
+                ROUTES.is_routed(Source::Log, Sink::Usb),
+            ) {
+                (true, true) => "both",
+                (true, false) => "display",
+                (false, true) => "usb",
+                (false, false) => "off",
+            };
+            let mut text: heapless::String<48> = heapless::String::new();
+            let _ = write!(text, "logger: {}, up to {}\r\n", sinks, log::max_level());
+            send_to_host(text.as_bytes());
+        }
+        _ => (),
+    }
+}
+
+/// Show the sinks of each source, or turn the route from a source to a sink on or off
+fn route_command(_state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next(), args.next(), args.next()) {
+        (None, None, None, None) => {
+            for source in Source::ALL {
+                let mut text: heapless::String<64> = heapless::String::new();
+                let _ = write!(text, "{} ->", source.name());
+                for sink in ROUTES.sinks(source) {
+                    let _ = write!(text, " {}", sink.name());
+                }
+                if ROUTES.sinks(source).next().is_none() {
+                    let _ = text.push_str(" none");
+                }
+                let _ = text.push_str("\r\n");
+                send_to_host(text.as_bytes());
+            }
+        }
+        (Some(source), Some(sink), Some(enabled @ ("on" | "off")), None) => {
+            match (Source::from_name(source), Sink::from_name(sink)) {
+                // Without it, no command could turn it back on
+                (Some(Source::UsbSerial), Some(Sink::Shell)) => {
+                    send_to_host(b"route: the shell always takes the data from usb\r\n")
+                }
+                (Some(source), Some(sink)) => {
+                    ROUTES.set(source, sink, enabled == "on");
+                    send_to_host(b"route set\r\n");
+                }
+                _ => send_to_host(
+                    b"route: use usb, buttons, log or uart, then display, usb, shell, page, uart \
+                    or buffer\r\n",
+                ),
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Send the data kept by the log buffer to the host, emptying it, or clear it
+fn buffer_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (None, None) => {
+            let len = LOG_BUFFER.lock(|buffer| buffer.len()).unwrap_or(0);
+            state.buffer_dump = Some(len).filter(|&len| len > 0);
+        }
+        (Some("clear"), None) => {
+            LOG_BUFFER.lock(|buffer| buffer.clear());
+            send_to_host(b"buffer cleared\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Bind a bitmap to a private use code point, to show icons inline with the text
+fn glyph_command(_state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("set"), Some(index)) => {
+            let bitmap = args.next().and_then(Bitmap::parse_hex);
+            match (index.parse(), bitmap) {
+                (Ok(index), Some(bitmap)) if index < custom_glyph::MAX_GLYPHS => {
+                    TERMINAL.lock(|terminal| terminal.set_custom_glyph(index, Some(bitmap)));
+                    send_to_host(b"glyph set\r\n");
+                }
+                _ => send_to_host(b"glyph: use `glyph set <0-15> <rows in hex>`\r\n"),
+            }
+        }
+        (Some("clear"), index) => match index.map(str::parse::<usize>) {
+            Some(Err(_)) => send_to_host(b"glyph: use `glyph clear [0-15]`\r\n"),
+            index => {
+                let indexes = match index {
+                    Some(Ok(index)) => index..index + 1,
+                    _ => 0..custom_glyph::MAX_GLYPHS,
+                };
+                TERMINAL.lock(|terminal| {
+                    for index in indexes {
+                        terminal.set_custom_glyph(index, None);
+                    }
+                });
+                send_to_host(b"glyph cleared\r\n");
+            }
+        },
+        (Some("list"), None) => {
+            let mut text: heapless::String<192> = heapless::String::new();
+            let _ = text.push_str("custom glyphs:");
+            TERMINAL.lock(|terminal| {
+                for index in terminal.custom_glyphs().defined() {
+                    let code_point = custom_glyph::FIRST_CODE_POINT + index as u32;
+                    let _ = write!(text, " {}=U+{:04X}", index, code_point);
+                }
+            });
+            let _ = text.push_str("\r\n");
+            send_to_host(text.as_bytes());
+        }
+        _ => (),
+    }
+}
+
+/// Update parts of the screen, for host UIs only sending what changed
+fn draw_command(_state: &mut CommandState, args: Args) {
+    let updates = args.rest();
+    if updates.is_empty() {
+        return;
+    }
+    if let Err(index) = draw_updates(updates) {
+        let mut text: heapless::String<40> = heapless::String::new();
+        let _ = write!(text, "draw: invalid update {}\r\n", index + 1);
+        send_to_host(text.as_bytes());
+    }
+}
+
+/// Hold back small writes to the host, to send them in fewer packets
+fn coalesce_command(_state: &mut CommandState, mut args: Args) {
+    if let (Some(policy), None) = (args.next(), args.next()) {
+        let policy = match policy {
+            "off" => FlushPolicy::Immediate,
+            "line" => FlushPolicy::Newline,
+            size => match size.parse() {
+                Ok(size) if size > 0 => FlushPolicy::Size(size),
+                _ => {
+                    send_to_host(b"coalesce: must be off, line or a number of bytes\r\n");
+                    return;
+                }
+            },
+        };
+        USB_CONSOLE.lock(|console| console.set_flush_policy(policy));
+    }
+}
+
+/// Read the screen kept from the last crash
+fn crash_command(state: &mut CommandState, mut args: Args) {
+    let json = state.json;
+    match (args.next(), args.next()) {
+        (None, None) => match Crash::load() {
+            Some(crash) if json => {
+                let mut text: heapless::String<{ MAX_MESSAGE * 6 + 64 }> = heapless::String::new();
+                let _ = JsonLine::new(&mut text)
+                    .string("kind", crash.kind.name())
+                    .number("uptime_ms", crash.uptime_ms)
+                    .bytes("message", crash.message())
+                    .finish();
+                send_to_host(text.as_bytes());
+            }
+            Some(crash) => {
+                let mut text: heapless::String<48> = heapless::String::new();
+                let _ = write!(
+                    text,
+                    "crash: {} after {} ms: ",
+                    crash.kind.name(),
+                    crash.uptime_ms
+                );
+                send_to_host(text.as_bytes());
+                send_to_host(crash.message());
+                send_to_host(b"\r\n");
+            }
+            None if json => send_to_host(b"{\"kind\":null}\r\n"),
+            None => send_to_host(b"crash: none stored\r\n"),
+        },
+        (Some("show"), None) => match Crash::load() {
+            Some(crash) => {
+                TERMINAL.lock(|terminal| {
+                    for row in 0..crash.rows() {
+                        terminal.set_cells(0, row, crash.row(row));
+                    }
+                });
+            }
+            None => send_to_host(b"crash: none stored\r\n"),
+        },
+        (Some("dump"), None) => state.crash_dump = Some(0),
+        (Some("clear"), None) => {
+            crash::clear();
+            send_to_host(b"crash cleared\r\n");
+        }
+        _ => (),
+    }
+}
+
+/// Record the pulses on the capture pin, then send their durations
+fn pulses_command(state: &mut CommandState, mut args: Args) {
+    let pulse_capture = match state.pulse_capture.as_mut() {
+        Some(pulse_capture) => pulse_capture,
+        None => {
+            send_to_host(b"pulses: off in safe mode\r\n");
+            return;
+        }
+    };
+    if let (ms, None) = (args.next(), args.next()) {
+        match ms.map_or(Ok(1000), str::parse::<u32>) {
+            Ok(ms) if ms > 0 && ms <= MAX_CAPTURE_MS => {
+                let mut durations = [0; MAX_PULSES];
+                let capture = pulse_capture.capture(&mut durations, ms * 1000, now_us);
+                state.pulses.clear();
+                let _ = state.pulses.extend_from_slice(&durations[..capture.len]);
+                let mut text: heapless::String<64> = heapless::String::new();
+                let _ = write!(
+                    text,
+                    "pulses: {} recorded in microseconds{}\r\n",
+                    capture.len,
+                    if capture.overrun {
+                        ", some too fast"
+                    } else {
+                        ""
+                    }
+                );
+                send_to_host(text.as_bytes());
+                state.pulse_dump = Some(0);
+            }
+            _ => send_to_host(b"pulses: use a time of 1 to 5000 milliseconds\r\n"),
+        }
+    }
+}
+
+/// Show the assets uploaded over the data channel
+fn assets_command(_state: &mut CommandState, mut args: Args) {
+    if args.next().is_some() {
+        return;
+    }
+    for kind in [AssetKind::Splash, AssetKind::Notes] {
+        let mut line: heapless::String<64> = heapless::String::new();
+        let _ = match Asset::load(kind) {
+            Some(asset) => write!(
+                line,
+                "{}: {} bytes, upload {}\r\n",
+                kind.name(),
+                asset.data().len(),
+                asset.sequence
+            ),
+            None => write!(line, "{}: none\r\n", kind.name()),
+        };
+        send_to_host(line.as_bytes());
+    }
+}
+
+/// Send the configuration and the assets to the host, see `restore` to write them back
+fn backup_command(state: &mut CommandState, mut args: Args) {
+    if args.next().is_some() {
+        return;
+    }
+    if MUX_ENABLED.load(Ordering::Relaxed) {
+        let backup = Backup::new();
+        let mut line: heapless::String<48> = heapless::String::new();
+        let _ = write!(line, "backup of {} sectors started\r\n", backup.sectors());
+        send_to_host(line.as_bytes());
+        state.backup = Some(backup);
+    } else {
+        send_to_host(b"backup needs `mux on` first\r\n");
+    }
+}
+
+/// Write back a backup received on the data channel
+fn restore_command(state: &mut CommandState, mut args: Args) {
+    if args.next().is_some() {
+        return;
+    }
+    if MUX_ENABLED.load(Ordering::Relaxed) {
+        state.restore = Some(Restore::new());
+        send_to_host(b"restore: send the backup on the data channel\r\n");
+    } else {
+        send_to_host(b"restore needs `mux on` first\r\n");
+    }
+}
+
+/// Run the command waiting for a confirmation, with the token of its reply
+fn confirm_command(state: &mut CommandState, mut args: Args) {
+    if let (Some(token), None) = (args.next(), args.next()) {
+        match u16::from_str_radix(token, 16) {
+            _ if state.settings.confirm == ConfirmMode::Button => {
+                send_to_host(b"confirm: press X on the device\r\n")
+            }
+            Ok(token) => match state.confirm.confirm(token, (now_us() / 1000) as u32) {
+                Ok(action) => run_disruptive(action, state),
+                Err(error) => send_to_host(match error {
+                    ConfirmError::NothingPending => b"confirm: nothing to confirm\r\n",
+                    ConfirmError::WrongToken => b"confirm: wrong token, cancelled\r\n",
+                    ConfirmError::TooSoon => b"confirm: wait after a wrong token\r\n",
+                }),
+            },
+            Err(_) => send_to_host(b"confirm: invalid token\r\n"),
+        }
+    }
+}
+
+/// Search the terminal, stepping through the matches with the buttons
+fn search_command(state: &mut CommandState, args: Args) {
+    let query = args.rest().trim_end();
+    if query.is_empty() {
+        return;
+    }
+    match SearchPage::new(query) {
+        Some(page) => state.open_page = Some(Page::Search(page)),
+        None => send_to_host(b"search text too long\r\n"),
+    }
+}
+
+/// Measure the typing speed against the prompt
+fn typing_command(state: &mut CommandState, mut args: Args) {
+    if args.next().is_some() {
+        return;
+    }
+    state.open_page = Some(Page::Typing(TypingPage::new()));
+}
+
+/// Open a page, to drive it with the arrow keys, enter and Ctrl+C from the host
+fn page_command(state: &mut CommandState, mut args: Args) {
+    match (args.next(), args.next()) {
+        (Some("settings"), None) if state.lock.is_locked() => {
+            send_to_host(b"page: the settings can't be changed while locked\r\n")
+        }
+        (Some("settings"), None) => state.open_page = Some(Page::Settings(SettingsPage::new())),
+        (Some("switches"), None) => state.open_page = Some(Page::Switches(switches_menu())),
+        _ => (),
+    }
+}
+
+/// Apply the updates of a `draw` command, returning the index of the first invalid one
+///
+/// The updates are separated by `;`. Each one is either `cell <row> <col> <text>`, to set
+/// terminal cells without moving the cursor, or `rect <x> <y> <width> <height> <rrggbb>`, to
+/// fill an area of the screen. The updates before an invalid one are still applied.
+fn draw_updates(updates: &str) -> Result<(), usize> {
+    cortex_m::interrupt::free(|cs| {
+        let mut terminal = TERMINAL.borrow(cs);
+        let terminal = match terminal.as_mut() {
+            Some(terminal) => terminal,
+            None => return Ok(()),
+        };
+        for (index, update) in updates.split(';').enumerate() {
+            let mut fields = update.trim_start().splitn(4, ' ');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("cell"), Some(row), Some(col)) => match (row.parse(), col.parse()) {
+                    (Ok(row), Ok(col)) => {
+                        terminal.set_cells(col, row, fields.next().unwrap_or("").as_bytes())
+                    }
+                    _ => return Err(index),
+                },
+                (Some("rect"), Some(x), Some(y)) => {
+                    let (area, color) = parse_rect(x, y, fields.next()).ok_or(index)?;
+                    let _ = area
+                        .into_styled(PrimitiveStyle::with_fill(color))
+                        .draw(terminal.screen_mut());
+                }
+                // Nothing after the last `;`
+                (Some(""), None, None) => (),
+                _ => return Err(index),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Parse the fields of a `rect` update: position, then size and color in `rest`
+fn parse_rect(x: &str, y: &str, rest: Option<&str>) -> Option<(Rectangle, Rgb565)> {
+    let mut rest = rest?.split_whitespace();
+    let top_left = Point::new(x.parse().ok()?, y.parse().ok()?);
+    let size = Size::new(rest.next()?.parse().ok()?, rest.next()?.parse().ok()?);
+    let [_, r, g, b] = u32::from_str_radix(rest.next()?, 16).ok()?.to_be_bytes();
+    Some((
+        Rectangle::new(top_left, size),
+        Rgb565::from(Rgb888::new(r, g, b)),
+    ))
+}
+
+/// Parse a decimal number, or a hexadecimal one with a `0x` prefix
+fn parse_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,2339 @@
+//! # Pico USB Serial (with Interrupts) Example
+//!
+//! Creates a USB Serial device on a Pico board, with the USB driver running in
+//! the USB interrupt.
+//!
+//! This will create a USB Serial device echoing anything it receives. Incoming
+//! ASCII characters are converted to upercase, so you can tell it is working
+//! and not just local-echo!
+//!
+//! Run it with `cargo run --example serial_echo`.
+//!
+//! The commands of the shell are in `commands`, the pages shown over the terminal in `pages`,
+//! and the steps starting the hardware in `steps`.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    image::{Image, ImageRaw, ImageRawLE},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle, MonoTextStyleBuilder,
+    },
+    pixelcolor::{Rgb565, Rgb888, RgbColor},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+// The macro for marking our interrupt functions
+use rp2040_test::hal::pac::interrupt;
+
+use rp2040_test::assets::{Asset, AssetKind, Upload, UploadError};
+use rp2040_test::backlight::Backlight;
+use rp2040_test::backup::{self, Backup, Restore};
+use rp2040_test::baud::BaudLimiter;
+use rp2040_test::bench;
+use rp2040_test::blink::BlinkPattern;
+#[cfg(feature = "uart-route")]
+use rp2040_test::board::FreePin;
+use rp2040_test::board::{Board, Buttons, Panel};
+use rp2040_test::boot_mode::{self, BootMode};
+use rp2040_test::buttons::Debouncer;
+use rp2040_test::clock::{Timestamp, CLOCK};
+use rp2040_test::config;
+use rp2040_test::confirm::{self, ConfirmError, ConfirmMode, Confirmation};
+use rp2040_test::crash::{self, Crash, CrashKind, MAX_MESSAGE};
+use rp2040_test::custom_glyph::{self, Bitmap};
+use rp2040_test::datalog::{DataLog, LogDump, LOG_OFFSET, LOG_SIZE};
+use rp2040_test::device_name::DeviceName;
+use rp2040_test::display::{self, Display, PanelMode};
+use rp2040_test::entropy::Entropy;
+use rp2040_test::errors::{ErrorKind, ErrorLed, ERRORS};
+use rp2040_test::events::{ButtonEvents, Event, EventKind, TemperatureAlarm, EVENTS, MAX_INPUT};
+use rp2040_test::fault::{Fault, FAULTS};
+use rp2040_test::flash::{self, FLASH_SIZE};
+use rp2040_test::hash::{Crc32, Sha256};
+use rp2040_test::heartbeat::{Heartbeat, HeartbeatEvent};
+use rp2040_test::idle::{self, IdleAnimator, Pose, BLINK, HOP, MAX_LIFT, WAVE};
+use rp2040_test::input_field::InputField;
+use rp2040_test::json::JsonLine;
+use rp2040_test::keys::{ButtonKeys, Key, KeyDecoder, KeyMap};
+use rp2040_test::latency::{LatencyProbe, Marker, MarkerStamps};
+use rp2040_test::lock::{Lock, LockError};
+use rp2040_test::logger::{self, Logger};
+use rp2040_test::menu::{Menu, MenuAction};
+use rp2040_test::metrics::{Histogram, Metric, MetricValue, METRICS};
+use rp2040_test::mirror::{self, Mirror};
+#[cfg(feature = "msc")]
+use rp2040_test::msc::MassStorage;
+use rp2040_test::mux::{self, Channel, Decoder, MAX_FRAME};
+#[cfg(feature = "oled")]
+use rp2040_test::oled::{self, Oled, OledSclPin, OledSdaPin, StatusPanel};
+#[cfg(feature = "hid-payload")]
+use rp2040_test::payload::{Interlock, InterlockEvent, ScriptPlayer, Step, TextTyper};
+use rp2040_test::pin_info::{self, PinInfo, GPIO_COUNT};
+use rp2040_test::pin_owners::{PinConflict, PinOwner, PinOwners};
+use rp2040_test::plot::{Plot, PlotMode};
+use rp2040_test::pulsecap::PulseCapture;
+use rp2040_test::rgb_led::{Effect, RgbLed};
+use rp2040_test::routing::{LogBuffer, Sink, Source, ROUTES};
+use rp2040_test::search::MAX_QUERY;
+use rp2040_test::palette::{self, ansi, MIN_CONTRAST};
+use rp2040_test::settings::{EchoMode, Rotation, Settings, Theme};
+use rp2040_test::shared::{Core0Shared, Shared};
+use rp2040_test::shell::{
+    self, Args, Command, LineBuffer, Pager, Shell, ShellContext, CLEAR_PROMPT, MAX_LINE,
+    MORE_PROMPT,
+};
+use rp2040_test::sleep;
+use rp2040_test::soft_keyboard::{SoftKeyAction, SoftKeyboard};
+use rp2040_test::startup::{self, Outcome, Progress, Step as StartupStep};
+use rp2040_test::status::{Segment, SegmentRenderer, StatusBar, StatusInfo, StatusLayout};
+use rp2040_test::switch::{Schedule, Switches, MAX_SWITCHES};
+use rp2040_test::terminal::{TerminalBuilder, ANSI_COLORS};
+use rp2040_test::typing::{CharState, TypingTest};
+use rp2040_test::{PicoDisplayTerminal, PicoUsbConsole};
+use rp2040_test::{diag_debug, diag_info, diag_warn};
+use rp2040_test::triggers::{TriggerAction, Triggers};
+#[cfg(feature = "uart-route")]
+use rp2040_test::uart_bridge::UartBridge;
+#[cfg(feature = "msc")]
+use rp2040_test::virtual_fs::{VirtualFat, VirtualFile};
+
+// GPIO traits
+use embedded_hal::digital::v2::OutputPin;
+use log::LevelFilter;
+#[cfg(feature = "uart-route")]
+use rp2040_test::hal::gpio::pin::bank0;
+use rp2040_test::hal::gpio::DynPin;
+
+// Time handling traits
+use embedded_time::duration::*;
+use embedded_time::rate::*;
+
+// Counters shared with the interrupt
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// Formatting replies to the host
+use core::fmt::Write;
+
+// Whether the startup steps show their progress
+use core::cell::Cell;
+
+// Reading the ADC
+use embedded_hal::adc::OneShot;
+
+// Keeping the screen on panics and faults
+#[cfg(not(feature = "panic-display"))]
+use core::panic::PanicInfo;
+use cortex_m_rt::{exception, ExceptionFrame};
+
+// Pull in any important traits
+// use pico::hal::prelude::*;
+use rp2040_test::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+// use pico::hal::pac;
+use rp2040_test::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+// use pico::hal;
+use rp2040_test::hal;
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use rp2040_test::usb_console::FlushPolicy;
+#[cfg(feature = "log-port")]
+use rp2040_test::{usb_log, PicoLogPort};
+use rp2040_test::usb_link::{LinkMonitor, LinkState};
+use usbd_serial::SerialPort;
+
+// The commands of the shell, the pages shown over the terminal and the startup steps
+mod commands;
+mod pages;
+mod steps;
+
+use commands::{
+    ask_confirmation, build_shell, run_command, run_disruptive, Disruptive, RebootTarget,
+};
+use pages::{close_page, open_page, switches_menu, KeyboardPage, Page, SettingsPage};
+#[cfg(feature = "msc")]
+use steps::start_msc;
+#[cfg(feature = "oled")]
+use steps::start_oled;
+#[cfg(feature = "uart-route")]
+use steps::start_uart;
+use steps::{
+    draw_startup_progress, start_backlight, start_buttons, start_config, start_data_log,
+    start_display, start_host_features, start_idle, start_lock, start_pulse_capture, start_rgb_led,
+    start_settings, start_triggers, start_usb, start_usb_device,
+};
+
+// Note (safety): the USB objects are only used by `main` and the USB interrupt, on core 0, and
+// this example doesn't start core 1.
+
+/// The USB Device Driver (shared with the interrupt).
+static USB_DEVICE: Core0Shared<UsbDevice<hal::usb::UsbBus>> = unsafe { Core0Shared::new() };
+
+/// The USB Serial Device Driver (shared with the interrupt).
+static USB_CONSOLE: Core0Shared<PicoUsbConsole> = unsafe { Core0Shared::new() };
+
+/// The USB HID keyboard driver (shared with the interrupt).
+#[cfg(feature = "hid-payload")]
+static USB_HID: Core0Shared<usbd_hid::hid_class::HIDClass<hal::usb::UsbBus>> =
+    unsafe { Core0Shared::new() };
+
+/// The USB serial port carrying the logs (shared with the interrupt).
+#[cfg(feature = "log-port")]
+static USB_LOG: Core0Shared<PicoLogPort> = unsafe { Core0Shared::new() };
+
+/// The USB mass storage driver, showing the virtual files (shared with the interrupt).
+#[cfg(feature = "msc")]
+static USB_MSC: Core0Shared<MassStorage<hal::usb::UsbBus, VirtualFat>> =
+    unsafe { Core0Shared::new() };
+
+/// Files shown on the USB mass storage device.
+#[cfg(feature = "msc")]
+static FILES: [&dyn VirtualFile; 2] = [&LogFile, &InfoFile];
+
+/// The microsecond timer (shared with the interrupt).
+static TIMER: Shared<hal::timer::Timer> = Shared::new();
+
+/// The terminal on the display (shared with the interrupt).
+static TERMINAL: Shared<PicoDisplayTerminal> = Shared::new();
+
+/// The UART of the routes, on GPIO28 and GPIO1 (shared with the interrupt).
+#[cfg(feature = "uart-route")]
+static UART: Shared<UartBridge> = Shared::new();
+
+/// Bytes for the UART of the routes (shared with the interrupt).
+#[cfg(feature = "uart-route")]
+static UART_TX: Shared<heapless::Deque<u8, UART_QUEUE_SIZE>> = Shared::new();
+
+/// The most recent data routed to the log buffer (shared with the interrupt).
+static LOG_BUFFER: Shared<LogBuffer<LOG_BUFFER_SIZE>> = Shared::new();
+
+/// Command line received from the host, waiting to be run by the main loop.
+static PENDING_LINE: Shared<heapless::String<MAX_LINE>> = Shared::new();
+
+/// Patterns watched in the data received from the host (shared with the interrupt).
+static TRIGGERS: Shared<Triggers> = Shared::new();
+
+/// Triggers that fired since the main loop last checked, as bits.
+static TRIGGERED: AtomicU8 = AtomicU8::new(0);
+
+/// Echo the data received from the host in lower case, see `EchoMode`.
+static ECHO_LOWERCASE: AtomicBool = AtomicBool::new(true);
+
+/// Frame the serial data into channels, see the `mux` module.
+static MUX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The pager prompt is shown, until the interrupt receives a key from the host.
+static PAGER_WAITING: AtomicBool = AtomicBool::new(false);
+
+/// The host closed the port at 1200 baud, asking to reboot into the USB bootloader.
+static BOOTLOADER_TOUCH: AtomicBool = AtomicBool::new(false);
+
+/// A host is sending USB frames, see `LinkMonitor`.
+static HOST_LISTENING: AtomicBool = AtomicBool::new(true);
+
+/// Total number of bytes received over USB serial (only written by the interrupt).
+static RX_BYTES: Metric = Metric::counter("usb_rx_bytes");
+/// Time the main loop works between its 10ms waits, in microseconds
+static LOOP_US: Metric = Metric::histogram("loop_us");
+/// Temperature of the chip, in tenths of a degree
+static TEMPERATURE: Metric = Metric::gauge("temperature_dc");
+/// Commands run, from the host or the soft keyboard
+static COMMANDS: Metric = Metric::counter("commands");
+/// Time from receiving a latency marker to drawing the character after it, in microseconds
+static LATENCY_US: Metric = Metric::histogram("latency_us");
+
+/// Input of the pulse capture, see the `pulses` command
+#[cfg(not(feature = "uart-route"))]
+const PULSE_GPIO: usize = 28;
+/// Input of the pulse capture, moved off GPIO28 which the UART sends on
+#[cfg(feature = "uart-route")]
+const PULSE_GPIO: usize = 21;
+/// Baud rate of the UART of the routes, with 8 data bits, no parity and 1 stop bit
+#[cfg(feature = "uart-route")]
+const UART_BAUD: u32 = 115_200;
+/// Bytes waiting for room in the FIFO of the UART
+#[cfg(feature = "uart-route")]
+const UART_QUEUE_SIZE: usize = 256;
+/// Bytes kept by the log buffer, see the `buffer` command
+const LOG_BUFFER_SIZE: usize = 1024;
+/// Durations kept by a pulse capture
+const MAX_PULSES: usize = 256;
+/// Durations on each line of a pulse dump
+const PULSES_PER_LINE: usize = 8;
+/// Longest pulse capture, well within the watchdog timeout
+const MAX_CAPTURE_MS: u32 = 5000;
+
+/// Stamp the latency markers received, see the `latency` command
+static LATENCY_TEST: AtomicBool = AtomicBool::new(false);
+/// Times the latency markers were received, stamped by the USB interrupt
+static MARKER_STAMPS: Shared<MarkerStamps> = Shared::new();
+
+static FERRIS: &[u8] = include_bytes!("../ferris.raw");
+
+/// Script typed through the HID keyboard, see the `payload` module for its format.
+#[cfg(feature = "hid-payload")]
+static PAYLOAD: &str = include_str!("../payload.txt");
+
+/// Text of the typing test, see the `typing` command.
+static PROMPT: &str = include_str!("../prompt.txt");
+
+/// Bytes from the host for the page shown over the terminal.
+static mut PAGE_INPUT: heapless::spsc::Queue<u8, 64> = heapless::spsc::Queue::new();
+
+/// Data from the host waiting to be drawn on the terminal by the main loop.
+///
+/// It is split once in `main`: the USB interrupt only enqueues through `RENDER_TX`, and the
+/// main loop drains the other end, so no drawing happens under interrupt.
+static mut RENDER_QUEUE: heapless::spsc::Queue<u8, RENDER_QUEUE_SIZE> =
+    heapless::spsc::Queue::new();
+const RENDER_QUEUE_SIZE: usize = 512;
+/// Producer end of the render queue, used by the USB interrupt.
+static RENDER_TX: Shared<heapless::spsc::Producer<'static, u8, RENDER_QUEUE_SIZE>> =
+    Shared::new();
+/// Bytes drawn from the render queue before letting the USB interrupt run again.
+const RENDER_CHUNK: usize = 16;
+
+/// Tiles of the screen read back per main loop tick, while looking for changes to mirror.
+const MIRROR_SCAN: usize = 4;
+
+/// Frames received on the data channel, for the asset uploads.
+static mut DATA_FRAMES: heapless::spsc::Queue<heapless::Vec<u8, MAX_FRAME>, 4> =
+    heapless::spsc::Queue::new();
+
+/// Time without feeding the watchdog before it resets the firmware, long enough for `bench`.
+const WATCHDOG_TIMEOUT_US: u32 = 8_000_000;
+
+/// Ticks of the main loop after which the boot counts as healthy, about 10 seconds.
+const HEALTHY_TICKS: u32 = 1000;
+
+/// Ticks of the main loop a host has to configure the device once it has an address, about
+/// 5 seconds.
+const USB_ENUMERATION_TICKS: u32 = 500;
+
+/// Backlight of the display, dimmed by the settings and while the terminal is idle.
+static BACKLIGHT: Shared<Backlight> = Shared::new();
+
+/// Animations of Ferris in the corner of the terminal, while it is quiet.
+static IDLE: Shared<IdleAnimator<IDLE_ANIMATIONS>> = Shared::new();
+
+/// Room for the animations registered with `IdleAnimator::register`.
+const IDLE_ANIMATIONS: usize = 4;
+
+/// Seconds without terminal activity before Ferris shows up.
+const IDLE_AFTER_S: u32 = 30;
+
+/// Time between two animations of Ferris.
+const IDLE_EVERY_MS: u32 = 8000;
+
+/// Top left corner of Ferris while idle, in the bottom right corner of the terminal.
+const IDLE_SPRITE_POS: Point = Point::new(216, 98);
+
+/// Rows scrolled through the history by a press on X or Y
+const SCROLL_ROWS: usize = 4;
+
+/// Writes the records of `log` to the terminal, the host and the log buffer, see the `logger`
+/// command.
+static LOGGER: Logger = Logger::new(
+    write_to_terminal,
+    log_to_host,
+    write_to_log_buffer,
+    now_timestamp,
+);
+
+/// Name of the device, shown in the greetings and by `info`.
+static DEVICE_NAME: Shared<DeviceName> = Shared::new();
+
+/// Segments shown in the status bar while searching the terminal.
+static SEARCH_LAYOUT: StatusLayout = StatusLayout::new([
+    Some(Segment::Search),
+    Some(Segment::Clock),
+    Some(Segment::UsbState),
+    Some(Segment::Errors),
+    None,
+    None,
+]);
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function configures the RP2040 peripherals, then blinks the LED and
+/// watches the buttons in an infinite loop.
+///
+/// Holding a button at power on selects a different mode, see `BootMode`.
+#[entry]
+fn main() -> ! {
+    LOG_BUFFER.put(LogBuffer::new());
+    // Note (safety): the interrupts aren't enabled yet, so nothing logs at the same time
+    unsafe { Logger::init(&LOGGER, LevelFilter::Info) };
+
+    // Start the clocks and take the pins, the panel stays off until the boot mode is known
+    let mut board = Board::take().unwrap();
+
+    // A pin claimed twice stays with its first owner, and the conflict is shown on the screen
+    let mut pin_owners = PinOwners::new();
+    let mut pin_conflict = None;
+    let claims = [
+        (1 << 23 | 1 << 24 | 1 << 29, PinOwner::Board),
+        (1 << 25, PinOwner::Led),
+        (0x7 << 6, PinOwner::RgbLed),
+        (0xF << 12, PinOwner::Buttons),
+        // Including the data out of the panel
+        (1 << 0 | 0x1F << 16, PinOwner::Display),
+        (0xF << 2, PinOwner::Switches),
+        (1 << 22, PinOwner::StallPin),
+        (1 << PULSE_GPIO, PinOwner::PulseCapture),
+    ];
+    for &(gpios, owner) in claims.iter() {
+        if let Err(conflict) = pin_owners.claim(gpios, owner) {
+            pin_conflict.get_or_insert(conflict);
+        }
+    }
+    #[cfg(feature = "oled")]
+    {
+        if let Err(conflict) = pin_owners.claim(0x3 << 26, PinOwner::Oled) {
+            pin_conflict.get_or_insert(conflict);
+        }
+    }
+    #[cfg(feature = "uart-route")]
+    {
+        if let Err(conflict) = pin_owners.claim(1 << 1 | 1 << 28, PinOwner::Uart) {
+            pin_conflict.get_or_insert(conflict);
+        }
+    }
+    rp2040_test::unused_pins::PinProfile::PICO_DISPLAY
+        .with_used(pin_owners.used())
+        .park_unused();
+
+    // The board gives away its parts from here on, most of them to the startup steps below
+    let bus_ref = board.usb_bus();
+    let buttons = board.buttons();
+    let panel = board.panel();
+    let mut led_pin = board.led().unwrap();
+    let mut delay = board.delay;
+    let mut watchdog = board.watchdog;
+
+    // Set up the timer, used to pace the USB serial data, and to wake the main loop sleeping
+    // while no host is listening
+    TIMER.put(board.timer);
+    sleep::init();
+
+    // The temperature sensor is read through the ADC
+    let mut adc = hal::adc::Adc::new(board.adc, &mut board.resets);
+    let mut temp_sense = adc.enable_temp_sensor();
+
+    // Failures found at boot, shown on the terminal, and blinked on the LED without one
+    let boot_fault = if Settings::is_corrupt() {
+        Some(Fault::ConfigCorrupt)
+    } else if Asset::is_corrupt(AssetKind::Splash) || Asset::is_corrupt(AssetKind::Notes) {
+        Some(Fault::FlashCrc)
+    } else {
+        None
+    };
+    if let Some(fault) = boot_fault {
+        diag_warn!("fault: {}", fault.name());
+    }
+
+    // Start the parts of the firmware in the same order on every boot. The buttons select the
+    // boot mode the other steps follow, and the progress shows under the splash once there is one
+    let show_progress = Cell::new(false);
+    let mut context = Startup {
+        boot_mode: BootMode::Normal,
+        reboot_target: None,
+        watchdog_resets: 0,
+        settings: Settings::default(),
+        name: None,
+        pin_conflict,
+        boot_fault,
+        show_progress: &show_progress,
+        bus: bus_ref,
+        pwm: &board.pwm,
+        resets: &mut board.resets,
+        delay: &mut delay,
+        adc: &mut adc,
+        temp_sense: &mut temp_sense,
+        buttons,
+        panel,
+        #[cfg(feature = "uart-route")]
+        uart_parts: Some((
+            board.uart0,
+            board.pins.gpio28,
+            board.pins.gpio1,
+            board.peripheral_clock.freq().integer(),
+        )),
+        pulse_parts: Some((board.pio1, board.system_clock.freq().integer())),
+        pulse_capture: None,
+        rgb_led: None,
+        data_log: None,
+        lock: Lock::unread(),
+        #[cfg(feature = "oled")]
+        oled_parts: Some((
+            board.i2c1,
+            board.pins.gpio26.into_mode(),
+            board.pins.gpio27.into_mode(),
+            board.system_clock.freq(),
+        )),
+        #[cfg(feature = "oled")]
+        oled: None,
+    };
+    let steps = [
+        StartupStep::new("buttons", start_buttons),
+        StartupStep::new("config", start_config).after(&["buttons"]),
+        StartupStep::new("lock", start_lock).after(&["buttons"]),
+        StartupStep::new("usb", start_usb),
+        #[cfg(feature = "msc")]
+        StartupStep::new("msc", start_msc)
+            .after(&["usb", "buttons"])
+            .optional(),
+        StartupStep::new("usb device", start_usb_device).after(&["usb", "config"]),
+        StartupStep::new("display", start_display)
+            .after(&["config"])
+            .optional(),
+        StartupStep::new("backlight", start_backlight),
+        StartupStep::new("settings", start_settings).after(&["backlight", "config"]),
+        StartupStep::new("idle", start_idle)
+            .after(&["settings"])
+            .optional(),
+        StartupStep::new("triggers", start_triggers)
+            .after(&["buttons"])
+            .optional(),
+        StartupStep::new("host", start_host_features)
+            .after(&["buttons"])
+            .optional(),
+        #[cfg(feature = "uart-route")]
+        StartupStep::new("uart", start_uart)
+            .after(&["buttons"])
+            .optional(),
+        StartupStep::new("pulses", start_pulse_capture)
+            .after(&["buttons"])
+            .optional(),
+        StartupStep::new("rgb", start_rgb_led)
+            .after(&["buttons"])
+            .optional(),
+        StartupStep::new("log", start_data_log)
+            .after(&["buttons"])
+            .optional(),
+        #[cfg(feature = "oled")]
+        StartupStep::new("oled", start_oled)
+            .after(&["buttons"])
+            .optional(),
+    ];
+    let started = startup::run(&steps, &mut context, |progress| {
+        if let Outcome::Failed(reason) = progress.outcome {
+            diag_warn!("startup: {} failed, {}", progress.name, reason);
+        }
+        if show_progress.get() {
+            draw_startup_progress(Some(progress));
+        }
+    });
+    if show_progress.get() {
+        draw_startup_progress(None);
+    }
+    if let Err(error) = started {
+        diag_warn!("startup: stopped");
+        TERMINAL.lock(|terminal| {
+            let _ = writeln!(terminal, "Startup stopped: {:?}", error);
+        });
+    }
+
+    // The parts the main loop keeps using
+    let boot_mode = context.boot_mode;
+    let reboot_target = context.reboot_target;
+    let settings = context.settings;
+    let buttons = context.buttons;
+    let pulse_capture = context.pulse_capture;
+    let rgb_led = context.rgb_led;
+    let data_log = context.data_log;
+    let lock = context.lock;
+    #[cfg(feature = "oled")]
+    let mut oled_screen = context.oled;
+
+    // Note (safety): the queue is split once, before the USB interrupt is enabled
+    let (render_tx, mut render_rx) = unsafe { RENDER_QUEUE.split() };
+    RENDER_TX.put(render_tx);
+
+    // Enable the USB interrupt
+    unsafe {
+        pac::NVIC::unmask(hal::pac::Interrupt::USBCTRL_IRQ);
+    };
+
+    // No more USB code after this point in main! We can do anything we want in
+    // here since USB is handled in the interrupt - let's blink an LED!
+
+    // Driven high while the host is stalled, see the `heartbeat` command
+    let mut stall_pin = board.pins.gpio22.into_push_pull_output();
+
+    // Filters the bounces of the buttons, so a press isn't taken twice
+    let mut debouncer = Debouncer::new();
+    let mut button_keys = ButtonKeys::new(KeyMap::default());
+    // Keys from the host, while a page is open
+    let mut host_keys = KeyDecoder::new();
+
+    // Physical interactions are reported to the host as tagged lines
+    let mut button_events = ButtonEvents::new();
+    let mut temperature_alarm = TemperatureAlarm::new(600, 20);
+
+    // Payloads only run after holding Y, then confirming with A on the display
+    // They are disabled in safe mode
+    #[cfg(feature = "hid-payload")]
+    let mut interlock = Interlock::new();
+    #[cfg(feature = "hid-payload")]
+    let mut player: Option<ScriptPlayer> = None;
+    // Text from the on-screen keyboard being typed
+    #[cfg(feature = "hid-payload")]
+    let mut typer: Option<TextTyper> = None;
+
+    let mut last_rx_bytes = 0;
+    let status_bar = StatusBar::new(
+        Point::new(40, 59),
+        40,
+        MonoTextStyleBuilder::new()
+            .font(&FONT_6X10)
+            .text_color(Rgb565::BLACK)
+            .background_color(Rgb565::WHITE)
+            .build(),
+    );
+
+    let shell = build_shell();
+    let mut state = CommandState {
+        // Samples sent with the `plot` command
+        plot: Plot::new(
+            Rectangle::new(Point::new(40, 150), Size::new(240, 38)),
+            Rgb565::YELLOW,
+            Rgb565::BLACK,
+        ),
+        data_log,
+        log_dump: None,
+        log_data: &[],
+        crash_dump: None,
+        pulse_capture,
+        pulses: heapless::Vec::new(),
+        pulse_dump: None,
+        buffer_dump: None,
+        upload: None,
+        backup: None,
+        restore: None,
+        // Long command output stops after a page of a usual host terminal
+        pager: Pager::new(Some(24)),
+        json_output: false,
+        json: false,
+        stats_every: None,
+        latency: None,
+        temperature: None,
+        led_hold: 0,
+        blink: None,
+        identify: 0,
+        rgb_led,
+        fault: boot_fault,
+        // No terminal when headless, or when its step didn't run
+        display_ok: TERMINAL.lock(|_| ()).is_some(),
+        lock,
+        rng: Entropy::new(board.rosc),
+        confirm: Confirmation::new(),
+        heartbeat: Heartbeat::new(),
+        stall_pin: false,
+        pin_dump: None,
+        mirror: None,
+        settings,
+        switches: Switches::new(),
+        panel_id: None,
+        open_page: reboot_target.and_then(RebootTarget::page),
+        page_display_route: true,
+        #[cfg(feature = "hid-payload")]
+        hid_text: None,
+    };
+
+    // Relay outputs, off at boot unless they restore their last state
+    let mut switch_pins: [DynPin; MAX_SWITCHES] = [
+        board.pins.gpio2.into(),
+        board.pins.gpio3.into(),
+        board.pins.gpio4.into(),
+        board.pins.gpio5.into(),
+    ];
+    for pin in switch_pins.iter_mut() {
+        pin.into_push_pull_output();
+        let _ = pin.set_low();
+    }
+    if boot_mode != BootMode::Safe {
+        state.switches.load();
+    }
+
+    // Holding X and Y together opens the settings page, A and B the switches page, and A and X
+    // the keyboard page
+    let mut page: Option<Page> = None;
+    let mut page_combo = false;
+
+    // When data is lost, the LED flashes quickly instead
+    let mut error_led = ErrorLed::new();
+
+    // Less is done while no host is listening
+    let mut usb_link = LinkMonitor::new().with_hook(link_changed);
+
+    // A main loop stuck for this long resets the firmware, and a few resets in a row start it
+    // in safe mode
+    watchdog.start(WATCHDOG_TIMEOUT_US.microseconds());
+
+    // Check the buttons and update the LED every 10ms
+    let mut ticks: u32 = 0;
+    let mut usb_addressed_ticks: u32 = 0;
+    loop {
+        let loop_start_us = now_us();
+        watchdog.feed();
+        // After running this long, the firmware doesn't count as crashing at boot anymore. Safe
+        // mode keeps the count, so it is kept until the firmware is restarted on purpose.
+        if ticks == HEALTHY_TICKS && boot_mode != BootMode::Safe {
+            boot_mode::clear_watchdog_resets();
+        }
+
+        let usb_state = USB_DEVICE.lock(|usb_dev| usb_dev.state());
+        let configured = usb_state == Some(UsbDeviceState::Configured);
+        // A host that gave the device an address but never configured it failed to enumerate it
+        usb_addressed_ticks = match usb_state {
+            Some(UsbDeviceState::Addressed) => usb_addressed_ticks + 1,
+            _ => 0,
+        };
+        if usb_addressed_ticks == USB_ENUMERATION_TICKS {
+            diag_warn!("usb: addressed but not configured");
+            state.fault.get_or_insert(Fault::UsbInit);
+        }
+        usb_link.update(configured);
+        let host_listening = usb_link.state() == LinkState::Active;
+
+        // The screen flashes white once a second while identifying the board
+        if state.identify > 0 {
+            state.identify -= 1;
+            match state.identify % 100 {
+                99 => TERMINAL.lock(|terminal| {
+                    let _ = terminal.screen_mut().clear(Rgb565::WHITE);
+                }),
+                79 => TERMINAL.lock(|terminal| terminal.refresh()),
+                _ => None,
+            };
+            if state.identify == 0 {
+                apply_settings(&state.settings);
+            }
+        }
+
+        // Blink at 1 Hz, or at 10 Hz while data is being lost. The signature set by the host
+        // replaces the usual blink.
+        let now_ms = (now_us() / 1000) as u32;
+        let led_on = if state.led_hold > 0 {
+            state.led_hold -= 1;
+            true
+        } else if state.identify > 0 {
+            match &state.blink {
+                Some(pattern) => pattern.is_on(now_ms),
+                None => ticks % 20 < 10,
+            }
+        } else if let Some(fault) = state.fault.filter(|_| !state.display_ok) {
+            fault.pattern().is_on(now_ms)
+        } else if state.heartbeat.is_stalled() {
+            ticks % 20 < 10
+        } else if error_led.tick(ERRORS.snapshot().total()) {
+            ticks % 10 < 5
+        } else if let Some(pattern) = &state.blink {
+            pattern.is_on(now_ms)
+        } else if !host_listening {
+            // Only a short flash every 2 seconds, to save power
+            ticks % 200 < 5
+        } else {
+            ticks % 100 < 50
+        };
+        if led_on {
+            led_pin.set_high().unwrap();
+        } else {
+            led_pin.set_low().unwrap();
+        }
+        if let Some(rgb_led) = &state.rgb_led {
+            rgb_led.update(now_ms);
+        }
+        BACKLIGHT.lock(|backlight| backlight.update(now_ms));
+        // Pages are left alone, the animations go on once they are closed
+        if page.is_none() {
+            if let Some(pose) = IDLE.lock(|idle| idle.update(now_ms)).flatten() {
+                draw_on_screen(|screen| draw_idle_pose(screen, pose, boot_mode));
+            }
+        }
+
+        if ticks % 100 == 0 {
+            let rx_bytes = RX_BYTES.total();
+            let temperature = adc
+                .read(&mut temp_sense)
+                .ok()
+                .map(rp2040_test::temperature_from_adc);
+            state.temperature = temperature;
+            if let Some(event) = temperature.and_then(|temp| temperature_alarm.update(temp)) {
+                notify_host(&event);
+            }
+            if let Some(temp) = temperature {
+                TEMPERATURE.set(temp);
+                let mut line: heapless::String<40> = heapless::String::new();
+                let timestamp = CLOCK.timestamp(now_us());
+                if write!(
+                    line,
+                    "{},{}.{}\r\n",
+                    timestamp,
+                    temp / 10,
+                    (temp % 10).abs()
+                )
+                .is_ok()
+                {
+                    if let Some(data_log) = state.data_log.as_mut() {
+                        data_log.append(line.as_bytes());
+                    }
+                }
+            }
+            if let Some(every) = state.stats_every {
+                if ticks / 100 % every == 0 && host_listening {
+                    send_stats(state.json_output);
+                }
+            }
+            // The layout is taken from the settings on each update, as `status set` changes it
+            let status_renderer = SegmentRenderer::new(state.settings.status);
+            let _info = update_status_bar(
+                &status_bar,
+                &status_renderer,
+                temperature,
+                rx_bytes.wrapping_sub(last_rx_bytes),
+            );
+            last_rx_bytes = rx_bytes;
+            // Outside of the critical section, as the whole OLED buffer is sent over I2C
+            #[cfg(feature = "oled")]
+            if let (Some(screen), Some(info)) = (oled_screen.as_mut(), _info.as_ref()) {
+                let _ = StatusPanel::new(state.settings.status).update(screen, info);
+            }
+
+            if let Some(id) = state.panel_id {
+                let mut reset = false;
+                // This blocks the USB interrupt during the init sequence, which is fine for a
+                // rare recovery
+                let mut answering = true;
+                draw_on_screen(|screen| {
+                    if display::read_id(screen) != Some(id) {
+                        display::reinit(screen, &mut delay);
+                        reset = true;
+                        answering = display::read_id(screen) == Some(id);
+                    }
+                });
+                // Still not answering, so the LED takes over
+                state.display_ok = answering;
+                if !answering {
+                    state.fault.get_or_insert(Fault::DisplayInit);
+                }
+                if reset {
+                    diag_warn!("display: panel reset, answering: {}", answering);
+                    // Redraw the terminal from its cells, the status bar follows on the next tick
+                    apply_settings(&state.settings);
+                    notify_host(&Event::DisplayReset);
+                }
+            }
+        }
+
+        let raw = buttons.as_ref().map_or([false; 4], Buttons::pressed);
+        // The code below follows the changes of the debounced levels itself
+        debouncer.update(raw, (now_us() / 1000) as u32, |_| ());
+        let pressed = debouncer.pressed();
+        // Holding a button keeps the screen awake
+        if pressed.iter().any(|&pressed| pressed) {
+            terminal_activity(now_ms);
+        }
+        // The pager shows the next page on a key from the host or a button press
+        if state.pager.is_waiting()
+            && (!PAGER_WAITING.load(Ordering::Relaxed) || pressed.iter().any(|&pressed| pressed))
+        {
+            PAGER_WAITING.store(false, Ordering::Relaxed);
+            state.pager.resume();
+            send_to_host(CLEAR_PROMPT);
+        }
+
+        // X and Y scroll the history, unless they are PIN digits, X confirms a command or the
+        // buttons are keys of the host
+        let scroll_buttons = !state.lock.is_locked()
+            && state.confirm.pending(now_ms).is_none()
+            && !ROUTES.is_routed(Source::Buttons, Sink::Usb);
+        button_events.update(pressed, |event| {
+            notify_host(&event);
+            // While locked, button presses are also PIN digits
+            if let Event::Button {
+                index,
+                pressed: true,
+            } = event
+            {
+                if let Some(result) = state.lock.press_button(index, now_us()) {
+                    write_to_terminal(match result {
+                        Ok(()) => b"\nUnlocked\n",
+                        Err(_) => b"\nWrong PIN\n",
+                    });
+                } else if index == 2 {
+                    // X confirms the command waiting, the other buttons cancel it. While locked,
+                    // the presses are PIN digits
+                    let confirmed = state.confirm.press(now_ms);
+                    if let Some(action) = confirmed.filter(|_| !state.lock.is_locked()) {
+                        run_disruptive(action, &mut state);
+                    }
+                } else if let Some(action) = state.confirm.cancel(now_ms) {
+                    write_to_terminal(action.name().as_bytes());
+                    write_to_terminal(b" cancelled\n");
+                }
+            }
+        });
+
+        if let Some(line) = cortex_m::interrupt::free(|cs| PENDING_LINE.borrow(cs).take()) {
+            run_command(&line, &mut state, &shell);
+        }
+
+        if let Some(event) = state.heartbeat.check(now_us()) {
+            show_heartbeat(event);
+        }
+        if state.stall_pin && state.heartbeat.is_stalled() {
+            stall_pin.set_high().unwrap();
+        } else {
+            stall_pin.set_low().unwrap();
+        }
+
+        // Draw the data queued by the USB interrupt, a chunk at a time so the interrupt can
+        // run in between. This comes before the triggers, which highlight the row of the cursor
+        let mut drawn = false;
+        while render_rx.ready() {
+            let mut chunk = [0u8; RENDER_CHUNK];
+            let mut len = 0;
+            while let Some(byte) = render_rx.dequeue() {
+                chunk[len] = byte;
+                len += 1;
+                if len == RENDER_CHUNK {
+                    break;
+                }
+            }
+            // There is no terminal when running headless, and the data is dropped
+            let mut markers: heapless::Vec<Marker, RENDER_CHUNK> = heapless::Vec::new();
+            TERMINAL.lock(|terminal| {
+                for &byte in &chunk[..len] {
+                    match state.latency.as_mut() {
+                        Some(probe) => {
+                            if let Some(byte) = probe.feed(byte) {
+                                terminal.write_char(byte);
+                                if let Some(marker) = probe.drawn() {
+                                    let _ = markers.push(marker);
+                                }
+                            }
+                        }
+                        None => terminal.write_char(byte),
+                    }
+                }
+            });
+            for marker in markers {
+                report_marker(marker);
+            }
+            drawn = true;
+        }
+        // The interrupt holds data back while the queue is full, have it read the rest
+        if drawn {
+            pac::NVIC::pend(hal::pac::Interrupt::USBCTRL_IRQ);
+            terminal_activity((now_us() / 1000) as u32);
+        }
+
+        // Run the actions of the triggers that fired
+        // The Cortex-M0+ has no atomic swap
+        let triggered = cortex_m::interrupt::free(|_| {
+            let triggered = TRIGGERED.load(Ordering::Relaxed);
+            TRIGGERED.store(0, Ordering::Relaxed);
+            triggered
+        });
+        if triggered != 0 {
+            run_triggers(triggered, &mut state);
+        }
+
+        // Reboot into the USB bootloader when asked the Arduino way, for flashing from the host
+        // without the BOOTSEL button. Like the commands that change the device, not while locked,
+        // and only once a button confirms it if the settings ask for one
+        if BOOTLOADER_TOUCH.load(Ordering::Relaxed) {
+            BOOTLOADER_TOUCH.store(false, Ordering::Relaxed);
+            diag_info!("bootloader touch");
+            if state.lock.is_locked() {
+                write_to_terminal(b"Bootloader refused: locked\n");
+            } else if state.settings.confirm == ConfirmMode::Button {
+                ask_confirmation(Disruptive::Bootsel, &mut state);
+            } else {
+                hal::rom_data::reset_to_usb_boot(0, 0);
+            }
+        }
+        if let Some(action) = state.confirm.expire(now_ms) {
+            let mut line: heapless::String<48> = heapless::String::new();
+            let _ = write!(line, "{} not confirmed in time\r\n", action.name());
+            send_to_host(line.as_bytes());
+            write_to_terminal(line.as_bytes());
+        }
+
+        // Send the log as room frees up for it, in pieces that fit in a frame
+        while let Some(dump) = state.log_dump.as_mut() {
+            if state.pager.is_waiting() {
+                break;
+            }
+            if state.log_data.is_empty() {
+                match dump.next() {
+                    Some(data) => state.log_data = data,
+                    None => {
+                        state.log_dump = None;
+                        break;
+                    }
+                }
+            }
+            let mut len = state.log_data.len().min(MAX_FRAME);
+            // The log has its own channel when multiplexing, so it is only paged otherwise
+            let line_end = if MUX_ENABLED.load(Ordering::Relaxed) {
+                None
+            } else {
+                state.log_data[..len].iter().position(|&byte| byte == b'\n')
+            };
+            if let Some(end) = line_end {
+                len = end + 1;
+            }
+            if host_tx_available() < mux::max_encoded_len(len) {
+                break;
+            }
+            send_on_channel(Channel::Log, &state.log_data[..len]);
+            state.log_data = &state.log_data[len..];
+            if line_end.is_some() {
+                page_line(&mut state.pager);
+            }
+        }
+
+        // Same for the crash screen, one row at a time
+        if let Some(row) = state.crash_dump.filter(|_| !state.pager.is_waiting()) {
+            match Crash::load().filter(|crash| row < crash.rows()) {
+                Some(crash) if host_tx_available() >= crash.columns() + 2 => {
+                    send_to_host(crash.row(row));
+                    send_to_host(b"\r\n");
+                    state.crash_dump = Some(row + 1);
+                    page_line(&mut state.pager);
+                }
+                Some(_) => (),
+                None => state.crash_dump = None,
+            }
+        }
+
+        // Same for the pulse durations, a few per line
+        if let Some(start) = state.pulse_dump.filter(|_| !state.pager.is_waiting()) {
+            let end = (start + PULSES_PER_LINE).min(state.pulses.len());
+            if start >= end {
+                state.pulse_dump = None;
+            } else if host_tx_available() >= PULSES_PER_LINE * 12 + 2 {
+                let mut line: heapless::String<{ PULSES_PER_LINE * 12 + 2 }> =
+                    heapless::String::new();
+                for (index, duration) in (start..end).zip(&state.pulses[start..end]) {
+                    let level = if index % 2 == 0 { 'L' } else { 'H' };
+                    let separator = if index == start { "" } else { " " };
+                    let _ = write!(line, "{}{}{}", separator, level, duration);
+                }
+                let _ = line.push_str("\r\n");
+                send_to_host(line.as_bytes());
+                state.pulse_dump = Some(end);
+                page_line(&mut state.pager);
+            }
+        }
+
+        // Same for the log buffer, a chunk at a time
+        if let Some(left) = state.buffer_dump {
+            let mut chunk = [0u8; 64];
+            if host_tx_available() >= chunk.len() {
+                let len = LOG_BUFFER
+                    .lock(|buffer| buffer.take(&mut chunk[..left.min(64)]))
+                    .unwrap_or(0);
+                send_to_host(&chunk[..len]);
+                state.buffer_dump = Some(left - len).filter(|&left| left > 0 && len > 0);
+            }
+        }
+
+        // Data from the UART goes where it is routed, and the data for it goes out as its FIFO
+        // empties
+        #[cfg(feature = "uart-route")]
+        {
+            let mut buf = [0u8; 32];
+            let count = cortex_m::interrupt::free(|cs| {
+                flush_uart(cs);
+                UART.with(cs, |uart| uart.read(&mut buf)).unwrap_or(0)
+            });
+            route_data(Source::Uart, &buf[..count]);
+        }
+
+        // Asset uploads from the data channel, each frame is acknowledged before the next
+        // Note (safety): interrupts are disabled, so the USB interrupt cannot fill the queue at
+        // the same time.
+        while let Some(frame) = cortex_m::interrupt::free(|_| unsafe { DATA_FRAMES.dequeue() }) {
+            handle_data_frame(&frame, &mut state);
+        }
+
+        // Mirror the screen to the host, sending the tiles that changed as room frees up. The
+        // screen isn't read back while no host is listening
+        if let Some(mirror) = state.mirror.as_mut().filter(|_| host_listening) {
+            if !mirror.is_sending() {
+                draw_on_screen(|screen| mirror.scan(screen, MIRROR_SCAN));
+            }
+            let mut frame = [0u8; mirror::MAX_FRAME];
+            while host_tx_available() >= mux::max_encoded_len(mirror::MAX_FRAME) {
+                match mirror.next_frame(&mut frame) {
+                    Some(len) => send_on_channel(Channel::Data, &frame[..len]),
+                    None => break,
+                }
+            }
+        }
+
+        // Same for a backup, until its last frame
+        if let Some(backup) = state.backup.as_mut() {
+            let mut frame = [0u8; backup::MAX_FRAME];
+            let mut done = false;
+            while host_tx_available() >= mux::max_encoded_len(backup::MAX_FRAME) {
+                match backup.next_frame(&mut frame) {
+                    Some(len) => send_on_channel(Channel::Data, &frame[..len]),
+                    None => {
+                        done = true;
+                        break;
+                    }
+                }
+            }
+            if done {
+                state.backup = None;
+            }
+        }
+
+        // Same for the table of pins, one line at a time
+        if let Some(gpio) = state.pin_dump.filter(|_| !state.pager.is_waiting()) {
+            if host_tx_available() >= 128 {
+                let mut line: heapless::String<128> = heapless::String::new();
+                let info = PinInfo::read(gpio);
+                let result = if state.json {
+                    let mut json = JsonLine::new(&mut line);
+                    json.number("gpio", info.gpio)
+                        .string("function", info.function_name())
+                        .string("pull", info.pull.name());
+                    match info.input {
+                        Some(input) => json.boolean("input", input),
+                        None => json.null("input"),
+                    };
+                    json.boolean("output_disabled", info.output_disabled)
+                        .number("drive_ma", info.drive_ma)
+                        .finish()
+                } else {
+                    write!(line, "{}\r\n", info)
+                };
+                if result.is_ok() {
+                    send_to_host(line.as_bytes());
+                }
+                state.pin_dump = Some(gpio + 1).filter(|&gpio| gpio < GPIO_COUNT);
+                page_line(&mut state.pager);
+            }
+        }
+
+        #[cfg(feature = "hid-payload")]
+        {
+            let trigger = pressed[3] && boot_mode != BootMode::Safe && !state.lock.is_locked();
+            match interlock.update(trigger, pressed[0], pressed[1]) {
+                Some(InterlockEvent::Prompt) => {
+                    write_to_terminal(b"\nRun HID payload? A: yes, B: no\n")
+                }
+                Some(InterlockEvent::Confirmed) => {
+                    write_to_terminal(b"Running payload\n");
+                    player = Some(ScriptPlayer::new(PAYLOAD));
+                }
+                Some(InterlockEvent::Cancelled) => write_to_terminal(b"Payload cancelled\n"),
+                None => (),
+            }
+
+            if let Some(script) = player.as_mut() {
+                match script.poll(now_ms()) {
+                    Step::Report(report) => send_hid_report(&report),
+                    Step::Wait => (),
+                    Step::Done => {
+                        write_to_terminal(b"Payload done\n");
+                        player = None;
+                    }
+                    Step::Error(_line) => {
+                        write_to_terminal(b"Invalid payload line\n");
+                        player = None;
+                    }
+                }
+            }
+
+            // Only the buttons open the keyboard page, so like a payload, the typing starts on
+            // the device
+            if let Some(text) = state.hid_text.take() {
+                if boot_mode == BootMode::Safe || state.lock.is_locked() {
+                    write_to_terminal(b"HID typing is disabled\n");
+                } else if player.is_none() {
+                    typer = Some(TextTyper::new(text));
+                }
+            }
+            if let Some(text) = typer.as_mut() {
+                match text.poll() {
+                    Step::Report(report) => send_hid_report(&report),
+                    _ => typer = None,
+                }
+            }
+        }
+
+        // The buttons are used by the payload interlock while it asks for a confirmation
+        #[cfg(feature = "hid-payload")]
+        let pressed = if interlock.is_idle() {
+            pressed
+        } else {
+            [false; 4]
+        };
+
+        // The buttons drive the page while it is open
+        let mut page_closed = false;
+        button_keys.update(pressed, |key| {
+            if let Some(page) = page.as_mut() {
+                page_closed |= !page.handle_key(key, &mut state);
+                return;
+            }
+            match key {
+                Key::Enter if scroll_buttons => {
+                    TERMINAL.lock(|terminal| terminal.scroll_back(SCROLL_ROWS));
+                }
+                Key::Escape if scroll_buttons => {
+                    TERMINAL.lock(|terminal| terminal.scroll_forward(SCROLL_ROWS));
+                }
+                _ => (),
+            }
+            route_data(Source::Buttons, key.escape_sequence());
+        });
+        // So does the data from the host: keys for the menus, text for the typing test
+        let mut host_input = false;
+        if let Some(opened) = page.as_mut() {
+            // Note (safety): interrupts are disabled, so the USB interrupt cannot fill the
+            // queue at the same time.
+            while let Some(byte) = cortex_m::interrupt::free(|_| unsafe { PAGE_INPUT.dequeue() }) {
+                host_input = true;
+                let key = match opened {
+                    Page::Typing(typing) => {
+                        typing.push(byte);
+                        None
+                    }
+                    _ => host_keys.push(byte),
+                };
+                if let Some(key) = key {
+                    if !opened.handle_key(key, &mut state) {
+                        page_closed = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if page_closed {
+            page = None;
+            close_page(&state);
+        } else if let Some(page) = page.as_ref() {
+            // The statistics of the typing test are updated every second
+            let tick = matches!(page, Page::Typing(_)) && ticks % 100 == 0;
+            if host_input || tick || pressed.iter().any(|&pressed| pressed) {
+                draw_on_screen(|screen| page.draw(screen, &state));
+            }
+        }
+
+        // Checked after the keys, so the keys of the combination don't go to the page
+        let combo = match pressed {
+            _ if page_combo => None,
+            [false, false, true, true] => Some(Page::Settings(SettingsPage::new())),
+            [true, true, false, false] => Some(Page::Switches(switches_menu())),
+            [true, false, true, false] => Some(Page::Keyboard(KeyboardPage::new())),
+            _ => None,
+        };
+        page_combo = pressed.iter().filter(|&&pressed| pressed).count() >= 2;
+        // A page asked for while another one is open is dropped, and so are the settings while
+        // locked, as they write to the flash
+        if let Some(opened) = combo.or_else(|| state.open_page.take()) {
+            let refused = matches!(opened, Page::Settings(_)) && state.lock.is_locked();
+            if page.is_none() && !refused {
+                open_page(&opened, &mut state);
+                page = Some(opened);
+            }
+        }
+
+        // Drive the relay outputs, following their schedule once the time is set
+        if ticks % 100 == 0 {
+            if let Timestamp::Unix(unix_ms) = CLOCK.timestamp(now_us()) {
+                if state.switches.update(unix_ms) {
+                    state.switches.save();
+                }
+            }
+        }
+        for (index, pin) in switch_pins.iter_mut().enumerate() {
+            let _ = if state.switches.is_on(index) {
+                pin.set_high()
+            } else {
+                pin.set_low()
+            };
+        }
+
+        // When pacing the serial data, the interrupt needs to run again to pick up data
+        // that was held back.
+        if cfg!(feature = "emulated-baud") && host_listening {
+            pac::NVIC::pend(hal::pac::Interrupt::USBCTRL_IRQ);
+        }
+
+        // Send the bytes held back by the console, in case no USB interrupt comes to do it
+        if host_listening {
+            let now = now_us();
+            USB_CONSOLE.lock(|console| {
+                let _ = console.poll(now);
+            });
+        }
+
+        LOOP_US.record((now_us() - loop_start_us) as u32);
+        ticks = ticks.wrapping_add(1);
+        // Sleep instead of spinning while no host is listening, the tick stays as long
+        if host_listening {
+            delay.delay_ms(10);
+        } else {
+            sleep::until(now_us() + 10_000);
+        }
+    }
+}
+
+/// Let the USB interrupt know whether a host is listening, see `LinkMonitor::with_hook`
+fn link_changed(link: LinkState) {
+    HOST_LISTENING.store(link == LinkState::Active, Ordering::Relaxed);
+    diag_info!("usb: link {:?}", link);
+}
+
+/// Redraw the status bar with the current state of the device, and return that state
+fn update_status_bar(
+    status_bar: &StatusBar<Rgb565>,
+    renderer: &SegmentRenderer,
+    temperature: Option<i32>,
+    rx_rate: u32,
+) -> Option<StatusInfo> {
+    cortex_m::interrupt::free(|cs| {
+        let (usb_dev, console) = (USB_DEVICE.borrow(cs), USB_CONSOLE.borrow(cs));
+        let (usb_dev, console) = match (usb_dev.as_ref(), console.as_ref()) {
+            (Some(usb_dev), Some(console)) => (usb_dev, console),
+            _ => return None,
+        };
+
+        let info = StatusInfo {
+            uptime: (now_us() / 1_000_000) as u32,
+            temperature,
+            usb_state: usb_dev.state(),
+            rx_rate,
+            baud_rate: console.line_coding().data_rate(),
+            errors: ERRORS.snapshot().total(),
+            search: TERMINAL.with(cs, |terminal| terminal.search_status()).flatten(),
+        };
+        // The matches take the place of the rates while searching
+        let search_renderer = SegmentRenderer::new(SEARCH_LAYOUT);
+        let renderer = if info.search.is_some() {
+            &search_renderer
+        } else {
+            renderer
+        };
+        // There is no terminal when running headless, the state can still go to the OLED
+        TERMINAL.with(cs, |terminal| {
+            let _ = status_bar.update(terminal.screen_mut(), renderer, &info);
+        });
+        Some(info)
+    })
+}
+
+/// Time of the clock, synchronized by the host or since boot
+fn now_timestamp() -> Timestamp {
+    CLOCK.timestamp(now_us())
+}
+
+/// Microseconds since boot
+fn now_us() -> u64 {
+    TIMER.lock(|timer| timer.get_counter()).unwrap_or(0)
+}
+
+/// Milliseconds since boot
+#[cfg(feature = "hid-payload")]
+fn now_ms() -> u64 {
+    now_us() / 1000
+}
+
+/// Run the actions of the triggers whose bit is set in `triggered`
+fn run_triggers(triggered: u8, state: &mut CommandState) {
+    cortex_m::interrupt::free(|cs| {
+        let triggers = TRIGGERS.borrow(cs);
+        triggers
+            .iter()
+            .flat_map(Triggers::iter)
+            .enumerate()
+            .filter(|&(index, _)| triggered & 1 << index != 0)
+            .for_each(|(index, trigger)| match trigger.action {
+                TriggerAction::Highlight => {
+                    TERMINAL.with(cs, |terminal| terminal.highlight_row(Rgb565::new(0, 0, 12)));
+                }
+                TriggerAction::Led => state.led_hold = 100,
+                TriggerAction::Marker => {
+                    let mut text: heapless::String<16> = heapless::String::new();
+                    if write!(text, "!TRG {}\r\n", index).is_ok() {
+                        send_to_host(text.as_bytes());
+                    }
+                }
+            });
+    });
+}
+
+/// State changed by the commands
+struct CommandState {
+    plot: Plot<Rgb565>,
+    /// Temperature log in flash, not opened in safe mode
+    data_log: Option<DataLog>,
+    /// Log being sent to the host
+    log_dump: Option<LogDump>,
+    /// Data of the log page being sent
+    log_data: &'static [u8],
+    /// Next row of the crash screen being sent to the host
+    crash_dump: Option<usize>,
+    /// Recorder of the pulses on `PULSE_GPIO`, not started in safe mode
+    pulse_capture: Option<PulseCapture>,
+    /// Durations of the last pulse capture, from a low level
+    pulses: heapless::Vec<u32, MAX_PULSES>,
+    /// Next duration of the pulses being sent to the host
+    pulse_dump: Option<usize>,
+    /// Bytes of the log buffer left to send to the host
+    buffer_dump: Option<usize>,
+    /// Asset being received on the data channel
+    upload: Option<Upload>,
+    /// Backup being sent to the host, on the data channel
+    backup: Option<Backup>,
+    /// Backup being received on the data channel
+    restore: Option<Restore>,
+    /// Pauses the dumps after each page of lines
+    pager: Pager,
+    /// Reply to all commands in JSON, see the `output` command
+    json_output: bool,
+    /// Reply to the current command in JSON
+    json: bool,
+    /// Seconds between the metrics sent to the host, see the `stats` command
+    stats_every: Option<u32>,
+    /// Takes the latency markers out of the text drawn, see the `latency` command
+    latency: Option<LatencyProbe>,
+    /// Latest temperature, in tenths of degrees Celsius
+    temperature: Option<i32>,
+    /// Ticks left with the LED held on by a trigger
+    led_hold: u32,
+    /// Signature blinked by the LED instead of its usual blink, set by the host
+    blink: Option<BlinkPattern>,
+    /// Ticks left of `identify`
+    identify: u32,
+    /// RGB LED of the display, set by the host, not started in safe mode
+    rgb_led: Option<RgbLed>,
+    /// First failure found, see `fault codes`
+    fault: Option<Fault>,
+    /// The display can show the faults, otherwise the LED blinks them
+    display_ok: bool,
+    /// PIN required by the commands that change the device
+    lock: Lock,
+    /// Random numbers, for the salt of the PIN and the confirmation tokens
+    rng: Entropy,
+    /// Command taking the device offline, waiting to be confirmed
+    confirm: Confirmation<Disruptive>,
+    /// Watchdog of the heartbeats sent by the host
+    heartbeat: Heartbeat,
+    /// Drive the stall pin while the host is stalled
+    stall_pin: bool,
+    /// Next pin of the table being sent to the host
+    pin_dump: Option<usize>,
+    /// Screen being mirrored to the host, on the data channel
+    mirror: Option<Mirror>,
+    settings: Settings,
+    /// Relay outputs
+    switches: Switches,
+    /// ID of the panel, checked every second to initialize it again if it stops answering
+    panel_id: Option<[u8; 3]>,
+    /// Page to open, asked for by a command
+    open_page: Option<Page>,
+    /// The data from the host was shown on the terminal before the page was opened
+    page_display_route: bool,
+    /// Text from the on-screen keyboard to type through the HID keyboard
+    #[cfg(feature = "hid-payload")]
+    hid_text: Option<heapless::String<MAX_INPUT>>,
+}
+
+/// What the startup steps of `main` work with, holding the parts until their step starts them
+struct Startup<'a> {
+    boot_mode: BootMode,
+    /// Page to open after `reboot into`
+    reboot_target: Option<RebootTarget>,
+    watchdog_resets: u32,
+    settings: Settings,
+    name: Option<&'static DeviceName>,
+    /// Shown on the terminal once it started
+    pin_conflict: Option<PinConflict>,
+    boot_fault: Option<Fault>,
+    /// Whether to draw the progress under the splash, set once the terminal started
+    show_progress: &'a Cell<bool>,
+    bus: &'static UsbBusAllocator<hal::usb::UsbBus>,
+    pwm: &'a pac::PWM,
+    resets: &'a mut pac::RESETS,
+    delay: &'a mut cortex_m::delay::Delay,
+    adc: &'a mut hal::adc::Adc,
+    temp_sense: &'a mut hal::adc::TempSense,
+    buttons: Option<Buttons>,
+    panel: Option<Panel>,
+    /// UART, pins and peripheral clock of the bridge, until its step takes them
+    #[cfg(feature = "uart-route")]
+    uart_parts: Option<(
+        pac::UART0,
+        FreePin<bank0::Gpio28>,
+        FreePin<bank0::Gpio1>,
+        u32,
+    )>,
+    /// PIO and system clock of the pulse capture, until its step takes them
+    pulse_parts: Option<(pac::PIO1, u32)>,
+    pulse_capture: Option<PulseCapture>,
+    rgb_led: Option<RgbLed>,
+    data_log: Option<DataLog>,
+    lock: Lock,
+    /// I2C controller, pins and system clock of the OLED, until its step takes them
+    #[cfg(feature = "oled")]
+    oled_parts: Option<(pac::I2C1, OledSdaPin, OledSclPin, Hertz)>,
+    #[cfg(feature = "oled")]
+    oled: Option<Oled>,
+}
+
+/// Apply the settings to the terminal, the screen and the echo
+fn apply_settings(settings: &Settings) {
+    let (text_color, background_color) = match settings.theme {
+        Theme::Dark => (ansi::RED, ansi::BLACK),
+        Theme::Light => (ansi::BLACK, ansi::WHITE),
+        Theme::Green => (ansi::GREEN, ansi::BLACK),
+    };
+    let (mut text_color, background_color) = (
+        settings.palette.color(text_color),
+        settings.palette.color(background_color),
+    );
+    if settings.min_contrast {
+        text_color = palette::with_contrast(text_color, background_color, MIN_CONTRAST);
+    }
+    let [r, g, b] = text_color;
+    let text_color = Rgb565::from(Rgb888::new(r, g, b));
+    let [r, g, b] = background_color;
+    let background_color = Rgb565::from(Rgb888::new(r, g, b));
+    // The colors set by the host and the logger follow the palette too
+    let mut ansi_colors = [Rgb565::BLACK; ANSI_COLORS];
+    for (index, color) in ansi_colors.iter_mut().enumerate() {
+        let [r, g, b] = settings.palette.color(index);
+        *color = Rgb565::from(Rgb888::new(r, g, b));
+    }
+    TERMINAL.lock(|terminal| {
+        terminal.set_ansi_colors(ansi_colors);
+        let _ = terminal
+            .screen_mut()
+            .set_orientation(match settings.rotation {
+                Rotation::Normal => st7789::Orientation::LandscapeSwapped,
+                Rotation::Flipped => st7789::Orientation::Landscape,
+            });
+        terminal.set_style(
+            MonoTextStyleBuilder::new()
+                .font(&FONT_6X10)
+                .text_color(text_color)
+                .background_color(background_color)
+                .build(),
+        );
+    });
+
+    let brightness = (settings.brightness as u32 * 255 / 100) as u8;
+    BACKLIGHT.lock(|backlight| backlight.set_brightness(brightness));
+    ROUTES.set(Source::UsbSerial, Sink::Usb, settings.echo != EchoMode::Off);
+    ECHO_LOWERCASE.store(settings.echo == EchoMode::Lower, Ordering::Relaxed);
+}
+
+/// Handle a frame of an asset upload, replying on the data channel
+///
+/// `B` starts an upload, followed by the asset ID, the length and CRC-32 of the data as 32-bit
+/// and the parameter as 16-bit little endian numbers. `W` appends the rest of the frame to the
+/// data, and `C` verifies and commits it. After `restore`, the `H`, `S`, `D` and `F` frames of a
+/// backup are written back. Replies are `K`, or `E` followed by the reason.
+fn handle_data_frame(frame: &[u8], state: &mut CommandState) {
+    let result = match frame.split_first() {
+        _ if state.lock.is_locked() => Err("locked"),
+        Some((&b'B', args)) if args.len() == 11 => {
+            let word = |at: usize| {
+                u32::from_le_bytes([args[at], args[at + 1], args[at + 2], args[at + 3]])
+            };
+            let param = u16::from_le_bytes([args[9], args[10]]);
+            match AssetKind::from_id(args[0]) {
+                // Erasing the slot blocks for a moment, the host waits for the reply anyway
+                Some(kind) => Upload::begin(kind, word(1) as usize, word(5), param)
+                    .map(|upload| state.upload = Some(upload))
+                    .map_err(|error| error.name()),
+                None => Err("unknown asset"),
+            }
+        }
+        Some((&b'W', data)) => match state.upload.as_mut() {
+            Some(upload) => upload.write(data).map_err(|error| error.name()),
+            None => Err("no upload"),
+        },
+        Some((&b'C', [])) => match state.upload.take() {
+            Some(upload) => upload.commit().map_err(|error| error.name()),
+            None => Err("no upload"),
+        },
+        Some((&(b'H' | b'S' | b'D' | b'F'), _)) => match state.restore.as_mut() {
+            // Rewriting a sector blocks for a moment, the host waits for the reply anyway
+            Some(restore) => match restore.frame(frame) {
+                Ok(Some(summary)) => {
+                    state.restore = None;
+                    let mut line: heapless::String<96> = heapless::String::new();
+                    let _ = write!(
+                        line,
+                        "restore done: {} sectors written, {} unchanged, {} erased, reboot to \
+                         use them\r\n",
+                        summary.written, summary.unchanged, summary.erased
+                    );
+                    send_to_host(line.as_bytes());
+                    Ok(())
+                }
+                Ok(None) => Ok(()),
+                Err(error) => {
+                    // The restore has to start over, the sectors already written stay
+                    state.restore = None;
+                    Err(error.name())
+                }
+            },
+            None => Err("no restore"),
+        },
+        _ => Err("bad frame"),
+    };
+
+    match result {
+        Ok(()) => send_on_channel(Channel::Data, b"K"),
+        Err(reason) => {
+            // The upload has to start over, the previous asset stays in use
+            state.upload = None;
+            let mut reply: heapless::Vec<u8, 32> = heapless::Vec::new();
+            let _ = reply.push(b'E');
+            let _ = reply.extend_from_slice(reason.as_bytes());
+            send_on_channel(Channel::Data, &reply);
+        }
+    }
+}
+
+/// Count a line of command output, showing the pager prompt once the page is full
+fn page_line(pager: &mut Pager) {
+    if pager.line_sent() {
+        send_to_host(MORE_PROMPT);
+        PAGER_WAITING.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Send the metrics to the host, one line each
+fn send_stats(json: bool) {
+    for index in 0..METRICS.len() {
+        let (name, value) = match METRICS.get(index) {
+            Some(metric) => metric,
+            None => break,
+        };
+        let mut text: heapless::String<200> = heapless::String::new();
+        let _ = match (value, json) {
+            (MetricValue::Counter(total), true) => JsonLine::new(&mut text)
+                .string("name", name)
+                .number("counter", total)
+                .finish(),
+            (MetricValue::Gauge(level), true) => JsonLine::new(&mut text)
+                .string("name", name)
+                .number("gauge", level)
+                .finish(),
+            (MetricValue::Histogram(histogram), true) => {
+                let mut line = JsonLine::new(&mut text);
+                line.string("name", name)
+                    .number("count", histogram.count)
+                    .number("mean", histogram.mean())
+                    .number("max", histogram.max);
+                for (bucket, &count) in histogram.buckets.iter().enumerate() {
+                    let mut field: heapless::String<12> = heapless::String::new();
+                    let _ = match Histogram::bucket_bound(bucket) {
+                        Some(bound) => write!(field, "lt_{}", bound),
+                        None => write!(field, "rest"),
+                    };
+                    line.number(&field, count);
+                }
+                line.finish()
+            }
+            (MetricValue::Counter(total), false) => write!(text, "{}: {}\r\n", name, total),
+            (MetricValue::Gauge(level), false) => write!(text, "{}: {}\r\n", name, level),
+            (MetricValue::Histogram(histogram), false) => {
+                let _ = write!(
+                    text,
+                    "{}: {} samples, mean {}, max {}, buckets",
+                    name,
+                    histogram.count,
+                    histogram.mean(),
+                    histogram.max
+                );
+                for count in &histogram.buckets {
+                    let _ = write!(text, " {}", count);
+                }
+                write!(text, "\r\n")
+            }
+        };
+        send_to_host(text.as_bytes());
+    }
+}
+
+/// Show a banner over the terminal while the host is stalled, and remove it when it resumes
+fn show_heartbeat(event: HeartbeatEvent) {
+    cortex_m::interrupt::free(|cs| {
+        let mut terminal = TERMINAL.borrow(cs);
+        let terminal = match terminal.as_mut() {
+            Some(terminal) => terminal,
+            None => return,
+        };
+        match event {
+            HeartbeatEvent::Stalled { last_us } => {
+                let mut since: heapless::String<40> = heapless::String::new();
+                let _ = write!(since, "at {}", CLOCK.timestamp(last_us));
+                let screen = terminal.screen_mut();
+                let _ = Rectangle::new(Point::new(50, 78), Size::new(220, 56))
+                    .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+                    .draw(screen);
+                let _ = Text::new(
+                    "HOST STALLED",
+                    Point::new(100, 100),
+                    MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE),
+                )
+                .draw(screen);
+                let _ = Text::new(
+                    &since,
+                    Point::new(60, 122),
+                    MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE),
+                )
+                .draw(screen);
+            }
+            HeartbeatEvent::Resumed => terminal.refresh(),
+        }
+    });
+}
+
+///
+/// This is used outside of the USB interrupt, so interrupts are disabled while drawing.
+fn draw_on_screen<F: FnOnce(&mut Display)>(f: F) {
+    TERMINAL.lock(|terminal| f(terminal.screen_mut()));
+}
+
+/// Send a keyboard report to the host
+///
+/// Reports are sent as keys are pressed and released, so a report that cannot be sent is
+/// dropped rather than retried.
+#[cfg(feature = "hid-payload")]
+fn send_hid_report(report: &usbd_hid::descriptor::KeyboardReport) {
+    USB_HID.lock(|hid| {
+        if hid.push_input(report).is_err() {
+            ERRORS.record(ErrorKind::TxDrop, 1);
+        }
+    });
+}
+
+/// Write bytes to the terminal on the display
+///
+/// This is used outside of the USB interrupt, so interrupts are disabled while writing.
+fn write_to_terminal(data: &[u8]) {
+    TERMINAL.lock(|terminal| terminal.write(data));
+    terminal_activity((now_us() / 1000) as u32);
+}
+
+/// Send data from `source` to the display, the host, the UART and the log buffer, as routed
+///
+/// The shell and the page only take the data from the host, in the USB interrupt.
+fn route_data(source: Source, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    if ROUTES.is_routed(source, Sink::Usb) {
+        send_to_host(data);
+    }
+    if ROUTES.is_routed(source, Sink::Display) {
+        write_to_terminal(data);
+    }
+    if ROUTES.is_routed(source, Sink::Uart) {
+        write_to_uart(data);
+    }
+    if ROUTES.is_routed(source, Sink::LogBuffer) {
+        write_to_log_buffer(data);
+    }
+}
+
+/// Queue bytes for the UART of the routes, and send as many as fit in its FIFO
+///
+/// Without the `uart-route` feature there is no UART, and the bytes are dropped.
+fn write_to_uart(data: &[u8]) {
+    #[cfg(feature = "uart-route")]
+    cortex_m::interrupt::free(|cs| {
+        UART_TX.with(cs, |queue| {
+            for &byte in data {
+                if queue.push_back(byte).is_err() {
+                    ERRORS.record(ErrorKind::QueueOverrun, 1);
+                }
+            }
+        });
+        flush_uart(cs);
+    });
+    #[cfg(not(feature = "uart-route"))]
+    let _ = data;
+}
+
+/// Move the queued bytes to the FIFO of the UART, until it is full
+#[cfg(feature = "uart-route")]
+fn flush_uart(cs: &cortex_m::interrupt::CriticalSection) {
+    UART.with(cs, |uart| {
+        UART_TX.with(cs, |queue| {
+            while let Some(&byte) = queue.front() {
+                if uart.write(&[byte]) == 0 {
+                    break;
+                }
+                queue.pop_front();
+            }
+        })
+    });
+}
+
+/// Keep bytes in the log buffer, see the `buffer` command
+fn write_to_log_buffer(data: &[u8]) {
+    LOG_BUFFER.lock(|buffer| buffer.push(data));
+}
+
+/// Wake the backlight and stop the idle animations, drawing the terminal over Ferris
+fn terminal_activity(now_ms: u32) {
+    BACKLIGHT.lock(|backlight| backlight.activity(now_ms));
+    if IDLE.lock(|idle| idle.activity(now_ms)) == Some(true) {
+        TERMINAL.lock(|terminal| terminal.refresh());
+    }
+}
+
+/// Image of the splash and the idle animations, with its width and number of frames
+///
+/// This is the uploaded splash, or Ferris, which only has one frame.
+fn sprite_sheet(boot_mode: BootMode) -> (&'static [u8], u32, usize) {
+    match Asset::load(AssetKind::Splash) {
+        Some(asset) if boot_mode != BootMode::Safe && idle::sheet_width(asset.param) > 0 => (
+            asset.data(),
+            idle::sheet_width(asset.param),
+            idle::sheet_frames(asset.param),
+        ),
+        _ => (FERRIS, 64, 1),
+    }
+}
+
+/// Draw a frame of the sprite sheet in the corner of the terminal, clearing the room above it
+fn draw_idle_pose(screen: &mut Display, pose: Pose, boot_mode: BootMode) {
+    let (data, width, frames) = sprite_sheet(boot_mode);
+    let frame_len = data.len() / frames;
+    // Frames missing from the sheet show the last one
+    let frame = (pose.frame as usize).min(frames - 1);
+    let image: ImageRawLE<Rgb565> =
+        ImageRaw::new(&data[frame * frame_len..][..frame_len], width);
+    let height = (frame_len / 2) as u32 / width.max(1);
+    let lift = MAX_LIFT as i32;
+    let _ = Rectangle::new(
+        IDLE_SPRITE_POS - Point::new(0, lift),
+        Size::new(width, height + lift as u32),
+    )
+    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+    .draw(screen);
+    let _ = Image::new(&image, IDLE_SPRITE_POS - Point::new(0, pose.lift as i32)).draw(screen);
+}
+
+/// Send bytes to the host over the USB serial port, on the console channel
+fn send_to_host(data: &[u8]) {
+    send_on_channel(Channel::Console, data);
+}
+
+/// Send a line of the logger to the host, on the log port if there is one
+fn log_to_host(line: &[u8]) {
+    #[cfg(feature = "log-port")]
+    usb_log::queue_line(line.strip_suffix(b"\r\n").unwrap_or(line));
+    #[cfg(not(feature = "log-port"))]
+    send_to_host(line);
+}
+
+/// Send data to the host on `channel`, in frames when multiplexing
+fn send_on_channel(channel: Channel, data: &[u8]) {
+    if !MUX_ENABLED.load(Ordering::Relaxed) {
+        write_to_host(data);
+        return;
+    }
+    for chunk in data.chunks(MAX_FRAME) {
+        let mut frame = [0u8; mux::max_encoded_len(MAX_FRAME)];
+        if let Some(len) = mux::encode(channel, chunk, &mut frame) {
+            write_to_host(&frame[..len]);
+        }
+    }
+}
+
+/// Send raw bytes to the host
+///
+/// This is used outside of the USB interrupt, so interrupts are disabled while writing. While
+/// no host is listening, the bytes are dropped without counting them as lost.
+fn write_to_host(data: &[u8]) {
+    if !HOST_LISTENING.load(Ordering::Relaxed) {
+        return;
+    }
+    let now = now_us();
+    USB_CONSOLE.lock(|console| {
+        // Bytes that don't fit behind the pending ones are lost
+        let sent = console.write(data, now).unwrap_or(0);
+        if sent < data.len() {
+            ERRORS.record(ErrorKind::TxDrop, (data.len() - sent) as u32);
+        }
+    });
+}
+
+/// Tell the host a latency marker was drawn, with the time since it was received
+fn report_marker(marker: Marker) {
+    let now_us = now_us();
+    let received_us = MARKER_STAMPS.lock(|stamps| stamps.take(marker.number)).flatten();
+    let mut line: heapless::Vec<u8, 48> = heapless::Vec::new();
+    let _ = line.extend_from_slice(b"!LAT ");
+    let _ = line.extend_from_slice(&marker.token);
+    let mut text: heapless::String<24> = heapless::String::new();
+    let _ = match received_us {
+        Some(received_us) => {
+            let latency_us = now_us.saturating_sub(received_us);
+            LATENCY_US.record(latency_us as u32);
+            write!(text, " {}\r\n", latency_us)
+        }
+        None => write!(text, " -\r\n"),
+    };
+    let _ = line.extend_from_slice(text.as_bytes());
+    send_to_host(&line);
+}
+
+/// Send a device event to the host, if notifications are enabled for it
+fn notify_host(event: &Event) {
+    if !EVENTS.accepts(event) {
+        return;
+    }
+
+    let mut line: heapless::String<64> = heapless::String::new();
+    if event
+        .write_line(CLOCK.timestamp(now_us()), &mut line)
+        .is_ok()
+    {
+        send_to_host(line.as_bytes());
+    }
+}
+
+/// Number of bytes that can be sent to the host without dropping any
+fn host_tx_available() -> usize {
+    USB_CONSOLE.lock(|console| console.available()).unwrap_or(0)
+}
+
+/// Log of the data logger, as CSV
+#[cfg(feature = "msc")]
+struct LogFile;
+
+#[cfg(feature = "msc")]
+impl VirtualFile for LogFile {
+    fn name(&self) -> &[u8; 11] {
+        b"LOG     TXT"
+    }
+
+    fn size(&self) -> u32 {
+        rp2040_test::datalog::stored()
+            .map(|data| data.len() as u32)
+            .sum()
+    }
+
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        let mut start = 0;
+        let mut filled = 0;
+        for data in rp2040_test::datalog::stored() {
+            let end = start + data.len() as u32;
+            let from = offset + filled as u32;
+            if from < end {
+                let chunk = &data[(from - start) as usize..];
+                let len = chunk.len().min(buf.len() - filled);
+                buf[filled..filled + len].copy_from_slice(&chunk[..len]);
+                filled += len;
+                if filled == buf.len() {
+                    return;
+                }
+            }
+            start = end;
+        }
+    }
+}
+
+/// State of the device
+///
+/// The values are padded to a fixed width, so the size of the file doesn't change.
+#[cfg(feature = "msc")]
+struct InfoFile;
+
+#[cfg(feature = "msc")]
+impl InfoFile {
+    fn content() -> heapless::String<128> {
+        let mut text = heapless::String::new();
+        let _ = write!(
+            text,
+            "rp2040-test {}\r\nUptime (ms): {:>20}\r\nRX bytes: {:>10}\r\nErrors: {:>10}\r\n",
+            env!("CARGO_PKG_VERSION"),
+            now_us() / 1000,
+            RX_BYTES.total(),
+            ERRORS.snapshot().total(),
+        );
+        text
+    }
+}
+
+#[cfg(feature = "msc")]
+impl VirtualFile for InfoFile {
+    fn name(&self) -> &[u8; 11] {
+        b"INFO    TXT"
+    }
+
+    fn size(&self) -> u32 {
+        Self::content().len() as u32
+    }
+
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        let content = Self::content();
+        let data = content.as_bytes().get(offset as usize..).unwrap_or(&[]);
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+/// Keep the screen and the panic message in flash, then blink the panic code on the LED
+///
+/// With the `panic-display` feature, the panic is shown on the screen instead.
+#[cfg(not(feature = "panic-display"))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message: heapless::String<MAX_MESSAGE> = heapless::String::new();
+    // A message that doesn't fit is cut
+    let _ = write!(message, "{}", info);
+    save_crash(CrashKind::Panic, message.as_bytes())
+}
+
+/// Keep the screen and the faulting address in flash, then blink the hard fault code on the LED
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    let mut message: heapless::String<MAX_MESSAGE> = heapless::String::new();
+    let _ = write!(message, "pc 0x{:08x}", frame.pc());
+    save_crash(CrashKind::HardFault, message.as_bytes())
+}
+
+fn save_crash(kind: CrashKind, message: &[u8]) -> ! {
+    cortex_m::interrupt::disable();
+    // Note (safety): interrupts are disabled and the main loop doesn't run anymore, so nothing
+    // else accesses the terminal, even if it was borrowed when the firmware crashed. The screen
+    // may be left halfway through an update.
+    let cs = unsafe { cortex_m::interrupt::CriticalSection::new() };
+    let terminal = unsafe { TERMINAL.peek(&cs) };
+    let rows = terminal.into_iter().flat_map(|terminal| terminal.visible_rows());
+    crash::save(kind, message, (now_us() / 1000) as u32, rows);
+    // The screen may be what failed, so the LED tells it too
+    let fault = match kind {
+        CrashKind::Panic => Fault::Panic,
+        CrashKind::HardFault => Fault::HardFault,
+    };
+    fault.blink_forever(|| (now_us() / 1000) as u32)
+}
+
+/// This function is called whenever the USB Hardware generates an Interrupt
+/// Request.
+///
+/// We do all our USB work under interrupt, so the main thread can continue on
+/// knowing nothing about USB.
+#[allow(non_snake_case)]
+#[interrupt]
+unsafe fn USBCTRL_IRQ() {
+    /// Paces the serial data when emulating the baud rate.
+    static mut BAUD_LIMITER: BaudLimiter = BaudLimiter::new();
+
+    /// Assembles the command lines.
+    static mut LINE_BUFFER: LineBuffer = LineBuffer::new();
+
+    /// Reassembles the frames when multiplexing.
+    static mut MUX_DECODER: Decoder = Decoder::new();
+
+    // The main loop borrows the same objects, but with the interrupts disabled
+    cortex_m::interrupt::free(|cs| {
+        let (mut usb_dev, mut console) = (USB_DEVICE.borrow(cs), USB_CONSOLE.borrow(cs));
+        if let (Some(usb_dev), Some(console)) = (usb_dev.as_mut(), console.as_mut()) {
+            poll_usb(cs, usb_dev, console, BAUD_LIMITER, LINE_BUFFER, MUX_DECODER);
+        }
+    });
+}
+
+/// Poll the USB device, and route the data received from the host
+///
+/// # Safety
+///
+/// This must only run from the USB interrupt, as it fills the queues read by the main loop.
+unsafe fn poll_usb(
+    cs: &cortex_m::interrupt::CriticalSection,
+    usb_dev: &mut UsbDevice<hal::usb::UsbBus>,
+    console: &mut PicoUsbConsole,
+    baud_limiter: &mut BaudLimiter,
+    line_buffer: &mut LineBuffer,
+    mux_decoder: &mut Decoder,
+) {
+    /// Note whether we've already printed the "hello" message.
+    static SAID_HELLO: AtomicBool = AtomicBool::new(false);
+
+    // Send what couldn't be sent during the previous poll, or was held back
+    let _ = console.poll(now_us());
+
+    // Say hello exactly once on start-up
+    if !SAID_HELLO.load(Ordering::Relaxed) {
+        SAID_HELLO.store(true, Ordering::Relaxed);
+        let mut hello: heapless::String<48> = heapless::String::new();
+        let _ = match DEVICE_NAME.borrow(cs).as_ref() {
+            Some(name) => write!(hello, "Hello, World! I am {}\r\n", name),
+            None => write!(hello, "Hello, World!\r\n"),
+        };
+        let _ = console.write(hello.as_bytes(), now_us());
+    }
+
+    // Poll the USB driver with all of our supported USB Classes
+    #[cfg(feature = "log-port")]
+    let mut log_port = USB_LOG.borrow(cs);
+    #[cfg(feature = "hid-payload")]
+    let mut hid = USB_HID.borrow(cs);
+    #[cfg(feature = "msc")]
+    let mut msc = USB_MSC.borrow(cs);
+    let mut classes: heapless::Vec<&mut dyn UsbClass<hal::usb::UsbBus>, 4> = heapless::Vec::new();
+    let _ = classes.push(console.port());
+    #[cfg(feature = "log-port")]
+    if let Some(log_port) = log_port.as_mut() {
+        let _ = classes.push(log_port.port());
+    }
+    #[cfg(feature = "hid-payload")]
+    if let Some(hid) = hid.as_mut() {
+        let _ = classes.push(hid);
+    }
+    #[cfg(feature = "msc")]
+    if let Some(msc) = msc.as_mut() {
+        let _ = classes.push(msc);
+    }
+    let polled = usb_dev.poll(&mut classes);
+    #[cfg(feature = "log-port")]
+    if let Some(log_port) = log_port.as_mut() {
+        log_port.poll();
+    }
+
+    // The main loop reboots, once it checked the lock
+    if console.bootloader_touched() {
+        BOOTLOADER_TOUCH.store(true, Ordering::Relaxed);
+    }
+
+    if polled || cfg!(feature = "emulated-baud") {
+        let mut buf = [0u8; 64];
+
+        // Bytes that are not read yet stay on the host side, so they are delayed rather than
+        // dropped.
+        let mut max_len = buf.len();
+
+        // Only read as many bytes as there is room to echo
+        if ROUTES.is_routed(Source::UsbSerial, Sink::Usb) {
+            max_len = max_len.min(console.available());
+            if max_len == 0 {
+                return;
+            }
+        }
+
+        // Only read as many bytes as there is room to draw
+        let mut render_tx = RENDER_TX.borrow(cs);
+        if ROUTES.is_routed(Source::UsbSerial, Sink::Display) {
+            if let Some(render_tx) = render_tx.as_ref() {
+                max_len = max_len.min(render_tx.capacity() - render_tx.len());
+                if max_len == 0 {
+                    return;
+                }
+            }
+        }
+
+        // Only read as many bytes as the emulated link could carry
+        if cfg!(feature = "emulated-baud") {
+            let line_coding = console.line_coding();
+            baud_limiter.set_line_coding(
+                line_coding.data_rate(),
+                line_coding.data_bits(),
+                line_coding.parity_type(),
+                line_coding.stop_bits(),
+            );
+            max_len = max_len.min(baud_limiter.available(now_us()));
+            if max_len == 0 {
+                return;
+            }
+        }
+
+        match console.read(&mut buf[..max_len]) {
+            Err(UsbError::WouldBlock) => {
+                // Do nothing
+            }
+            Err(_e) => {
+                // The received packet could not be read and is lost
+                ERRORS.record(ErrorKind::RxOverflow, 1);
+            }
+            Ok(0) => {
+                // Do nothing
+            }
+            Ok(count) => {
+                if cfg!(feature = "emulated-baud") {
+                    baud_limiter.consume(count);
+                }
+                RX_BYTES.add(count as u32);
+
+                // When multiplexing, the console channel goes on like the plain data, and the
+                // data channel to the main loop for the asset uploads
+                let mut console_data = [0u8; 2 * MAX_FRAME];
+                let (buf, count) = if MUX_ENABLED.load(Ordering::Relaxed) {
+                    let mut len = 0;
+                    for &byte in &buf[..count] {
+                        match mux_decoder.push(byte) {
+                            Some(frame) if frame.channel == Channel::Console => {
+                                let end = (len + frame.payload.len()).min(console_data.len());
+                                console_data[len..end].copy_from_slice(&frame.payload[..end - len]);
+                                len = end;
+                            }
+                            Some(frame) if frame.channel == Channel::Data => {
+                                let len = frame.payload.len();
+                                let payload = heapless::Vec::from_slice(frame.payload);
+                                if DATA_FRAMES.enqueue(payload.unwrap_or_default()).is_err() {
+                                    ERRORS.record(ErrorKind::QueueOverrun, len as u32);
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+                    (&mut console_data[..], len)
+                } else {
+                    (&mut buf[..], count)
+                };
+
+                // The key shows the next page of a command output, and goes no further
+                if PAGER_WAITING.load(Ordering::Relaxed) {
+                    PAGER_WAITING.store(false, Ordering::Relaxed);
+                    return;
+                }
+
+                // Watch for the trigger patterns, the main loop runs their actions
+                let triggered = TRIGGERS
+                    .with(cs, |triggers| {
+                        buf[..count]
+                            .iter()
+                            .fold(0, |triggered, &byte| triggered | triggers.feed(byte))
+                    })
+                    .unwrap_or(0);
+                if triggered != 0 {
+                    TRIGGERED.store(
+                        TRIGGERED.load(Ordering::Relaxed) | triggered,
+                        Ordering::Relaxed,
+                    );
+                }
+
+                // Collect command lines for the main loop
+                if ROUTES.is_routed(Source::UsbSerial, Sink::Shell) {
+                    for &byte in &buf[..count] {
+                        if let Some(line) = line_buffer.push(byte) {
+                            // A line not picked up yet by the main loop is replaced
+                            PENDING_LINE.put(line);
+                        }
+                    }
+                }
+
+                // Queue for the page shown over the terminal
+                if ROUTES.is_routed(Source::UsbSerial, Sink::Page) {
+                    for &byte in &buf[..count] {
+                        if PAGE_INPUT.enqueue(byte).is_err() {
+                            ERRORS.record(ErrorKind::QueueOverrun, 1);
+                        }
+                    }
+                }
+
+                // Queue for the screen, the main loop draws it to keep the interrupt short
+                if ROUTES.is_routed(Source::UsbSerial, Sink::Display) {
+                    if LATENCY_TEST.load(Ordering::Relaxed) {
+                        let now = now_us();
+                        MARKER_STAMPS.with(cs, |stamps| {
+                            for &byte in &buf[..count] {
+                                stamps.stamp(byte, now);
+                            }
+                        });
+                    }
+                    if let Some(render_tx) = render_tx.as_mut() {
+                        for &byte in &buf[..count] {
+                            if render_tx.enqueue(byte).is_err() {
+                                ERRORS.record(ErrorKind::QueueOverrun, 1);
+                            }
+                        }
+                    }
+                }
+
+                // Pass on to the UART, and keep in the log buffer
+                if ROUTES.is_routed(Source::UsbSerial, Sink::Uart) {
+                    write_to_uart(&buf[..count]);
+                }
+                if ROUTES.is_routed(Source::UsbSerial, Sink::LogBuffer) {
+                    LOG_BUFFER.with(cs, |buffer| buffer.push(&buf[..count]));
+                }
+
+                if !ROUTES.is_routed(Source::UsbSerial, Sink::Usb) {
+                    return;
+                }
+
+                // Convert to lower case
+                if ECHO_LOWERCASE.load(Ordering::Relaxed) {
+                    buf.iter_mut().take(count).for_each(|b| {
+                        b.make_ascii_lowercase();
+                    });
+                }
+
+                // With the shell, line edits are echoed the way a terminal shows them
+                let mut edited = heapless::Vec::<u8, { 2 * MAX_FRAME }>::new();
+                let echo = if ROUTES.is_routed(Source::UsbSerial, Sink::Shell) {
+                    for &byte in &buf[..count] {
+                        if !shell::push_echo(&mut edited, byte) {
+                            ERRORS.record(ErrorKind::TxDrop, 1);
+                        }
+                    }
+                    &edited[..]
+                } else {
+                    &buf[..count]
+                };
+
+                // Send back to the host. There is always room for the echo, as no more bytes
+                // were read than what fits in the pending buffer, unless it is framed or edited.
+                if MUX_ENABLED.load(Ordering::Relaxed) {
+                    let mut frame = [0u8; mux::max_encoded_len(2 * MAX_FRAME)];
+                    if let Some(len) = mux::encode(Channel::Console, echo, &mut frame) {
+                        let sent = console.write(&frame[..len], now_us()).unwrap_or(0);
+                        if sent < len {
+                            ERRORS.record(ErrorKind::TxDrop, (len - sent) as u32);
+                        }
+                    }
+                } else {
+                    let sent = console.write(echo, now_us()).unwrap_or(0);
+                    if sent < echo.len() {
+                        ERRORS.record(ErrorKind::TxDrop, (echo.len() - sent) as u32);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// End of file
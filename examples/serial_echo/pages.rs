@@ -0,0 +1,452 @@
+//! Pages shown over the terminal of `serial_echo`
+//!
+//! A page is opened by holding two buttons, by a command or after `reboot into`, and is driven by
+//! the buttons or the keys of the host until it closes.
+
+use super::*;
+
+/// Page shown over the terminal, driven by the buttons
+pub(crate) enum Page {
+    Settings(SettingsPage),
+    /// State and schedule of the relay outputs
+    Switches(Menu<'static, Rgb565>),
+    Typing(TypingPage),
+    Keyboard(KeyboardPage),
+    /// Matches of a search of the terminal, shown on the terminal itself
+    Search(SearchPage),
+}
+
+impl Page {
+    /// Handle a key from the buttons, returning false once the page is closed
+    pub(crate) fn handle_key(&mut self, key: Key, state: &mut CommandState) -> bool {
+        match self {
+            // Locked while the page was open, it can only be closed
+            Page::Settings(_) if state.lock.is_locked() => key != Key::Escape,
+            Page::Settings(page) => page.handle_key(key, &mut state.settings),
+            Page::Switches(menu) => match menu.handle_key(key, MAX_SWITCHES) {
+                // Switching an output by hand is refused while locked
+                Some(MenuAction::Select(index)) if !state.lock.is_locked() => {
+                    state.switches.toggle(index);
+                    state.switches.save();
+                    true
+                }
+                Some(MenuAction::Close) => false,
+                _ => true,
+            },
+            Page::Typing(page) => page.handle_key(key),
+            Page::Keyboard(page) => page.handle_key(key, state),
+            Page::Search(page) => page.handle_key(key),
+        }
+    }
+
+    pub(crate) fn draw(&self, screen: &mut Display, state: &CommandState) {
+        match self {
+            Page::Settings(page) => page.draw(screen, &state.settings),
+            Page::Switches(menu) => {
+                let mut values: [heapless::String<24>; MAX_SWITCHES] = Default::default();
+                for (index, value) in values.iter_mut().enumerate() {
+                    let _ = write!(
+                        value,
+                        "{:<4}",
+                        if state.switches.is_on(index) {
+                            "on"
+                        } else {
+                            "off"
+                        }
+                    );
+                    if let Some(schedule) = state.switches.schedule(index) {
+                        let _ = write!(value, "{}", schedule);
+                    }
+                }
+                let labels = ["Switch 1", "Switch 2", "Switch 3", "Switch 4"];
+                let mut items = [("", ""); MAX_SWITCHES];
+                for (item, (label, value)) in items.iter_mut().zip(labels.iter().zip(&values)) {
+                    *item = (*label, value.as_str());
+                }
+                let _ = menu.draw(screen, &items);
+            }
+            Page::Typing(page) => page.draw(screen),
+            Page::Keyboard(page) => page.draw(screen),
+            // The terminal draws the matches
+            Page::Search(_) => (),
+        }
+    }
+}
+
+/// Show a page over the terminal, and give it the data from the host instead
+pub(crate) fn open_page(page: &Page, state: &mut CommandState) {
+    state.page_display_route = ROUTES.is_routed(Source::UsbSerial, Sink::Display);
+    ROUTES.set(Source::UsbSerial, Sink::Display, false);
+    ROUTES.set(Source::UsbSerial, Sink::Page, true);
+    if let Page::Search(page) = page {
+        page.start();
+    }
+    draw_on_screen(|screen| page.draw(screen, state));
+}
+
+/// Give the data from the host back to the terminal, and redraw it over the closed page
+pub(crate) fn close_page(state: &CommandState) {
+    ROUTES.set(Source::UsbSerial, Sink::Page, false);
+    ROUTES.set(Source::UsbSerial, Sink::Display, state.page_display_route);
+    cortex_m::interrupt::free(|cs| {
+        // Note (safety): interrupts are disabled, so the USB interrupt cannot access the queue
+        // at the same time.
+        while unsafe { PAGE_INPUT.dequeue() }.is_some() {}
+        TERMINAL.with(cs, |terminal| {
+            terminal.end_search();
+            terminal.refresh();
+            let _ = state.plot.draw(terminal.screen_mut());
+        });
+    });
+}
+
+/// Menu of the switches page
+pub(crate) fn switches_menu() -> Menu<'static, Rgb565> {
+    Menu::new(
+        Rectangle::new(Point::new(40, 61), Size::new(240, 88)),
+        "Switches  A/B: move  X: toggle  Y: back",
+        page_style(),
+        Rgb565::BLACK,
+        Rgb565::new(0, 0, 16),
+    )
+}
+
+/// Text style of the pages
+fn page_style() -> MonoTextStyle<'static, Rgb565> {
+    MonoTextStyleBuilder::new()
+        .font(&FONT_6X10)
+        .text_color(Rgb565::WHITE)
+        .background_color(Rgb565::BLACK)
+        .build()
+}
+
+/// Number of options on the settings page
+const SETTINGS_ROWS: usize = 7;
+
+/// Settings page, shown over the terminal
+pub(crate) struct SettingsPage {
+    menu: Menu<'static, Rgb565>,
+    /// Field to enter the brightness
+    brightness: InputField<'static, Rgb565>,
+}
+
+impl SettingsPage {
+    pub(crate) fn new() -> Self {
+        let style = page_style();
+        let menu = Menu::new(
+            Rectangle::new(Point::new(40, 61), Size::new(240, 100)),
+            "Settings  A/B: move  X: change  Y: back",
+            style,
+            Rgb565::BLACK,
+            Rgb565::new(0, 0, 16),
+        );
+        let mut brightness = InputField::new(
+            // Over the value of the brightness row
+            menu.below(2) + Point::new(6 * 13, 1),
+            3,
+            style,
+            Rgb565::BLACK,
+            Rgb565::GREEN,
+        );
+        brightness.set_charset(b"0123456789");
+        Self { menu, brightness }
+    }
+
+    /// Handle a key from the buttons, returning false once the page is closed
+    ///
+    /// The options with a few values cycle through them, the brightness is entered with the
+    /// input field. Changes are applied and stored right away.
+    fn handle_key(&mut self, key: Key, settings: &mut Settings) -> bool {
+        if self.brightness.is_focused() {
+            // The buttons have no right key, so B moves to the next digit instead
+            let key = if key == Key::Down { Key::Right } else { key };
+            if let Some(event) = self.brightness.handle_key(key) {
+                notify_host(&event);
+                if let Ok(brightness) = self.brightness.value().parse::<u8>() {
+                    settings.brightness = brightness.min(100);
+                    settings.save();
+                }
+            }
+            return true;
+        }
+
+        match self.menu.handle_key(key, SETTINGS_ROWS) {
+            Some(MenuAction::Select(0)) => settings.theme = settings.theme.next(),
+            Some(MenuAction::Select(1)) => settings.rotation = settings.rotation.next(),
+            Some(MenuAction::Select(2)) => {
+                self.brightness.set_value("");
+                self.brightness.focus();
+                return true;
+            }
+            Some(MenuAction::Select(3)) => settings.echo = settings.echo.next(),
+            Some(MenuAction::Select(4)) => settings.palette = settings.palette.next(),
+            Some(MenuAction::Select(5)) => settings.min_contrast = !settings.min_contrast,
+            Some(MenuAction::Select(_)) => settings.confirm = settings.confirm.next(),
+            Some(MenuAction::Close) => return false,
+            None => return true,
+        }
+        settings.save();
+        apply_settings(settings);
+        true
+    }
+
+    fn draw(&self, screen: &mut Display, settings: &Settings) {
+        let mut brightness: heapless::String<8> = heapless::String::new();
+        let _ = write!(brightness, "{}%", settings.brightness);
+        let items = [
+            ("Theme", settings.theme.name()),
+            ("Rotation", settings.rotation.name()),
+            ("Brightness", brightness.as_str()),
+            ("Echo", settings.echo.name()),
+            ("Palette", settings.palette.name()),
+            (
+                "Contrast",
+                if settings.min_contrast { "min" } else { "any" },
+            ),
+            ("Confirm", settings.confirm.name()),
+        ];
+        let _ = self.menu.draw(screen, &items);
+        if self.brightness.is_focused() {
+            let _ = self.brightness.draw(screen);
+        }
+    }
+}
+
+/// Where the text of the keyboard page goes
+#[cfg(feature = "hid-payload")]
+const KEYBOARD_TARGETS: [&str; 4] = ["Terminal", "Search", "Notes", "HID"];
+#[cfg(not(feature = "hid-payload"))]
+const KEYBOARD_TARGETS: [&str; 3] = ["Terminal", "Search", "Notes"];
+
+/// On-screen keyboard page, to enter text without a host
+///
+/// Once the text is done, a menu asks where it goes: the terminal, a search of the terminal,
+/// the notes shown at boot, or the host through the HID keyboard.
+pub(crate) struct KeyboardPage {
+    keyboard: SoftKeyboard<'static, Rgb565>,
+    /// Menu of the targets, shown once the text is done
+    targets: Menu<'static, Rgb565>,
+    text: Option<heapless::String<MAX_INPUT>>,
+}
+
+impl KeyboardPage {
+    pub(crate) fn new() -> Self {
+        let area = Rectangle::new(Point::new(40, 61), Size::new(240, 88));
+        Self {
+            keyboard: SoftKeyboard::new(
+                area,
+                "Keyboard  A/B: move  X: press  Y: back",
+                page_style(),
+                Rgb565::BLACK,
+                Rgb565::new(0, 0, 16),
+            ),
+            targets: Menu::new(
+                area,
+                "Send to  A/B: move  X: send  Y: back",
+                page_style(),
+                Rgb565::BLACK,
+                Rgb565::new(0, 0, 16),
+            ),
+            text: None,
+        }
+    }
+
+    /// Handle a key from the buttons, returning false once the page is closed
+    fn handle_key(&mut self, key: Key, state: &mut CommandState) -> bool {
+        let text = match self.text.as_ref() {
+            Some(text) => text,
+            None => {
+                match self.keyboard.handle_key(key) {
+                    Some(SoftKeyAction::Done(text)) => self.text = Some(text),
+                    Some(SoftKeyAction::Cancel) => return false,
+                    None => (),
+                }
+                return true;
+            }
+        };
+
+        match self.targets.handle_key(key, KEYBOARD_TARGETS.len()) {
+            Some(MenuAction::Select(0)) => {
+                write_to_terminal(text.as_bytes());
+                write_to_terminal(b"\n");
+            }
+            Some(MenuAction::Select(1)) => {
+                state.open_page = SearchPage::new(text).map(Page::Search);
+            }
+            Some(MenuAction::Select(2)) => {
+                // Like an upload, the notes can't be replaced while locked
+                let result = if state.lock.is_locked() {
+                    Err("locked")
+                } else {
+                    save_notes(text.as_bytes()).map_err(|error| error.name())
+                };
+                if let Err(reason) = result {
+                    let mut line: heapless::String<48> = heapless::String::new();
+                    let _ = write!(line, "Notes not saved: {}\n", reason);
+                    write_to_terminal(line.as_bytes());
+                }
+            }
+            #[cfg(feature = "hid-payload")]
+            Some(MenuAction::Select(_)) => state.hid_text = Some(text.clone()),
+            #[cfg(not(feature = "hid-payload"))]
+            Some(MenuAction::Select(_)) => (),
+            // Back to the keyboard, with the text kept
+            Some(MenuAction::Close) => {
+                self.text = None;
+                return true;
+            }
+            None => return true,
+        }
+        false
+    }
+
+    fn draw(&self, screen: &mut Display) {
+        if self.text.is_none() {
+            let _ = self.keyboard.draw(screen);
+            return;
+        }
+        let mut items = [("", ""); KEYBOARD_TARGETS.len()];
+        for (item, target) in items.iter_mut().zip(KEYBOARD_TARGETS.iter()) {
+            *item = (*target, "");
+        }
+        let _ = self.targets.draw(screen, &items);
+    }
+}
+
+/// Search of the terminal, with the buttons stepping through the matches
+///
+/// Up and down go to the older and newer matches, enter and escape go back to the live rows.
+pub(crate) struct SearchPage {
+    query: heapless::String<MAX_QUERY>,
+}
+
+impl SearchPage {
+    /// Page searching for `query`, `None` if it is empty or too long
+    pub(crate) fn new(query: &str) -> Option<Self> {
+        let mut text = heapless::String::new();
+        if query.is_empty() || text.push_str(query).is_err() {
+            return None;
+        }
+        Some(Self { query: text })
+    }
+
+    /// Search the terminal, showing the newest match, and tell the host the number of matches
+    fn start(&self) {
+        let color = Rgb565::new(0, 0, 16);
+        if let Some(Some(status)) = TERMINAL.lock(|terminal| terminal.search(&self.query, color))
+        {
+            let mut line: heapless::String<32> = heapless::String::new();
+            let _ = write!(line, "search {}\r\n", status);
+            send_to_host(line.as_bytes());
+        }
+    }
+
+    /// Handle a key from the buttons, returning false once the page is closed
+    fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Up => TERMINAL.lock(|terminal| terminal.older_match()),
+            Key::Down => TERMINAL.lock(|terminal| terminal.newer_match()),
+            Key::Enter | Key::Escape => return false,
+            _ => None,
+        };
+        true
+    }
+}
+
+/// Store text from the device as the notes shown at boot, on their own line
+fn save_notes(text: &[u8]) -> Result<(), UploadError> {
+    let mut crc = Crc32::new();
+    crc.update(text);
+    crc.update(b"\n");
+    let mut upload = Upload::begin(AssetKind::Notes, text.len() + 1, crc.finish(), 0)?;
+    upload.write(text)?;
+    upload.write(b"\n")?;
+    upload.commit()
+}
+
+/// Typing test page
+pub(crate) struct TypingPage {
+    test: TypingTest,
+}
+
+impl TypingPage {
+    pub(crate) fn new() -> Self {
+        Self {
+            test: TypingTest::new(PROMPT.trim_end().as_bytes()),
+        }
+    }
+
+    /// Handle a key from the buttons, returning false once the page is closed
+    ///
+    /// Enter restarts the test and escape closes the page.
+    fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Enter => self.test = TypingTest::new(self.test.prompt()),
+            Key::Escape => return false,
+            _ => (),
+        }
+        true
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) {
+        self.test.push(byte, now_us());
+    }
+
+    /// Draw the prompt, colored as it is typed, and the statistics
+    fn draw(&self, screen: &mut Display) {
+        let area = Rectangle::new(Point::new(40, 61), Size::new(240, 88));
+        let _ = area
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(screen);
+        let style = page_style();
+        let _ = Text::with_baseline(
+            "Typing  X: restart  Y: back",
+            area.top_left,
+            style,
+            Baseline::Top,
+        )
+        .draw(screen);
+
+        let columns = (area.size.width / 6) as usize;
+        for (index, &byte) in self.test.prompt().iter().enumerate() {
+            let color = match self.test.state(index) {
+                CharState::Pending => Rgb565::WHITE,
+                CharState::Correct => Rgb565::GREEN,
+                CharState::Wrong => Rgb565::RED,
+            };
+            let mut char_style = MonoTextStyleBuilder::from(&style).text_color(color);
+            if index == self.test.position() {
+                char_style = char_style.background_color(Rgb565::new(0, 0, 16));
+            }
+            let mut text = [0; 4];
+            let text = (byte as char).encode_utf8(&mut text);
+            let pos = Point::new(
+                (index % columns) as i32 * 6,
+                (index / columns + 2) as i32 * 12,
+            );
+            let _ = Text::with_baseline(
+                text,
+                area.top_left + pos,
+                char_style.build(),
+                Baseline::Top,
+            )
+            .draw(screen);
+        }
+
+        let mut stats: heapless::String<40> = heapless::String::new();
+        let _ = write!(
+            stats,
+            "{} WPM  {}% accuracy{}",
+            self.test.wpm(now_us()),
+            self.test.accuracy(),
+            if self.test.is_done() { "  done" } else { "" }
+        );
+        let _ = Text::with_baseline(
+            &stats,
+            area.top_left + Point::new(0, 76),
+            style,
+            Baseline::Top,
+        )
+        .draw(screen);
+    }
+}
@@ -0,0 +1,345 @@
+//! Startup steps of `serial_echo`
+//!
+//! Each step starts a part of the hardware, following the boot mode selected by the buttons. See
+//! `main` for their dependencies and what their failure does.
+
+use super::commands::RebootTarget;
+use super::*;
+
+/// Select the boot mode, with the buttons held at power on and the watchdog resets
+pub(crate) fn start_buttons(context: &mut Startup) -> Result<(), &'static str> {
+    // Repeated watchdog resets select safe mode. Give the pull-ups some time before reading the
+    // buttons, which pull their pin low when pressed
+    context.delay.delay_ms(1);
+    let pressed = context
+        .buttons
+        .as_ref()
+        .map_or([false; 4], Buttons::pressed);
+    context.watchdog_resets = boot_mode::count_watchdog_resets();
+    let boot_mode = BootMode::select(pressed, context.watchdog_resets);
+    // After `reboot into`, come back up straight into the target, without the splash and the
+    // greeting. The other modes still win, so they stay reachable
+    context.reboot_target = boot_mode::take_reboot_target()
+        .and_then(RebootTarget::from_id)
+        .filter(|_| boot_mode == BootMode::Normal);
+    context.boot_mode = match context.reboot_target {
+        Some(RebootTarget::Headless) => BootMode::Headless,
+        _ => boot_mode,
+    };
+    if context.boot_mode == BootMode::Bootsel {
+        hal::rom_data::reset_to_usb_boot(0, 0);
+    }
+    diag_info!("boot: {:?} mode", context.boot_mode);
+    Ok(())
+}
+
+/// Stored settings are skipped in safe mode
+pub(crate) fn start_config(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode != BootMode::Safe {
+        context.settings = Settings::load();
+    }
+
+    // Name the device after the unique ID of the flash, unless another name was set
+    let name = context
+        .settings
+        .name
+        .unwrap_or_else(|| DeviceName::from_unique_id(&flash::unique_id()));
+    DEVICE_NAME.put(name);
+    context.name = Some(cortex_m::singleton!(: DeviceName = name).ok_or("already started")?);
+    Ok(())
+}
+
+/// Safe mode reads the stored lock on the first `unlock` instead, and stays locked until then,
+/// so holding a button doesn't skip the PIN
+pub(crate) fn start_lock(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode != BootMode::Safe {
+        context.lock = Lock::load();
+    }
+    Ok(())
+}
+
+/// The serial port of the console, then the log port and the HID keyboard used to type payloads
+pub(crate) fn start_usb(context: &mut Startup) -> Result<(), &'static str> {
+    USB_CONSOLE.put(PicoUsbConsole::new(SerialPort::new(context.bus)));
+
+    // The second serial port keeps the logs from mixing with the console
+    #[cfg(feature = "log-port")]
+    USB_LOG.put(PicoLogPort::new(context.bus, now_timestamp));
+
+    #[cfg(feature = "hid-payload")]
+    {
+        use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
+        let hid = usbd_hid::hid_class::HIDClass::new(context.bus, KeyboardReport::desc(), 10);
+        USB_HID.put(hid);
+    }
+    Ok(())
+}
+
+/// Mass storage to read the virtual files. Safe mode only starts what the console needs, in case
+/// one of the other parts is what hangs the firmware
+#[cfg(feature = "msc")]
+pub(crate) fn start_msc(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode == BootMode::Safe {
+        return Ok(());
+    }
+    USB_MSC.put(MassStorage::new(
+        context.bus,
+        VirtualFat::new(*b"RP2040     ", &FILES),
+    ));
+    Ok(())
+}
+
+/// USB device with a fake VID and PID, and the name as serial number so the host tells the
+/// boards apart, once all the classes are added
+pub(crate) fn start_usb_device(context: &mut Startup) -> Result<(), &'static str> {
+    let name = context.name.ok_or("no name")?;
+    // With the HID keyboard, the mass storage or the log port next to the serial port, the
+    // miscellaneous class groups the interfaces with interface associations
+    let composite = cfg!(any(feature = "hid-payload", feature = "log-port"))
+        || (cfg!(feature = "msc") && context.boot_mode != BootMode::Safe);
+    let usb_dev = UsbDeviceBuilder::new(context.bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("Fake company")
+        .product("Serial port")
+        .serial_number(name.as_str())
+        // from: https://www.usb.org/defined-class-codes
+        .device_class(if composite { 0xEF } else { 2 })
+        .device_sub_class(if composite { 0x02 } else { 0 })
+        .device_protocol(if composite { 0x01 } else { 0 })
+        .build();
+    USB_DEVICE.put(usb_dev);
+    Ok(())
+}
+
+/// Start the panel and greet on it, unless running headless
+pub(crate) fn start_display(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode == BootMode::Headless {
+        return Ok(());
+    }
+    let boot_mode = context.boot_mode;
+    let reboot_target = context.reboot_target;
+    let panel = context.panel.take().ok_or("taken")?;
+    let mut screen = panel.start(context.resets, context.delay);
+
+    // Show the selected mode for a moment
+    if boot_mode != BootMode::Normal {
+        Text::new(
+            boot_mode.name(),
+            Point::new(60, 125),
+            MonoTextStyle::new(&FONT_10X20, Rgb565::YELLOW),
+        )
+        .draw(&mut screen)
+        .unwrap();
+        context.delay.delay_ms(1000);
+        screen.clear(Rgb565::BLACK).unwrap();
+    }
+
+    // Cycle through the primary colors, to spot dead pixels or channels
+    if boot_mode == BootMode::SelfTest {
+        for color in [Rgb565::RED, Rgb565::GREEN, Rgb565::BLUE] {
+            screen.clear(color).unwrap();
+            context.delay.delay_ms(300);
+        }
+        screen.clear(Rgb565::BLACK).unwrap();
+    }
+
+    // Draw the uploaded splash, or ferris. Safe mode only shows the text of the terminal
+    if boot_mode != BootMode::Safe && reboot_target.is_none() {
+        let (data, width, frames) = sprite_sheet(boot_mode);
+        // A sprite sheet only shows its first frame
+        let splash: ImageRawLE<Rgb565> = ImageRaw::new(&data[..data.len() / frames], width);
+        Image::new(&splash, Point::new(40, 50))
+            .draw(&mut screen)
+            .unwrap();
+    }
+
+    // Setup the terminal, leaving the first line for the status bar and the bottom of the
+    // screen for the plot
+    let mut terminal = TerminalBuilder::new(screen)
+        .with_cursor(Rgb565::GREEN)
+        .with_wrap_marker(Rgb565::new(8, 16, 8))
+        .with_offset(Point::new(40, 69))
+        .with_size(Size::new(240, 80))
+        .build();
+    if let (None, Some(name)) = (reboot_target, context.name) {
+        let _ = writeln!(terminal, "Hello, world! I am {}", name);
+    }
+    if context.watchdog_resets >= boot_mode::MAX_WATCHDOG_RESETS {
+        let _ = writeln!(
+            terminal,
+            "Safe mode, after {} watchdog resets",
+            context.watchdog_resets
+        );
+    }
+    if let Some(conflict) = context.pin_conflict {
+        let _ = writeln!(terminal, "{}", conflict);
+    }
+    if let Some(fault) = context.boot_fault {
+        let _ = writeln!(terminal, "Fault {}: {}", fault.code(), fault.name());
+    }
+    if let Some(notes) = Asset::load(AssetKind::Notes) {
+        if boot_mode != BootMode::Safe && reboot_target.is_none() {
+            terminal.write(notes.data());
+        }
+    }
+    if Crash::load().is_some() {
+        terminal.write(b"Crashed before this boot, see `crash`\n");
+    }
+
+    if boot_mode == BootMode::SelfTest {
+        // The chip should be somewhere between freezing and too hot to touch
+        let _ = match context.adc.read(context.temp_sense) {
+            Ok(raw) => {
+                let temperature = rp2040_test::temperature_from_adc(raw);
+                let result = if (0..=600).contains(&temperature) {
+                    "OK"
+                } else {
+                    "FAIL"
+                };
+                writeln!(
+                    terminal,
+                    "Temperature sensor: {} ({}.{}C)",
+                    result,
+                    temperature / 10,
+                    (temperature % 10).abs()
+                )
+            }
+            Err(_) => writeln!(terminal, "Temperature sensor: FAIL"),
+        };
+    }
+
+    TERMINAL.put(terminal);
+    context
+        .show_progress
+        .set(boot_mode != BootMode::Safe && reboot_target.is_none());
+    Ok(())
+}
+
+pub(crate) fn start_backlight(context: &mut Startup) -> Result<(), &'static str> {
+    BACKLIGHT.put(Backlight::new(context.pwm, context.resets));
+    Ok(())
+}
+
+pub(crate) fn start_settings(context: &mut Startup) -> Result<(), &'static str> {
+    apply_settings(&context.settings);
+    Ok(())
+}
+
+/// Ferris plays in the corner of the quiet terminal, except in safe mode
+pub(crate) fn start_idle(context: &mut Startup) -> Result<(), &'static str> {
+    let mut idle = IdleAnimator::new(IDLE_EVERY_MS);
+    for animation in [BLINK, WAVE, HOP] {
+        let _ = idle.register(animation);
+    }
+    if context.boot_mode != BootMode::Safe {
+        idle.set_idle_after(Some(IDLE_AFTER_S), 0);
+    }
+    IDLE.put(idle);
+    Ok(())
+}
+
+/// Stored triggers are skipped in safe mode
+pub(crate) fn start_triggers(context: &mut Startup) -> Result<(), &'static str> {
+    let mut triggers = Triggers::new();
+    if context.boot_mode != BootMode::Safe {
+        triggers.load();
+    }
+    TRIGGERS.put(triggers);
+    Ok(())
+}
+
+/// Keys and events sent to the host, skipped in safe mode
+pub(crate) fn start_host_features(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode == BootMode::Safe {
+        return Ok(());
+    }
+    if cfg!(feature = "host-keys") {
+        ROUTES.set(Source::Buttons, Sink::Usb, true);
+    }
+    if cfg!(feature = "host-events") {
+        EVENTS.set_enabled(true);
+    }
+    Ok(())
+}
+
+/// The UART of the routes sends on GPIO28, as GPIO0 is the data out of the panel. It is left
+/// alone in safe mode
+#[cfg(feature = "uart-route")]
+pub(crate) fn start_uart(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode == BootMode::Safe {
+        return Ok(());
+    }
+    let (uart0, tx, rx, peripheral_clock) = context.uart_parts.take().ok_or("already started")?;
+    let mut uart = UartBridge::new(
+        uart0,
+        tx.into_mode(),
+        rx.into_mode(),
+        context.resets,
+        peripheral_clock,
+    );
+    uart.set_data_rate(UART_BAUD);
+    UART.put(uart);
+    UART_TX.put(heapless::Deque::new());
+    Ok(())
+}
+
+/// Safe mode leaves the capture PIO alone
+pub(crate) fn start_pulse_capture(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode == BootMode::Safe {
+        return Ok(());
+    }
+    let (pio, system_clock) = context.pulse_parts.take().ok_or("already started")?;
+    context.pulse_capture = Some(PulseCapture::new(
+        pio,
+        context.resets,
+        PULSE_GPIO,
+        system_clock,
+    ));
+    Ok(())
+}
+
+/// Safe mode leaves the RGB LED alone
+pub(crate) fn start_rgb_led(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode != BootMode::Safe {
+        context.rgb_led = Some(RgbLed::new(context.pwm, context.resets));
+    }
+    Ok(())
+}
+
+/// Safe mode leaves the data log alone
+pub(crate) fn start_data_log(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode != BootMode::Safe {
+        context.data_log = Some(DataLog::open());
+    }
+    Ok(())
+}
+
+/// The OLED shows the same segments as the status bar, when one is connected. It is left alone
+/// in safe mode, in case it is what hangs the firmware.
+#[cfg(feature = "oled")]
+pub(crate) fn start_oled(context: &mut Startup) -> Result<(), &'static str> {
+    if context.boot_mode == BootMode::Safe {
+        return Ok(());
+    }
+    let (i2c, sda, scl, system_clock) = context.oled_parts.take().ok_or("already started")?;
+    let oled = oled::init(i2c, sda, scl, context.resets, system_clock)
+        .map_err(|_| "no answer on I2C1")?;
+    context.oled = Some(oled);
+    Ok(())
+}
+
+/// Fill the bar under the splash as the startup steps end, or clear it with `None`
+pub(crate) fn draw_startup_progress(progress: Option<&Progress<&'static str>>) {
+    let bar = Rectangle::new(Point::new(40, 185), Size::new(240, 3));
+    let (width, color) = match progress {
+        Some(progress) => (
+            bar.size.width * progress.done as u32 / progress.total as u32,
+            Rgb565::GREEN,
+        ),
+        None => (bar.size.width, Rgb565::BLACK),
+    };
+    TERMINAL.lock(|terminal| {
+        let _ = Rectangle::new(bar.top_left, Size::new(width, bar.size.height))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(terminal.screen_mut());
+    });
+}
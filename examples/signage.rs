@@ -0,0 +1,74 @@
+//! # Pico Display Signage Example
+//!
+//! Shows a static sign on the Pico Display: Ferris and a few lines of text. Nothing else runs
+//! after the sign is drawn.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+use embedded_graphics::{
+    image::{Image, ImageRaw, ImageRawLE},
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle,
+    },
+    pixelcolor::{Rgb565, RgbColor},
+    prelude::*,
+    text::Text,
+};
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+use rp2040_test::board::Board;
+
+static FERRIS: &[u8] = include_bytes!("../ferris.raw");
+
+/// Title of the sign.
+static TITLE: &str = "Hello, Rust!";
+
+/// Lines of text below the title.
+static LINES: &[&str] = &["Running on an RP2040", "with a Pico Display"];
+
+/// Entry point to our bare-metal application.
+///
+/// The function configures the RP2040 peripherals, draws the sign, then waits
+/// for interrupts in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the pins and the display
+    let mut board = Board::take().unwrap();
+    let mut screen = board.display().unwrap();
+
+    // Draw ferris
+    let ferris: ImageRawLE<Rgb565> = ImageRaw::new(FERRIS, 64);
+    Image::new(&ferris, Point::new(40, 50))
+        .draw(&mut screen)
+        .unwrap();
+
+    // Draw the text next to ferris
+    let title_style = MonoTextStyle::new(&FONT_10X20, Rgb565::YELLOW);
+    Text::new(TITLE, Point::new(110, 80), title_style)
+        .draw(&mut screen)
+        .unwrap();
+
+    let line_style = MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE);
+    for (i, line) in LINES.iter().enumerate() {
+        Text::new(line, Point::new(110, 100 + 12 * i as i32), line_style)
+            .draw(&mut screen)
+            .unwrap();
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+// End of file
@@ -0,0 +1,61 @@
+//! # Pico Display Split Console Example
+//!
+//! Echoes the data received over USB serial back to the host and shows it on the
+//! Pico Display, drawn by core 1 so core 0 only has the USB device to serve.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+use rp2040_test::board::Board;
+use rp2040_test::render_core::Renderer;
+
+/// Entry point to our bare-metal application.
+///
+/// The function starts core 1 with the terminal, then polls the USB device and
+/// queues the data received for core 1 in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the pins, the display and the USB serial port
+    let mut board = Board::take().unwrap();
+    let terminal = board.terminal().unwrap();
+    let mut console = board.serial();
+    let mut usb_dev = board.usb_device();
+
+    let Board {
+        mut psm,
+        mut ppb,
+        fifo,
+        timer,
+        ..
+    } = board;
+    let mut renderer = Renderer::spawn(terminal, &mut psm, &mut ppb, fifo).unwrap();
+
+    loop {
+        let now = timer.get_counter();
+        usb_dev.poll(&mut [console.port()]);
+        let _ = console.poll(now);
+
+        // Only read what core 1 has room for, the rest waits in the USB buffers
+        let mut buf = [0u8; 64];
+        let room = renderer.space().min(buf.len());
+        if room == 0 {
+            continue;
+        }
+        if let Ok(count) = console.read(&mut buf[..room]) {
+            let _ = console.write(&buf[..count], now);
+            renderer.write(&buf[..count]);
+        }
+    }
+}
+
+// End of file
@@ -0,0 +1,90 @@
+//! # Pico Display Tachometer Example
+//!
+//! Counts the pulses of a fan or motor tachometer output wired to GPIO11, shows
+//! the RPM on a gauge on the Pico Display, and sends it to the host over USB
+//! serial as one `rpm,<value>` line every half second.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
+    pixelcolor::{Rgb565, RgbColor},
+    prelude::*,
+};
+
+// Formatting into fixed-size strings
+use core::fmt::Write;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+use rp2040_test::board::Board;
+use rp2040_test::gauge::Gauge;
+use rp2040_test::tachometer::Tachometer;
+
+/// Pulses sent by the fan on each revolution, 2 for most PC fans.
+const PULSES_PER_REV: u32 = 2;
+
+/// RPM at the end of the gauge.
+const MAX_RPM: u32 = 3000;
+
+/// Gate interval of the pulse count.
+const GATE_US: u64 = 500_000;
+
+/// Entry point to our bare-metal application.
+///
+/// The function configures the RP2040 peripherals, then polls the USB device
+/// and updates the gauge in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the pins, the display and the USB serial port
+    let mut board = Board::take().unwrap();
+    let mut screen = board.display().unwrap();
+    let mut console = board.serial();
+    let mut usb_dev = board.usb_device();
+
+    let gauge = Gauge::new(
+        Point::new(160, 120),
+        120,
+        MAX_RPM,
+        "RPM",
+        MonoTextStyleBuilder::new()
+            .font(&FONT_10X20)
+            .text_color(Rgb565::WHITE)
+            .background_color(Rgb565::BLACK)
+            .build(),
+        Rgb565::GREEN,
+        Rgb565::new(4, 8, 4),
+    );
+    gauge.draw(&mut screen, 0).unwrap();
+
+    let now = board.timer.get_counter();
+    let mut tachometer = Tachometer::new(&board.pwm, &mut board.resets, PULSES_PER_REV, now);
+    let mut gate_start = now;
+    loop {
+        let now = board.timer.get_counter();
+        usb_dev.poll(&mut [console.port()]);
+        let _ = console.poll(now);
+
+        if now - gate_start >= GATE_US {
+            gate_start = now;
+            let rpm = tachometer.sample(&board.pwm, now);
+            gauge.draw(&mut screen, rpm).unwrap();
+
+            let mut line: heapless::String<24> = heapless::String::new();
+            let _ = write!(line, "rpm,{}\r\n", rpm);
+            // Dropped while no host is reading
+            let _ = console.write(line.as_bytes(), now);
+        }
+    }
+}
+
+// End of file
@@ -0,0 +1,132 @@
+//! # Pico USB to UART Bridge Example
+//!
+//! Forwards the data between a USB serial port and UART0 on GPIO0 (TX) and GPIO1 (RX),
+//! applying the baud rate, parity and stop bits set by the host to the UART.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use cortex_m_rt::entry;
+
+// Time handling traits
+use embedded_time::rate::*;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+#[cfg(not(feature = "panic-display"))]
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use rp2040_test::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use rp2040_test::hal;
+
+use rp2040_test::pin_owners::{PinOwner, PinOwners};
+use rp2040_test::uart_bridge::UartBridge;
+use rp2040_test::unused_pins::PinProfile;
+
+// USB Device support
+use usb_device::{class_prelude::*, prelude::*};
+
+// USB Communications Class Device support
+use usbd_serial::SerialPort;
+
+/// Pins of this example, giving one twice fails to compile
+const PIN_OWNERS: PinOwners = PinOwners::new()
+    .with(1 << 23 | 1 << 24 | 1 << 29, PinOwner::Board)
+    .with(1 << 25, PinOwner::Led)
+    .with(0x3, PinOwner::UartBridge);
+
+/// Entry point to our bare-metal application.
+///
+/// The function configures the RP2040 peripherals, then polls the USB device
+/// and the UART in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::watchdog::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    let clocks = hal::clocks::init_clocks_and_plls(
+        rp2040_test::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // Set up the USB driver
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+    let mut serial = SerialPort::new(&usb_bus);
+
+    // Create a USB device with a fake VID and PID
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("Fake company")
+        .product("UART bridge")
+        .serial_number("TEST")
+        .device_class(2) // from: https://www.usb.org/defined-class-codes
+        .build();
+
+    // Set the pins up according to their function on this particular board
+    let sio = hal::sio::Sio::new(pac.SIO);
+    let pins = rp2040_test::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let mut bridge = UartBridge::new(
+        pac.UART0,
+        pins.gpio0.into_mode(),
+        pins.gpio1.into_mode(),
+        &mut pac.RESETS,
+        clocks.peripheral_clock.freq().integer(),
+    );
+    PinProfile::PICO.with_used(PIN_OWNERS.used()).park_unused();
+
+    // Bytes read from one side that the other side hasn't taken yet
+    let mut to_uart = [0u8; 64];
+    let (mut to_uart_start, mut to_uart_end) = (0, 0);
+    let mut to_usb = [0u8; 64];
+    let (mut to_usb_start, mut to_usb_end) = (0, 0);
+    loop {
+        usb_dev.poll(&mut [&mut serial]);
+        bridge.apply(serial.line_coding());
+
+        if to_uart_start == to_uart_end {
+            to_uart_start = 0;
+            to_uart_end = serial.read(&mut to_uart).unwrap_or(0);
+        }
+        to_uart_start += bridge.write(&to_uart[to_uart_start..to_uart_end]);
+
+        if to_usb_start == to_usb_end {
+            to_usb_start = 0;
+            to_usb_end = bridge.read(&mut to_usb);
+        }
+        if to_usb_start < to_usb_end {
+            to_usb_start += serial.write(&to_usb[to_usb_start..to_usb_end]).unwrap_or(0);
+        }
+    }
+}
+
+// End of file
@@ -0,0 +1,153 @@
+//! ANSI escape sequence parser
+//!
+//! Splits a stream of bytes into printable characters, control characters and escape
+//! sequences, loosely following the VT100 state machine.
+
+/// Maximum number of parameters kept for a control sequence
+pub const MAX_PARAMS: usize = 8;
+
+/// Control sequence (`ESC [ ...`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Csi {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    /// More than `MAX_PARAMS` parameters were received, the digits of the extra ones are ignored
+    overflow: bool,
+    /// The sequence had a `?` prefix, for DEC private modes
+    pub private: bool,
+    /// Final byte of the sequence
+    pub action: u8,
+}
+
+impl Csi {
+    const fn new() -> Self {
+        Self {
+            params: [0; MAX_PARAMS],
+            len: 0,
+            overflow: false,
+            private: false,
+            action: 0,
+        }
+    }
+
+    /// Parameters of the sequence
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /// Parameter `index`, or `default` if it is missing or zero
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+/// Parsed item of the byte stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// The byte is part of an unfinished sequence
+    None,
+    /// Printable character
+    Print(u8),
+    /// C0 control character or delete
+    Control(u8),
+    /// Escape sequence other than a control sequence (`ESC x`)
+    Escape(u8),
+    /// Control sequence
+    Csi(Csi),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+/// Escape sequence parser
+pub struct Parser {
+    state: State,
+    csi: Csi,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            csi: Csi::new(),
+        }
+    }
+
+    /// Feed a byte to the parser
+    pub fn advance(&mut self, byte: u8) -> Action {
+        // CAN and SUB abort any sequence
+        if byte == 0x18 || byte == 0x1A {
+            self.state = State::Ground;
+            return Action::None;
+        }
+
+        match self.state {
+            State::Ground => match byte {
+                0x1B => {
+                    self.state = State::Escape;
+                    Action::None
+                }
+                0x00..=0x1F | 0x7F => Action::Control(byte),
+                _ => Action::Print(byte),
+            },
+            State::Escape => match byte {
+                b'[' => {
+                    self.state = State::Csi;
+                    self.csi = Csi::new();
+                    Action::None
+                }
+                0x30..=0x7E => {
+                    self.state = State::Ground;
+                    Action::Escape(byte)
+                }
+                // Intermediate bytes are ignored
+                _ => Action::None,
+            },
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    if self.csi.overflow {
+                        return Action::None;
+                    }
+                    if self.csi.len == 0 {
+                        self.csi.len = 1;
+                    }
+                    let param = &mut self.csi.params[self.csi.len - 1];
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as u16);
+                    Action::None
+                }
+                b';' => {
+                    if self.csi.len == 0 {
+                        self.csi.len = 1;
+                    }
+                    // Extra parameters are dropped
+                    if self.csi.len < MAX_PARAMS {
+                        self.csi.len += 1;
+                    } else {
+                        self.csi.overflow = true;
+                    }
+                    Action::None
+                }
+                b'?' => {
+                    self.csi.private = true;
+                    Action::None
+                }
+                0x40..=0x7E => {
+                    self.state = State::Ground;
+                    self.csi.action = byte;
+                    Action::Csi(self.csi)
+                }
+                // Other parameter and intermediate bytes are ignored
+                _ => Action::None,
+            },
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Assets in flash
+//!
+//! Data such as the boot splash, replaced from the host without flashing the firmware again.
+//! Each asset has two slots: an upload goes to the slot not in use, and its header is written
+//! last, once the data is verified, so an interrupted upload leaves the previous asset in place.
+
+use crate::flash::{self, checksum, PAGE_SIZE};
+use crate::hash::Crc32;
+
+/// Start of the asset slots, from the start of the flash
+pub const ASSETS_OFFSET: u32 = 0x1A_0000;
+/// Size of a slot, including its header page
+pub const SLOT_SIZE: u32 = 0x8000;
+/// Size of the slots of all the assets
+pub const ASSETS_SIZE: u32 = 4 * SLOT_SIZE;
+/// Largest asset
+pub const MAX_ASSET: usize = (SLOT_SIZE - PAGE_SIZE) as usize;
+
+const MAGIC: [u8; 4] = *b"ASET";
+/// Header: magic, sequence, length, CRC-32 of the data, parameter and checksum
+const HEADER_SIZE: usize = 20;
+
+/// Assets that can be replaced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    /// Image shown at boot, in little endian RGB565, with its width as parameter
+    Splash,
+    /// Text written to the terminal at boot
+    Notes,
+}
+
+impl AssetKind {
+    pub fn id(self) -> u8 {
+        match self {
+            AssetKind::Splash => 0,
+            AssetKind::Notes => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(AssetKind::Splash),
+            1 => Some(AssetKind::Notes),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssetKind::Splash => "splash",
+            AssetKind::Notes => "notes",
+        }
+    }
+
+    /// Offset of one of the two slots of the asset
+    fn slot_offset(self, slot: usize) -> u32 {
+        ASSETS_OFFSET + (self.id() as u32 * 2 + slot as u32) * SLOT_SIZE
+    }
+}
+
+/// Asset stored in flash
+pub struct Asset {
+    pub kind: AssetKind,
+    /// Number of the upload, the newest asset of the two slots is used
+    pub sequence: u32,
+    /// Depends on the kind, such as the width of an image
+    pub param: u16,
+    slot: usize,
+    data: &'static [u8],
+}
+
+impl Asset {
+    /// Load the newest valid asset of a kind, if there is one
+    pub fn load(kind: AssetKind) -> Option<Self> {
+        let load = |slot| Self::load_slot(kind, slot).and_then(Result::ok);
+        match (load(0), load(1)) {
+            (Some(first), Some(second)) if second.sequence > first.sequence => Some(second),
+            (Some(first), _) => Some(first),
+            (None, second) => second,
+        }
+    }
+
+    /// Returns true if a slot of `kind` has a valid header, but data not matching its CRC-32
+    ///
+    /// The other slot or the built-in asset is used instead, but the flash may be wearing out.
+    pub fn is_corrupt(kind: AssetKind) -> bool {
+        (0..2).any(|slot| matches!(Self::load_slot(kind, slot), Some(Err(CorruptSlot))))
+    }
+
+    /// Asset of a slot, `None` if it has no valid header
+    fn load_slot(kind: AssetKind, slot: usize) -> Option<Result<Self, CorruptSlot>> {
+        let offset = kind.slot_offset(slot);
+        let header = flash::read(offset, HEADER_SIZE);
+        if header[..4] != MAGIC {
+            return None;
+        }
+        let sum = u16::from_le_bytes([header[18], header[19]]);
+        if checksum(&header[4..18]) != sum {
+            return None;
+        }
+        let word = |at: usize| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+        let len = word(8) as usize;
+        if len > MAX_ASSET {
+            return None;
+        }
+        // The data is checked on every load, so a worn out flash shows the previous asset
+        let data = flash::read(offset + PAGE_SIZE, len);
+        if crc32(data) != word(12) {
+            crate::diag_warn!("assets: {} slot {} is corrupt", kind.name(), slot);
+            return Some(Err(CorruptSlot));
+        }
+
+        Some(Ok(Self {
+            kind,
+            sequence: word(4),
+            param: u16::from_le_bytes([header[16], header[17]]),
+            slot,
+            data,
+        }))
+    }
+
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+}
+
+/// Slot whose data doesn't match the CRC-32 of its header
+struct CorruptSlot;
+
+/// Reasons an upload is refused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadError {
+    /// The asset doesn't fit in a slot
+    TooLarge,
+    /// More data was sent than announced
+    Overflow,
+    /// Less data was sent than announced
+    Incomplete,
+    /// The data in flash doesn't match the announced CRC-32
+    Mismatch,
+}
+
+impl UploadError {
+    pub fn name(self) -> &'static str {
+        match self {
+            UploadError::TooLarge => "too large",
+            UploadError::Overflow => "overflow",
+            UploadError::Incomplete => "incomplete",
+            UploadError::Mismatch => "crc mismatch",
+        }
+    }
+}
+
+/// Asset being written to the slot not in use
+///
+/// The data must be written in order. Nothing changes for `Asset::load` until `commit`.
+pub struct Upload {
+    kind: AssetKind,
+    offset: u32,
+    sequence: u32,
+    len: usize,
+    crc: u32,
+    param: u16,
+    written: usize,
+    page: [u8; PAGE_SIZE as usize],
+}
+
+impl Upload {
+    /// Start an upload of `len` bytes with a CRC-32 of `crc`, erasing the slot not in use
+    pub fn begin(kind: AssetKind, len: usize, crc: u32, param: u16) -> Result<Self, UploadError> {
+        if len > MAX_ASSET {
+            return Err(UploadError::TooLarge);
+        }
+        let (slot, sequence) = match Asset::load(kind) {
+            Some(current) => (1 - current.slot, current.sequence.wrapping_add(1)),
+            None => (0, 1),
+        };
+        let offset = kind.slot_offset(slot);
+        // Note (safety): the slot is reserved for the asset, and not in use
+        unsafe { flash::erase(offset, SLOT_SIZE) };
+
+        Ok(Self {
+            kind,
+            offset,
+            sequence,
+            len,
+            crc,
+            param,
+            written: 0,
+            page: [0xFF; PAGE_SIZE as usize],
+        })
+    }
+
+    pub fn kind(&self) -> AssetKind {
+        self.kind
+    }
+
+    /// Number of bytes written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Append data to the asset, programming the flash a page at a time
+    pub fn write(&mut self, data: &[u8]) -> Result<(), UploadError> {
+        if self.written + data.len() > self.len {
+            return Err(UploadError::Overflow);
+        }
+        for &byte in data {
+            let index = self.written % PAGE_SIZE as usize;
+            self.page[index] = byte;
+            self.written += 1;
+            if index == PAGE_SIZE as usize - 1 {
+                self.flush();
+            }
+        }
+        Ok(())
+    }
+
+    /// Program the page being filled, the first page of the slot being the header
+    fn flush(&mut self) {
+        let page = (self.written - 1) / PAGE_SIZE as usize + 1;
+        // Note (safety): the slot is reserved for the asset, and not in use
+        unsafe { flash::program(self.offset + page as u32 * PAGE_SIZE, &self.page) };
+        self.page = [0xFF; PAGE_SIZE as usize];
+    }
+
+    /// Verify the data in flash, and write the header to make it the current asset
+    pub fn commit(mut self) -> Result<(), UploadError> {
+        if self.written != self.len {
+            return Err(UploadError::Incomplete);
+        }
+        if self.written % PAGE_SIZE as usize != 0 {
+            self.flush();
+        }
+        if crc32(flash::read(self.offset + PAGE_SIZE, self.len)) != self.crc {
+            return Err(UploadError::Mismatch);
+        }
+
+        let mut header = [0xFF; PAGE_SIZE as usize];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        header[8..12].copy_from_slice(&(self.len as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&self.crc.to_le_bytes());
+        header[16..18].copy_from_slice(&self.param.to_le_bytes());
+        let sum = checksum(&header[4..18]);
+        header[18..20].copy_from_slice(&sum.to_le_bytes());
+        // Note (safety): the slot is reserved for the asset, and not in use
+        unsafe { flash::program(self.offset, &header) };
+        Ok(())
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
@@ -0,0 +1,115 @@
+//! Backlight of the Pico Display
+//!
+//! Dims the backlight on GPIO20 with channel A of PWM slice 2, and can dim it further once the
+//! terminal has been idle for a while, waking it on the next activity.
+
+use crate::pac;
+
+/// GPIO of the backlight, the A channel of PWM slice 2
+pub const BACKLIGHT_GPIO: usize = 20;
+const SLICE: usize = 2;
+
+/// Function select of a GPIO for its PWM channel
+const FUNCSEL_PWM: u8 = 4;
+
+/// Counter wrap of the slice, so a brightness squared is its level, which looks more even
+const TOP: u16 = 255 * 255;
+
+/// Brightness while idle, low enough to save power and still show the text
+pub const IDLE_BRIGHTNESS: u8 = 16;
+
+/// Driver of the backlight
+pub struct Backlight {
+    brightness: u8,
+    /// Time without activity before dimming, `None` to never dim
+    idle_timeout_ms: Option<u32>,
+    last_activity_ms: u32,
+    idle: bool,
+}
+
+impl Backlight {
+    /// Set up the PWM slice of the backlight, at full brightness
+    ///
+    /// Only slice 2 of `pwm` is used, the other slices are left alone. Slice 2 also drives
+    /// GPIO21, which is left as it is.
+    pub fn new(pwm: &pac::PWM, resets: &mut pac::RESETS) -> Self {
+        resets.reset.modify(|_, w| w.pwm().clear_bit());
+        while resets.reset_done.read().pwm().bit_is_clear() {}
+
+        let ch = &pwm.ch[SLICE];
+        ch.top.write(|w| unsafe { w.top().bits(TOP) });
+        ch.cc.modify(|_, w| unsafe { w.a().bits(TOP) });
+        ch.csr.modify(|_, w| w.en().set_bit());
+
+        // Note (safety): only the registers of the backlight pin are written
+        let io = unsafe { &*pac::IO_BANK0::ptr() };
+        io.gpio[BACKLIGHT_GPIO]
+            .gpio_ctrl
+            .write(|w| unsafe { w.funcsel().bits(FUNCSEL_PWM) });
+
+        Self {
+            brightness: 255,
+            idle_timeout_ms: None,
+            last_activity_ms: 0,
+            idle: false,
+        }
+    }
+
+    /// Set the brightness, from 0 for off to 255 for full brightness
+    ///
+    /// While idle, this is kept for when the screen wakes up.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+        if !self.idle {
+            self.write(brightness);
+        }
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Dim the screen after `seconds` without activity, or never with `None`
+    pub fn set_idle_timeout(&mut self, seconds: Option<u32>, now_ms: u32) {
+        self.idle_timeout_ms = seconds.map(|seconds| seconds.saturating_mul(1000));
+        self.activity(now_ms);
+    }
+
+    /// Seconds without activity before dimming, `None` if the screen is never dimmed
+    pub fn idle_timeout(&self) -> Option<u32> {
+        self.idle_timeout_ms.map(|ms| ms / 1000)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Record activity on the terminal at `now_ms`, waking the screen if it was dimmed
+    pub fn activity(&mut self, now_ms: u32) {
+        self.last_activity_ms = now_ms;
+        if self.idle {
+            self.idle = false;
+            self.write(self.brightness);
+        }
+    }
+
+    /// Dim the screen if the idle timeout passed at `now_ms`, in the time base of `activity`
+    pub fn update(&mut self, now_ms: u32) {
+        let timeout_ms = match self.idle_timeout_ms {
+            Some(timeout_ms) => timeout_ms,
+            None => return,
+        };
+        if !self.idle && now_ms.wrapping_sub(self.last_activity_ms) >= timeout_ms {
+            self.idle = true;
+            self.write(self.brightness.min(IDLE_BRIGHTNESS));
+        }
+    }
+
+    fn write(&self, brightness: u8) {
+        let level = brightness as u16 * brightness as u16;
+        // Note (safety): slice 2 was given to the backlight in `new`, and only channel A is
+        // written
+        let pwm = unsafe { &*pac::PWM::ptr() };
+        pwm.ch[SLICE].cc.modify(|_, w| unsafe { w.a().bits(level) });
+    }
+}
@@ -0,0 +1,322 @@
+//! Backup and restore of the configuration
+//!
+//! Streams the configuration sectors and the asset slots to the host as frames, and writes the
+//! same frames back, to clone a configured board or recover it after a reflash. Erased sectors
+//! aren't sent, and a restore only rewrites the sectors that differ.
+
+use crate::assets::{ASSETS_OFFSET, ASSETS_SIZE};
+use crate::config::{CONFIG_OFFSET, CONFIG_SIZE};
+use crate::flash::{self, SECTOR_SIZE};
+use crate::hash::Crc32;
+use crate::switch::SWITCHES_OFFSET;
+use crate::triggers::TRIGGERS_OFFSET;
+
+/// Version of the frames, in the header of a backup
+pub const VERSION: u8 = 3;
+
+/// Data bytes in a `D` frame
+const CHUNK: usize = 60;
+/// Largest frame: `D` and the data
+pub const MAX_FRAME: usize = 1 + CHUNK;
+
+/// Areas backed up, as offset and length
+///
+/// The lock isn't, so a backup doesn't carry the PIN to another board.
+const AREAS: [(u32, u32); 4] = [
+    (TRIGGERS_OFFSET, SECTOR_SIZE),
+    (CONFIG_OFFSET, CONFIG_SIZE),
+    (SWITCHES_OFFSET, SECTOR_SIZE),
+    (ASSETS_OFFSET, ASSETS_SIZE),
+];
+
+const fn sector_count() -> usize {
+    let (mut count, mut area) = (0, 0);
+    while area < AREAS.len() {
+        count += (AREAS[area].1 / SECTOR_SIZE) as usize;
+        area += 1;
+    }
+    count
+}
+
+/// Number of sectors in the areas
+const SECTOR_COUNT: usize = sector_count();
+
+/// Offset of a sector of the areas, by index
+fn sector_offset(index: usize) -> Option<u32> {
+    AREAS
+        .iter()
+        .flat_map(|&(offset, len)| (0..len / SECTOR_SIZE).map(move |n| offset + n * SECTOR_SIZE))
+        .nth(index)
+}
+
+fn sector_index(offset: u32) -> Option<usize> {
+    (0..SECTOR_COUNT).find(|&index| sector_offset(index) == Some(offset))
+}
+
+fn is_erased(offset: u32) -> bool {
+    flash::read(offset, SECTOR_SIZE as usize)
+        .iter()
+        .all(|&byte| byte == 0xFF)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Splits the areas into frames for the host
+///
+/// The frames are `H`, the version and the number of sectors as a 16-bit little endian number,
+/// then for each sector not erased `S` with its offset and CRC-32 as 32-bit little endian
+/// numbers, followed by `D` frames with its data, and finally `F`.
+pub struct Backup {
+    /// Sectors not erased, counted when the backup starts
+    count: u16,
+    started: bool,
+    /// Index of the sector being sent
+    sector: usize,
+    /// Bytes of the sector already sent, `None` before its `S` frame
+    sent: Option<usize>,
+    finished: bool,
+}
+
+impl Backup {
+    pub fn new() -> Self {
+        let count = (0..SECTOR_COUNT)
+            .filter_map(sector_offset)
+            .filter(|&offset| !is_erased(offset))
+            .count();
+        Self {
+            count: count as u16,
+            started: false,
+            sector: 0,
+            sent: None,
+            finished: false,
+        }
+    }
+
+    /// Number of sectors in the backup
+    pub fn sectors(&self) -> u16 {
+        self.count
+    }
+
+    /// Write the next frame to `out`, returning its length, or `None` once `F` was sent
+    pub fn next_frame(&mut self, out: &mut [u8; MAX_FRAME]) -> Option<usize> {
+        if !self.started {
+            self.started = true;
+            out[0] = b'H';
+            out[1] = VERSION;
+            out[2..4].copy_from_slice(&self.count.to_le_bytes());
+            return Some(4);
+        }
+
+        // The erased sectors are left out, a restore erases them on its side
+        while self.sent.is_none() && sector_offset(self.sector).map_or(false, is_erased) {
+            self.sector += 1;
+        }
+        let offset = match sector_offset(self.sector) {
+            Some(offset) => offset,
+            None if self.finished => return None,
+            None => {
+                self.finished = true;
+                out[0] = b'F';
+                return Some(1);
+            }
+        };
+
+        let data = flash::read(offset, SECTOR_SIZE as usize);
+        match self.sent {
+            None => {
+                out[0] = b'S';
+                out[1..5].copy_from_slice(&offset.to_le_bytes());
+                out[5..9].copy_from_slice(&crc32(data).to_le_bytes());
+                self.sent = Some(0);
+                Some(9)
+            }
+            Some(sent) => {
+                let chunk = &data[sent..data.len().min(sent + CHUNK)];
+                out[0] = b'D';
+                out[1..=chunk.len()].copy_from_slice(chunk);
+                if sent + chunk.len() == data.len() {
+                    self.sector += 1;
+                    self.sent = None;
+                } else {
+                    self.sent = Some(sent + chunk.len());
+                }
+                Some(1 + chunk.len())
+            }
+        }
+    }
+}
+
+impl Default for Backup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reasons a restore is stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The backup was made with other frames
+    Version,
+    /// A frame came before the header, or data before its sector
+    Order,
+    /// The sector isn't in the areas backed up
+    UnknownSector,
+    /// More data was sent than a sector holds
+    Overflow,
+    /// A sector, or the backup, ended early
+    Incomplete,
+    /// The data of a sector doesn't match its CRC-32
+    Mismatch,
+    BadFrame,
+}
+
+impl RestoreError {
+    pub fn name(self) -> &'static str {
+        match self {
+            RestoreError::Version => "unknown version",
+            RestoreError::Order => "out of order",
+            RestoreError::UnknownSector => "unknown sector",
+            RestoreError::Overflow => "overflow",
+            RestoreError::Incomplete => "incomplete",
+            RestoreError::Mismatch => "crc mismatch",
+            RestoreError::BadFrame => "bad frame",
+        }
+    }
+}
+
+/// What a restore changed in flash
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RestoreSummary {
+    /// Sectors rewritten, because they differed from the backup
+    pub written: u16,
+    /// Sectors already matching the backup
+    pub unchanged: u16,
+    /// Sectors erased, because they were erased on the board backed up
+    pub erased: u16,
+}
+
+/// Sector being received
+struct PendingSector {
+    offset: u32,
+    crc: u32,
+    len: usize,
+    data: [u8; SECTOR_SIZE as usize],
+}
+
+/// Writes the frames of a backup back to flash
+///
+/// Each sector is verified before it is written, so a corrupted backup stops before changing
+/// it. The sectors left out of the backup are only erased once all the others are in.
+pub struct Restore {
+    /// Sectors announced by the header, `None` before it
+    expected: Option<u16>,
+    received: [bool; SECTOR_COUNT],
+    sector: Option<PendingSector>,
+    summary: RestoreSummary,
+}
+
+impl Restore {
+    pub fn new() -> Self {
+        Self {
+            expected: None,
+            received: [false; SECTOR_COUNT],
+            sector: None,
+            summary: RestoreSummary::default(),
+        }
+    }
+
+    /// Handle a frame of the backup, returning what changed once `F` completes it
+    pub fn frame(&mut self, frame: &[u8]) -> Result<Option<RestoreSummary>, RestoreError> {
+        match frame.split_first() {
+            Some((&b'H', &[version, low, high])) => {
+                if version != VERSION {
+                    return Err(RestoreError::Version);
+                }
+                *self = Self::new();
+                self.expected = Some(u16::from_le_bytes([low, high]));
+                Ok(None)
+            }
+            _ if self.expected.is_none() => Err(RestoreError::Order),
+            Some((&b'S', args)) if args.len() == 8 => {
+                if self.sector.is_some() {
+                    return Err(RestoreError::Incomplete);
+                }
+                let word = |at: usize| {
+                    u32::from_le_bytes([args[at], args[at + 1], args[at + 2], args[at + 3]])
+                };
+                let offset = word(0);
+                sector_index(offset).ok_or(RestoreError::UnknownSector)?;
+                self.sector = Some(PendingSector {
+                    offset,
+                    crc: word(4),
+                    len: 0,
+                    data: [0xFF; SECTOR_SIZE as usize],
+                });
+                Ok(None)
+            }
+            Some((&b'D', data)) => {
+                let sector = self.sector.as_mut().ok_or(RestoreError::Order)?;
+                let end = sector.len + data.len();
+                if end > SECTOR_SIZE as usize {
+                    return Err(RestoreError::Overflow);
+                }
+                sector.data[sector.len..end].copy_from_slice(data);
+                sector.len = end;
+                if end == SECTOR_SIZE as usize {
+                    self.write_sector()?;
+                }
+                Ok(None)
+            }
+            Some((&b'F', [])) => {
+                let received = self.received.iter().filter(|&&received| received).count();
+                if self.sector.is_some() || Some(received as u16) != self.expected {
+                    return Err(RestoreError::Incomplete);
+                }
+                for index in (0..SECTOR_COUNT).filter(|&index| !self.received[index]) {
+                    let offset = sector_offset(index).unwrap();
+                    if !is_erased(offset) {
+                        // Note (safety): the sector is in the areas backed up, which hold data
+                        // read from flash again on the next boot
+                        unsafe { flash::erase(offset, SECTOR_SIZE) };
+                        self.summary.erased += 1;
+                    }
+                }
+                Ok(Some(self.summary))
+            }
+            _ => Err(RestoreError::BadFrame),
+        }
+    }
+
+    /// Verify the sector received, and write it if it differs from the flash
+    fn write_sector(&mut self) -> Result<(), RestoreError> {
+        let sector = self.sector.take().ok_or(RestoreError::Order)?;
+        if crc32(&sector.data) != sector.crc {
+            return Err(RestoreError::Mismatch);
+        }
+        if flash::read(sector.offset, SECTOR_SIZE as usize) == sector.data {
+            self.summary.unchanged += 1;
+        } else {
+            // Note (safety): the sector is in the areas backed up, which hold data read from
+            // flash again on the next boot
+            unsafe {
+                flash::erase(sector.offset, SECTOR_SIZE);
+                flash::program(sector.offset, &sector.data);
+            }
+            self.summary.written += 1;
+        }
+        if let Some(index) = sector_index(sector.offset) {
+            self.received[index] = true;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Restore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,85 @@
+//! Emulated serial link speed
+//!
+//! USB CDC ignores the baud rate set by the host. `BaudLimiter` paces the data so it flows
+//! as fast as it would on a real serial link with the same line coding.
+
+use usbd_serial::{ParityType, StopBits};
+
+/// Maximum number of characters that can be sent in a single burst
+const MAX_BURST: u32 = 64;
+
+/// Token bucket limiting throughput to the host-set baud rate
+pub struct BaudLimiter {
+    data_rate: u32,
+    bits_per_char: u32,
+    /// Available credit, in bits
+    credit: u64,
+    /// Timestamp of the last refill, in microseconds
+    last_refill: u64,
+}
+
+impl BaudLimiter {
+    pub const fn new() -> Self {
+        Self {
+            data_rate: 9600,
+            bits_per_char: 10,
+            credit: 0,
+            last_refill: 0,
+        }
+    }
+
+    /// Update the line coding used to compute the throughput
+    pub fn set_line_coding(
+        &mut self,
+        data_rate: u32,
+        data_bits: u8,
+        parity: ParityType,
+        stop_bits: StopBits,
+    ) {
+        let parity_bits = match parity {
+            ParityType::None => 0,
+            _ => 1,
+        };
+        // One and a half stop bits are rounded up
+        let stop_bits = match stop_bits {
+            StopBits::One => 1,
+            _ => 2,
+        };
+
+        self.data_rate = data_rate;
+        // Start bit + data bits + parity bit + stop bits
+        self.bits_per_char = 1 + data_bits as u32 + parity_bits + stop_bits;
+    }
+
+    /// Current data rate, in bits per second
+    pub fn data_rate(&self) -> u32 {
+        self.data_rate
+    }
+
+    /// Number of characters that can be sent at `now` (in microseconds)
+    ///
+    /// A data rate of 0 means the host didn't set one, and the data isn't paced.
+    pub fn available(&mut self, now: u64) -> usize {
+        if self.data_rate == 0 {
+            return MAX_BURST as usize;
+        }
+        let elapsed = now.saturating_sub(self.last_refill);
+        let bits = elapsed * self.data_rate as u64 / 1_000_000;
+        // Only move the refill timestamp forward once at least one bit was earned, otherwise
+        // frequent calls would never accumulate any credit at low baud rates.
+        if bits > 0 {
+            self.last_refill = now;
+            let max_credit = (MAX_BURST * self.bits_per_char) as u64;
+            self.credit = (self.credit + bits).min(max_credit);
+        }
+
+        (self.credit / self.bits_per_char as u64) as usize
+    }
+
+    /// Mark `count` characters as sent
+    pub fn consume(&mut self, count: usize) {
+        self.credit = self
+            .credit
+            .saturating_sub(count as u64 * self.bits_per_char as u64);
+    }
+}
@@ -0,0 +1,93 @@
+//! Terminal benchmark
+//!
+//! Draws the same lines on every run, so configurations such as the SPI speed can be compared by
+//! the characters drawn per second and by what was sent to the panel.
+
+use crate::display::{self, PanelStats};
+use core::fmt::Write;
+use heapless::String;
+
+/// Lines drawn when no count is given
+pub const DEFAULT_LINES: u32 = 200;
+
+/// Text of the lines, after their number
+const TEXT: &str = "The quick brown fox jumps over the lazy dog 0123456789";
+
+/// Outcome of a benchmark run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BenchResult {
+    /// Bytes written to the terminal, escape sequences included
+    pub chars: u32,
+    pub elapsed_us: u64,
+    /// What was sent to the panel during the run
+    pub panel: PanelStats,
+}
+
+impl BenchResult {
+    pub fn chars_per_second(&self) -> u32 {
+        if self.elapsed_us == 0 {
+            return 0;
+        }
+        (self.chars as u64 * 1_000_000 / self.elapsed_us) as u32
+    }
+}
+
+/// Clear the terminal and draw `lines` lines of text through `write`, then report the speed
+///
+/// Each line changes color, and there are more lines than rows, so the run includes scrolling.
+/// Each line is written on its own, so `write` can hold a lock for one line at a time.
+pub fn run<W, T>(lines: u32, mut write: W, now_us: T) -> BenchResult
+where
+    W: FnMut(&[u8]),
+    T: Fn() -> u64,
+{
+    let panel = display::stats();
+    let start = now_us();
+
+    let mut chars = 0;
+    let mut emit = |data: &[u8]| {
+        chars += data.len() as u32;
+        write(data);
+    };
+    emit(b"\x1b[2J\x1b[H");
+    for index in 0..lines {
+        let mut line: String<80> = String::new();
+        // Colors 1 to 7, as black is the background
+        let color = index % 7 + 1;
+        let _ = write!(line, "\x1b[3{}m{:04} {}\x1b[0m\r\n", color, index, TEXT);
+        emit(line.as_bytes());
+    }
+
+    let elapsed_us = now_us() - start;
+    let end = display::stats();
+    BenchResult {
+        chars,
+        elapsed_us,
+        panel: PanelStats {
+            spi_bytes: end.spi_bytes.wrapping_sub(panel.spi_bytes),
+            ram_writes: end.ram_writes.wrapping_sub(panel.ram_writes),
+        },
+    }
+}
+
+/// Run the benchmark with the rows drawn again on each scroll, then with the pixels moved
+///
+/// `pixel_scroll` switches the terminal between both, such as with `Terminal::set_pixel_scroll`,
+/// and is left on. The results are in that order.
+pub fn compare_scroll<W, T, P>(
+    lines: u32,
+    mut write: W,
+    now_us: T,
+    mut pixel_scroll: P,
+) -> (BenchResult, BenchResult)
+where
+    W: FnMut(&[u8]),
+    T: Fn() -> u64,
+    P: FnMut(bool),
+{
+    pixel_scroll(false);
+    let redraw = run(lines, &mut write, &now_us);
+    pixel_scroll(true);
+    let moved = run(lines, &mut write, &now_us);
+    (redraw, moved)
+}
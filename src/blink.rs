@@ -0,0 +1,67 @@
+//! LED blink signatures
+//!
+//! A short pattern of dots and dashes set by the host, which the LED repeats instead of its
+//! usual blink. With several identical boards on a desk, it shows which one a serial port
+//! belongs to.
+
+use heapless::String;
+
+/// Longest signature, in symbols
+pub const MAX_SYMBOLS: usize = 12;
+
+/// Length of a dot, in milliseconds. Dashes are 3 dots long, and the signature repeats after a
+/// pause of 6 dots.
+pub const DOT_MS: u32 = 150;
+
+/// Signature blinked by the LED, made of `.` and `-`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlinkPattern {
+    symbols: String<MAX_SYMBOLS>,
+}
+
+impl BlinkPattern {
+    /// Parse a signature, returning `None` if it is empty, too long or has other characters
+    pub fn parse(text: &str) -> Option<Self> {
+        let valid = |byte| byte == b'.' || byte == b'-';
+        if text.is_empty() || text.len() > MAX_SYMBOLS || !text.bytes().all(valid) {
+            return None;
+        }
+        Some(Self {
+            symbols: String::from(text),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.symbols
+    }
+
+    /// Length of one repetition, in dots
+    fn period(&self) -> u32 {
+        self.symbols.bytes().map(|byte| dots(byte) + 1).sum::<u32>() + 6
+    }
+
+    /// Whether the LED is on at `now_ms`, in any time base
+    pub fn is_on(&self, now_ms: u32) -> bool {
+        let mut dot = now_ms / DOT_MS % self.period();
+        for byte in self.symbols.bytes() {
+            if dot < dots(byte) {
+                return true;
+            }
+            // The symbol, then the gap after it
+            dot = match dot.checked_sub(dots(byte) + 1) {
+                Some(dot) => dot,
+                None => return false,
+            };
+        }
+        false
+    }
+}
+
+/// Length of a symbol, in dots
+fn dots(symbol: u8) -> u32 {
+    if symbol == b'-' {
+        3
+    } else {
+        1
+    }
+}
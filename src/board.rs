@@ -0,0 +1,299 @@
+//! Pico Display board
+//!
+//! Sets up the clocks, the pins, the panel and the USB controller of a Pico on a Pico Display,
+//! so firmware starts from a terminal, a serial port, the buttons and the LED instead of
+//! repeating the same setup in every `main`. The panel only starts once taken, so firmware
+//! can run without it.
+
+use crate::device_name::DeviceName;
+use crate::display::{self, CsPin, DcPin, Display, MisoPin, MosiPin, SclkPin};
+use crate::dual_display::VISIBLE_OFFSET;
+use crate::framebuffer::Framebuffer;
+use crate::terminal::TerminalBuilder;
+use crate::{
+    flash, hal, pac, PicoBufferedTerminal, PicoDisplayTerminal, PicoDisplayTerminalBuilder, Pins,
+};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+use embedded_hal::digital::v2::InputPin;
+use embedded_time::rate::*;
+use hal::gpio::pin::{bank0, Pin, PinId, PullUpInput, PushPullOutput};
+#[cfg(feature = "usb")]
+use {
+    crate::{usb_console::UsbConsole, PicoUsbConsole},
+    usb_device::{class_prelude::UsbBusAllocator, prelude::*},
+    usbd_serial::SerialPort,
+};
+
+pub type LedPin = Pin<bank0::Gpio25, PushPullOutput>;
+/// GPIO in its mode at reset
+pub type FreePin<I> = Pin<I, <I as PinId>::Reset>;
+
+/// GPIOs the board doesn't use, left as they are at reset
+pub struct FreePins {
+    pub gpio1: FreePin<bank0::Gpio1>,
+    pub gpio2: FreePin<bank0::Gpio2>,
+    pub gpio3: FreePin<bank0::Gpio3>,
+    pub gpio4: FreePin<bank0::Gpio4>,
+    pub gpio5: FreePin<bank0::Gpio5>,
+    pub gpio6: FreePin<bank0::Gpio6>,
+    pub gpio7: FreePin<bank0::Gpio7>,
+    pub gpio8: FreePin<bank0::Gpio8>,
+    pub gpio9: FreePin<bank0::Gpio9>,
+    pub gpio10: FreePin<bank0::Gpio10>,
+    pub gpio11: FreePin<bank0::Gpio11>,
+    pub gpio21: FreePin<bank0::Gpio21>,
+    pub gpio22: FreePin<bank0::Gpio22>,
+    pub gpio26: FreePin<bank0::Gpio26>,
+    pub gpio27: FreePin<bank0::Gpio27>,
+    pub gpio28: FreePin<bank0::Gpio28>,
+}
+
+/// SPI0 and the pins of the panel, until it is started
+pub struct Panel {
+    spi: pac::SPI0,
+    dc: DcPin,
+    cs: CsPin,
+    sclk: SclkPin,
+    mosi: MosiPin,
+    miso: MisoPin,
+}
+
+impl Panel {
+    /// Start the panel, clearing it to black
+    pub fn start(self, resets: &mut pac::RESETS, delay: &mut cortex_m::delay::Delay) -> Display {
+        display::init(
+            self.spi, self.dc, self.cs, self.sclk, self.mosi, self.miso, resets, delay,
+        )
+    }
+}
+
+/// Buttons of the Pico Display, which read low while pressed
+pub struct Buttons {
+    pub a: Pin<bank0::Gpio12, PullUpInput>,
+    pub b: Pin<bank0::Gpio13, PullUpInput>,
+    pub x: Pin<bank0::Gpio14, PullUpInput>,
+    pub y: Pin<bank0::Gpio15, PullUpInput>,
+}
+
+impl Buttons {
+    /// Buttons held down, in A, B, X, Y order
+    pub fn pressed(&self) -> [bool; 4] {
+        [
+            self.a.is_low().unwrap(),
+            self.b.is_low().unwrap(),
+            self.x.is_low().unwrap(),
+            self.y.is_low().unwrap(),
+        ]
+    }
+}
+
+/// Pico on a Pico Display, with its clocks running
+///
+/// The panel, the LED and the buttons are taken once each, by `terminal`, `buffered_terminal`,
+/// `display` or `panel`, `led` and `buttons`. The peripherals and pins the board doesn't use are
+/// left in its public fields.
+pub struct Board {
+    pub system_clock: hal::clocks::SystemClock,
+    pub peripheral_clock: hal::clocks::PeripheralClock,
+    pub delay: cortex_m::delay::Delay,
+    pub timer: hal::timer::Timer,
+    pub watchdog: hal::watchdog::Watchdog,
+    pub resets: pac::RESETS,
+    pub adc: pac::ADC,
+    pub i2c1: pac::I2C1,
+    pub pwm: pac::PWM,
+    pub pio1: pac::PIO1,
+    pub rosc: pac::ROSC,
+    pub uart0: pac::UART0,
+    pub uart1: pac::UART1,
+    /// Power-on state machine, FIFO and core peripherals to start core 1, see `render_core`
+    pub psm: pac::PSM,
+    pub ppb: pac::PPB,
+    pub fifo: hal::sio::SioFifo,
+    /// Name of the board, derived from the unique ID of the flash
+    pub name: &'static DeviceName,
+    pub pins: FreePins,
+    panel: Option<Panel>,
+    led: Option<LedPin>,
+    buttons: Option<Buttons>,
+    #[cfg(feature = "usb")]
+    usb_bus: &'static UsbBusAllocator<hal::usb::UsbBus>,
+}
+
+impl Board {
+    /// Set up the board, returning `None` if the peripherals were already taken
+    ///
+    /// The system clock runs at 125MHz. The panel stays off until taken.
+    pub fn take() -> Option<Self> {
+        let pac = pac::Peripherals::take()?;
+        let core = pac::CorePeripherals::take()?;
+        Self::new(pac, core.SYST)
+    }
+
+    /// Set up the board from peripherals taken elsewhere, such as by the `init` of an RTIC app
+    ///
+    /// Returns `None` if the clocks don't start, or if a board was already set up.
+    pub fn new(mut pac: pac::Peripherals, syst: pac::SYST) -> Option<Self> {
+        let mut watchdog = hal::watchdog::Watchdog::new(pac.WATCHDOG);
+        let clocks = hal::clocks::init_clocks_and_plls(
+            crate::XOSC_CRYSTAL_FREQ,
+            pac.XOSC,
+            pac.CLOCKS,
+            pac.PLL_SYS,
+            pac.PLL_USB,
+            &mut pac.RESETS,
+            &mut watchdog,
+        )
+        .ok()?;
+        let delay = cortex_m::delay::Delay::new(syst, clocks.system_clock.freq().integer());
+        let timer = hal::timer::Timer::new(pac.TIMER, &mut pac.RESETS);
+
+        let sio = hal::sio::Sio::new(pac.SIO);
+        let pins = Pins::new(
+            pac.IO_BANK0,
+            pac.PADS_BANK0,
+            sio.gpio_bank0,
+            &mut pac.RESETS,
+        );
+        let panel = Panel {
+            spi: pac.SPI0,
+            dc: pins.lcd_dc.into_push_pull_output(),
+            cs: pins.lcd_cs.into_push_pull_output(),
+            sclk: pins.spi_sclk.into_mode(),
+            mosi: pins.spi_mosi.into_mode(),
+            miso: pins.gpio0.into_mode(),
+        };
+
+        // The classes and the device keep a reference to the bus for the rest of the program
+        #[cfg(feature = "usb")]
+        let usb_bus: &'static UsbBusAllocator<hal::usb::UsbBus> = cortex_m::singleton!(
+            : UsbBusAllocator<hal::usb::UsbBus> = UsbBusAllocator::new(hal::usb::UsbBus::new(
+                pac.USBCTRL_REGS,
+                pac.USBCTRL_DPRAM,
+                clocks.usb_clock,
+                true,
+                &mut pac.RESETS,
+            ))
+        )?;
+
+        let name = DeviceName::from_unique_id(&flash::unique_id());
+        let name: &'static DeviceName = cortex_m::singleton!(: DeviceName = name)?;
+
+        Some(Self {
+            system_clock: clocks.system_clock,
+            peripheral_clock: clocks.peripheral_clock,
+            delay,
+            timer,
+            watchdog,
+            resets: pac.RESETS,
+            adc: pac.ADC,
+            i2c1: pac.I2C1,
+            pwm: pac.PWM,
+            pio1: pac.PIO1,
+            rosc: pac.ROSC,
+            uart0: pac.UART0,
+            uart1: pac.UART1,
+            psm: pac.PSM,
+            ppb: pac.PPB,
+            fifo: sio.fifo,
+            name,
+            pins: FreePins {
+                gpio1: pins.gpio1,
+                gpio2: pins.gpio2,
+                gpio3: pins.gpio3,
+                gpio4: pins.gpio4,
+                gpio5: pins.gpio5,
+                gpio6: pins.gpio6,
+                gpio7: pins.gpio7,
+                gpio8: pins.gpio8,
+                gpio9: pins.gpio9,
+                gpio10: pins.gpio10,
+                gpio11: pins.gpio11,
+                gpio21: pins.gpio21,
+                gpio22: pins.gpio22,
+                gpio26: pins.gpio26,
+                gpio27: pins.gpio27,
+                gpio28: pins.gpio28,
+            },
+            panel: Some(panel),
+            led: Some(pins.led.into_push_pull_output()),
+            buttons: Some(Buttons {
+                a: pins.btn_a.into_pull_up_input(),
+                b: pins.btn_b.into_pull_up_input(),
+                x: pins.btn_x.into_pull_up_input(),
+                y: pins.btn_y.into_pull_up_input(),
+            }),
+            #[cfg(feature = "usb")]
+            usb_bus,
+        })
+    }
+
+    /// Start the panel, clearing it to black, and take it to draw on it directly
+    pub fn display(&mut self) -> Option<Display> {
+        let panel = self.panel.take()?;
+        Some(panel.start(&mut self.resets, &mut self.delay))
+    }
+
+    /// Take the panel without starting it, to start it later with `Panel::start`, such as once
+    /// the boot mode is known
+    pub fn panel(&mut self) -> Option<Panel> {
+        self.panel.take()
+    }
+
+    /// Take the panel as a terminal covering the whole screen
+    pub fn terminal(&mut self) -> Option<PicoDisplayTerminal<'static>> {
+        let display = self.display()?;
+        Some(
+            PicoDisplayTerminalBuilder::new(display)
+                .with_offset(VISIBLE_OFFSET)
+                .with_size(Size::new(display::WIDTH as u32, display::HEIGHT as u32))
+                .build(),
+        )
+    }
+
+    /// Take the panel as a terminal drawing into a framebuffer, and the panel to flush it to
+    ///
+    /// The terminal is drawn in RAM until `terminal.screen_mut().flush(&mut display,
+    /// VISIBLE_OFFSET)`, and scrolls by moving the pixels of the framebuffer. The framebuffer takes
+    /// 64kB, so the terminal is better kept in a `static`.
+    pub fn buffered_terminal(&mut self) -> Option<(PicoBufferedTerminal<'static>, Display)> {
+        let display = self.display()?;
+        let terminal = TerminalBuilder::new(Framebuffer::new(Rgb565::BLACK))
+            .with_pixel_scroll(Framebuffer::scroll_up)
+            .build();
+        Some((terminal, display))
+    }
+
+    pub fn led(&mut self) -> Option<LedPin> {
+        self.led.take()
+    }
+
+    pub fn buttons(&mut self) -> Option<Buttons> {
+        self.buttons.take()
+    }
+
+    /// Bus of the USB controller, to add other classes before `usb_device`
+    #[cfg(feature = "usb")]
+    pub fn usb_bus(&self) -> &'static UsbBusAllocator<hal::usb::UsbBus> {
+        self.usb_bus
+    }
+
+    /// Add a USB serial port, wrapped in a console
+    #[cfg(feature = "usb")]
+    pub fn serial(&self) -> PicoUsbConsole<'static> {
+        UsbConsole::new(SerialPort::new(self.usb_bus))
+    }
+
+    /// Build the USB device, with the name of the board as serial number
+    ///
+    /// The classes must all be added before, such as with `serial`.
+    #[cfg(feature = "usb")]
+    pub fn usb_device(&self) -> UsbDevice<'static, hal::usb::UsbBus> {
+        UsbDeviceBuilder::new(self.usb_bus, UsbVidPid(0x16c0, 0x27dd))
+            .manufacturer("Fake company")
+            .product("Serial port")
+            .serial_number(self.name.as_str())
+            .device_class(2)
+            .build()
+    }
+}
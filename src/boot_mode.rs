@@ -0,0 +1,123 @@
+//! Power-on modes
+//!
+//! Holding one of the buttons while the board powers on selects a mode, giving recovery paths
+//! when the normal startup misbehaves. Safe mode is also selected after the watchdog reset the
+//! firmware a few times in a row, such as when a stored setting hangs it. A reboot can also name
+//! where the firmware comes back up, see `reboot_into`.
+
+use crate::pac;
+
+/// Watchdog resets in a row after which the firmware starts in safe mode
+pub const MAX_WATCHDOG_RESETS: u32 = 3;
+
+/// High bits of the target of `reboot_into`, so a value left by another firmware isn't taken
+const TARGET_MAGIC: u32 = 0xB007_0000;
+
+/// Mode selected at power on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootMode {
+    /// No button held
+    Normal,
+    /// A: check the display and sensors before starting
+    SelfTest,
+    /// B, or A and B together: reboot into the USB bootloader
+    Bootsel,
+    /// X: USB serial only, without the display
+    Headless,
+    /// Y: skip the stored configuration and optional behaviors
+    Safe,
+}
+
+impl BootMode {
+    /// Mode selected by the A, B, X and Y buttons held at power on
+    ///
+    /// If more than one button is held, the first one in that order wins, except for A and B
+    /// together which select the USB bootloader like B alone.
+    pub fn from_buttons(pressed: [bool; 4]) -> Self {
+        match pressed {
+            [true, true, _, _] => BootMode::Bootsel,
+            [true, _, _, _] => BootMode::SelfTest,
+            [_, true, _, _] => BootMode::Bootsel,
+            [_, _, true, _] => BootMode::Headless,
+            [_, _, _, true] => BootMode::Safe,
+            _ => BootMode::Normal,
+        }
+    }
+
+    /// Mode selected by the buttons, or safe mode after `MAX_WATCHDOG_RESETS` watchdog resets
+    ///
+    /// A button held at power on still wins, so the other modes stay reachable.
+    pub fn select(pressed: [bool; 4], watchdog_resets: u32) -> Self {
+        match Self::from_buttons(pressed) {
+            BootMode::Normal if watchdog_resets >= MAX_WATCHDOG_RESETS => BootMode::Safe,
+            mode => mode,
+        }
+    }
+
+    /// Name of the mode, as shown on the display
+    pub fn name(self) -> &'static str {
+        match self {
+            BootMode::Normal => "Normal",
+            BootMode::SelfTest => "Self-test",
+            BootMode::Bootsel => "BOOTSEL",
+            BootMode::Headless => "Headless",
+            BootMode::Safe => "Safe mode",
+        }
+    }
+}
+
+/// Count the watchdog resets in a row, including the one that started this boot
+///
+/// The count is kept in a scratch register of the watchdog, which survives its resets but not a
+/// power cycle. It must only be called once per boot.
+pub fn count_watchdog_resets() -> u32 {
+    // Note (safety): the scratch register isn't used by the boot ROM or the HAL, and only this
+    // module writes it
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    let count = if watchdog.reason.read().timer().bit_is_set() {
+        watchdog.scratch0.read().bits().saturating_add(1)
+    } else {
+        0
+    };
+    watchdog.scratch0.write(|w| unsafe { w.bits(count) });
+    count
+}
+
+/// Forget the watchdog resets, once the firmware has run long enough to be considered healthy
+pub fn clear_watchdog_resets() {
+    // Note (safety): see `count_watchdog_resets`
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    watchdog.scratch0.write(|w| unsafe { w.bits(0) });
+}
+
+/// Reboot with the watchdog, coming back up into `target`
+///
+/// The target is chosen by the application, such as a page to open. It is kept in another
+/// scratch register of the watchdog, until `take_reboot_target` reads it on the next boot.
+pub fn reboot_into(target: u8) -> ! {
+    // Note (safety): the scratch register isn't used by the boot ROM or the HAL, and only this
+    // module writes it
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    watchdog
+        .scratch1
+        .write(|w| unsafe { w.bits(TARGET_MAGIC | target as u32) });
+    watchdog.ctrl.modify(|_, w| w.trigger().set_bit());
+    loop {
+        cortex_m::asm::nop();
+    }
+}
+
+/// Target of the `reboot_into` that started this boot, if it did
+///
+/// The target is cleared, so the next reset starts as usual. It must only be called once per
+/// boot.
+pub fn take_reboot_target() -> Option<u8> {
+    // Note (safety): see `reboot_into`
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    let value = watchdog.scratch1.read().bits();
+    watchdog.scratch1.write(|w| unsafe { w.bits(0) });
+    // A forced reset of the watchdog is the one of `reboot_into`
+    let forced = watchdog.reason.read().force().bit_is_set();
+    (forced && value & !0xFF == TARGET_MAGIC).then(|| value as u8)
+}
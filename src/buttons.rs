@@ -0,0 +1,99 @@
+//! Debounced buttons
+//!
+//! Filters the bounces of the A, B, X and Y buttons of the Pico Display, and turns them into
+//! press, release and hold events. It only needs the button levels and the time, so it can run
+//! in the main loop or in a timer interrupt feeding a queue.
+
+/// Time a button level must stay the same to be taken, in milliseconds
+pub const DEBOUNCE_MS: u32 = 20;
+/// Time a button must stay pressed to be held, in milliseconds
+pub const HOLD_MS: u32 = 800;
+
+/// Button of the Pico Display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    X,
+    Y,
+}
+
+impl Button {
+    /// In the order of the levels given to `Debouncer::update`
+    pub const ALL: [Button; 4] = [Button::A, Button::B, Button::X, Button::Y];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What happened to a button
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Pressed(Button),
+    Released(Button),
+    /// Still pressed `HOLD_MS` after the press, sent once per press
+    Held(Button),
+}
+
+/// Queue of button events, such as from a timer interrupt to the main loop
+pub type ButtonQueue<const N: usize> = heapless::spsc::Queue<ButtonEvent, N>;
+
+/// Debouncer of the four buttons
+pub struct Debouncer {
+    /// Levels read last, and when they last changed
+    raw: [bool; 4],
+    changed_ms: [u32; 4],
+    pressed: [bool; 4],
+    held: [bool; 4],
+}
+
+impl Debouncer {
+    pub const fn new() -> Self {
+        Self {
+            raw: [false; 4],
+            changed_ms: [0; 4],
+            pressed: [false; 4],
+            held: [false; 4],
+        }
+    }
+
+    /// Buttons pressed once debounced, in A, B, X, Y order
+    pub fn pressed(&self) -> [bool; 4] {
+        self.pressed
+    }
+
+    /// Update with the levels read from the buttons, calling `f` for each event
+    ///
+    /// `raw` is true for the buttons reading as pressed, in A, B, X, Y order. `now_ms` may wrap
+    /// around. Calling this every few milliseconds is enough.
+    pub fn update<F: FnMut(ButtonEvent)>(&mut self, raw: [bool; 4], now_ms: u32, mut f: F) {
+        for button in Button::ALL {
+            let i = button.index();
+            if raw[i] != self.raw[i] {
+                self.raw[i] = raw[i];
+                self.changed_ms[i] = now_ms;
+                continue;
+            }
+            let stable_ms = now_ms.wrapping_sub(self.changed_ms[i]);
+            if stable_ms >= DEBOUNCE_MS && raw[i] != self.pressed[i] {
+                self.pressed[i] = raw[i];
+                self.held[i] = false;
+                f(if raw[i] {
+                    ButtonEvent::Pressed(button)
+                } else {
+                    ButtonEvent::Released(button)
+                });
+            } else if self.pressed[i] && !self.held[i] && stable_ms >= HOLD_MS {
+                self.held[i] = true;
+                f(ButtonEvent::Held(button));
+            }
+        }
+    }
+}
+
+impl Default for Debouncer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,65 @@
+//! Wall clock synchronized from the host
+//!
+//! The host sets the current Unix time once, and the device derives absolute timestamps from
+//! the microsecond timer afterwards, so device and host logs can be merged.
+
+use core::cell::Cell;
+use core::fmt;
+use cortex_m::interrupt::Mutex;
+
+/// Offset between the timer and the Unix epoch
+pub struct WallClock {
+    /// Unix time in milliseconds when the timer was at zero
+    epoch_ms: Mutex<Cell<Option<u64>>>,
+}
+
+impl WallClock {
+    /// Create a clock that isn't synchronized yet
+    pub const fn new() -> Self {
+        Self {
+            epoch_ms: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Set the current Unix time in milliseconds, `uptime_us` being the current timer value
+    pub fn sync(&self, unix_ms: u64, uptime_us: u64) {
+        let epoch_ms = unix_ms.wrapping_sub(uptime_us / 1000);
+        cortex_m::interrupt::free(|cs| self.epoch_ms.borrow(cs).set(Some(epoch_ms)));
+    }
+
+    /// Returns true once the host has set the time
+    pub fn is_synced(&self) -> bool {
+        cortex_m::interrupt::free(|cs| self.epoch_ms.borrow(cs).get().is_some())
+    }
+
+    /// Timestamp for the timer value `uptime_us`
+    pub fn timestamp(&self, uptime_us: u64) -> Timestamp {
+        let uptime_ms = uptime_us / 1000;
+        match cortex_m::interrupt::free(|cs| self.epoch_ms.borrow(cs).get()) {
+            Some(epoch_ms) => Timestamp::Unix(epoch_ms.wrapping_add(uptime_ms)),
+            None => Timestamp::Uptime(uptime_ms),
+        }
+    }
+}
+
+/// Clock of the device
+pub static CLOCK: WallClock = WallClock::new();
+
+/// Point in time, in milliseconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Since the Unix epoch
+    Unix(u64),
+    /// Since boot, when the clock isn't synchronized
+    Uptime(u64),
+}
+
+impl fmt::Display for Timestamp {
+    /// Unix timestamps are written as is, uptimes are prefixed with `+`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timestamp::Unix(ms) => write!(f, "{}", ms),
+            Timestamp::Uptime(ms) => write!(f, "+{}", ms),
+        }
+    }
+}
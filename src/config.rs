@@ -0,0 +1,151 @@
+//! Configuration storage
+//!
+//! A value serialized with postcard in the last two sectors of the flash. Each save takes the next
+//! free page of the current sector, and once it is full, the other sector is erased and the save
+//! goes to its first page, so the previous record is kept until a newer one is written. The valid
+//! record with the highest sequence number is loaded, so a save cut by a power loss leaves the
+//! previous one.
+
+use crate::flash::{self, FLASH_SIZE, PAGE_SIZE, SECTOR_SIZE};
+use crate::hash::Crc32;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Start of the configuration, the last two sectors of the flash
+pub const CONFIG_OFFSET: u32 = FLASH_SIZE - CONFIG_SIZE;
+/// Size of the configuration, two sectors used in turn
+pub const CONFIG_SIZE: u32 = 2 * SECTOR_SIZE;
+/// Records written to a sector before the other one is used, one per page
+pub const RECORDS: usize = (SECTOR_SIZE / PAGE_SIZE) as usize;
+
+const MAGIC: [u8; 4] = *b"CFG2";
+/// Header of a record: magic, sequence number, length of the value and CRC-32 of the sequence
+/// number, length and value
+const HEADER_SIZE: usize = 14;
+/// Largest serialized value
+pub const MAX_VALUE: usize = PAGE_SIZE as usize - HEADER_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The value takes more than `MAX_VALUE` bytes
+    TooLarge,
+}
+
+/// Record in a page of a sector
+struct Record {
+    /// Incremented by each save
+    sequence: u32,
+    value: &'static [u8],
+    /// The record matches its CRC-32
+    valid: bool,
+}
+
+fn sector_offset(sector: usize) -> u32 {
+    CONFIG_OFFSET + sector as u32 * SECTOR_SIZE
+}
+
+fn read_record(sector: usize, page: usize) -> Option<Record> {
+    let bytes = flash::read(
+        sector_offset(sector) + page as u32 * PAGE_SIZE,
+        PAGE_SIZE as usize,
+    );
+    if bytes[..4] != MAGIC {
+        return None;
+    }
+    let sequence = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let crc = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]);
+    let value = &bytes[HEADER_SIZE..HEADER_SIZE + len.min(MAX_VALUE)];
+    Some(Record {
+        sequence,
+        value,
+        valid: len <= MAX_VALUE && crc32(&bytes[4..10], value) == crc,
+    })
+}
+
+/// Records of a sector, from the oldest
+///
+/// They are written in order, so the first free page ends them.
+fn records(sector: usize) -> impl DoubleEndedIterator<Item = Record> {
+    let mut records = heapless::Vec::<Record, RECORDS>::new();
+    for record in (0..RECORDS).map_while(|page| read_record(sector, page)) {
+        let _ = records.push(record);
+    }
+    records.into_iter()
+}
+
+/// Newest valid record of a sector
+fn newest(sector: usize) -> Option<Record> {
+    records(sector).rev().find(|record| record.valid)
+}
+
+/// Sector holding the newest valid record, the first one if neither has any
+fn current() -> usize {
+    match (newest(0), newest(1)) {
+        (Some(first), Some(second)) if second.sequence > first.sequence => 1,
+        (None, Some(_)) => 1,
+        _ => 0,
+    }
+}
+
+/// Number of records written to the current sector
+pub fn used() -> usize {
+    records(current()).count()
+}
+
+/// Returns true if the newest record doesn't match its CRC-32
+///
+/// This is the last record of the current sector, or the first one of the other sector when the
+/// save moving to it was cut. `load` then gives the previous record, if there is one.
+pub fn is_corrupt() -> bool {
+    let sector = current();
+    records(sector).last().map_or(false, |record| !record.valid)
+        || records(1 - sector).next().map_or(false, |record| {
+            !record.valid && newest(1 - sector).is_none()
+        })
+}
+
+/// Load the newest value that is intact, `None` if there is none or it doesn't deserialize
+pub fn load<T: DeserializeOwned>() -> Option<T> {
+    let record = newest(current())?;
+    postcard::from_bytes(record.value).ok()
+}
+
+/// Store `value` in the next free page of the current sector, moving to the other one once it is
+/// full
+///
+/// Returns the number of records written to the sector, this one included.
+pub fn save<T: Serialize>(value: &T) -> Result<usize, ConfigError> {
+    let mut page = [0xFF; PAGE_SIZE as usize];
+    let len = postcard::to_slice(value, &mut page[HEADER_SIZE..])
+        .map_err(|_| ConfigError::TooLarge)?
+        .len();
+    let mut sector = current();
+    let sequence = newest(sector).map_or(0, |record| record.sequence.wrapping_add(1));
+    page[..4].copy_from_slice(&MAGIC);
+    page[4..8].copy_from_slice(&sequence.to_le_bytes());
+    page[8..10].copy_from_slice(&(len as u16).to_le_bytes());
+    let crc = crc32(&page[4..10], &page[HEADER_SIZE..HEADER_SIZE + len]);
+    page[10..14].copy_from_slice(&crc.to_le_bytes());
+
+    let mut next = records(sector).count();
+    // Note (safety): the sectors are reserved for the configuration, and a page is only
+    // programmed once it is free. The full sector is left as it is, so its newest record stays
+    // until this one is written
+    unsafe {
+        if next == RECORDS {
+            sector = 1 - sector;
+            flash::erase(sector_offset(sector), SECTOR_SIZE);
+            next = 0;
+        }
+        flash::program(sector_offset(sector) + next as u32 * PAGE_SIZE, &page);
+    }
+    Ok(next + 1)
+}
+
+/// CRC-32 of the header fields after the magic, then of the value
+fn crc32(header: &[u8], value: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(header);
+    crc.update(value);
+    crc.finish()
+}
@@ -0,0 +1,137 @@
+//! Confirmation of disruptive commands
+//!
+//! Commands that take the board offline, such as a reboot, only ask to run: they wait for the
+//! token they replied with, or for a button press, until a timeout. A stray line of a script then
+//! can't knock a deployed board offline.
+
+use rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Time to confirm a command, in milliseconds
+pub const CONFIRM_MS: u32 = 10_000;
+/// Time after a wrong token during which tokens are refused, in milliseconds
+pub const COOLDOWN_MS: u32 = 5_000;
+
+/// How the disruptive commands are confirmed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConfirmMode {
+    /// They run right away
+    Off,
+    /// With the token of the reply, or a button press
+    Token,
+    /// Only with a button press, so the host alone can't run them
+    Button,
+}
+
+impl ConfirmMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfirmMode::Off => "off",
+            ConfirmMode::Token => "token",
+            ConfirmMode::Button => "button",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ConfirmMode::Off => ConfirmMode::Token,
+            ConfirmMode::Token => ConfirmMode::Button,
+            ConfirmMode::Button => ConfirmMode::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfirmError {
+    /// No command is waiting, or it timed out
+    NothingPending,
+    /// The token doesn't match, and the command is cancelled
+    WrongToken,
+    /// A wrong token was sent less than `COOLDOWN_MS` ago
+    TooSoon,
+}
+
+/// Command waiting for its confirmation
+#[derive(Clone, Copy, Debug)]
+struct Pending<A> {
+    action: A,
+    token: u16,
+    asked_ms: u32,
+}
+
+/// Holds a disruptive command until it is confirmed
+///
+/// A wrong token cancels the command, and the tokens are refused for a while after it, so they
+/// can't be guessed by a script trying them all.
+pub struct Confirmation<A> {
+    pending: Option<Pending<A>>,
+    /// Time of the last wrong token
+    wrong_ms: Option<u32>,
+}
+
+impl<A: Copy> Confirmation<A> {
+    pub const fn new() -> Self {
+        Self {
+            pending: None,
+            wrong_ms: None,
+        }
+    }
+
+    /// Hold `action` until it is confirmed, replacing the one waiting
+    ///
+    /// Returns the token confirming it.
+    pub fn ask<R: RngCore>(&mut self, action: A, rng: &mut R, now_ms: u32) -> u16 {
+        let token = rng.next_u32() as u16;
+        self.pending = Some(Pending {
+            action,
+            token,
+            asked_ms: now_ms,
+        });
+        token
+    }
+
+    /// Command waiting at `now_ms`, if it hasn't timed out
+    pub fn pending(&self, now_ms: u32) -> Option<A> {
+        self.pending
+            .filter(|pending| now_ms.wrapping_sub(pending.asked_ms) < CONFIRM_MS)
+            .map(|pending| pending.action)
+    }
+
+    /// Confirm the command waiting with its `token`, returning it to run
+    pub fn confirm(&mut self, token: u16, now_ms: u32) -> Result<A, ConfirmError> {
+        if let Some(wrong_ms) = self.wrong_ms {
+            if now_ms.wrapping_sub(wrong_ms) < COOLDOWN_MS {
+                return Err(ConfirmError::TooSoon);
+            }
+        }
+        let action = self.pending(now_ms).ok_or(ConfirmError::NothingPending)?;
+        let expected = self.pending.take().map(|pending| pending.token);
+        if expected != Some(token) {
+            self.wrong_ms = Some(now_ms);
+            return Err(ConfirmError::WrongToken);
+        }
+        Ok(action)
+    }
+
+    /// Confirm the command waiting with a button press, returning it to run
+    pub fn press(&mut self, now_ms: u32) -> Option<A> {
+        let action = self.pending(now_ms);
+        self.pending = None;
+        action
+    }
+
+    /// Drop the command waiting, returning it if it hadn't timed out
+    pub fn cancel(&mut self, now_ms: u32) -> Option<A> {
+        self.press(now_ms)
+    }
+
+    /// Drop the command waiting once it timed out at `now_ms`, returning it
+    pub fn expire(&mut self, now_ms: u32) -> Option<A> {
+        let pending = self.pending?;
+        if self.pending(now_ms).is_some() {
+            return None;
+        }
+        self.pending = None;
+        Some(pending.action)
+    }
+}
@@ -0,0 +1,151 @@
+//! Crash screenshots
+//!
+//! Keeps the cells of the screen in flash when the firmware panics or faults, with the panic
+//! message or the faulting address, so the screen at the time of the crash can be read after a
+//! reboot.
+
+use crate::flash::{self, checksum, PAGE_SIZE, SECTOR_SIZE};
+
+/// Sector where the crash is stored, from the start of the flash
+pub const CRASH_OFFSET: u32 = 0x1F_4000;
+
+/// Largest screen kept, in cells
+pub const MAX_COLUMNS: usize = 64;
+pub const MAX_ROWS: usize = 32;
+/// Longest message kept, longer messages are cut
+pub const MAX_MESSAGE: usize = 120;
+
+const MAGIC: [u8; 4] = *b"CRSH";
+/// Header: magic, kind, columns, rows, message length, uptime and checksum
+const HEADER_SIZE: usize = 16;
+const CELLS_START: usize = HEADER_SIZE + MAX_MESSAGE;
+/// Bytes programmed, rounded up to whole pages
+const SIZE: usize = (CELLS_START + MAX_COLUMNS * MAX_ROWS + PAGE_SIZE as usize - 1)
+    / PAGE_SIZE as usize
+    * PAGE_SIZE as usize;
+
+/// What stopped the firmware
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashKind {
+    Panic,
+    HardFault,
+}
+
+impl CrashKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CrashKind::Panic => "panic",
+            CrashKind::HardFault => "hard fault",
+        }
+    }
+}
+
+/// Crash stored in flash
+pub struct Crash {
+    pub kind: CrashKind,
+    /// Time since boot when the firmware crashed, in milliseconds
+    pub uptime_ms: u32,
+    data: &'static [u8],
+}
+
+impl Crash {
+    /// Load the crash stored in flash, if there is one
+    pub fn load() -> Option<Self> {
+        let data = flash::read(CRASH_OFFSET, SIZE);
+        if data[..4] != MAGIC {
+            return None;
+        }
+        let (columns, rows) = (data[5] as usize, data[6] as usize);
+        if columns > MAX_COLUMNS || rows > MAX_ROWS || data[7] as usize > MAX_MESSAGE {
+            return None;
+        }
+        let sum = u16::from_le_bytes([data[12], data[13]]);
+        let message = &data[HEADER_SIZE..][..data[7] as usize];
+        let cells = &data[CELLS_START..][..columns * rows];
+        if checksum(&data[4..12]) ^ checksum(message) ^ checksum(cells) != sum {
+            return None;
+        }
+
+        Some(Self {
+            kind: match data[4] {
+                1 => CrashKind::HardFault,
+                _ => CrashKind::Panic,
+            },
+            uptime_ms: u32::from_le_bytes([data[8], data[9], data[10], data[11]]),
+            data,
+        })
+    }
+
+    /// Panic message, or faulting address
+    pub fn message(&self) -> &'static [u8] {
+        &self.data[HEADER_SIZE..][..self.data[7] as usize]
+    }
+
+    /// Number of columns of the screen
+    pub fn columns(&self) -> usize {
+        self.data[5] as usize
+    }
+
+    /// Number of rows of the screen
+    pub fn rows(&self) -> usize {
+        self.data[6] as usize
+    }
+
+    /// Cells of a row of the screen
+    pub fn row(&self, row: usize) -> &'static [u8] {
+        let columns = self.columns();
+        &self.data[CELLS_START + row * columns..][..columns]
+    }
+}
+
+/// Store a crash in flash, with the rows of cells on the screen
+///
+/// A crash already stored is kept, as the first crash is usually the interesting one and a
+/// crash loop would wear the flash out. Rows and columns past the maximum are cut.
+pub fn save<'a, I>(kind: CrashKind, message: &[u8], uptime_ms: u32, rows: I)
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    if Crash::load().is_some() {
+        return;
+    }
+
+    let mut data = [0xFF; SIZE];
+    let (mut columns, mut count) = (0, 0);
+    for row in rows.take(MAX_ROWS) {
+        // The first row sets the width, as all the rows of a screen have the same
+        if count == 0 {
+            columns = row.len().min(MAX_COLUMNS);
+        }
+        let start = CELLS_START + count * columns;
+        for (cell, &c) in data[start..][..columns].iter_mut().zip(row) {
+            *cell = c;
+        }
+        count += 1;
+    }
+    let message = &message[..message.len().min(MAX_MESSAGE)];
+
+    data[..4].copy_from_slice(&MAGIC);
+    data[4] = kind as u8;
+    data[5] = columns as u8;
+    data[6] = count as u8;
+    data[7] = message.len() as u8;
+    data[8..12].copy_from_slice(&uptime_ms.to_le_bytes());
+    data[HEADER_SIZE..][..message.len()].copy_from_slice(message);
+    let sum = checksum(&data[4..12])
+        ^ checksum(message)
+        ^ checksum(&data[CELLS_START..][..columns * count]);
+    data[12..14].copy_from_slice(&sum.to_le_bytes());
+
+    // Note (safety): the sector is reserved for the crash
+    unsafe {
+        flash::erase(CRASH_OFFSET, SECTOR_SIZE);
+        flash::program(CRASH_OFFSET, &data);
+    }
+}
+
+/// Forget the stored crash, so the next one is kept
+pub fn clear() {
+    // Note (safety): the sector is reserved for the crash
+    unsafe { flash::erase(CRASH_OFFSET, SECTOR_SIZE) };
+}
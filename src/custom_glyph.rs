@@ -0,0 +1,99 @@
+//! Custom glyphs
+//!
+//! Small bitmaps sent by the host and bound to private use code points from U+E000, so a
+//! dashboard can show icons such as Wi-Fi bars or a battery inline with the text. The terminal
+//! keeps them in its cells as the bytes from `FIRST_BYTE`, which ASCII never uses.
+
+/// Number of custom glyphs
+pub const MAX_GLYPHS: usize = 16;
+/// Widest glyph, one bit per column of a row
+pub const MAX_WIDTH: usize = 8;
+/// Tallest glyph, one byte per row
+pub const MAX_HEIGHT: usize = 16;
+/// Code point of the first custom glyph
+pub const FIRST_CODE_POINT: u32 = 0xE000;
+/// Byte of the first custom glyph in the cells of the terminal
+pub const FIRST_BYTE: u8 = 0x80;
+
+/// Pixels of a glyph, a byte per row from the top, with the leftmost pixel in the high bit
+///
+/// The glyph takes the size of the font, so the columns and rows past it aren't drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    rows: [u8; MAX_HEIGHT],
+}
+
+impl Bitmap {
+    /// Bitmap from its rows in hex, two digits per row, such as `00183c7e` for the top of a
+    /// triangle
+    ///
+    /// The rows left out are blank. Returns `None` with an odd number of digits, an invalid
+    /// digit or more than `MAX_HEIGHT` rows.
+    pub fn parse_hex(hex: &str) -> Option<Self> {
+        let hex = hex.as_bytes();
+        if hex.len() % 2 != 0 || hex.len() / 2 > MAX_HEIGHT {
+            return None;
+        }
+        let mut rows = [0; MAX_HEIGHT];
+        for (row, digits) in rows.iter_mut().zip(hex.chunks(2)) {
+            let digits = core::str::from_utf8(digits).ok()?;
+            *row = u8::from_str_radix(digits, 16).ok()?;
+        }
+        Some(Self { rows })
+    }
+
+    /// Returns true if the pixel at column `x` and row `y` is on
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        x < MAX_WIDTH && self.rows.get(y).map_or(false, |row| row & (0x80 >> x) != 0)
+    }
+}
+
+/// Byte of the cells for `code_point`, if it is the one of a custom glyph
+pub fn cell_byte(code_point: u32) -> Option<u8> {
+    let index = code_point.checked_sub(FIRST_CODE_POINT)? as usize;
+    (index < MAX_GLYPHS).then(|| FIRST_BYTE + index as u8)
+}
+
+/// Custom glyphs defined by the host, by index from `FIRST_CODE_POINT`
+pub struct CustomGlyphs {
+    glyphs: [Option<Bitmap>; MAX_GLYPHS],
+}
+
+impl CustomGlyphs {
+    pub const fn new() -> Self {
+        Self {
+            glyphs: [None; MAX_GLYPHS],
+        }
+    }
+
+    /// Define the glyph `index`, or remove it with `None`, returning false if the index is out of
+    /// range
+    pub fn set(&mut self, index: usize, bitmap: Option<Bitmap>) -> bool {
+        match self.glyphs.get_mut(index) {
+            Some(glyph) => {
+                *glyph = bitmap;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove all the glyphs
+    pub fn clear(&mut self) {
+        self.glyphs = [None; MAX_GLYPHS];
+    }
+
+    /// Glyph of a byte of the cells, if it is defined
+    pub fn get(&self, byte: u8) -> Option<&Bitmap> {
+        let index = byte.checked_sub(FIRST_BYTE)? as usize;
+        self.glyphs.get(index)?.as_ref()
+    }
+
+    /// Indexes of the glyphs defined
+    pub fn defined(&self) -> impl Iterator<Item = usize> + '_ {
+        self.glyphs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, glyph)| glyph.map(|_| index))
+    }
+}
@@ -0,0 +1,185 @@
+//! CSV data logger in flash
+//!
+//! Appends lines of samples to a ring of flash sectors, erasing the oldest sector when the ring
+//! is full. Lines are kept in RAM until a page is full, and each page carries a checksum, so a
+//! power loss mid-write loses at most the page being written instead of corrupting the log.
+
+use crate::flash::{self, checksum, PAGE_SIZE, SECTOR_SIZE};
+
+/// Start of the log area, from the start of the flash
+pub const LOG_OFFSET: u32 = 0x1C_0000;
+/// Size of the log area
+pub const LOG_SIZE: u32 = 0x3_0000;
+
+const PAGES: u32 = LOG_SIZE / PAGE_SIZE;
+
+/// Page header: magic, sequence number, data length and checksum
+const MAGIC: [u8; 2] = *b"LG";
+const HEADER_SIZE: usize = 10;
+/// Bytes of data in a page
+pub const PAGE_DATA_SIZE: usize = PAGE_SIZE as usize - HEADER_SIZE;
+
+/// Data of the page `index`, if it is valid
+fn read_page(index: u32) -> Option<(u32, &'static [u8])> {
+    let page = flash::read(LOG_OFFSET + index * PAGE_SIZE, PAGE_SIZE as usize);
+    if page[0..2] != MAGIC {
+        return None;
+    }
+    let seq = u32::from_le_bytes([page[2], page[3], page[4], page[5]]);
+    let len = u16::from_le_bytes([page[6], page[7]]) as usize;
+    let sum = u16::from_le_bytes([page[8], page[9]]);
+    if len > PAGE_DATA_SIZE || checksum(&page[2..8]) ^ checksum(&page[HEADER_SIZE..][..len]) != sum
+    {
+        return None;
+    }
+    Some((seq, &page[HEADER_SIZE..][..len]))
+}
+
+fn is_erased(index: u32) -> bool {
+    flash::read(LOG_OFFSET + index * PAGE_SIZE, PAGE_SIZE as usize)
+        .iter()
+        .all(|&byte| byte == 0xFF)
+}
+
+/// Sequence number and index of the newest valid page
+fn newest_page() -> Option<(u32, u32)> {
+    (0..PAGES)
+        .filter_map(|index| read_page(index).map(|(seq, _)| (seq, index)))
+        .max()
+}
+
+/// Read the log stored in flash, without a `DataLog`
+///
+/// This is slower than `DataLog::dump`, as it looks for the oldest page first.
+pub fn stored() -> LogDump {
+    LogDump {
+        next: newest_page().map_or(0, |(_, index)| (index + 1) % PAGES),
+        remaining: PAGES,
+    }
+}
+
+/// Data logger
+pub struct DataLog {
+    /// Page written next
+    next_page: u32,
+    /// Sequence number of the next page
+    seq: u32,
+    page: [u8; PAGE_SIZE as usize],
+    len: usize,
+    running: bool,
+}
+
+impl DataLog {
+    /// Find where the log stopped, from the pages already in flash
+    pub fn open() -> Self {
+        let (next_page, seq) = match newest_page() {
+            Some((seq, index)) => ((index + 1) % PAGES, seq.wrapping_add(1)),
+            None => (0, 0),
+        };
+
+        Self {
+            next_page,
+            seq,
+            page: [0xFF; PAGE_SIZE as usize],
+            len: 0,
+            running: false,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+    }
+
+    /// Stop logging, writing the lines still in RAM
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.flush();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Append a line, if the logger is running
+    ///
+    /// Lines longer than a page are cut.
+    pub fn append(&mut self, line: &[u8]) {
+        if !self.running {
+            return;
+        }
+        let line = &line[..line.len().min(PAGE_DATA_SIZE)];
+        if self.len + line.len() > PAGE_DATA_SIZE {
+            self.flush();
+        }
+        self.page[HEADER_SIZE + self.len..][..line.len()].copy_from_slice(line);
+        self.len += line.len();
+    }
+
+    /// Write the lines kept in RAM to flash
+    pub fn flush(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+
+        self.page[0..2].copy_from_slice(&MAGIC);
+        self.page[2..6].copy_from_slice(&self.seq.to_le_bytes());
+        self.page[6..8].copy_from_slice(&(self.len as u16).to_le_bytes());
+        let sum = checksum(&self.page[2..8]) ^ checksum(&self.page[HEADER_SIZE..][..self.len]);
+        self.page[8..10].copy_from_slice(&sum.to_le_bytes());
+
+        // Pages that were partially written before a power loss are skipped. Entering a new
+        // sector erases it, dropping the oldest data.
+        loop {
+            let offset = LOG_OFFSET + self.next_page * PAGE_SIZE;
+            if offset % SECTOR_SIZE == 0 {
+                // Note (safety): the sector is in the log area
+                unsafe { flash::erase(offset, SECTOR_SIZE) };
+                break;
+            }
+            if is_erased(self.next_page) {
+                break;
+            }
+            self.next_page = (self.next_page + 1) % PAGES;
+        }
+
+        // Note (safety): the page is in the log area, and was erased
+        unsafe { flash::program(LOG_OFFSET + self.next_page * PAGE_SIZE, &self.page) };
+
+        self.next_page = (self.next_page + 1) % PAGES;
+        self.seq = self.seq.wrapping_add(1);
+        self.page = [0xFF; PAGE_SIZE as usize];
+        self.len = 0;
+    }
+
+    /// Read the log from the oldest page, one page at a time
+    ///
+    /// Lines still in RAM are not included, see `flush`.
+    pub fn dump(&self) -> LogDump {
+        LogDump {
+            next: self.next_page,
+            remaining: PAGES,
+        }
+    }
+}
+
+/// Iterator over the data of the pages in flash, oldest first
+pub struct LogDump {
+    next: u32,
+    remaining: u32,
+}
+
+impl Iterator for LogDump {
+    type Item = &'static [u8];
+
+    fn next(&mut self) -> Option<&'static [u8]> {
+        while self.remaining > 0 {
+            let index = self.next;
+            self.next = (self.next + 1) % PAGES;
+            self.remaining -= 1;
+            if let Some((_, data)) = read_page(index) {
+                return Some(data);
+            }
+        }
+        None
+    }
+}
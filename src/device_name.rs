@@ -0,0 +1,113 @@
+//! Device names
+//!
+//! A name such as `brave-otter-42`, derived from the unique ID of the flash, so boards on the
+//! same desk can be told apart by more than a serial number. It can be replaced in the settings.
+
+use crate::hash::Crc32;
+use core::fmt::{self, Write};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Longest name, in bytes
+pub const MAX_NAME: usize = 24;
+
+const ADJECTIVES: [&str; 32] = [
+    "brave", "calm", "clever", "cosy", "crisp", "daring", "eager", "fancy", "fuzzy", "gentle",
+    "giddy", "happy", "jolly", "keen", "lively", "lucky", "mellow", "merry", "nimble", "plucky",
+    "proud", "quick", "quiet", "rapid", "shiny", "silly", "sleepy", "snappy", "sunny", "swift",
+    "tidy", "witty",
+];
+
+const ANIMALS: [&str; 32] = [
+    "badger", "beaver", "bison", "crab", "crane", "dingo", "eagle", "ferret", "finch", "gecko",
+    "heron", "ibis", "koala", "lemur", "lynx", "marten", "moose", "newt", "otter", "owl",
+    "panda", "puffin", "quokka", "raven", "robin", "seal", "stoat", "tapir", "toad", "walrus",
+    "wombat", "yak",
+];
+
+/// Name of the device, made of lower case letters, digits and dashes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceName {
+    bytes: [u8; MAX_NAME],
+    len: u8,
+}
+
+impl DeviceName {
+    /// Derive the name from a unique ID, always giving the same name for the same ID
+    pub fn from_unique_id(id: &[u8; 8]) -> Self {
+        let mut crc = Crc32::new();
+        crc.update(id);
+        let hash = crc.finish();
+
+        let adjective = ADJECTIVES[hash as usize % ADJECTIVES.len()];
+        let animal = ANIMALS[(hash >> 5) as usize % ANIMALS.len()];
+        let number = (hash >> 10) % 100;
+
+        let mut name = Self {
+            bytes: [0; MAX_NAME],
+            len: 0,
+        };
+        // The longest words and number fit
+        let _ = write!(name, "{}-{}-{}", adjective, animal, number);
+        name
+    }
+
+    /// Parse a name given by the user, or stored in flash
+    ///
+    /// Returns `None` if it is empty, too long, or has characters other than lower case letters,
+    /// digits and dashes.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let valid = |byte: &u8| byte.is_ascii_lowercase() || byte.is_ascii_digit() || *byte == b'-';
+        if bytes.is_empty() || bytes.len() > MAX_NAME || !bytes.iter().all(valid) {
+            return None;
+        }
+        let mut name = Self {
+            bytes: [0; MAX_NAME],
+            len: bytes.len() as u8,
+        };
+        name.bytes[..bytes.len()].copy_from_slice(bytes);
+        Some(name)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Note (safety): the bytes are only ever ASCII
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len as usize]) }
+    }
+}
+
+impl Write for DeviceName {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.len as usize;
+        let end = start + s.len();
+        if end > MAX_NAME {
+            return Err(fmt::Error);
+        }
+        self.bytes[start..end].copy_from_slice(s.as_bytes());
+        self.len = end as u8;
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Stored as its string
+impl Serialize for DeviceName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Checked like the names given by the user
+impl<'de> Deserialize<'de> for DeviceName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = <&str>::deserialize(deserializer)?;
+        Self::parse(name.as_bytes()).ok_or_else(|| de::Error::custom("invalid device name"))
+    }
+}
@@ -0,0 +1,63 @@
+//! Diagnostics
+//!
+//! Macros for the diagnostics of the firmware. With the `defmt` feature they are defmt logs, sent
+//! over RTT to a debug probe without formatting them on the device. Otherwise they are records
+//! of the `log` crate, see the `logger` module.
+//!
+//! The format strings have to suit both, so the arguments stick to `{}` and `{:?}` of numbers,
+//! strings and types deriving `defmt::Format` with the feature. The code generated by defmt
+//! names its crate, so an application using the feature depends on `defmt` too.
+
+/// Diagnostic of a failure the firmware works around
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! diag_warn {
+    ($($arg:tt)*) => {
+        ::defmt::warn!($($arg)*)
+    };
+}
+
+/// Diagnostic of a failure the firmware works around
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! diag_warn {
+    ($($arg:tt)*) => {
+        $crate::log::warn!($($arg)*)
+    };
+}
+
+/// Diagnostic of a change of state
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! diag_info {
+    ($($arg:tt)*) => {
+        ::defmt::info!($($arg)*)
+    };
+}
+
+/// Diagnostic of a change of state
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! diag_info {
+    ($($arg:tt)*) => {
+        $crate::log::info!($($arg)*)
+    };
+}
+
+/// Diagnostic only useful while debugging, such as each command run
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! diag_debug {
+    ($($arg:tt)*) => {
+        ::defmt::debug!($($arg)*)
+    };
+}
+
+/// Diagnostic only useful while debugging, such as each command run
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! diag_debug {
+    ($($arg:tt)*) => {
+        $crate::log::debug!($($arg)*)
+    };
+}
@@ -0,0 +1,251 @@
+//! Pico Display panel
+//!
+//! The Pimoroni Pico Display is a 240x135 ST7789 panel, wired to SPI0.
+
+use crate::metrics::Metric;
+use crate::{hal, pac, DummyPin};
+use display_interface_spi::SPIInterface;
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_time::rate::*;
+use hal::gpio::pin::{bank0, FunctionSpi, Pin, PushPullOutput};
+
+/// Width of the panel, in pixels
+pub const WIDTH: u16 = 240;
+/// Height of the panel, in pixels
+pub const HEIGHT: u16 = 135;
+
+pub type DisplaySpi = CountingSpi<hal::spi::Spi<hal::spi::Enabled, pac::SPI0, 8>>;
+pub type DcPin = Pin<bank0::Gpio16, PushPullOutput>;
+pub type CsPin = Pin<bank0::Gpio17, PushPullOutput>;
+pub type SclkPin = Pin<bank0::Gpio18, FunctionSpi>;
+pub type MosiPin = Pin<bank0::Gpio19, FunctionSpi>;
+/// Data out of the panel, only used to check it is still answering
+pub type MisoPin = Pin<bank0::Gpio0, FunctionSpi>;
+
+/// Driver for the panel
+pub type Display = st7789::ST7789<SPIInterface<DisplaySpi, DcPin, CsPin>, DummyPin>;
+
+/// Set up SPI0 and initialize the panel, clearing it to black
+///
+/// The clock and data pins are only taken to make sure they are configured for SPI. The panel
+/// data out isn't wired on the Pico Display, see `read_id`.
+pub fn init<D: DelayUs<u32>>(
+    spi: pac::SPI0,
+    dc: DcPin,
+    cs: CsPin,
+    _sclk: SclkPin,
+    _mosi: MosiPin,
+    _miso: MisoPin,
+    resets: &mut pac::RESETS,
+    delay: &mut D,
+) -> Display {
+    let spi = hal::spi::Spi::<_, _, 8>::new(spi).init(
+        resets,
+        125_000_000u32.Hz(),
+        16_000_000u32.Hz(),
+        &embedded_hal::spi::MODE_0,
+    );
+    let interface = SPIInterface::new(CountingSpi(spi), dc, cs);
+    let mut display = st7789::ST7789::new(interface, DummyPin, WIDTH, HEIGHT);
+    reinit(&mut display, delay);
+
+    display
+}
+
+/// Run the init sequence of the panel again and clear it to black
+///
+/// This recovers a panel that lost its state, such as after a glitch on its supply or cable.
+/// The mode and orientation are back to their defaults.
+pub fn reinit<D: DelayUs<u32>>(display: &mut Display, delay: &mut D) {
+    display.init(delay).unwrap();
+    display
+        .set_orientation(st7789::Orientation::LandscapeSwapped)
+        .unwrap();
+    display.clear(Rgb565::BLACK).unwrap();
+}
+
+/// Bytes sent to the panel since boot
+static SPI_BYTES: Metric = Metric::counter("panel_spi_bytes");
+/// Memory writes started since boot
+static RAM_WRITES: Metric = Metric::counter("panel_ram_writes");
+
+/// What the driver sent to the panel since boot, to compare drawing configurations
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PanelStats {
+    pub spi_bytes: u32,
+    /// Areas of the panel memory written, each one being a frame sent to the panel
+    pub ram_writes: u32,
+}
+
+pub fn stats() -> PanelStats {
+    PanelStats {
+        spi_bytes: SPI_BYTES.total(),
+        ram_writes: RAM_WRITES.total(),
+    }
+}
+
+/// SPI bus counting what it sends, for `stats`
+pub struct CountingSpi<S>(S);
+
+impl<S: embedded_hal::blocking::spi::Write<u8>> embedded_hal::blocking::spi::Write<u8>
+    for CountingSpi<S>
+{
+    type Error = S::Error;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        // Note (safety): reading the output levels has no side effect
+        let dc_low = unsafe { (*pac::SIO::ptr()).gpio_out.read().bits() } & DC == 0;
+        count(words.len() as u32, (dc_low && words == [RAMWR]) as u32);
+        self.0.write(words)
+    }
+}
+
+/// Add to the counts of `stats`, for what is sent to the panel without the driver
+pub(crate) fn count(spi_bytes: u32, ram_writes: u32) {
+    SPI_BYTES.add(spi_bytes);
+    RAM_WRITES.add(ram_writes);
+}
+
+/// Read the ID of the panel, returning `None` if it doesn't answer
+///
+/// This needs the data out of the panel wired to GPIO0. Otherwise, the line reads as all zeros
+/// or all ones, which isn't a valid ID.
+pub fn read_id(display: &mut Display) -> Option<[u8; 3]> {
+    let mut response = [0; 4];
+    transfer(display, RDDID, &[], &mut response);
+    // The panel sends a dummy bit before the ID
+    let bits = u32::from_be_bytes(response) << 1;
+    let [a, b, c, _] = bits.to_be_bytes();
+    match [a, b, c] {
+        [0, 0, 0] | [0xFF, 0xFF, 0xFF] => None,
+        id => Some(id),
+    }
+}
+
+/// Largest area read at once by `read_pixels`, in pixels
+pub const MAX_READ_PIXELS: usize = 256;
+
+/// Read the pixels of `area` back from the panel memory, row by row
+///
+/// Like `read_id`, this needs the data out of the panel wired to GPIO0. The panel sends 6 bits
+/// per channel, which are cut down to RGB565. `area` is in panel memory coordinates, and at most
+/// `MAX_READ_PIXELS` are read.
+pub fn read_pixels(display: &mut Display, area: &Rectangle, out: &mut [Rgb565]) {
+    let (x, y) = (area.top_left.x as u16, area.top_left.y as u16);
+    let (width, height) = (area.size.width as u16, area.size.height as u16);
+    if width == 0 || height == 0 {
+        return;
+    }
+    set_window(display, x, y, width, height);
+
+    let len = out.len().min(area.size.width as usize * area.size.height as usize);
+    let len = len.min(MAX_READ_PIXELS);
+    let mut response = [0; 3 * MAX_READ_PIXELS + 1];
+    transfer(display, RAMRD, &[], &mut response[..3 * len + 1]);
+    // The panel sends a dummy bit before the pixels, see `read_id`
+    for (i, pixel) in out[..len].iter_mut().enumerate() {
+        let bits = |at: usize| response[at] << 1 | response[at + 1] >> 7;
+        let (r, g, b) = (bits(3 * i), bits(3 * i + 1), bits(3 * i + 2));
+        *pixel = Rgb565::new(r >> 3, g >> 2, b >> 3);
+    }
+}
+
+/// Set the area of the panel memory the next pixels are read from or written to
+pub(crate) fn set_window(display: &mut Display, x: u16, y: u16, width: u16, height: u16) {
+    let (start, end) = (x.to_be_bytes(), (x + width - 1).to_be_bytes());
+    write_command(display, CASET, &[start[0], start[1], end[0], end[1]]);
+    let (start, end) = (y.to_be_bytes(), (y + height - 1).to_be_bytes());
+    write_command(display, RASET, &[start[0], start[1], end[0], end[1]]);
+}
+
+/// Power states of the panel, on top of the sleep mode of the driver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanelMode {
+    /// Full refresh, full colors
+    Normal,
+    /// Only the 8 colors with each channel fully on or off are shown, which draws less power
+    Idle,
+    /// Only refresh the panel lines from `start` to `end`, the others are blank
+    ///
+    /// The panel lines are physical rows of the panel, so they run along the screen X axis in
+    /// landscape.
+    Partial { start: u16, end: u16 },
+}
+
+/// Put the panel in `mode`
+///
+/// The drawing commands are the same in every mode. Idle and partial mode only change how the
+/// panel shows its memory.
+pub fn set_mode(display: &mut Display, mode: PanelMode) {
+    match mode {
+        PanelMode::Normal => {
+            write_command(display, IDMOFF, &[]);
+            write_command(display, NORON, &[]);
+        }
+        PanelMode::Idle => write_command(display, IDMON, &[]),
+        PanelMode::Partial { start, end } => {
+            let (start, end) = (start.to_be_bytes(), end.to_be_bytes());
+            write_command(display, PTLAR, &[start[0], start[1], end[0], end[1]]);
+            write_command(display, PTLON, &[]);
+        }
+    }
+}
+
+/// Read display ID
+const RDDID: u8 = 0x04;
+/// Partial mode on
+const PTLON: u8 = 0x12;
+/// Normal mode on, which also leaves partial mode
+const NORON: u8 = 0x13;
+/// Column address set
+const CASET: u8 = 0x2A;
+/// Row address set
+const RASET: u8 = 0x2B;
+/// Memory write
+pub(crate) const RAMWR: u8 = 0x2C;
+/// Memory read
+const RAMRD: u8 = 0x2E;
+/// Partial area
+const PTLAR: u8 = 0x30;
+/// Idle mode off
+const IDMOFF: u8 = 0x38;
+/// Idle mode on
+const IDMON: u8 = 0x39;
+
+/// Data or command pin, low for a command
+pub(crate) const DC: u32 = 1 << 16;
+/// Chip select pin, active low
+pub(crate) const CS: u32 = 1 << 17;
+
+/// Send a command to the panel, bypassing the driver which has no way to send arbitrary commands
+pub(crate) fn write_command(display: &mut Display, command: u8, params: &[u8]) {
+    transfer(display, command, params, &mut []);
+}
+
+/// Send a command and its parameters, then read `response.len()` bytes back
+///
+/// Borrowing the display makes sure the driver isn't using the SPI bus at the same time.
+fn transfer(_display: &mut Display, command: u8, params: &[u8], response: &mut [u8]) {
+    // Note (safety): the display owns SPI0 and the DC and CS pins, and it is borrowed for the
+    // duration of the command.
+    let (spi, sio) = unsafe { (&*pac::SPI0::ptr(), &*pac::SIO::ptr()) };
+    // Returns the byte received at the same time
+    let send = |byte: u8| {
+        while spi.sspsr.read().tnf().bit_is_clear() {}
+        spi.sspdr.write(|w| unsafe { w.data().bits(byte as u16) });
+        while spi.sspsr.read().rne().bit_is_clear() {}
+        spi.sspdr.read().data().bits() as u8
+    };
+
+    // The command byte is sent with DC low, its parameters with DC high
+    sio.gpio_out_clr.write(|w| unsafe { w.bits(CS | DC) });
+    send(command);
+    sio.gpio_out_set.write(|w| unsafe { w.bits(DC) });
+    params.iter().for_each(|&byte| {
+        send(byte);
+    });
+    response.iter_mut().for_each(|byte| *byte = send(0));
+    while spi.sspsr.read().bsy().bit_is_set() {}
+    sio.gpio_out_set.write(|w| unsafe { w.bits(CS) });
+}
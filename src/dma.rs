@@ -0,0 +1,157 @@
+//! DMA transfers to the panel
+//!
+//! Streams pixels from RAM to SPI0 with a DMA channel, so the CPU carries on with other work while
+//! an area of the panel is written, instead of waiting on each byte.
+
+use crate::display::{self, Display};
+use crate::pac;
+use embedded_graphics::primitives::Rectangle;
+
+/// Data request of the SPI0 transmit FIFO
+const DREQ_SPI0_TX: u8 = 16;
+
+/// DMA channel writing to the panel
+pub struct PanelDma {
+    channel: usize,
+}
+
+impl PanelDma {
+    /// Take `channel`, from 0 to 11, bringing the DMA controller out of reset
+    ///
+    /// The channel must not be used by anything else.
+    pub fn new(channel: usize, resets: &mut pac::RESETS) -> Self {
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+        Self { channel }
+    }
+
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Raise `DMA_IRQ_0` at the end of each transfer, instead of polling `PanelTransfer::is_done`
+    ///
+    /// The handler must call `clear_interrupt` with the channel.
+    pub fn enable_interrupt(&mut self) {
+        // Note (safety): only the bit of the channel is changed
+        let dma = unsafe { &*pac::DMA::ptr() };
+        dma.inte0.modify(|r, w| unsafe { w.bits(r.bits() | 1 << self.channel) });
+    }
+
+    /// Start writing `pixels` to `area` of the panel memory, row by row
+    ///
+    /// The pixels are raw RGB565 values, such as from `RawU16::from(color).into_inner()`. The
+    /// display and the pixels stay borrowed until the transfer is finished, and dropping the
+    /// transfer waits for it.
+    pub fn write<'a>(
+        &'a mut self,
+        display: &'a mut Display,
+        area: &Rectangle,
+        pixels: &'a [u16],
+    ) -> PanelTransfer<'a> {
+        let (x, y) = (area.top_left.x as u16, area.top_left.y as u16);
+        let (width, height) = (area.size.width as u16, area.size.height as u16);
+        let len = pixels.len().min(width as usize * height as usize);
+        if len == 0 {
+            return PanelTransfer {
+                channel: self.channel,
+                active: false,
+                _display: display,
+                _pixels: pixels,
+            };
+        }
+        display::set_window(display, x, y, width, height);
+        display::write_command(display, display::RAMWR, &[]);
+        display::count(2 * len as u32, 1);
+
+        // Note (safety): the display owns SPI0 and the DC and CS pins, and it stays borrowed
+        // until the transfer is finished. The channel is only used by this `PanelDma`.
+        let (spi, sio, dma) = unsafe { (&*pac::SPI0::ptr(), &*pac::SIO::ptr(), &*pac::DMA::ptr()) };
+        // With 16 bit frames, each pixel is sent high byte first as the panel expects
+        while spi.sspsr.read().bsy().bit_is_set() {}
+        spi.sspcr0.modify(|_, w| unsafe { w.dss().bits(15) });
+        spi.sspdmacr.write(|w| w.txdmae().set_bit());
+        // The panel takes the data as the pixels of the memory write, until CS goes high
+        sio.gpio_out_clr.write(|w| unsafe { w.bits(display::CS) });
+        sio.gpio_out_set.write(|w| unsafe { w.bits(display::DC) });
+
+        let ch = &dma.ch[self.channel];
+        ch.ch_read_addr.write(|w| unsafe { w.bits(pixels.as_ptr() as u32) });
+        ch.ch_write_addr.write(|w| unsafe { w.bits(&spi.sspdr as *const _ as u32) });
+        ch.ch_trans_count.write(|w| unsafe { w.bits(len as u32) });
+        // Chaining to itself means no chaining
+        ch.ch_ctrl_trig.write(|w| unsafe {
+            w.data_size()
+                .size_halfword()
+                .incr_read()
+                .set_bit()
+                .incr_write()
+                .clear_bit()
+                .treq_sel()
+                .bits(DREQ_SPI0_TX)
+                .chain_to()
+                .bits(self.channel as u8)
+                .en()
+                .set_bit()
+        });
+
+        PanelTransfer {
+            channel: self.channel,
+            active: true,
+            _display: display,
+            _pixels: pixels,
+        }
+    }
+}
+
+/// Clear the interrupt of `channel`, from the `DMA_IRQ_0` handler
+pub fn clear_interrupt(channel: usize) {
+    // Note (safety): writing a one only clears the bit of the channel
+    let dma = unsafe { &*pac::DMA::ptr() };
+    dma.ints0.write(|w| unsafe { w.bits(1 << channel) });
+}
+
+/// Pixels being written to the panel
+pub struct PanelTransfer<'a> {
+    channel: usize,
+    active: bool,
+    _display: &'a mut Display,
+    _pixels: &'a [u16],
+}
+
+impl PanelTransfer<'_> {
+    /// Whether the DMA channel sent all the pixels
+    ///
+    /// The last ones may still be in the SPI FIFO, `wait` also waits for them.
+    pub fn is_done(&self) -> bool {
+        // Note (safety): reading the control register has no side effect
+        let dma = unsafe { &*pac::DMA::ptr() };
+        !self.active || dma.ch[self.channel].ch_ctrl_trig.read().busy().bit_is_clear()
+    }
+
+    /// Wait for the end of the transfer, giving the display back
+    pub fn wait(self) {}
+}
+
+impl Drop for PanelTransfer<'_> {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        while !self.is_done() {}
+
+        // Note (safety): the display is still borrowed, see `PanelDma::write`
+        let (spi, sio) = unsafe { (&*pac::SPI0::ptr(), &*pac::SIO::ptr()) };
+        while spi.sspsr.read().bsy().bit_is_set() {}
+        sio.gpio_out_set.write(|w| unsafe { w.bits(display::CS) });
+        spi.sspdmacr.write(|w| w.txdmae().clear_bit());
+        spi.sspcr0.modify(|_, w| unsafe { w.dss().bits(7) });
+        // Nothing was read during the transfer, so the receive FIFO is full of junk that the
+        // driver would otherwise take as its next replies
+        while spi.sspsr.read().rne().bit_is_set() {
+            spi.sspdr.read();
+        }
+        // Clear the receive overrun
+        spi.sspicr.write(|w| w.sspric().set_bit());
+    }
+}
@@ -0,0 +1,158 @@
+//! Two display panels
+//!
+//! A second ST7789 panel on SPI1, next to the Pico Display on SPI0. Each panel can be drawn on
+//! as its own screen, or both joined into a single wide screen with a seam in the middle.
+
+use crate::display::{HEIGHT, WIDTH};
+use crate::{hal, pac, DummyPin};
+use display_interface_spi::SPIInterface;
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_time::rate::*;
+use hal::gpio::pin::{bank0, FunctionSpi, Pin, PushPullOutput};
+
+pub type SecondSpi = hal::spi::Spi<hal::spi::Enabled, pac::SPI1, 8>;
+pub type SecondDcPin = Pin<bank0::Gpio21, PushPullOutput>;
+pub type SecondCsPin = Pin<bank0::Gpio9, PushPullOutput>;
+pub type SecondSclkPin = Pin<bank0::Gpio10, FunctionSpi>;
+pub type SecondMosiPin = Pin<bank0::Gpio11, FunctionSpi>;
+
+/// Driver for the second panel
+pub type SecondDisplay =
+    st7789::ST7789<SPIInterface<SecondSpi, SecondDcPin, SecondCsPin>, DummyPin>;
+
+/// Top left corner of the visible area in the memory of the panels
+pub const VISIBLE_OFFSET: Point = Point::new(40, 53);
+/// X coordinate of the first column of the right panel, on a `WideScreen`
+pub const SEAM: i32 = WIDTH as i32;
+
+/// Set up SPI1 and initialize the second panel, clearing it to black
+///
+/// The panel is wired like the Pico Display, with its clock, data, chip select and data/command
+/// lines on GPIO10, GPIO11, GPIO9 and GPIO21.
+pub fn init_second<D: DelayUs<u32>>(
+    spi: pac::SPI1,
+    dc: SecondDcPin,
+    cs: SecondCsPin,
+    _sclk: SecondSclkPin,
+    _mosi: SecondMosiPin,
+    resets: &mut pac::RESETS,
+    delay: &mut D,
+) -> SecondDisplay {
+    let spi = hal::spi::Spi::<_, _, 8>::new(spi).init(
+        resets,
+        125_000_000u32.Hz(),
+        16_000_000u32.Hz(),
+        &embedded_hal::spi::MODE_0,
+    );
+    let interface = SPIInterface::new(spi, dc, cs);
+    let mut display = st7789::ST7789::new(interface, DummyPin, WIDTH, HEIGHT);
+    display.init(delay).unwrap();
+    display
+        .set_orientation(st7789::Orientation::LandscapeSwapped)
+        .unwrap();
+    display.clear(Rgb565::BLACK).unwrap();
+
+    display
+}
+
+/// Two panels side by side, drawn as a single screen of twice the width
+///
+/// Coordinates start at the top left of the visible area of the left panel, and the right
+/// panel starts at `SEAM`. Text crossing the seam is cut by the bezels, see `avoid_seam`.
+pub struct WideScreen<L, R> {
+    left: L,
+    right: R,
+}
+
+impl<L, R> WideScreen<L, R> {
+    pub fn new(left: L, right: R) -> Self {
+        Self { left, right }
+    }
+
+    /// Left panel, to draw on it on its own
+    pub fn left_mut(&mut self) -> &mut L {
+        &mut self.left
+    }
+
+    /// Right panel, to draw on it on its own
+    pub fn right_mut(&mut self) -> &mut R {
+        &mut self.right
+    }
+
+    /// Split the screen back into its panels
+    pub fn into_inner(self) -> (L, R) {
+        (self.left, self.right)
+    }
+}
+
+/// Area of the left panel on a `WideScreen`
+fn left_area() -> Rectangle {
+    Rectangle::new(Point::zero(), Size::new(WIDTH as u32, HEIGHT as u32))
+}
+
+/// Area of the right panel on a `WideScreen`
+fn right_area() -> Rectangle {
+    Rectangle::new(Point::new(SEAM, 0), Size::new(WIDTH as u32, HEIGHT as u32))
+}
+
+/// Returns true if `area` is on both panels
+pub fn crosses_seam(area: &Rectangle) -> bool {
+    !area.intersection(&left_area()).is_zero_sized()
+        && !area.intersection(&right_area()).is_zero_sized()
+}
+
+/// Move an area crossing the seam to the start of the right panel, if it fits there
+///
+/// This keeps text and widgets from being cut by the bezels.
+pub fn avoid_seam(area: Rectangle) -> Rectangle {
+    if crosses_seam(&area) && area.size.width <= WIDTH as u32 {
+        Rectangle::new(Point::new(SEAM, area.top_left.y), area.size)
+    } else {
+        area
+    }
+}
+
+impl<L, R> OriginDimensions for WideScreen<L, R> {
+    fn size(&self) -> Size {
+        Size::new(2 * WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+impl<L, R> DrawTarget for WideScreen<L, R>
+where
+    L: DrawTarget,
+    R: DrawTarget<Color = L::Color, Error = L::Error>,
+{
+    type Color = L::Color;
+    type Error = L::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if left_area().contains(point) {
+                let pixel = Pixel(point + VISIBLE_OFFSET, color);
+                self.left.draw_iter(core::iter::once(pixel))?;
+            } else if right_area().contains(point) {
+                let pixel = Pixel(point - Point::new(SEAM, 0) + VISIBLE_OFFSET, color);
+                self.right.draw_iter(core::iter::once(pixel))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let left = area.intersection(&left_area());
+        if !left.is_zero_sized() {
+            self.left.fill_solid(&left.translate(VISIBLE_OFFSET), color)?;
+        }
+        let right = area.intersection(&right_area());
+        if !right.is_zero_sized() {
+            let offset = VISIBLE_OFFSET - Point::new(SEAM, 0);
+            self.right.fill_solid(&right.translate(offset), color)?;
+        }
+        Ok(())
+    }
+}
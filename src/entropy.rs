@@ -0,0 +1,58 @@
+//! Random numbers from the ring oscillator
+//!
+//! The ring oscillator runs freely from the other clocks, so sampling its output gives bits with
+//! some randomness. They are biased, so pairs of bits go through a von Neumann extractor first.
+
+use crate::pac::ROSC;
+use rand_core::{impls, Error, RngCore};
+
+/// Random number generator reading the ring oscillator
+///
+/// This is good enough for salts and jitter, but isn't a vetted cryptographic generator.
+pub struct Entropy {
+    rosc: ROSC,
+}
+
+impl Entropy {
+    /// The ring oscillator runs from reset, unless it was disabled
+    pub fn new(rosc: ROSC) -> Self {
+        Self { rosc }
+    }
+
+    pub fn free(self) -> ROSC {
+        self.rosc
+    }
+
+    fn raw_bit(&self) -> bool {
+        self.rosc.randombit.read().randombit().bit()
+    }
+
+    /// Unbiased bit: `10` gives 1 and `01` gives 0, equal pairs are dropped
+    fn bit(&self) -> bool {
+        loop {
+            let (first, second) = (self.raw_bit(), self.raw_bit());
+            if first != second {
+                return first;
+            }
+        }
+    }
+}
+
+impl RngCore for Entropy {
+    fn next_u32(&mut self) -> u32 {
+        (0..32).fold(0, |value, _| value << 1 | self.bit() as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
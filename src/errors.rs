@@ -0,0 +1,133 @@
+//! Data loss tracking
+//!
+//! Counts the bytes lost on the data paths of the device, so data loss is never silent: the
+//! counts are shown in the status bar and flash the LED through `ErrorLed`.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Ways data can be lost
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Bytes received from the host could not be read
+    RxOverflow,
+    /// Bytes for the host were dropped
+    TxDrop,
+    /// Bytes did not fit in an internal queue
+    QueueOverrun,
+}
+
+/// Copy of the error counters at a point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ErrorSnapshot {
+    pub rx_overflow: u32,
+    pub tx_drop: u32,
+    pub queue_overrun: u32,
+}
+
+impl ErrorSnapshot {
+    /// Total number of bytes lost
+    pub fn total(&self) -> u32 {
+        self.rx_overflow
+            .wrapping_add(self.tx_drop)
+            .wrapping_add(self.queue_overrun)
+    }
+}
+
+/// Error counters shared between the main loop and interrupts
+pub struct ErrorCounters {
+    rx_overflow: AtomicU32,
+    tx_drop: AtomicU32,
+    queue_overrun: AtomicU32,
+}
+
+impl ErrorCounters {
+    pub const fn new() -> Self {
+        Self {
+            rx_overflow: AtomicU32::new(0),
+            tx_drop: AtomicU32::new(0),
+            queue_overrun: AtomicU32::new(0),
+        }
+    }
+
+    fn counter(&self, kind: ErrorKind) -> &AtomicU32 {
+        match kind {
+            ErrorKind::RxOverflow => &self.rx_overflow,
+            ErrorKind::TxDrop => &self.tx_drop,
+            ErrorKind::QueueOverrun => &self.queue_overrun,
+        }
+    }
+
+    /// Record `count` bytes lost
+    pub fn record(&self, kind: ErrorKind, count: u32) {
+        // The Cortex-M0+ has no atomic read-modify-write instructions
+        cortex_m::interrupt::free(|_| {
+            let counter = self.counter(kind);
+            counter.store(
+                counter.load(Ordering::Relaxed).wrapping_add(count),
+                Ordering::Relaxed,
+            );
+        });
+    }
+
+    /// Current value of the counters
+    pub fn snapshot(&self) -> ErrorSnapshot {
+        ErrorSnapshot {
+            rx_overflow: self.rx_overflow.load(Ordering::Relaxed),
+            tx_drop: self.tx_drop.load(Ordering::Relaxed),
+            queue_overrun: self.queue_overrun.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Return the current value of the counters and reset them
+    pub fn take(&self) -> ErrorSnapshot {
+        cortex_m::interrupt::free(|_| {
+            let snapshot = self.snapshot();
+            self.rx_overflow.store(0, Ordering::Relaxed);
+            self.tx_drop.store(0, Ordering::Relaxed);
+            self.queue_overrun.store(0, Ordering::Relaxed);
+            snapshot
+        })
+    }
+}
+
+/// Error counters of the device
+pub static ERRORS: ErrorCounters = ErrorCounters::new();
+
+/// Amount added to the bucket for each new error
+const BUCKET_FILL: u32 = 100;
+/// Maximum level of the bucket
+const BUCKET_MAX: u32 = 500;
+
+/// Leaky bucket driving the error LED pattern
+///
+/// Each new error fills the bucket, which then drains by one unit every tick. The LED shows
+/// the error pattern for as long as the bucket isn't empty, so a single drop is still visible
+/// and continuous drops keep the pattern going.
+pub struct ErrorLed {
+    level: u32,
+    last_total: u32,
+}
+
+impl ErrorLed {
+    pub const fn new() -> Self {
+        Self {
+            level: 0,
+            last_total: 0,
+        }
+    }
+
+    /// Update the bucket with the latest error total, returns true while errors should be shown
+    pub fn tick(&mut self, total: u32) -> bool {
+        // The counters go down when they are cleared
+        let new_errors = total.saturating_sub(self.last_total);
+        self.last_total = total;
+
+        if new_errors > 0 {
+            self.level = (self.level + BUCKET_FILL).min(BUCKET_MAX);
+        } else {
+            self.level = self.level.saturating_sub(1);
+        }
+
+        self.level > 0
+    }
+}
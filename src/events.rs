@@ -0,0 +1,225 @@
+//! Device event notifications
+//!
+//! Reports physical interactions with the board to the host as tagged lines, such as
+//! `!EVT 1700000000000 button A down`, so host software can tell them apart from the echoed
+//! data.
+
+use crate::clock::Timestamp;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use heapless::String;
+
+/// Prefix of the lines sent to the host
+pub const EVENT_TAG: &str = "!EVT";
+
+/// Maximum length of the text entered in an input field
+pub const MAX_INPUT: usize = 32;
+
+/// Kinds of events, used to filter them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Button,
+    Temperature,
+    Input,
+    Display,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [
+        EventKind::Button,
+        EventKind::Temperature,
+        EventKind::Input,
+        EventKind::Display,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Button => "button",
+            EventKind::Temperature => "temp",
+            EventKind::Input => "input",
+            EventKind::Display => "display",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// Filter mask of kinds separated by commas, such as `button,temp`, or `all`
+    pub fn parse_mask(kinds: &str) -> Option<u8> {
+        if kinds == "all" {
+            return Some(0xFF);
+        }
+        kinds
+            .split(',')
+            .try_fold(0, |mask, name| Some(mask | Self::from_name(name)?.mask()))
+    }
+
+    /// Bit of the kind in the filter mask
+    pub fn mask(self) -> u8 {
+        match self {
+            EventKind::Button => 1 << 0,
+            EventKind::Temperature => 1 << 1,
+            EventKind::Input => 1 << 2,
+            EventKind::Display => 1 << 3,
+        }
+    }
+}
+
+/// Something that happened on the device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A button was pressed or released, by index in A, B, X, Y order
+    Button { index: usize, pressed: bool },
+    /// The temperature went above the alarm threshold, in tenths of degrees Celsius
+    TemperatureHigh(i32),
+    /// The temperature went back below the alarm threshold
+    TemperatureNormal(i32),
+    /// Text was entered in an input field on the device
+    Input(String<MAX_INPUT>),
+    /// The panel stopped answering and was initialized again
+    DisplayReset,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Button { .. } => EventKind::Button,
+            Event::TemperatureHigh(_) | Event::TemperatureNormal(_) => EventKind::Temperature,
+            Event::Input(_) => EventKind::Input,
+            Event::DisplayReset => EventKind::Display,
+        }
+    }
+
+    /// Write the event as a tagged line, see `Timestamp` for the format of the time
+    pub fn write_line(&self, timestamp: Timestamp, out: &mut dyn Write) -> fmt::Result {
+        match *self {
+            Event::Button { index, pressed } => write!(
+                out,
+                "{} {} button {} {}\r\n",
+                EVENT_TAG,
+                timestamp,
+                ["A", "B", "X", "Y"].get(index).unwrap_or(&"?"),
+                if pressed { "down" } else { "up" }
+            ),
+            Event::TemperatureHigh(temp) | Event::TemperatureNormal(temp) => write!(
+                out,
+                "{} {} temp {} {}.{}\r\n",
+                EVENT_TAG,
+                timestamp,
+                if let Event::TemperatureHigh(_) = self {
+                    "high"
+                } else {
+                    "normal"
+                },
+                temp / 10,
+                (temp % 10).abs()
+            ),
+            Event::Input(ref text) => write!(out, "{} {} input {}\r\n", EVENT_TAG, timestamp, text),
+            Event::DisplayReset => write!(out, "{} {} display reset\r\n", EVENT_TAG, timestamp),
+        }
+    }
+}
+
+/// Enable switch and filter for the notifications
+pub struct EventFilter {
+    enabled: AtomicBool,
+    mask: AtomicU8,
+}
+
+impl EventFilter {
+    /// Create a disabled filter that lets all kinds of events through once enabled
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            mask: AtomicU8::new(0xFF),
+        }
+    }
+
+    /// Turn the notifications on or off
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Only send the kinds of events whose bit is set in `mask`, see `EventKind::mask`
+    pub fn set_mask(&self, mask: u8) {
+        self.mask.store(mask, Ordering::Relaxed);
+    }
+
+    pub fn mask(&self) -> u8 {
+        self.mask.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if `event` should be sent to the host
+    pub fn accepts(&self, event: &Event) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+            && self.mask.load(Ordering::Relaxed) & event.kind().mask() != 0
+    }
+}
+
+/// Notification filter of the device
+pub static EVENTS: EventFilter = EventFilter::new();
+
+/// Detects the press and release edges of the buttons
+pub struct ButtonEvents {
+    pressed: [bool; 4],
+}
+
+impl ButtonEvents {
+    pub fn new() -> Self {
+        Self {
+            pressed: [false; 4],
+        }
+    }
+
+    /// Update with the current state of the buttons, calling `f` for each change
+    pub fn update<F: FnMut(Event)>(&mut self, pressed: [bool; 4], mut f: F) {
+        for (index, (&now, before)) in pressed.iter().zip(self.pressed.iter_mut()).enumerate() {
+            if now != *before {
+                *before = now;
+                f(Event::Button {
+                    index,
+                    pressed: now,
+                });
+            }
+        }
+    }
+}
+
+/// Raises an event when the temperature crosses a threshold
+///
+/// The temperature has to drop `hysteresis` below the threshold before the alarm clears, so a
+/// reading hovering around the threshold doesn't flood the host.
+pub struct TemperatureAlarm {
+    threshold: i32,
+    hysteresis: i32,
+    high: bool,
+}
+
+impl TemperatureAlarm {
+    /// Both values are in tenths of degrees Celsius
+    pub fn new(threshold: i32, hysteresis: i32) -> Self {
+        Self {
+            threshold,
+            hysteresis,
+            high: false,
+        }
+    }
+
+    /// Update with a new reading, in tenths of degrees Celsius
+    pub fn update(&mut self, temperature: i32) -> Option<Event> {
+        if !self.high && temperature >= self.threshold {
+            self.high = true;
+            Some(Event::TemperatureHigh(temperature))
+        } else if self.high && temperature < self.threshold - self.hysteresis {
+            self.high = false;
+            Some(Event::TemperatureNormal(temperature))
+        } else {
+            None
+        }
+    }
+}
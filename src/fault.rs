@@ -0,0 +1,89 @@
+//! Fault codes
+//!
+//! Numbers for the failures the firmware knows about, blinked on the LED when the display can't
+//! show them: a dash, then one dot per unit of the code. A board without a screen or a host can
+//! still say what is wrong, and `FAULTS` decodes the blinks.
+
+use crate::blink::BlinkPattern;
+use crate::pac;
+
+/// GPIO of the LED of the Pico
+const LED_GPIO: u32 = 25;
+
+/// Failure the firmware knows about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Panic,
+    HardFault,
+    /// The panel doesn't answer, at boot or after being initialized again
+    DisplayInit,
+    /// The host started to enumerate the device but never configured it
+    UsbInit,
+    /// Data in flash doesn't match its CRC-32, such as a worn out asset slot
+    FlashCrc,
+    /// The stored settings don't match their checksum, so the defaults are used
+    ConfigCorrupt,
+}
+
+/// Every fault, by code, to decode the blinks
+pub const FAULTS: [Fault; 6] = [
+    Fault::Panic,
+    Fault::HardFault,
+    Fault::DisplayInit,
+    Fault::UsbInit,
+    Fault::FlashCrc,
+    Fault::ConfigCorrupt,
+];
+
+impl Fault {
+    /// Code of the fault, from 1, which is the number of dots blinked
+    pub fn code(self) -> u8 {
+        match self {
+            Fault::Panic => 1,
+            Fault::HardFault => 2,
+            Fault::DisplayInit => 3,
+            Fault::UsbInit => 4,
+            Fault::FlashCrc => 5,
+            Fault::ConfigCorrupt => 6,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        FAULTS.iter().copied().find(|fault| fault.code() == code)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Fault::Panic => "panic",
+            Fault::HardFault => "hard fault",
+            Fault::DisplayInit => "display init",
+            Fault::UsbInit => "USB init",
+            Fault::FlashCrc => "flash CRC",
+            Fault::ConfigCorrupt => "config corrupt",
+        }
+    }
+
+    /// Blinks of the code: a dash, then one dot per unit
+    pub fn pattern(self) -> BlinkPattern {
+        let symbols = "-..........";
+        // The codes are below the number of dots of `symbols`
+        BlinkPattern::parse(&symbols[..1 + self.code() as usize]).unwrap()
+    }
+
+    /// Blink the code on the LED forever, such as once the firmware crashed
+    ///
+    /// The LED must already be an output of the SIO, and `now_ms` may come from any time base.
+    pub fn blink_forever(self, now_ms: impl Fn() -> u32) -> ! {
+        let pattern = self.pattern();
+        // Note (safety): only the output of the LED pin is written, with the set and clear
+        // registers, which don't touch the other pins
+        let sio = unsafe { &*pac::SIO::ptr() };
+        loop {
+            if pattern.is_on(now_ms()) {
+                sio.gpio_out_set.write(|w| unsafe { w.bits(1 << LED_GPIO) });
+            } else {
+                sio.gpio_out_clr.write(|w| unsafe { w.bits(1 << LED_GPIO) });
+            }
+        }
+    }
+}
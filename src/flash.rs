@@ -0,0 +1,202 @@
+//! Internal flash access
+//!
+//! Erases and programs the QSPI flash the firmware runs from, through the boot ROM routines.
+//! The flash can't be read while it is written, so the routines run from RAM with interrupts
+//! disabled.
+
+/// Size of the flash on the Pico
+pub const FLASH_SIZE: u32 = 2 * 1024 * 1024;
+/// Smallest area that can be erased
+pub const SECTOR_SIZE: u32 = 4096;
+/// Smallest area that can be programmed
+pub const PAGE_SIZE: u32 = 256;
+
+/// Address where the flash is mapped for reading
+const XIP_BASE: u32 = 0x1000_0000;
+/// Status and data registers of the SSI, which sends the commands to the flash
+const SSI_SR: *const u32 = 0x1800_0028 as *const u32;
+const SSI_DR0: *mut u32 = 0x1800_0060 as *mut u32;
+/// Chip select control of the flash, in the QSPI bank
+const QSPI_SS_CTRL: *mut u32 = 0x4001_800C as *mut u32;
+
+/// Boot ROM routines used to write to the flash
+#[repr(C)]
+struct RomFunctions {
+    connect_internal_flash: extern "C" fn(),
+    flash_exit_xip: extern "C" fn(),
+    flash_range_erase: extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: extern "C" fn(),
+}
+
+/// Look up a boot ROM routine by its two-letter tag
+///
+/// See section 2.8.3 of the RP2040 datasheet.
+fn rom_function(tag: &[u8; 2]) -> usize {
+    type LookupFn = extern "C" fn(*const u16, u32) -> usize;
+    // Note (safety): the pointers to the table and lookup function are at fixed addresses in
+    // the boot ROM.
+    unsafe {
+        let table = *(0x14 as *const u16) as *const u16;
+        let lookup: LookupFn = core::mem::transmute(*(0x18 as *const u16) as usize);
+        lookup(table, u16::from_le_bytes(*tag) as u32)
+    }
+}
+
+impl RomFunctions {
+    fn lookup() -> Self {
+        // Note (safety): the routines have the signatures documented in the datasheet
+        unsafe {
+            Self {
+                connect_internal_flash: core::mem::transmute(rom_function(b"IF")),
+                flash_exit_xip: core::mem::transmute(rom_function(b"EX")),
+                flash_range_erase: core::mem::transmute(rom_function(b"RE")),
+                flash_range_program: core::mem::transmute(rom_function(b"RP")),
+                flash_flush_cache: core::mem::transmute(rom_function(b"FC")),
+            }
+        }
+    }
+}
+
+/// Erase or program the flash, then restore the fast read mode of the second stage bootloader
+///
+/// Nothing in this function may run from flash, including functions it calls.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_from_ram(
+    functions: &RomFunctions,
+    boot2: *const u32,
+    offset: u32,
+    data: *const u8,
+    len: usize,
+    erase: bool,
+) {
+    (functions.connect_internal_flash)();
+    (functions.flash_exit_xip)();
+    if erase {
+        // Erase 64kB blocks when possible, with the block erase command of the W25Q080
+        (functions.flash_range_erase)(offset, len, 1 << 16, 0xD8);
+    } else {
+        (functions.flash_range_program)(offset, data, len);
+    }
+    (functions.flash_flush_cache)();
+    // The second stage bootloader is a function, with the thumb bit set
+    let boot2: extern "C" fn() = core::mem::transmute((boot2 as *const u8).add(1));
+    boot2();
+}
+
+/// Send the Read Unique ID command to the flash, then restore the fast read mode
+///
+/// The command is followed by 4 dummy bytes, then the flash sends its 64-bit ID. Nothing in this
+/// function may run from flash, including functions it calls.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn unique_id_from_ram(functions: &RomFunctions, boot2: *const u32, id: *mut u8) {
+    (functions.connect_internal_flash)();
+    (functions.flash_exit_xip)();
+    // Drive the chip select low by hand for the whole command
+    let ss_ctrl = core::ptr::read_volatile(QSPI_SS_CTRL) & !(0b11 << 8);
+    core::ptr::write_volatile(QSPI_SS_CTRL, ss_ctrl | 0b10 << 8);
+
+    const LEN: usize = 13;
+    let (mut sent, mut received) = (0, 0);
+    while received < LEN {
+        let status = core::ptr::read_volatile(SSI_SR);
+        // Keep no more bytes in flight than the receive FIFO holds
+        if status & 1 << 1 != 0 && sent < LEN && sent - received < 14 {
+            core::ptr::write_volatile(SSI_DR0, if sent == 0 { 0x4B } else { 0 });
+            sent += 1;
+        }
+        if status & 1 << 3 != 0 {
+            let byte = core::ptr::read_volatile(SSI_DR0) as u8;
+            if received >= LEN - 8 {
+                *id.add(received + 8 - LEN) = byte;
+            }
+            received += 1;
+        }
+    }
+
+    core::ptr::write_volatile(QSPI_SS_CTRL, ss_ctrl | 0b11 << 8);
+    (functions.flash_flush_cache)();
+    let boot2: extern "C" fn() = core::mem::transmute((boot2 as *const u8).add(1));
+    boot2();
+}
+
+/// Copy the second stage bootloader to RAM, to set up the fast read mode again afterwards
+fn copy_boot2() -> [u32; 64] {
+    let mut boot2 = [0u32; 64];
+    // Note (safety): the second stage bootloader is the first 256 bytes of the flash
+    unsafe {
+        core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), 64);
+    }
+    boot2
+}
+
+/// Run `write_from_ram` with everything it needs copied to RAM
+fn write(offset: u32, data: *const u8, len: usize, erase: bool) {
+    let functions = RomFunctions::lookup();
+    let boot2 = copy_boot2();
+
+    cortex_m::interrupt::free(|_| {
+        // Note (safety): interrupts are disabled, so nothing runs from flash while it is
+        // written.
+        unsafe { write_from_ram(&functions, boot2.as_ptr(), offset, data, len, erase) };
+    });
+}
+
+/// Read the 64-bit unique ID of the flash, which tells the boards apart
+///
+/// The RP2040 itself has no ID, so this is what the Pico SDK uses as the board ID.
+pub fn unique_id() -> [u8; 8] {
+    let functions = RomFunctions::lookup();
+    let boot2 = copy_boot2();
+    let mut id = [0u8; 8];
+
+    cortex_m::interrupt::free(|_| {
+        // Note (safety): interrupts are disabled, so nothing runs from flash while the command
+        // is sent.
+        unsafe { unique_id_from_ram(&functions, boot2.as_ptr(), id.as_mut_ptr()) };
+    });
+    id
+}
+
+/// Fletcher-16 checksum, to detect data left incomplete by a power loss
+pub fn checksum(data: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for &byte in data {
+        a = (a + byte as u16) % 255;
+        b = (b + a) % 255;
+    }
+    b << 8 | a
+}
+
+/// Read `len` bytes at `offset` from the start of the flash
+pub fn read(offset: u32, len: usize) -> &'static [u8] {
+    assert!(offset as usize + len <= FLASH_SIZE as usize);
+    // Note (safety): the flash is always mapped for reading
+    unsafe { core::slice::from_raw_parts((XIP_BASE + offset) as *const u8, len) }
+}
+
+/// Erase the sectors of `len` bytes at `offset` from the start of the flash
+///
+/// # Safety
+///
+/// The sectors must not contain the firmware, or any data still in use.
+pub unsafe fn erase(offset: u32, len: u32) {
+    assert!(offset % SECTOR_SIZE == 0 && len % SECTOR_SIZE == 0);
+    assert!(offset + len <= FLASH_SIZE);
+    write(offset, core::ptr::null(), len as usize, true);
+}
+
+/// Program `data` at `offset` from the start of the flash
+///
+/// Programming can only clear bits, so the pages should be erased first.
+///
+/// # Safety
+///
+/// The pages must not contain the firmware, or any data still in use.
+pub unsafe fn program(offset: u32, data: &[u8]) {
+    assert!(offset % PAGE_SIZE == 0 && data.len() as u32 % PAGE_SIZE == 0);
+    assert!(offset + data.len() as u32 <= FLASH_SIZE);
+    write(offset, data.as_ptr(), data.len(), false);
+}
@@ -0,0 +1,203 @@
+//! Framebuffer in RAM
+//!
+//! Keeps a copy of the screen in RAM, so drawing only changes memory and the panel is written
+//! once per flush, in one window covering what changed. Text arriving in bursts then costs one
+//! SPI transfer instead of one per glyph, and the panel never shows a half drawn screen.
+
+use crate::display::Display;
+use crate::dma::{PanelDma, PanelTransfer};
+use core::convert::TryFrom;
+use embedded_graphics::{
+    pixelcolor::raw::{RawData, RawU16},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+/// Screen of `W`x`H` pixels of a 16-bit color, in RAM
+///
+/// The Pico Display one takes 64kB, so it is better kept in a `static` than on the stack.
+pub struct Framebuffer<C, const W: usize, const H: usize> {
+    /// Raw colors, row by row, as sent to the panel
+    pixels: [[u16; W]; H],
+    /// Area changed since the last flush
+    dirty: Option<Rectangle>,
+    _color: core::marker::PhantomData<C>,
+}
+
+impl<C, const W: usize, const H: usize> Framebuffer<C, W, H>
+where
+    C: PixelColor + From<RawU16>,
+    RawU16: From<C>,
+{
+    /// Framebuffer filled with `color`, all of it to be flushed
+    pub fn new(color: C) -> Self {
+        Self {
+            pixels: [[RawU16::from(color).into_inner(); W]; H],
+            dirty: Some(Self::bounds()),
+            _color: core::marker::PhantomData,
+        }
+    }
+
+    fn bounds() -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(W as u32, H as u32))
+    }
+
+    pub fn pixel(&self, point: Point) -> Option<C> {
+        let (x, y) = (usize::try_from(point.x).ok()?, usize::try_from(point.y).ok()?);
+        let raw = *self.pixels.get(y)?.get(x)?;
+        Some(C::from(RawU16::new(raw)))
+    }
+
+    /// Area changed since the last flush, `None` if the panel is up to date
+    pub fn dirty(&self) -> Option<Rectangle> {
+        self.dirty
+    }
+
+    /// Flush the whole screen next time, such as after the panel was initialized again
+    pub fn invalidate(&mut self) {
+        self.dirty = Some(Self::bounds());
+    }
+
+    /// Move the pixels of `area` up by `pixels` rows, such as when a terminal scrolls
+    ///
+    /// The top rows of the area are dropped, and the bottom ones are cleared to `color`. Areas as
+    /// wide as the framebuffer are moved in a single copy, as their rows follow each other.
+    pub fn scroll_up(&mut self, area: &Rectangle, pixels: u32, color: C) {
+        let area = area.intersection(&Self::bounds());
+        if area.is_zero_sized() {
+            return;
+        }
+        let (x, y) = (area.top_left.x as usize, area.top_left.y as usize);
+        let (width, height) = (area.size.width as usize, area.size.height as usize);
+        let pixels = (pixels as usize).min(height);
+        if width == W {
+            self.pixels.copy_within(y + pixels..y + height, y);
+        } else {
+            for row in y..y + height - pixels {
+                let (above, below) = self.pixels.split_at_mut(row + pixels);
+                above[row][x..x + width].copy_from_slice(&below[0][x..x + width]);
+            }
+        }
+        let raw = RawU16::from(color).into_inner();
+        for row in &mut self.pixels[y + height - pixels..y + height] {
+            row[x..x + width].fill(raw);
+        }
+        self.mark_dirty(&area);
+    }
+
+    fn mark_dirty(&mut self, area: &Rectangle) {
+        let area = area.intersection(&Self::bounds());
+        if area.is_zero_sized() {
+            return;
+        }
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => envelope(&dirty, &area),
+            None => area,
+        });
+    }
+
+    /// Write the area changed since the last flush to `target`, with the top left corner of the
+    /// framebuffer at `offset`
+    ///
+    /// The area goes out in a single `fill_contiguous`, which the ST7789 driver sends as one
+    /// memory write.
+    pub fn flush<D>(&mut self, target: &mut D, offset: Point) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let dirty = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return Ok(()),
+        };
+        let (x, y) = (dirty.top_left.x as usize, dirty.top_left.y as usize);
+        let (width, height) = (dirty.size.width as usize, dirty.size.height as usize);
+        let colors = self.pixels[y..y + height]
+            .iter()
+            .flat_map(|row| row[x..x + width].iter())
+            .map(|&raw| C::from(RawU16::new(raw)));
+        target.fill_contiguous(&dirty.translate(offset), colors)
+    }
+
+    /// Start writing the rows changed since the last flush to the panel with DMA
+    ///
+    /// Whole rows are sent, as they are contiguous in RAM, with the top left corner of the
+    /// framebuffer at `offset` in the panel memory. The framebuffer stays borrowed until the
+    /// transfer is finished, so nothing is drawn in the middle of it.
+    pub fn flush_dma<'a>(
+        &'a mut self,
+        dma: &'a mut PanelDma,
+        display: &'a mut Display,
+        offset: Point,
+    ) -> Option<PanelTransfer<'a>> {
+        let dirty = self.dirty.take()?;
+        let (top, height) = (dirty.top_left.y as usize, dirty.size.height as usize);
+        let rows = &self.pixels[top..top + height];
+        // Note (safety): the rows of the array follow each other without padding
+        let pixels =
+            unsafe { core::slice::from_raw_parts(rows.as_ptr() as *const u16, W * height) };
+        let area = Rectangle::new(
+            offset + Point::new(0, top as i32),
+            Size::new(W as u32, height as u32),
+        );
+        Some(dma.write(display, &area, pixels))
+    }
+}
+
+/// Smallest rectangle holding both `a` and `b`
+fn envelope(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = (a.top_left + a.size).component_max(b.top_left + b.size);
+    Rectangle::with_corners(top_left, bottom_right - Point::new(1, 1))
+}
+
+impl<C, const W: usize, const H: usize> OriginDimensions for Framebuffer<C, W, H> {
+    fn size(&self) -> Size {
+        Size::new(W as u32, H as u32)
+    }
+}
+
+impl<C, const W: usize, const H: usize> DrawTarget for Framebuffer<C, W, H>
+where
+    C: PixelColor + From<RawU16>,
+    RawU16: From<C>,
+{
+    type Color = C;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (usize::try_from(point.x), usize::try_from(point.y)) {
+                if x < W && y < H {
+                    self.pixels[y][x] = RawU16::from(color).into_inner();
+                    self.mark_dirty(&Rectangle::new(point, Size::new(1, 1)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&Self::bounds());
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+        let raw = RawU16::from(color).into_inner();
+        let (x, y) = (area.top_left.x as usize, area.top_left.y as usize);
+        let (width, height) = (area.size.width as usize, area.size.height as usize);
+        for row in &mut self.pixels[y..y + height] {
+            row[x..x + width].fill(raw);
+        }
+        self.mark_dirty(&area);
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let raw = RawU16::from(color).into_inner();
+        self.pixels.iter_mut().for_each(|row| row.fill(raw));
+        self.invalidate();
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Round gauge widget
+//!
+//! An arc filled in proportion to a value, with the value and a label in the middle, like the
+//! dials of a dashboard.
+
+use core::fmt::Write;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    prelude::*,
+    primitives::{Arc, PrimitiveStyle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use heapless::String;
+
+/// Start of the arc, clockwise from the right, leaving the bottom open
+const START_DEG: f32 = 135.0;
+const SWEEP_DEG: f32 = 270.0;
+
+/// Round gauge widget
+pub struct Gauge<'f, C> {
+    center: Point,
+    diameter: u32,
+    max: u32,
+    label: &'static str,
+    style: MonoTextStyle<'f, C>,
+    /// Width of the arc, in pixels
+    thickness: u32,
+    color: C,
+    /// Color of the part of the arc above the value, and behind the text
+    background_color: C,
+}
+
+impl<'f, C: PixelColor> Gauge<'f, C> {
+    pub fn new(
+        center: Point,
+        diameter: u32,
+        max: u32,
+        label: &'static str,
+        style: MonoTextStyle<'f, C>,
+        color: C,
+        background_color: C,
+    ) -> Self {
+        Self {
+            center,
+            diameter,
+            max: max.max(1),
+            label,
+            style,
+            thickness: diameter / 8,
+            color,
+            background_color,
+        }
+    }
+
+    /// Draw the gauge at `value`, which is shown in full above `max`
+    ///
+    /// The value is drawn over the previous one, so the style needs a background color.
+    pub fn draw<D>(&self, target: &mut D, value: u32) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let filled = SWEEP_DEG * value.min(self.max) as f32 / self.max as f32;
+        let radius = self.diameter as i32 / 2;
+        let top_left = self.center - Point::new(radius, radius);
+        let arc = |start: f32, sweep: f32| {
+            Arc::new(top_left, self.diameter, start.deg(), sweep.deg())
+        };
+        arc(START_DEG + filled, SWEEP_DEG - filled)
+            .into_styled(PrimitiveStyle::with_stroke(self.background_color, self.thickness))
+            .draw(target)?;
+        if filled > 0.0 {
+            arc(START_DEG, filled)
+                .into_styled(PrimitiveStyle::with_stroke(self.color, self.thickness))
+                .draw(target)?;
+        }
+
+        // Padded, so a shorter value covers a longer one drawn before
+        let mut text: String<12> = String::new();
+        let _ = write!(text, "{:^7}", value);
+        let centered = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        Text::with_text_style(&text, self.center, self.style, centered).draw(target)?;
+        let label_pos = self.center + Point::new(0, radius - 4);
+        Text::with_text_style(self.label, label_pos, self.style, centered).draw(target)?;
+        Ok(())
+    }
+}
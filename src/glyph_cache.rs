@@ -0,0 +1,165 @@
+//! Cache of rendered glyphs
+//!
+//! Keeps the pixels of the most recently drawn characters, so drawing them again is a single
+//! copy to the screen instead of rendering the font bit by bit.
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::RgbColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+/// Number of glyphs kept
+pub const CACHE_SIZE: usize = 32;
+/// Largest glyph kept, in pixels, which fits the 6x10 font of the terminal
+pub const MAX_GLYPH_PIXELS: usize = 6 * 10;
+
+#[derive(Clone, Copy)]
+struct Entry<C> {
+    c: u8,
+    fg: C,
+    bg: C,
+    pixels: [C; MAX_GLYPH_PIXELS],
+    /// Value of the clock when the glyph was last used
+    last_used: u32,
+}
+
+/// Hit and miss counts of a `GlyphCache`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl CacheStats {
+    /// Share of the lookups found in the cache, in percent
+    pub fn hit_rate(&self) -> u32 {
+        let total = self.hits as u64 + self.misses as u64;
+        if total == 0 {
+            return 0;
+        }
+        (self.hits as u64 * 100 / total) as u32
+    }
+}
+
+/// Glyphs of a font rendered with their colors, the least recently used one is replaced first
+pub struct GlyphCache<C> {
+    entries: [Option<Entry<C>>; CACHE_SIZE],
+    clock: u32,
+    stats: CacheStats,
+}
+
+impl<C: RgbColor> GlyphCache<C> {
+    pub fn new() -> Self {
+        Self {
+            entries: [None; CACHE_SIZE],
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Forget the glyphs, when the font changes
+    pub fn clear(&mut self) {
+        self.entries = [None; CACHE_SIZE];
+    }
+
+    /// Pixels of `c` in `style`, row by row, rendering it on a miss
+    ///
+    /// Styles without a background color and fonts larger than `MAX_GLYPH_PIXELS` aren't
+    /// cached, as the glyph has to cover the whole cell.
+    pub fn get(&mut self, c: u8, style: &MonoTextStyle<'_, C>) -> Option<&[C]> {
+        let (fg, bg) = (style.text_color?, style.background_color?);
+        let size = style.font.character_size;
+        let len = (size.width * size.height) as usize;
+        if len > MAX_GLYPH_PIXELS {
+            return None;
+        }
+
+        self.clock = self.clock.wrapping_add(1);
+        let found = self
+            .entries
+            .iter()
+            .position(|entry| matches!(entry, Some(e) if e.c == c && e.fg == fg && e.bg == bg));
+        let index = match found {
+            Some(index) => {
+                self.stats.hits = self.stats.hits.wrapping_add(1);
+                index
+            }
+            None => {
+                self.stats.misses = self.stats.misses.wrapping_add(1);
+                let index = self.free_slot();
+                let mut canvas = Canvas {
+                    pixels: [bg; MAX_GLYPH_PIXELS],
+                    size,
+                };
+                let text = [c];
+                let text = core::str::from_utf8(&text).unwrap_or("?");
+                let _ = Text::with_baseline(text, Point::zero(), *style, Baseline::Top)
+                    .draw(&mut canvas);
+                self.entries[index] = Some(Entry {
+                    c,
+                    fg,
+                    bg,
+                    pixels: canvas.pixels,
+                    last_used: 0,
+                });
+                index
+            }
+        };
+
+        let entry = self.entries[index].as_mut()?;
+        entry.last_used = self.clock;
+        Some(&entry.pixels[..len])
+    }
+
+    /// Index of an empty slot, or of the least recently used glyph
+    fn free_slot(&self) -> usize {
+        let clock = self.clock;
+        self.entries
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, entry)| match entry {
+                // Ages are compared rather than times, so the clock can wrap around
+                Some(entry) => clock.wrapping_sub(entry.last_used),
+                None => u32::MAX,
+            })
+            .map_or(0, |(index, _)| index)
+    }
+}
+
+/// Draw target rendering a glyph into memory
+struct Canvas<C> {
+    pixels: [C; MAX_GLYPH_PIXELS],
+    size: Size,
+}
+
+impl<C> OriginDimensions for Canvas<C> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<C: PixelColor> DrawTarget for Canvas<C> {
+    type Color = C;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<C>>,
+    {
+        let area = Rectangle::new(Point::zero(), self.size);
+        for Pixel(point, color) in pixels {
+            if area.contains(point) {
+                let index = point.y as u32 * self.size.width + point.x as u32;
+                self.pixels[index as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
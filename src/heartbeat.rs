@@ -0,0 +1,81 @@
+//! Host heartbeat watchdog
+//!
+//! When enabled, the host has to send a heartbeat at least every few seconds. If it stops, the
+//! host is reported as stalled once, until the heartbeats resume.
+
+/// Change in the state of the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeartbeatEvent {
+    /// No heartbeat since `last_us`, in microseconds since boot
+    Stalled { last_us: u64 },
+    /// A heartbeat came in after the host stalled
+    Resumed,
+}
+
+/// Watchdog of the host heartbeats
+pub struct Heartbeat {
+    /// Longest time allowed between heartbeats, `None` when disabled
+    timeout_us: Option<u64>,
+    last_us: u64,
+    stalled: bool,
+}
+
+impl Heartbeat {
+    pub const fn new() -> Self {
+        Self {
+            timeout_us: None,
+            last_us: 0,
+            stalled: false,
+        }
+    }
+
+    /// Expect a heartbeat every `timeout_s` seconds, starting now
+    pub fn enable(&mut self, timeout_s: u32, now_us: u64) {
+        self.timeout_us = Some(timeout_s as u64 * 1_000_000);
+        self.last_us = now_us;
+    }
+
+    /// Stop watching the host
+    ///
+    /// This returns `Resumed` if the host was stalled, to clear the notice.
+    pub fn disable(&mut self) -> Option<HeartbeatEvent> {
+        self.timeout_us = None;
+        self.resume()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.timeout_us.is_some()
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// Handle a heartbeat from the host
+    pub fn beat(&mut self, now_us: u64) -> Option<HeartbeatEvent> {
+        self.last_us = now_us;
+        self.resume()
+    }
+
+    /// Check whether the host missed its heartbeat
+    pub fn check(&mut self, now_us: u64) -> Option<HeartbeatEvent> {
+        match self.timeout_us {
+            Some(timeout_us) if !self.stalled && now_us - self.last_us > timeout_us => {
+                self.stalled = true;
+                Some(HeartbeatEvent::Stalled {
+                    last_us: self.last_us,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn resume(&mut self) -> Option<HeartbeatEvent> {
+        if self.stalled {
+            self.stalled = false;
+            Some(HeartbeatEvent::Resumed)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Idle animations
+//!
+//! Plays short animations of a sprite, such as Ferris blinking or waving, once the terminal has
+//! been quiet for a while. An animation is a list of steps through the frames of a sprite sheet,
+//! and more can be registered next to the built-in ones.
+
+use heapless::Vec;
+
+/// Highest lift of a step, the room to keep above the sprite
+pub const MAX_LIFT: u8 = 4;
+
+/// Frame of the sprite sheet to draw, and how far up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pose {
+    pub frame: u8,
+    /// Pixels above the resting place, up to `MAX_LIFT`
+    pub lift: u8,
+}
+
+impl Pose {
+    /// First frame, in its place, shown between the animations
+    pub const REST: Pose = Pose { frame: 0, lift: 0 };
+}
+
+/// Pose of an animation, held for a time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub pose: Pose,
+    pub ms: u16,
+}
+
+const fn step(frame: u8, lift: u8, ms: u16) -> Step {
+    Step {
+        pose: Pose { frame, lift },
+        ms,
+    }
+}
+
+/// Animation played while idle, going back to `Pose::REST` after its steps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdleAnimation {
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+/// Eyes closed for a moment, on frame 1 of the sheet
+pub const BLINK: IdleAnimation = IdleAnimation {
+    name: "blink",
+    steps: &[step(1, 0, 150)],
+};
+
+/// Claw up and down, on frames 2 and 3 of the sheet
+pub const WAVE: IdleAnimation = IdleAnimation {
+    name: "wave",
+    steps: &[
+        step(2, 0, 200),
+        step(3, 0, 200),
+        step(2, 0, 200),
+        step(3, 0, 200),
+    ],
+};
+
+/// Small jump, which also works with a sheet of a single frame
+pub const HOP: IdleAnimation = IdleAnimation {
+    name: "hop",
+    steps: &[step(0, 2, 80), step(0, MAX_LIFT, 120), step(0, 2, 80)],
+};
+
+/// Plays the registered animations in turn while the terminal is idle
+///
+/// The caller draws the poses returned by `update`, and restores what was under the sprite
+/// once `activity` says it was shown.
+pub struct IdleAnimator<const N: usize> {
+    animations: Vec<IdleAnimation, N>,
+    /// Time without activity before the sprite shows up, `None` to never show it
+    idle_after_ms: Option<u32>,
+    /// Time between two animations
+    every_ms: u32,
+    last_activity_ms: u32,
+    /// End of the last animation, or the time the sprite showed up
+    last_played_ms: u32,
+    /// Next animation to play
+    next: usize,
+    /// Animation being played, its step and the time the step started
+    playing: Option<(usize, usize, u32)>,
+    shown: bool,
+}
+
+impl<const N: usize> IdleAnimator<N> {
+    /// Animator playing an animation every `every_ms` while idle, disabled until
+    /// `set_idle_after`
+    pub const fn new(every_ms: u32) -> Self {
+        Self {
+            animations: Vec::new(),
+            idle_after_ms: None,
+            every_ms,
+            last_activity_ms: 0,
+            last_played_ms: 0,
+            next: 0,
+            playing: None,
+            shown: false,
+        }
+    }
+
+    /// Add an animation, returning it back if there are already `N`
+    pub fn register(&mut self, animation: IdleAnimation) -> Result<(), IdleAnimation> {
+        self.animations.push(animation)
+    }
+
+    pub fn animations(&self) -> &[IdleAnimation] {
+        &self.animations
+    }
+
+    /// Show the sprite after `seconds` without activity, or never with `None`
+    ///
+    /// Returns true if the sprite was shown, like `activity`.
+    pub fn set_idle_after(&mut self, seconds: Option<u32>, now_ms: u32) -> bool {
+        self.idle_after_ms = seconds.map(|seconds| seconds.saturating_mul(1000));
+        self.activity(now_ms)
+    }
+
+    /// Seconds without activity before the sprite shows up, `None` if it never does
+    pub fn idle_after(&self) -> Option<u32> {
+        self.idle_after_ms.map(|ms| ms / 1000)
+    }
+
+    /// Record activity on the terminal at `now_ms`, stopping the animations
+    ///
+    /// Returns true if the sprite was shown, so the caller draws the terminal over it again.
+    pub fn activity(&mut self, now_ms: u32) -> bool {
+        self.last_activity_ms = now_ms;
+        self.playing = None;
+        core::mem::replace(&mut self.shown, false)
+    }
+
+    /// Advance the animations to `now_ms`, in the time base of `activity`
+    ///
+    /// Returns the pose to draw when it changes.
+    pub fn update(&mut self, now_ms: u32) -> Option<Pose> {
+        let idle_after_ms = self.idle_after_ms?;
+        if now_ms.wrapping_sub(self.last_activity_ms) < idle_after_ms {
+            return None;
+        }
+        if !self.shown {
+            self.shown = true;
+            self.last_played_ms = now_ms;
+            return Some(Pose::REST);
+        }
+
+        match self.playing {
+            Some((animation, index, started_ms)) => {
+                let steps = self.animations[animation].steps;
+                if now_ms.wrapping_sub(started_ms) < steps[index].ms as u32 {
+                    return None;
+                }
+                match steps.get(index + 1) {
+                    Some(step) => {
+                        self.playing = Some((animation, index + 1, now_ms));
+                        Some(step.pose)
+                    }
+                    None => {
+                        self.playing = None;
+                        self.last_played_ms = now_ms;
+                        Some(Pose::REST)
+                    }
+                }
+            }
+            None if now_ms.wrapping_sub(self.last_played_ms) >= self.every_ms => {
+                let animation = self.next % self.animations.len().max(1);
+                self.next = animation + 1;
+                // An animation without steps still waits for its turn
+                self.last_played_ms = now_ms;
+                let first = self.animations.get(animation)?.steps.first()?;
+                self.playing = Some((animation, 0, now_ms));
+                Some(first.pose)
+            }
+            None => None,
+        }
+    }
+}
+
+/// Width of a sprite sheet, from the low 12 bits of the parameter of an image asset
+pub fn sheet_width(param: u16) -> u32 {
+    (param & 0x0FFF) as u32
+}
+
+/// Frames of a sprite sheet, stacked from the top, from the high 4 bits of the parameter of an
+/// image asset
+///
+/// An image uploaded with only its width as parameter is a sheet of one frame.
+pub fn sheet_frames(param: u16) -> usize {
+    (param >> 12) as usize + 1
+}
@@ -0,0 +1,266 @@
+//! Text input widget
+//!
+//! Single line text field for the on-device menus. It is edited with keys, from the buttons or
+//! the host keyboard, and the buttons can also cycle the character under the cursor. The entered
+//! text is returned as an `Event::Input`.
+
+use crate::ansi::{Action, Parser};
+use crate::events::{Event, MAX_INPUT};
+use crate::keys::Key;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Baseline, Text},
+};
+use heapless::String;
+
+/// Characters the buttons cycle through by default, in order
+pub const CHARSET: &[u8] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_.:/@";
+
+/// Padding between the border and the text
+const PADDING: i32 = 2;
+
+/// Text input widget
+pub struct InputField<'f, C> {
+    top_left: Point,
+    /// Number of characters shown, the text scrolls to keep the cursor visible
+    width: usize,
+    style: MonoTextStyle<'f, C>,
+    background_color: C,
+    /// Color of the border and cursor while the field has the focus
+    accent_color: C,
+    value: String<MAX_INPUT>,
+    /// Value when the field got the focus, restored when editing is cancelled
+    original: String<MAX_INPUT>,
+    cursor: usize,
+    focused: bool,
+    parser: Parser,
+    /// Characters the buttons cycle through
+    charset: &'static [u8],
+}
+
+impl<'f, C: PixelColor> InputField<'f, C> {
+    pub fn new(
+        top_left: Point,
+        width: usize,
+        style: MonoTextStyle<'f, C>,
+        background_color: C,
+        accent_color: C,
+    ) -> Self {
+        Self {
+            top_left,
+            width: width.max(1),
+            style,
+            background_color,
+            accent_color,
+            value: String::new(),
+            original: String::new(),
+            cursor: 0,
+            focused: false,
+            parser: Parser::new(),
+            charset: CHARSET,
+        }
+    }
+
+    /// Cycle through `charset` with the buttons, such as only digits for numbers
+    pub fn set_charset(&mut self, charset: &'static [u8]) {
+        if !charset.is_empty() {
+            self.charset = charset;
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Replace the text, moving the cursor to its end
+    ///
+    /// Text longer than `MAX_INPUT` is cut.
+    pub fn set_value(&mut self, value: &str) {
+        self.value.clear();
+        for c in value.chars() {
+            if self.value.push(c).is_err() {
+                break;
+            }
+        }
+        self.cursor = self.value.len();
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Start editing
+    pub fn focus(&mut self) {
+        self.focused = true;
+        self.original = self.value.clone();
+        self.cursor = self.value.len();
+        self.parser = Parser::new();
+    }
+
+    /// Handle a key, returning the entered text once it is submitted
+    ///
+    /// Up and down cycle the character under the cursor, left and right move the cursor. Enter
+    /// submits the text and escape restores the text from before editing.
+    pub fn handle_key(&mut self, key: Key) -> Option<Event> {
+        if !self.focused {
+            return None;
+        }
+        match key {
+            Key::Up => self.cycle(1),
+            Key::Down => self.cycle(-1),
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            // The cursor can go one past the end, to append characters
+            Key::Right => self.cursor = (self.cursor + 1).min(self.value.len()),
+            Key::PageUp => self.cursor = 0,
+            Key::PageDown => self.cursor = self.value.len(),
+            Key::Enter => {
+                self.focused = false;
+                return Some(Event::Input(self.value.clone()));
+            }
+            Key::Escape => {
+                self.focused = false;
+                self.value = self.original.clone();
+                self.cursor = self.value.len();
+            }
+        }
+        None
+    }
+
+    /// Handle a byte typed on the host keyboard
+    ///
+    /// Printable characters are inserted at the cursor, and the arrow, home, end and delete
+    /// keys work as in a line editor. Ctrl-C cancels editing.
+    pub fn handle_byte(&mut self, byte: u8) -> Option<Event> {
+        if !self.focused {
+            return None;
+        }
+        match self.parser.advance(byte) {
+            Action::Print(c) => {
+                self.insert(c);
+            }
+            Action::Control(b'\r') => return self.handle_key(Key::Enter),
+            Action::Control(0x03) => return self.handle_key(Key::Escape),
+            Action::Control(0x08) | Action::Control(0x7F) => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.remove();
+                }
+            }
+            Action::Csi(csi) => match (csi.action, csi.param(0, 0)) {
+                (b'A', _) => return self.handle_key(Key::Up),
+                (b'B', _) => return self.handle_key(Key::Down),
+                (b'C', _) => return self.handle_key(Key::Right),
+                (b'D', _) => return self.handle_key(Key::Left),
+                (b'H', _) | (b'~', 1) => return self.handle_key(Key::PageUp),
+                (b'F', _) | (b'~', 4) => return self.handle_key(Key::PageDown),
+                (b'~', 3) => self.remove(),
+                _ => (),
+            },
+            _ => (),
+        }
+        None
+    }
+
+    /// Insert `c` at the cursor, returning false if the text is full
+    fn insert(&mut self, c: u8) -> bool {
+        if self.value.len() >= MAX_INPUT || !(0x20..0x7F).contains(&c) {
+            return false;
+        }
+        let mut value = String::new();
+        let (before, after) = self.value.split_at(self.cursor);
+        let _ = value.push_str(before);
+        let _ = value.push(c as char);
+        let _ = value.push_str(after);
+        self.value = value;
+        self.cursor += 1;
+        true
+    }
+
+    /// Remove the character under the cursor
+    fn remove(&mut self) {
+        if self.cursor >= self.value.len() {
+            return;
+        }
+        let mut value = String::new();
+        let _ = value.push_str(&self.value[..self.cursor]);
+        let _ = value.push_str(&self.value[self.cursor + 1..]);
+        self.value = value;
+    }
+
+    /// Replace the character under the cursor with the next or previous one in the charset
+    ///
+    /// Past the end of the text, this appends the first or last character of the charset.
+    fn cycle(&mut self, step: i32) {
+        let current = self.value.as_bytes().get(self.cursor).copied();
+        let index = match current.and_then(|c| self.charset.iter().position(|&other| other == c)) {
+            Some(index) => index as i32,
+            None if step > 0 => -1,
+            None => 0,
+        };
+        let next = self.charset[(index + step).rem_euclid(self.charset.len() as i32) as usize];
+        if current.is_some() {
+            self.remove();
+        }
+        if self.insert(next) {
+            self.cursor -= 1;
+        }
+    }
+
+    fn char_size(&self) -> Size {
+        self.style.font.character_size
+    }
+
+    /// Area covered by the field, including its border
+    pub fn bounding_box(&self) -> Rectangle {
+        let char_size = self.char_size();
+        let padding = 2 * PADDING as u32;
+        Rectangle::new(
+            self.top_left,
+            Size::new(
+                self.width as u32 * char_size.width + padding,
+                char_size.height + padding,
+            ),
+        )
+    }
+
+    pub fn draw<D: DrawTarget<Color = C>>(&self, target: &mut D) -> Result<(), D::Error> {
+        let border_color = if self.focused {
+            self.accent_color
+        } else {
+            self.style.text_color.unwrap_or(self.accent_color)
+        };
+        self.bounding_box()
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .stroke_color(border_color)
+                    .stroke_width(1)
+                    .fill_color(self.background_color)
+                    .build(),
+            )
+            .draw(target)?;
+
+        // Scroll the text to keep the cursor in view
+        let start = (self.cursor + 1).saturating_sub(self.width);
+        let end = (start + self.width).min(self.value.len());
+        let origin = self.top_left + Point::new(PADDING, PADDING);
+        Text::with_baseline(&self.value[start..end], origin, self.style, Baseline::Top)
+            .draw(target)?;
+
+        if self.focused {
+            let char_size = self.char_size();
+            Rectangle::new(
+                origin
+                    + Point::new(
+                        ((self.cursor - start) as u32 * char_size.width) as i32,
+                        char_size.height as i32 - 1,
+                    ),
+                Size::new(char_size.width, 1),
+            )
+            .into_styled(PrimitiveStyle::with_fill(self.accent_color))
+            .draw(target)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+//! JSON lines
+//!
+//! Writes flat JSON objects, one per line, so host automation can read the command replies
+//! without scraping the text meant for people.
+
+use core::fmt::{self, Write};
+
+/// JSON object being written to `out`, ended by `finish`
+pub struct JsonLine<'w, W> {
+    out: &'w mut W,
+    first: bool,
+    result: fmt::Result,
+}
+
+impl<'w, W: Write> JsonLine<'w, W> {
+    pub fn new(out: &'w mut W) -> Self {
+        let result = out.write_char('{');
+        Self {
+            out,
+            first: true,
+            result,
+        }
+    }
+
+    /// Add a number, or any value written as is
+    pub fn number(&mut self, name: &str, value: impl fmt::Display) -> &mut Self {
+        self.field(name, |out| write!(out, "{}", value))
+    }
+
+    pub fn boolean(&mut self, name: &str, value: bool) -> &mut Self {
+        self.field(name, |out| write!(out, "{}", value))
+    }
+
+    pub fn null(&mut self, name: &str) -> &mut Self {
+        self.field(name, |out| out.write_str("null"))
+    }
+
+    pub fn string(&mut self, name: &str, value: &str) -> &mut Self {
+        self.bytes(name, value.as_bytes())
+    }
+
+    /// Add bytes as a string, escaping the quotes, backslashes and bytes that aren't printable
+    pub fn bytes(&mut self, name: &str, value: &[u8]) -> &mut Self {
+        self.field(name, |out| write_string(out, value))
+    }
+
+    /// End the object and the line
+    pub fn finish(&mut self) -> fmt::Result {
+        if self.result.is_ok() {
+            self.result = self.out.write_str("}\r\n");
+        }
+        self.result
+    }
+
+    fn field<F>(&mut self, name: &str, value: F) -> &mut Self
+    where
+        F: FnOnce(&mut W) -> fmt::Result,
+    {
+        if self.result.is_ok() {
+            let separator = if self.first { "" } else { "," };
+            self.first = false;
+            self.result = write_field(self.out, separator, name, value);
+        }
+        self
+    }
+}
+
+fn write_field<W, F>(out: &mut W, separator: &str, name: &str, value: F) -> fmt::Result
+where
+    W: Write,
+    F: FnOnce(&mut W) -> fmt::Result,
+{
+    out.write_str(separator)?;
+    write_string(out, name.as_bytes())?;
+    out.write_char(':')?;
+    value(out)
+}
+
+fn write_string<W: Write>(out: &mut W, value: &[u8]) -> fmt::Result {
+    out.write_char('"')?;
+    for &byte in value {
+        match byte {
+            b'"' => out.write_str("\\\"")?,
+            b'\\' => out.write_str("\\\\")?,
+            0x20..=0x7E => out.write_char(byte as char)?,
+            _ => write!(out, "\\u{:04x}", byte)?,
+        }
+    }
+    out.write_char('"')
+}
@@ -0,0 +1,142 @@
+//! USB HID keyboard helpers
+//!
+//! Conversion of ASCII characters and key names into HID usage codes, for a US keyboard
+//! layout.
+
+use usbd_hid::descriptor::KeyboardReport;
+
+/// Modifier bits of a keyboard report
+pub mod modifier {
+    pub const CTRL: u8 = 0x01;
+    pub const SHIFT: u8 = 0x02;
+    pub const ALT: u8 = 0x04;
+    pub const GUI: u8 = 0x08;
+}
+
+/// A key press: a set of modifiers with an optional key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyPress {
+    pub modifier: u8,
+    pub keycode: u8,
+}
+
+impl KeyPress {
+    pub const fn new(modifier: u8, keycode: u8) -> Self {
+        Self { modifier, keycode }
+    }
+
+    /// Key press for an ASCII character
+    pub fn from_ascii(c: u8) -> Option<Self> {
+        let (shift, keycode) = match c {
+            b'a'..=b'z' => (false, 0x04 + c - b'a'),
+            b'A'..=b'Z' => (true, 0x04 + c - b'A'),
+            b'1'..=b'9' => (false, 0x1e + c - b'1'),
+            b'0' => (false, 0x27),
+            b'\n' => (false, 0x28),
+            0x1b => (false, 0x29),
+            0x08 => (false, 0x2a),
+            b'\t' => (false, 0x2b),
+            b' ' => (false, 0x2c),
+            b'!' => (true, 0x1e),
+            b'@' => (true, 0x1f),
+            b'#' => (true, 0x20),
+            b'$' => (true, 0x21),
+            b'%' => (true, 0x22),
+            b'^' => (true, 0x23),
+            b'&' => (true, 0x24),
+            b'*' => (true, 0x25),
+            b'(' => (true, 0x26),
+            b')' => (true, 0x27),
+            b'-' => (false, 0x2d),
+            b'_' => (true, 0x2d),
+            b'=' => (false, 0x2e),
+            b'+' => (true, 0x2e),
+            b'[' => (false, 0x2f),
+            b'{' => (true, 0x2f),
+            b']' => (false, 0x30),
+            b'}' => (true, 0x30),
+            b'\\' => (false, 0x31),
+            b'|' => (true, 0x31),
+            b';' => (false, 0x33),
+            b':' => (true, 0x33),
+            b'\'' => (false, 0x34),
+            b'"' => (true, 0x34),
+            b'`' => (false, 0x35),
+            b'~' => (true, 0x35),
+            b',' => (false, 0x36),
+            b'<' => (true, 0x36),
+            b'.' => (false, 0x37),
+            b'>' => (true, 0x37),
+            b'/' => (false, 0x38),
+            b'?' => (true, 0x38),
+            _ => return None,
+        };
+
+        let modifier = if shift { modifier::SHIFT } else { 0 };
+        Some(Self::new(modifier, keycode))
+    }
+
+    /// Report with this key pressed
+    pub fn report(self) -> KeyboardReport {
+        KeyboardReport {
+            modifier: self.modifier,
+            reserved: 0,
+            leds: 0,
+            keycodes: [self.keycode, 0, 0, 0, 0, 0],
+        }
+    }
+}
+
+/// Report with all keys released
+pub fn release_report() -> KeyboardReport {
+    KeyPress::default().report()
+}
+
+/// Modifier bit for a modifier name, such as `CTRL`
+pub fn modifier_from_name(name: &str) -> Option<u8> {
+    match name {
+        "CTRL" | "CONTROL" => Some(modifier::CTRL),
+        "SHIFT" => Some(modifier::SHIFT),
+        "ALT" => Some(modifier::ALT),
+        "GUI" | "WINDOWS" | "COMMAND" => Some(modifier::GUI),
+        _ => None,
+    }
+}
+
+/// Keycode for a key name, such as `ENTER` or `F5`, or a single character
+pub fn keycode_from_name(name: &str) -> Option<u8> {
+    let keycode = match name {
+        "ENTER" => 0x28,
+        "ESC" | "ESCAPE" => 0x29,
+        "BACKSPACE" => 0x2a,
+        "TAB" => 0x2b,
+        "SPACE" => 0x2c,
+        "F1" => 0x3a,
+        "F2" => 0x3b,
+        "F3" => 0x3c,
+        "F4" => 0x3d,
+        "F5" => 0x3e,
+        "F6" => 0x3f,
+        "F7" => 0x40,
+        "F8" => 0x41,
+        "F9" => 0x42,
+        "F10" => 0x43,
+        "F11" => 0x44,
+        "F12" => 0x45,
+        "INSERT" => 0x49,
+        "HOME" => 0x4a,
+        "PAGEUP" => 0x4b,
+        "DELETE" => 0x4c,
+        "END" => 0x4d,
+        "PAGEDOWN" => 0x4e,
+        "RIGHT" => 0x4f,
+        "LEFT" => 0x50,
+        "DOWN" => 0x51,
+        "UP" => 0x52,
+        _ => match name.as_bytes() {
+            [c] => return KeyPress::from_ascii(c.to_ascii_lowercase()).map(|k| k.keycode),
+            _ => return None,
+        },
+    };
+    Some(keycode)
+}
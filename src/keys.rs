@@ -0,0 +1,168 @@
+//! Terminal keys sent to the host
+//!
+//! Maps the physical controls of the board to the escape sequences a VT100/xterm
+//! terminal would send, so they can drive TUIs running on the host. `KeyDecoder` does the
+//! reverse, so a terminal on the host can drive the pages of the device.
+
+use crate::ansi::{Action, Parser};
+
+/// A key that can be sent to the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Right,
+    Left,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    /// Function key, from 1 to 12
+    Function(u8),
+}
+
+/// Escape sequences of the function keys, from F1
+const FUNCTION_KEYS: [&[u8]; 12] = [
+    b"\x1bOP",
+    b"\x1bOQ",
+    b"\x1bOR",
+    b"\x1bOS",
+    b"\x1b[15~",
+    b"\x1b[17~",
+    b"\x1b[18~",
+    b"\x1b[19~",
+    b"\x1b[20~",
+    b"\x1b[21~",
+    b"\x1b[23~",
+    b"\x1b[24~",
+];
+
+impl Key {
+    /// Escape sequence sent to the host for this key
+    pub fn escape_sequence(self) -> &'static [u8] {
+        match self {
+            Key::Up => b"\x1b[A",
+            Key::Down => b"\x1b[B",
+            Key::Right => b"\x1b[C",
+            Key::Left => b"\x1b[D",
+            Key::PageUp => b"\x1b[5~",
+            Key::PageDown => b"\x1b[6~",
+            Key::Enter => b"\r",
+            Key::Escape => b"\x1b",
+            Key::Function(n) => FUNCTION_KEYS
+                .get((n as usize).wrapping_sub(1))
+                .copied()
+                .unwrap_or(b""),
+        }
+    }
+}
+
+/// Turns the escape sequences of a terminal back into keys
+///
+/// A lone escape can't be told apart from the start of a sequence, so Ctrl+C stands for the
+/// escape key.
+pub struct KeyDecoder {
+    parser: Parser,
+    /// The previous sequence was `ESC O`, and the next byte says which key it is
+    ss3: bool,
+}
+
+impl KeyDecoder {
+    pub const fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+            ss3: false,
+        }
+    }
+
+    /// Feed a byte received from the host, returning the key it completes
+    pub fn push(&mut self, byte: u8) -> Option<Key> {
+        let action = self.parser.advance(byte);
+        if self.ss3 {
+            self.ss3 = false;
+            // Application mode cursor keys, and F1 to F4
+            if let Action::Print(c) = action {
+                return match c {
+                    b'A' => Some(Key::Up),
+                    b'B' => Some(Key::Down),
+                    b'C' => Some(Key::Right),
+                    b'D' => Some(Key::Left),
+                    b'P'..=b'S' => Some(Key::Function(c - b'P' + 1)),
+                    _ => None,
+                };
+            }
+        }
+
+        match action {
+            Action::Escape(b'O') => {
+                self.ss3 = true;
+                None
+            }
+            Action::Control(b'\r') => Some(Key::Enter),
+            Action::Control(0x03) => Some(Key::Escape),
+            Action::Csi(csi) if !csi.private => match (csi.action, csi.param(0, 0)) {
+                (b'A', _) => Some(Key::Up),
+                (b'B', _) => Some(Key::Down),
+                (b'C', _) => Some(Key::Right),
+                (b'D', _) => Some(Key::Left),
+                (b'~', 5) => Some(Key::PageUp),
+                (b'~', 6) => Some(Key::PageDown),
+                // F1 to F4 are also sent as 11 to 14 by some terminals
+                (b'~', code @ 11..=15) => Some(Key::Function(code as u8 - 10)),
+                (b'~', code @ 17..=21) => Some(Key::Function(code as u8 - 11)),
+                (b'~', code @ 23..=24) => Some(Key::Function(code as u8 - 12)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Keys bound to the A, B, X and Y buttons
+pub struct KeyMap {
+    pub a: Option<Key>,
+    pub b: Option<Key>,
+    pub x: Option<Key>,
+    pub y: Option<Key>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            a: Some(Key::Up),
+            b: Some(Key::Down),
+            x: Some(Key::Enter),
+            y: Some(Key::Escape),
+        }
+    }
+}
+
+/// Turns button presses into keys
+///
+/// A key is only emitted once per press, on the transition from released to pressed.
+pub struct ButtonKeys {
+    map: KeyMap,
+    pressed: [bool; 4],
+}
+
+impl ButtonKeys {
+    pub fn new(map: KeyMap) -> Self {
+        Self {
+            map,
+            pressed: [false; 4],
+        }
+    }
+
+    /// Update the state of the A, B, X and Y buttons, calling `f` for each newly pressed key
+    pub fn update<F: FnMut(Key)>(&mut self, pressed: [bool; 4], mut f: F) {
+        let keys = [self.map.a, self.map.b, self.map.x, self.map.y];
+        for (i, key) in keys.iter().enumerate() {
+            if pressed[i] && !self.pressed[i] {
+                if let Some(key) = key {
+                    f(*key);
+                }
+            }
+        }
+        self.pressed = pressed;
+    }
+}
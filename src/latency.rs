@@ -0,0 +1,161 @@
+//! Display latency test
+//!
+//! Markers sent by the host among the text for the terminal, to measure how long the text takes
+//! to reach the panel. A marker is a token between the ASCII record and unit separators: it isn't
+//! drawn, and is reported once the character after it is sent to the panel.
+
+use heapless::{Deque, Vec};
+
+/// Byte starting a marker, the ASCII record separator
+pub const MARKER_START: u8 = 0x1E;
+/// Byte ending a marker, the ASCII unit separator
+pub const MARKER_END: u8 = 0x1F;
+/// Longest token, such as the time the host sent the marker
+pub const MAX_TOKEN: usize = 20;
+/// Markers received but not drawn yet whose time is kept
+pub const MAX_STAMPS: usize = 16;
+
+pub type Token = Vec<u8, MAX_TOKEN>;
+
+/// What a byte of the text is, once the markers are taken out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scan {
+    /// A byte to draw
+    Byte(u8),
+    /// A byte of a marker
+    Hidden,
+    /// The end of a marker, with its token
+    Marker(Token),
+}
+
+/// Finds the markers in the text
+///
+/// A marker with a token longer than `MAX_TOKEN` is dropped, and its bytes hidden.
+pub struct MarkerScanner {
+    token: Option<Token>,
+    /// Token too long, hidden until its end
+    overlong: bool,
+}
+
+impl MarkerScanner {
+    pub const fn new() -> Self {
+        Self {
+            token: None,
+            overlong: false,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Scan {
+        if byte == MARKER_START {
+            self.token = Some(Token::new());
+            self.overlong = false;
+            return Scan::Hidden;
+        }
+        match self.token.as_mut() {
+            Some(_) if byte == MARKER_END => match self.token.take() {
+                Some(token) if !self.overlong => Scan::Marker(token),
+                _ => Scan::Hidden,
+            },
+            Some(token) => {
+                self.overlong |= token.push(byte).is_err();
+                Scan::Hidden
+            }
+            None => Scan::Byte(byte),
+        }
+    }
+}
+
+/// Times the markers were received, stamped by the USB interrupt
+///
+/// The markers are counted the same way by the `LatencyProbe` drawing them, which matches its
+/// markers with their time by their number. Both have to start together, before the host sends
+/// the first marker.
+pub struct MarkerStamps {
+    scanner: MarkerScanner,
+    /// Number of the next marker, and the times of the markers not drawn yet
+    markers: u32,
+    stamps: Deque<(u32, u64), MAX_STAMPS>,
+}
+
+impl MarkerStamps {
+    pub const fn new() -> Self {
+        Self {
+            scanner: MarkerScanner::new(),
+            markers: 0,
+            stamps: Deque::new(),
+        }
+    }
+
+    /// Look for the end of a marker in a byte received at `now_us`
+    ///
+    /// The time is lost if `MAX_STAMPS` markers are waiting to be drawn.
+    pub fn stamp(&mut self, byte: u8, now_us: u64) {
+        if let Scan::Marker(_) = self.scanner.feed(byte) {
+            let _ = self.stamps.push_back((self.markers, now_us));
+            self.markers = self.markers.wrapping_add(1);
+        }
+    }
+
+    /// Time marker `number` was received, dropping the older ones
+    pub fn take(&mut self, number: u32) -> Option<u64> {
+        while let Some(&(marker, received_us)) = self.stamps.front() {
+            // Markers whose time was lost leave a gap in the numbers
+            if (marker.wrapping_sub(number) as i32) > 0 {
+                return None;
+            }
+            self.stamps.pop_front();
+            if marker == number {
+                return Some(received_us);
+            }
+        }
+        None
+    }
+}
+
+/// Marker drawn, to report to the host
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Marker {
+    pub token: Token,
+    /// Number of the marker since the test started, see `MarkerStamps::take`
+    pub number: u32,
+}
+
+/// Takes the markers out of the text drawn by the terminal
+pub struct LatencyProbe {
+    scanner: MarkerScanner,
+    markers: u32,
+    /// Marker waiting for the next character to be drawn
+    armed: Option<Marker>,
+}
+
+impl LatencyProbe {
+    pub const fn new() -> Self {
+        Self {
+            scanner: MarkerScanner::new(),
+            markers: 0,
+            armed: None,
+        }
+    }
+
+    /// Scan a byte about to be drawn, returning it unless it is part of a marker
+    pub fn feed(&mut self, byte: u8) -> Option<u8> {
+        match self.scanner.feed(byte) {
+            Scan::Byte(byte) => Some(byte),
+            Scan::Hidden => None,
+            Scan::Marker(token) => {
+                // A marker right before another one is never reported
+                self.armed = Some(Marker {
+                    token,
+                    number: self.markers,
+                });
+                self.markers = self.markers.wrapping_add(1);
+                None
+            }
+        }
+    }
+
+    /// Marker whose character was just drawn, after drawing a byte returned by `feed`
+    pub fn drawn(&mut self) -> Option<Marker> {
+        self.armed.take()
+    }
+}
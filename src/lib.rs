@@ -6,7 +6,119 @@ pub extern crate rp2040_hal as hal;
 extern crate cortex_m_rt;
 pub use cortex_m_rt::entry;
 
+pub mod ansi;
+pub mod assets;
+pub mod backlight;
+pub mod backup;
+#[cfg(feature = "usb")]
+pub mod baud;
+#[cfg(feature = "display")]
+pub mod bench;
+pub mod blink;
+#[cfg(feature = "display")]
+pub mod board;
+pub mod buttons;
+pub mod boot_mode;
+pub mod clock;
+pub mod config;
+pub mod confirm;
+pub mod crash;
+pub mod custom_glyph;
+pub mod datalog;
+pub mod device_name;
+pub mod diag;
+#[cfg(feature = "display")]
+pub mod display;
+#[cfg(feature = "display")]
+pub mod dma;
+#[cfg(feature = "display")]
+pub mod dual_display;
+pub mod entropy;
+pub mod errors;
+pub mod events;
+pub mod fault;
+pub mod flash;
+#[cfg(feature = "display")]
+pub mod framebuffer;
+#[cfg(feature = "display")]
+pub mod gauge;
+#[cfg(feature = "display")]
+pub mod glyph_cache;
+pub mod hash;
+pub mod heartbeat;
+pub mod idle;
+#[cfg(feature = "display")]
+pub mod input_field;
+pub mod json;
+#[cfg(feature = "hid")]
+pub mod keyboard;
+pub mod keys;
+pub mod latency;
+pub mod lock;
+pub mod logger;
+pub mod math;
+#[cfg(feature = "display")]
+pub mod mirror;
+#[cfg(feature = "display")]
+pub mod menu;
+pub mod metrics;
+#[cfg(feature = "msc")]
+pub mod msc;
+pub mod mux;
+#[cfg(feature = "oled")]
+pub mod oled;
+#[cfg(feature = "panic-display")]
+pub mod panic_display;
+pub mod palette;
+#[cfg(feature = "hid")]
+pub mod payload;
+pub mod pin_info;
+pub mod pin_owners;
+#[cfg(feature = "display")]
+pub mod plot;
+pub mod pulsecap;
+#[cfg(feature = "display")]
+pub mod render_core;
+#[cfg(feature = "display")]
+pub mod rgb_led;
+pub mod routing;
+pub mod scrollback;
+pub mod search;
+pub mod settings;
+#[cfg(feature = "display")]
+pub mod seven_segment;
+pub mod shared;
+pub mod shell;
+pub mod sleep;
+#[cfg(feature = "display")]
+pub mod soft_keyboard;
+pub mod startup;
+#[cfg(all(feature = "display", feature = "usb"))]
+pub mod status;
+pub mod switch;
+pub mod sync;
+pub mod tachometer;
+#[cfg(feature = "display")]
 pub mod terminal;
+#[cfg(feature = "embassy")]
+pub mod time_driver;
+pub mod triggers;
+pub mod typing;
+#[cfg(feature = "usb")]
+pub mod uart_bridge;
+pub mod unused_pins;
+#[cfg(feature = "usb")]
+pub mod usb_console;
+#[cfg(feature = "hid")]
+pub mod usb_keyboard;
+#[cfg(feature = "usb")]
+pub mod usb_link;
+#[cfg(feature = "usb")]
+pub mod usb_log;
+#[cfg(feature = "usb")]
+pub mod usb_tx;
+#[cfg(feature = "msc")]
+pub mod virtual_fs;
 
 #[link_section = ".boot2"]
 #[no_mangle]
@@ -14,6 +126,42 @@ pub mod terminal;
 pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
+
+/// For the diagnostics macros, see the `diag` module
+pub use log;
+
+// Sends the defmt logs to the debug probe
+#[cfg(feature = "defmt")]
+use defmt_rtt as _;
+
+/// Terminal on the Pico Display panel, for firmware embedding the console
+#[cfg(feature = "display")]
+pub type PicoDisplayTerminal<'f> =
+    terminal::Terminal<'f, embedded_graphics::pixelcolor::Rgb565, display::Display>;
+/// Builder of a `PicoDisplayTerminal`
+#[cfg(feature = "display")]
+pub type PicoDisplayTerminalBuilder<'f> =
+    terminal::TerminalBuilder<'f, embedded_graphics::pixelcolor::Rgb565, display::Display>;
+/// Copy of the Pico Display panel in RAM
+#[cfg(feature = "display")]
+pub type PicoFramebuffer = framebuffer::Framebuffer<
+    embedded_graphics::pixelcolor::Rgb565,
+    { display::WIDTH as usize },
+    { display::HEIGHT as usize },
+>;
+/// Terminal drawing into a `PicoFramebuffer`, flushed to the panel on demand
+#[cfg(feature = "display")]
+pub type PicoBufferedTerminal<'f> =
+    terminal::Terminal<'f, embedded_graphics::pixelcolor::Rgb565, PicoFramebuffer>;
+/// USB serial console on the RP2040 USB controller
+#[cfg(feature = "usb")]
+pub type PicoUsbConsole<'a> = usb_console::UsbConsole<'a, hal::usb::UsbBus>;
+/// USB serial port for the logs on the RP2040 USB controller, next to the console
+#[cfg(feature = "usb")]
+pub type PicoLogPort<'a> = usb_log::LogPort<'a, hal::usb::UsbBus>;
+/// USB HID keyboard on the RP2040 USB controller, next to the console
+#[cfg(feature = "hid")]
+pub type PicoUsbKeyboard<'a> = usb_keyboard::UsbKeyboard<'a, hal::usb::UsbBus>;
 hal::bsp_pins!(
     Gpio0 { name: gpio0 },
     Gpio1 { name: gpio1 },
@@ -47,7 +195,7 @@ hal::bsp_pins!(
         name: spi_mosi,
         aliases: { FunctionSpi: Mosi }
     },
-    Gpio20 { name: gpio20 },
+    Gpio20 { name: lcd_backlight },
     Gpio21 { name: gpio21 },
     Gpio22 { name: gpio22 },
     Gpio23 { name: b_power_save },
@@ -63,6 +211,16 @@ hal::bsp_pins!(
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
 
+/// Convert a raw temperature sensor reading into tenths of degrees Celsius
+///
+/// See section 4.9.5 of the RP2040 datasheet.
+pub fn temperature_from_adc(raw: u16) -> i32 {
+    // 12-bit reading with a 3.3V reference
+    let millivolts = raw as i32 * 3300 / 4096;
+    // 27°C at 706mV, -1.721mV per degree
+    270 - (millivolts - 706) * 10_000 / 1721
+}
+
 pub struct DummyPin;
 
 impl embedded_hal::digital::v2::OutputPin for DummyPin {
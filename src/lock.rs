@@ -0,0 +1,248 @@
+//! Configuration lock
+//!
+//! Commands that change the flash, the GPIOs or the HID payloads can require a PIN, entered over
+//! serial or with the buttons. After a few failures, each new failure doubles the wait before the
+//! next attempt. The PIN and the failure count are kept in flash, so rebooting the device neither
+//! removes the lock nor resets the wait.
+
+use crate::flash::{self, checksum, PAGE_SIZE, SECTOR_SIZE};
+use heapless::String;
+use rand_core::RngCore;
+
+/// Sector where the lock is stored, from the start of the flash
+pub const LOCK_OFFSET: u32 = 0x1F_1000;
+
+/// Minimum length of a PIN
+pub const MIN_PIN: usize = 4;
+/// Maximum length of a PIN
+pub const MAX_PIN: usize = 8;
+
+const MAGIC: [u8; 4] = *b"LCK2";
+
+/// Failures allowed before having to wait between attempts
+const FREE_ATTEMPTS: u32 = 3;
+/// Wait after the first failure past the free attempts
+const BASE_BACKOFF_US: u64 = 5_000_000;
+/// Longest wait between attempts
+const MAX_BACKOFF_US: u64 = 3_600_000_000;
+/// Time after which a partial button sequence is dropped
+const BUTTON_TIMEOUT_US: u64 = 5_000_000;
+
+/// Reasons a lock operation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockError {
+    /// The PIN doesn't match
+    WrongPin,
+    /// Too many failures, the next attempt is accepted after this many seconds
+    Backoff(u32),
+    /// The PIN is too short, too long, or has digits other than 1 to 4
+    InvalidPin,
+    /// The lock must be opened first
+    Locked,
+}
+
+/// PIN as kept in flash, salted and hashed rather than in clear
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct StoredPin {
+    len: u8,
+    salt: [u8; 4],
+    digest: u32,
+}
+
+impl StoredPin {
+    fn new(pin: &str, salt: [u8; 4]) -> Self {
+        Self {
+            len: pin.len() as u8,
+            salt,
+            digest: digest(&salt, pin),
+        }
+    }
+
+    fn matches(&self, pin: &str) -> bool {
+        pin.len() == self.len as usize && digest(&self.salt, pin) == self.digest
+    }
+}
+
+/// PIN lock over the commands that change the device
+pub struct Lock {
+    pin: Option<StoredPin>,
+    /// Consecutive failed attempts
+    failures: u32,
+    unlocked: bool,
+    /// Uptime before which attempts are refused
+    retry_at_us: u64,
+    /// Digits entered with the buttons so far
+    entry: String<MAX_PIN>,
+    last_press_us: u64,
+    /// The stored lock is read on the first attempt, see `unread`
+    unread: bool,
+}
+
+impl Lock {
+    /// Load the lock stored in flash, locked if it has a PIN
+    pub fn load() -> Self {
+        let mut lock = Self::unread();
+        lock.unread = false;
+
+        // Magic, PIN length, salt, digest, failures and checksum
+        let page = flash::read(LOCK_OFFSET, 19);
+        let sum = u16::from_le_bytes([page[17], page[18]]);
+        if page[..4] != MAGIC || checksum(&page[4..17]) != sum {
+            return lock;
+        }
+        lock.pin = Some(StoredPin {
+            len: page[4],
+            salt: [page[5], page[6], page[7], page[8]],
+            digest: u32::from_le_bytes([page[9], page[10], page[11], page[12]]),
+        });
+        lock.failures = u32::from_le_bytes([page[13], page[14], page[15], page[16]]);
+        // The wait starts over from boot
+        lock.retry_at_us = backoff_us(lock.failures);
+        lock
+    }
+
+    /// Closed lock, reading the stored one on the first `unlock`
+    ///
+    /// Safe mode doesn't read the flash at boot, but a device with a PIN mustn't be opened by
+    /// starting it in safe mode. Without a PIN stored, any PIN opens it.
+    pub fn unread() -> Self {
+        Self {
+            pin: None,
+            failures: 0,
+            unlocked: false,
+            retry_at_us: 0,
+            entry: String::new(),
+            last_press_us: 0,
+            unread: true,
+        }
+    }
+
+    /// Returns true if a PIN is set
+    pub fn is_set(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    /// Returns true if the protected commands are refused
+    pub fn is_locked(&self) -> bool {
+        self.unread || (self.pin.is_some() && !self.unlocked)
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = false;
+        self.entry.clear();
+    }
+
+    /// Open the lock with `pin`
+    pub fn unlock(&mut self, pin: &str, now_us: u64) -> Result<(), LockError> {
+        if self.unread {
+            *self = Self::load();
+        }
+        let expected = match self.pin {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+        if now_us < self.retry_at_us {
+            let seconds = (self.retry_at_us - now_us + 999_999) / 1_000_000;
+            return Err(LockError::Backoff(seconds as u32));
+        }
+
+        if expected.matches(pin) {
+            self.unlocked = true;
+            if self.failures > 0 {
+                self.failures = 0;
+                self.save();
+            }
+            Ok(())
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            self.retry_at_us = now_us + backoff_us(self.failures);
+            self.save();
+            Err(LockError::WrongPin)
+        }
+    }
+
+    /// Handle a button press, trying the PIN once enough buttons were pressed
+    ///
+    /// Buttons A, B, X and Y are the digits 1 to 4. A lock not read yet takes no digits, as
+    /// their number isn't known.
+    pub fn press_button(&mut self, index: usize, now_us: u64) -> Option<Result<(), LockError>> {
+        if !self.is_locked() || self.unread || index > 3 {
+            return None;
+        }
+        if now_us.saturating_sub(self.last_press_us) > BUTTON_TIMEOUT_US {
+            self.entry.clear();
+        }
+        self.last_press_us = now_us;
+        let _ = self.entry.push((b'1' + index as u8) as char);
+
+        let len = self.pin.map_or(0, |pin| pin.len as usize);
+        if self.entry.len() < len {
+            return None;
+        }
+        let entry = core::mem::replace(&mut self.entry, String::new());
+        Some(self.unlock(&entry, now_us))
+    }
+
+    /// Change the PIN, or remove it with `None`
+    ///
+    /// The lock has to be open. It stays open until `lock` is called or the device reboots.
+    /// The PIN is stored with a salt from `rng`.
+    pub fn set_pin<R: RngCore>(&mut self, pin: Option<&str>, rng: &mut R) -> Result<(), LockError> {
+        if self.is_locked() {
+            return Err(LockError::Locked);
+        }
+        self.pin = match pin {
+            Some(pin) if is_valid(pin) => Some(StoredPin::new(pin, rng.next_u32().to_le_bytes())),
+            Some(_) => return Err(LockError::InvalidPin),
+            None => None,
+        };
+        self.failures = 0;
+        self.unlocked = true;
+        self.save();
+        Ok(())
+    }
+
+    /// Store the PIN and the failure count in flash
+    fn save(&self) {
+        let mut page = [0xFF; PAGE_SIZE as usize];
+        if let Some(pin) = self.pin {
+            page[..4].copy_from_slice(&MAGIC);
+            page[4] = pin.len;
+            page[5..9].copy_from_slice(&pin.salt);
+            page[9..13].copy_from_slice(&pin.digest.to_le_bytes());
+            page[13..17].copy_from_slice(&self.failures.to_le_bytes());
+            let sum = checksum(&page[4..17]);
+            page[17..19].copy_from_slice(&sum.to_le_bytes());
+        }
+
+        // Note (safety): the sector is reserved for the lock
+        unsafe {
+            flash::erase(LOCK_OFFSET, SECTOR_SIZE);
+            flash::program(LOCK_OFFSET, &page);
+        }
+    }
+}
+
+/// PINs are made of the digits that can be entered with the buttons
+fn is_valid(pin: &str) -> bool {
+    (MIN_PIN..=MAX_PIN).contains(&pin.len()) && pin.bytes().all(|c| (b'1'..=b'4').contains(&c))
+}
+
+/// FNV-1a hash of the salt and PIN
+///
+/// The salt keeps the same PIN from giving the same digest on different devices.
+fn digest(salt: &[u8; 4], pin: &str) -> u32 {
+    salt.iter()
+        .chain(pin.as_bytes())
+        .fold(0x811C_9DC5, |hash, &byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
+
+/// Wait before the next attempt after `failures` failures
+fn backoff_us(failures: u32) -> u64 {
+    match failures.checked_sub(FREE_ATTEMPTS) {
+        Some(extra) => (BASE_BACKOFF_US << extra.min(16)).min(MAX_BACKOFF_US),
+        None => 0,
+    }
+}
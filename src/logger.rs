@@ -0,0 +1,110 @@
+//! Logger
+//!
+//! Backend of the `log` crate, writing the records to the terminal, the host or the log buffer,
+//! following the routes of `Source::Log`. Each record starts with its time, the records above
+//! the level of `set_level` are dropped, and the ones shown on the terminal are colored by level.
+
+use crate::clock::Timestamp;
+use crate::routing::{Sink, Source, ROUTES};
+use core::fmt::Write;
+use heapless::String;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Longest line, the rest is cut
+pub const MAX_LINE: usize = 96;
+
+/// Writes the records where `ROUTES` sends them
+///
+/// The application gives the functions writing to each sink, as it owns the terminal and the
+/// USB serial port, and the one reading the time, as it owns the timer. They may be called from
+/// an interrupt, like the macros of `log`.
+pub struct Logger {
+    /// Writes to the terminal on the display
+    display: fn(&[u8]),
+    /// Writes to the host over USB serial
+    usb: fn(&[u8]),
+    /// Writes to the log buffer, see `routing::LogBuffer`
+    buffer: fn(&[u8]),
+    /// Time of the records, such as `CLOCK.timestamp` of the timer
+    now: fn() -> Timestamp,
+}
+
+impl Logger {
+    pub const fn new(
+        display: fn(&[u8]),
+        usb: fn(&[u8]),
+        buffer: fn(&[u8]),
+        now: fn() -> Timestamp,
+    ) -> Self {
+        Self {
+            display,
+            usb,
+            buffer,
+            now,
+        }
+    }
+
+    /// Install `logger` for the macros of `log`, keeping the records up to `level`
+    ///
+    /// # Safety
+    ///
+    /// Nothing may log while this runs, such as an interrupt, as the Cortex-M0+ can't set the
+    /// logger atomically. Call it once, before enabling the interrupts.
+    pub unsafe fn init(logger: &'static Logger, level: LevelFilter) {
+        let _ = log::set_logger_racy(logger);
+        set_level(level);
+    }
+}
+
+/// Keep the records up to `level`
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut line: String<MAX_LINE> = String::new();
+        let _ = write!(
+            line,
+            "{} [{}] {}",
+            (self.now)(),
+            record.level(),
+            record.args()
+        );
+        // A cut line still ends
+        line.truncate(MAX_LINE - 2);
+        let _ = line.push_str("\r\n");
+
+        if ROUTES.is_routed(Source::Log, Sink::Display) {
+            (self.display)(level_color(record.level()));
+            (self.display)(line.as_bytes());
+            (self.display)(b"\x1b[39m");
+        }
+        if ROUTES.is_routed(Source::Log, Sink::Usb) {
+            (self.usb)(line.as_bytes());
+        }
+        if ROUTES.is_routed(Source::Log, Sink::LogBuffer) {
+            (self.buffer)(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Color of the records of `level` on the terminal: errors in red, warnings in yellow and info
+/// in green, as SGR sequences
+fn level_color(level: Level) -> &'static [u8] {
+    match level {
+        Level::Error => b"\x1b[31m",
+        Level::Warn => b"\x1b[33m",
+        Level::Info => b"\x1b[32m",
+        Level::Debug | Level::Trace => b"\x1b[39m",
+    }
+}
@@ -0,0 +1,112 @@
+//! Fast integer math
+//!
+//! The Cortex-M0+ has no division instruction, so divisions run on the hardware divider of the
+//! SIO instead, and blends on its interpolator, in a few cycles each. Color and animation
+//! helpers for the render loops are built on them.
+
+use crate::{pac, palette::Rgb};
+use core::convert::TryFrom;
+
+/// Divide on the hardware divider, returning the quotient and the remainder
+///
+/// Panics if `divisor` is zero, like the `/` operator.
+pub fn div_u32(dividend: u32, divisor: u32) -> (u32, u32) {
+    assert!(divisor != 0, "division by zero");
+    cortex_m::interrupt::free(|_| {
+        // Note (safety): interrupts are disabled, so an interrupt handler cannot use the
+        // divider of this core at the same time.
+        let sio = unsafe { &*pac::SIO::ptr() };
+        sio.div_udividend.write(|w| unsafe { w.bits(dividend) });
+        sio.div_udivisor.write(|w| unsafe { w.bits(divisor) });
+        wait_divider(sio)
+    })
+}
+
+/// Signed version of `div_u32`, rounding towards zero like the `/` operator
+pub fn div_i32(dividend: i32, divisor: i32) -> (i32, i32) {
+    assert!(divisor != 0, "division by zero");
+    cortex_m::interrupt::free(|_| {
+        // Note (safety): interrupts are disabled, so an interrupt handler cannot use the
+        // divider of this core at the same time.
+        let sio = unsafe { &*pac::SIO::ptr() };
+        sio.div_sdividend.write(|w| unsafe { w.bits(dividend as u32) });
+        sio.div_sdivisor.write(|w| unsafe { w.bits(divisor as u32) });
+        let (quotient, remainder) = wait_divider(sio);
+        (quotient as i32, remainder as i32)
+    })
+}
+
+fn wait_divider(sio: &pac::sio::RegisterBlock) -> (u32, u32) {
+    // The result is ready 8 cycles after writing the divisor
+    while sio.div_csr.read().ready().bit_is_clear() {}
+    // Reading the quotient clears the dirty flag, so the remainder goes first
+    let remainder = sio.div_remainder.read().bits();
+    let quotient = sio.div_quotient.read().bits();
+    (quotient, remainder)
+}
+
+/// Blend from `a` to `b` by `alpha / 256`, on interpolator 0
+pub fn lerp(a: i32, b: i32, alpha: u8) -> i32 {
+    cortex_m::interrupt::free(|_| {
+        // Note (safety): interrupts are disabled, so an interrupt handler cannot use the
+        // interpolator of this core at the same time.
+        let sio = unsafe { &*pac::SIO::ptr() };
+        // In blend mode, lane 1 takes the alpha from the low byte of its accumulator
+        sio.interp0_ctrl_lane0.write(|w| w.blend().set_bit());
+        sio.interp0_ctrl_lane1
+            .write(|w| unsafe { w.mask_msb().bits(7).signed().set_bit() });
+        sio.interp0_accum1.write(|w| unsafe { w.bits(alpha as u32) });
+        sio.interp0_base0.write(|w| unsafe { w.bits(a as u32) });
+        sio.interp0_base1.write(|w| unsafe { w.bits(b as u32) });
+        sio.interp0_peek_lane1.read().bits() as i32
+    })
+}
+
+/// `value * numerator / denominator`, without overflowing in between
+pub fn scale(value: u32, numerator: u32, denominator: u32) -> u32 {
+    let (quotient, _) = div_u32_wide(value as u64 * numerator as u64, denominator);
+    quotient
+}
+
+/// Divide a 64-bit value, saturating the quotient to 32 bits
+fn div_u32_wide(dividend: u64, divisor: u32) -> (u32, u32) {
+    match u32::try_from(dividend) {
+        Ok(dividend) => div_u32(dividend, divisor),
+        // Rare enough in the render loops to leave to software
+        Err(_) => {
+            let quotient = dividend / divisor as u64;
+            (
+                quotient.min(u32::MAX as u64) as u32,
+                (dividend % divisor as u64) as u32,
+            )
+        }
+    }
+}
+
+/// Color of a hue from 0 to 359 degrees, with saturation and value from 0 to 255
+pub fn hsv_to_rgb(hue: u16, saturation: u8, value: u8) -> Rgb {
+    let (_, hue) = div_u32(hue as u32, 360);
+    let (sector, offset) = div_u32(hue, 60);
+    // Position in the sector, from 0 to 255
+    let fraction = scale(offset, 255, 59) as u8;
+    let v = value as i32;
+    let low = lerp(v, 0, saturation) as u8;
+    let falling = lerp(v, 0, scale(saturation as u32, fraction as u32, 255) as u8) as u8;
+    let rising = lerp(v, 0, scale(saturation as u32, 255 - fraction as u32, 255) as u8) as u8;
+    match sector {
+        0 => [value, rising, low],
+        1 => [falling, value, low],
+        2 => [low, value, rising],
+        3 => [low, falling, value],
+        4 => [rising, low, value],
+        _ => [value, low, falling],
+    }
+}
+
+/// Ease in and out, from 0 to 255, for animations starting and ending smoothly
+///
+/// This is the smoothstep curve, `3t² - 2t³`.
+pub fn ease_in_out(t: u8) -> u8 {
+    let t = t as u32;
+    scale(t * t, 3 * 255 - 2 * t, 255 * 255) as u8
+}
@@ -0,0 +1,127 @@
+//! List menu widget
+//!
+//! Vertical list of labelled values for the on-device pages, with one selected row. It only
+//! handles the navigation, the page decides what selecting a row does.
+
+use crate::keys::Key;
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, MonoTextStyleBuilder},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+/// Width of the labels, in characters
+const LABEL_WIDTH: usize = 12;
+
+/// What the menu asks the page to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuAction {
+    /// The row at this index was chosen
+    Select(usize),
+    /// Leave the page
+    Close,
+}
+
+/// List menu widget
+pub struct Menu<'f, C> {
+    area: Rectangle,
+    title: &'static str,
+    style: MonoTextStyle<'f, C>,
+    background_color: C,
+    /// Background of the selected row
+    highlight_color: C,
+    selected: usize,
+}
+
+impl<'f, C: PixelColor> Menu<'f, C> {
+    pub fn new(
+        area: Rectangle,
+        title: &'static str,
+        style: MonoTextStyle<'f, C>,
+        background_color: C,
+        highlight_color: C,
+    ) -> Self {
+        Self {
+            area,
+            title,
+            style,
+            background_color,
+            highlight_color,
+            selected: 0,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Handle a key, for a menu of `len` rows
+    ///
+    /// Up and down move the selection, wrapping around, enter selects the row and escape closes
+    /// the menu.
+    pub fn handle_key(&mut self, key: Key, len: usize) -> Option<MenuAction> {
+        if len == 0 {
+            return Some(MenuAction::Close);
+        }
+        match key {
+            Key::Up => self.selected = (self.selected + len - 1) % len,
+            Key::Down => self.selected = (self.selected + 1) % len,
+            Key::Enter => return Some(MenuAction::Select(self.selected.min(len - 1))),
+            Key::Escape => return Some(MenuAction::Close),
+            _ => (),
+        }
+        None
+    }
+
+    /// Height of a row, in pixels
+    fn row_height(&self) -> i32 {
+        self.style.font.character_size.height as i32 + 2
+    }
+
+    /// Top left corner of the area below the rows, for widgets shown along the menu
+    pub fn below(&self, len: usize) -> Point {
+        self.area.top_left + Point::new(0, self.row_height() * (len as i32 + 1))
+    }
+
+    /// Draw the title, then one row per item with its label and value
+    pub fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        target: &mut D,
+        items: &[(&str, &str)],
+    ) -> Result<(), D::Error> {
+        self.area
+            .into_styled(PrimitiveStyle::with_fill(self.background_color))
+            .draw(target)?;
+
+        let char_width = self.style.font.character_size.width as i32;
+        let row_height = self.row_height();
+        Text::with_baseline(self.title, self.area.top_left, self.style, Baseline::Top)
+            .draw(target)?;
+
+        let highlight_style = MonoTextStyleBuilder::from(&self.style)
+            .background_color(self.highlight_color)
+            .build();
+        for (index, (label, value)) in items.iter().enumerate() {
+            let top_left = self.area.top_left + Point::new(0, row_height * (index as i32 + 1));
+            let style = if index == self.selected {
+                Rectangle::new(top_left, Size::new(self.area.size.width, row_height as u32))
+                    .into_styled(PrimitiveStyle::with_fill(self.highlight_color))
+                    .draw(target)?;
+                highlight_style
+            } else {
+                self.style
+            };
+            let text_pos = top_left + Point::new(char_width, 1);
+            Text::with_baseline(label, text_pos, style, Baseline::Top).draw(target)?;
+            Text::with_baseline(
+                value,
+                text_pos + Point::new(char_width * LABEL_WIDTH as i32, 0),
+                style,
+                Baseline::Top,
+            )
+            .draw(target)?;
+        }
+        Ok(())
+    }
+}
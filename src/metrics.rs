@@ -0,0 +1,236 @@
+//! Metrics
+//!
+//! Counters, gauges and histograms in a registry of fixed slots, so any module can count what it
+//! does without adding its own statics and command. A `Metric` takes a slot on its first update,
+//! and the registry lists them all for the `stats` command.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Vec;
+
+/// Number of slots of the registry
+pub const MAX_METRICS: usize = 24;
+/// Buckets of a histogram, each one 4 times as wide as the one before
+pub const BUCKETS: usize = 8;
+
+/// Index of a metric without a slot yet
+const UNREGISTERED: u8 = u8::MAX;
+
+/// Distribution of the values recorded, such as durations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    pub count: u32,
+    pub sum: u32,
+    pub max: u32,
+    /// Values recorded below each bound of `bucket_bound`, and above the last one
+    pub buckets: [u32; BUCKETS],
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0,
+            max: 0,
+            buckets: [0; BUCKETS],
+        }
+    }
+
+    /// Values below which a bucket counts, from 1 then multiplied by 4, `None` for the last one
+    pub fn bucket_bound(bucket: usize) -> Option<u32> {
+        (bucket < BUCKETS - 1).then(|| 1 << (2 * bucket))
+    }
+
+    fn record(&mut self, value: u32) {
+        let bucket = (0..BUCKETS - 1)
+            .find(|&bucket| value < 1 << (2 * bucket))
+            .unwrap_or(BUCKETS - 1);
+        self.buckets[bucket] = self.buckets[bucket].wrapping_add(1);
+        self.count = self.count.wrapping_add(1);
+        self.sum = self.sum.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Average of the values recorded, 0 without any
+    pub fn mean(&self) -> u32 {
+        self.sum.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Value of a metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricValue {
+    /// Total that only goes up, such as bytes sent
+    Counter(u32),
+    /// Latest value, such as a temperature
+    Gauge(i32),
+    Histogram(Histogram),
+}
+
+impl MetricValue {
+    /// The same kind of value, starting over
+    fn reset(self) -> Self {
+        match self {
+            MetricValue::Counter(_) => MetricValue::Counter(0),
+            MetricValue::Gauge(_) => MetricValue::Gauge(0),
+            MetricValue::Histogram(_) => MetricValue::Histogram(Histogram::new()),
+        }
+    }
+}
+
+struct Slot {
+    name: &'static str,
+    value: MetricValue,
+}
+
+/// Slots of the metrics, shared between the main loop and the interrupts
+pub struct Registry {
+    slots: Mutex<RefCell<Vec<Slot, MAX_METRICS>>>,
+}
+
+/// Metrics of the device
+pub static METRICS: Registry = Registry::new();
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            slots: Mutex::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Number of metrics registered
+    pub fn len(&self) -> usize {
+        interrupt::free(|cs| self.slots.borrow(cs).borrow().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Name and value of the metric in slot `index`, in the order they registered
+    pub fn get(&self, index: usize) -> Option<(&'static str, MetricValue)> {
+        interrupt::free(|cs| {
+            let slots = self.slots.borrow(cs).borrow();
+            slots.get(index).map(|slot| (slot.name, slot.value))
+        })
+    }
+
+    /// Start all the metrics over, keeping their slots
+    pub fn reset(&self) {
+        interrupt::free(|cs| {
+            for slot in self.slots.borrow(cs).borrow_mut().iter_mut() {
+                slot.value = slot.value.reset();
+            }
+        });
+    }
+}
+
+/// Metric updated from anywhere, declared as a static by the module it measures
+///
+/// Updates are dropped once the registry is full, or if another metric of a different kind has
+/// the same name.
+pub struct Metric {
+    name: &'static str,
+    initial: MetricValue,
+    /// Slot in the registry, found on the first update
+    index: AtomicU8,
+}
+
+impl Metric {
+    pub const fn counter(name: &'static str) -> Self {
+        Self::new(name, MetricValue::Counter(0))
+    }
+
+    pub const fn gauge(name: &'static str) -> Self {
+        Self::new(name, MetricValue::Gauge(0))
+    }
+
+    pub const fn histogram(name: &'static str) -> Self {
+        Self::new(name, MetricValue::Histogram(Histogram::new()))
+    }
+
+    const fn new(name: &'static str, initial: MetricValue) -> Self {
+        Self {
+            name,
+            initial,
+            index: AtomicU8::new(UNREGISTERED),
+        }
+    }
+
+    /// Take a slot now, so the metric is listed before its first update
+    pub fn register(&self) {
+        self.update(|_| ());
+    }
+
+    /// Add `n` to a counter
+    pub fn add(&self, n: u32) {
+        self.update(|value| {
+            if let MetricValue::Counter(total) = value {
+                *total = total.wrapping_add(n);
+            }
+        });
+    }
+
+    /// Set the value of a gauge
+    pub fn set(&self, level: i32) {
+        self.update(|value| {
+            if let MetricValue::Gauge(gauge) = value {
+                *gauge = level;
+            }
+        });
+    }
+
+    /// Add a value to a histogram
+    pub fn record(&self, sample: u32) {
+        self.update(|value| {
+            if let MetricValue::Histogram(histogram) = value {
+                histogram.record(sample);
+            }
+        });
+    }
+
+    /// Current value, `None` before the metric has a slot
+    pub fn get(&self) -> Option<MetricValue> {
+        match self.index.load(Ordering::Relaxed) {
+            UNREGISTERED => None,
+            index => METRICS.get(index as usize).map(|(_, value)| value),
+        }
+    }
+
+    /// Current value of a counter, 0 before its first update
+    pub fn total(&self) -> u32 {
+        match self.get() {
+            Some(MetricValue::Counter(total)) => total,
+            _ => 0,
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut MetricValue)) {
+        interrupt::free(|cs| {
+            let mut slots = METRICS.slots.borrow(cs).borrow_mut();
+            let index = match self.index.load(Ordering::Relaxed) {
+                UNREGISTERED => {
+                    // Another static may have registered the same name
+                    let index = match slots.iter().position(|slot| slot.name == self.name) {
+                        Some(index) => index,
+                        None => {
+                            let slot = Slot {
+                                name: self.name,
+                                value: self.initial,
+                            };
+                            if slots.push(slot).is_err() {
+                                return;
+                            }
+                            slots.len() - 1
+                        }
+                    };
+                    self.index.store(index as u8, Ordering::Relaxed);
+                    index
+                }
+                index => index as usize,
+            };
+            f(&mut slots[index].value);
+        });
+    }
+}
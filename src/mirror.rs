@@ -0,0 +1,123 @@
+//! Screen mirroring
+//!
+//! Reads the panel memory back a tile at a time, and sends the tiles that changed since they
+//! were last sent, so a host can show and record what the device displays. Reading needs the
+//! data out of the panel wired to GPIO0, see `display::read_pixels`.
+
+use crate::display::{self, Display, HEIGHT, WIDTH};
+use crate::dual_display::VISIBLE_OFFSET;
+use crate::hash::Crc32;
+use embedded_graphics::{
+    pixelcolor::{IntoStorage, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+/// Size of a tile, in pixels
+pub const TILE_WIDTH: u32 = 16;
+pub const TILE_HEIGHT: u32 = 15;
+/// Number of tiles across and down the visible area
+pub const TILE_COLUMNS: usize = WIDTH as usize / TILE_WIDTH as usize;
+pub const TILE_ROWS: usize = HEIGHT as usize / TILE_HEIGHT as usize;
+pub const TILE_COUNT: usize = TILE_COLUMNS * TILE_ROWS;
+const TILE_PIXELS: usize = (TILE_WIDTH * TILE_HEIGHT) as usize;
+
+/// Pixels in a frame, which fits the payload of a `mux` frame with its header
+pub const FRAME_PIXELS: usize = 28;
+/// Largest frame: `M`, the tile index and the offset of the first pixel, then the pixels
+pub const MAX_FRAME: usize = 3 + 2 * FRAME_PIXELS;
+
+/// Tile read from the panel, waiting to be sent
+struct PendingTile {
+    index: usize,
+    sum: u32,
+    pixels: [Rgb565; TILE_PIXELS],
+    /// Number of pixels already sent
+    sent: usize,
+}
+
+/// Finds the tiles of the screen that changed and splits them into frames
+pub struct Mirror {
+    /// CRC-32 of each tile when it was last sent
+    sums: [Option<u32>; TILE_COUNT],
+    /// Next tile to read
+    next: usize,
+    tile: Option<PendingTile>,
+}
+
+impl Mirror {
+    pub fn new() -> Self {
+        Self {
+            sums: [None; TILE_COUNT],
+            next: 0,
+            tile: None,
+        }
+    }
+
+    /// Forget the tiles sent, so the whole screen is sent again, such as for a new viewer
+    pub fn reset(&mut self) {
+        self.sums = [None; TILE_COUNT];
+    }
+
+    /// Area of a tile, in panel memory coordinates
+    pub fn tile_area(index: usize) -> Rectangle {
+        let (column, row) = ((index % TILE_COLUMNS) as i32, (index / TILE_COLUMNS) as i32);
+        let top_left = Point::new(column * TILE_WIDTH as i32, row * TILE_HEIGHT as i32);
+        Rectangle::new(top_left + VISIBLE_OFFSET, Size::new(TILE_WIDTH, TILE_HEIGHT))
+    }
+
+    /// Returns true while a changed tile is being sent
+    pub fn is_sending(&self) -> bool {
+        self.tile.is_some()
+    }
+
+    /// Read up to `max` tiles from the panel, stopping at the first one that changed
+    pub fn scan(&mut self, display: &mut Display, max: usize) {
+        for _ in 0..max {
+            if self.tile.is_some() {
+                return;
+            }
+            let index = self.next;
+            self.next = (self.next + 1) % TILE_COUNT;
+
+            let mut pixels = [Rgb565::BLACK; TILE_PIXELS];
+            display::read_pixels(display, &Self::tile_area(index), &mut pixels);
+            let mut crc = Crc32::new();
+            for pixel in pixels.iter() {
+                crc.update(&pixel.into_storage().to_be_bytes());
+            }
+            let sum = crc.finish();
+            if self.sums[index] != Some(sum) {
+                self.tile = Some(PendingTile {
+                    index,
+                    sum,
+                    pixels,
+                    sent: 0,
+                });
+            }
+        }
+    }
+
+    /// Write the next frame of the tile being sent to `out`, returning its length
+    ///
+    /// The pixels are big endian RGB565, from the top left of the tile, row by row.
+    pub fn next_frame(&mut self, out: &mut [u8; MAX_FRAME]) -> Option<usize> {
+        let tile = self.tile.as_mut()?;
+        let pixels = &tile.pixels[tile.sent..];
+        let count = pixels.len().min(FRAME_PIXELS);
+        out[0] = b'M';
+        out[1] = tile.index as u8;
+        out[2] = tile.sent as u8;
+        for (bytes, pixel) in out[3..].chunks_mut(2).zip(&pixels[..count]) {
+            bytes.copy_from_slice(&pixel.into_storage().to_be_bytes());
+        }
+        tile.sent += count;
+
+        // The tile only counts as sent once its last frame is out
+        if tile.sent == TILE_PIXELS {
+            self.sums[tile.index] = Some(tile.sum);
+            self.tile = None;
+        }
+        Some(3 + 2 * count)
+    }
+}
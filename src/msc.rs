@@ -0,0 +1,297 @@
+//! USB mass storage class
+//!
+//! Read-only SCSI disk over the Bulk-Only Transport, backed by a `BlockDevice`. The host sees
+//! it as write protected, so it never tries to write to it.
+
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+/// Size of the blocks of the disk
+pub const BLOCK_SIZE: usize = 512;
+
+/// Packet size of the bulk endpoints
+const PACKET_SIZE: usize = 64;
+
+/// Source of the blocks of the disk
+pub trait BlockDevice {
+    /// Number of blocks of the disk
+    fn block_count(&self) -> u32;
+    /// Read the block at `lba`
+    fn read_block(&self, lba: u32, block: &mut [u8; BLOCK_SIZE]);
+}
+
+/// Command Block Wrapper signature, "USBC"
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+/// Command Status Wrapper signature, "USBS"
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+
+/// Class-specific requests
+const REQ_GET_MAX_LUN: u8 = 0xFE;
+const REQ_RESET: u8 = 0xFF;
+
+/// SCSI sense keys
+const SENSE_ILLEGAL_REQUEST: u8 = 0x05;
+const SENSE_DATA_PROTECT: u8 = 0x07;
+
+/// Progress of the current command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Waiting for a command
+    Idle,
+    /// Sending `buf[pos..len]`
+    DataIn,
+    /// Sending blocks, `remaining` blocks are left after the one in the buffer
+    ReadBlocks { lba: u32, remaining: u32 },
+    /// Sending the status of the command
+    Status,
+}
+
+/// Mass storage class for `usb-device`
+pub struct MassStorage<'a, B: UsbBus, D: BlockDevice> {
+    interface: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    device: D,
+    state: State,
+    buf: [u8; BLOCK_SIZE],
+    pos: usize,
+    len: usize,
+    /// Tag of the current command, echoed in its status
+    tag: u32,
+    /// Bytes the host expects to transfer, minus the ones already sent
+    residue: u32,
+    failed: bool,
+    /// Sense key and additional sense code of the last failure
+    sense: (u8, u8),
+}
+
+impl<'a, B: UsbBus, D: BlockDevice> MassStorage<'a, B, D> {
+    pub fn new(alloc: &'a UsbBusAllocator<B>, device: D) -> Self {
+        Self {
+            interface: alloc.interface(),
+            read_ep: alloc.bulk(PACKET_SIZE as u16),
+            write_ep: alloc.bulk(PACKET_SIZE as u16),
+            device,
+            state: State::Idle,
+            buf: [0; BLOCK_SIZE],
+            pos: 0,
+            len: 0,
+            tag: 0,
+            residue: 0,
+            failed: false,
+            sense: (0, 0),
+        }
+    }
+
+    /// Read and run a command from the host
+    fn read_command(&mut self) {
+        let mut cbw = [0u8; 31];
+        match self.read_ep.read(&mut cbw) {
+            Ok(31) => (),
+            Ok(_) | Err(UsbError::BufferOverflow) => {
+                // Not a command, the host has to reset the device
+                self.read_ep.stall();
+                self.write_ep.stall();
+                return;
+            }
+            Err(_) => return,
+        }
+        if u32::from_le_bytes([cbw[0], cbw[1], cbw[2], cbw[3]]) != CBW_SIGNATURE {
+            self.read_ep.stall();
+            self.write_ep.stall();
+            return;
+        }
+
+        self.tag = u32::from_le_bytes([cbw[4], cbw[5], cbw[6], cbw[7]]);
+        self.residue = u32::from_le_bytes([cbw[8], cbw[9], cbw[10], cbw[11]]);
+        self.failed = false;
+        let data_in = cbw[12] & 0x80 != 0;
+        let command = &cbw[15..31];
+
+        match command[0] {
+            // TEST UNIT READY, PREVENT ALLOW MEDIUM REMOVAL, START STOP UNIT
+            0x00 | 0x1E | 0x1B => self.state = State::Status,
+            // REQUEST SENSE
+            0x03 => {
+                let (key, asc) = self.sense;
+                self.sense = (0, 0);
+                self.respond(&[0x70, 0, key, 0, 0, 0, 0, 10, 0, 0, 0, 0, asc, 0, 0, 0, 0, 0]);
+            }
+            // INQUIRY
+            0x12 => {
+                let mut inquiry = [0u8; 36];
+                // Direct access, removable, SPC-2
+                inquiry[..5].copy_from_slice(&[0x00, 0x80, 0x04, 0x02, 31]);
+                inquiry[8..16].copy_from_slice(b"RP2040  ");
+                inquiry[16..32].copy_from_slice(b"Virtual files   ");
+                inquiry[32..36].copy_from_slice(b"0.1 ");
+                self.respond(&inquiry);
+            }
+            // MODE SENSE(6), with the write protect bit set
+            0x1A => self.respond(&[3, 0, 0x80, 0]),
+            // MODE SENSE(10)
+            0x5A => self.respond(&[0, 6, 0, 0x80, 0, 0, 0, 0]),
+            // READ FORMAT CAPACITIES
+            0x23 => {
+                let mut capacities = [0, 0, 0, 8, 0, 0, 0, 0, 0x02, 0, 0x02, 0];
+                capacities[4..8].copy_from_slice(&self.device.block_count().to_be_bytes());
+                self.respond(&capacities);
+            }
+            // READ CAPACITY(10)
+            0x25 => {
+                let mut capacity = [0u8; 8];
+                let last = self.device.block_count().saturating_sub(1);
+                capacity[..4].copy_from_slice(&last.to_be_bytes());
+                capacity[4..].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                self.respond(&capacity);
+            }
+            // READ(10)
+            0x28 => {
+                let lba = u32::from_be_bytes([command[2], command[3], command[4], command[5]]);
+                let count = u16::from_be_bytes([command[7], command[8]]) as u32;
+                if count == 0 {
+                    self.state = State::Status;
+                } else if lba.saturating_add(count) > self.device.block_count() {
+                    // Logical block address out of range
+                    self.fail(data_in, SENSE_ILLEGAL_REQUEST, 0x21);
+                } else {
+                    self.load_block(lba, count - 1);
+                }
+            }
+            // WRITE(10)
+            0x2A => self.fail(data_in, SENSE_DATA_PROTECT, 0x27),
+            // Invalid command operation code
+            _ => self.fail(data_in, SENSE_ILLEGAL_REQUEST, 0x20),
+        }
+
+        self.send();
+    }
+
+    /// Send `data` in reply to the command, cut to the length the host expects
+    fn respond(&mut self, data: &[u8]) {
+        let len = data.len().min(self.residue as usize);
+        self.buf[..len].copy_from_slice(&data[..len]);
+        self.pos = 0;
+        self.len = len;
+        self.state = State::DataIn;
+    }
+
+    /// Read the block at `lba` into the buffer, to send it
+    fn load_block(&mut self, lba: u32, remaining: u32) {
+        self.device.read_block(lba, &mut self.buf);
+        self.pos = 0;
+        self.len = BLOCK_SIZE.min(self.residue as usize);
+        self.state = State::ReadBlocks { lba, remaining };
+    }
+
+    /// Fail the command
+    ///
+    /// If the host expects data, the endpoint it reads from is stalled until it clears it and
+    /// reads the status.
+    fn fail(&mut self, data_in: bool, key: u8, asc: u8) {
+        self.failed = true;
+        self.sense = (key, asc);
+        if self.residue > 0 {
+            if data_in {
+                self.write_ep.stall();
+            } else {
+                self.read_ep.stall();
+            }
+        }
+        self.state = State::Status;
+    }
+
+    /// Send as much as the endpoint takes
+    fn send(&mut self) {
+        loop {
+            match self.state {
+                State::Idle => return,
+                State::DataIn | State::ReadBlocks { .. } if self.pos < self.len => {
+                    let end = (self.pos + PACKET_SIZE).min(self.len);
+                    match self.write_ep.write(&self.buf[self.pos..end]) {
+                        Ok(count) => {
+                            self.pos += count;
+                            self.residue -= count as u32;
+                        }
+                        Err(_) => return,
+                    }
+                }
+                State::ReadBlocks { lba, remaining } if remaining > 0 && self.residue > 0 => {
+                    self.load_block(lba + 1, remaining - 1)
+                }
+                State::DataIn | State::ReadBlocks { .. } => self.state = State::Status,
+                State::Status => {
+                    if self.write_ep.is_stalled() {
+                        return;
+                    }
+                    let mut csw = [0u8; 13];
+                    csw[..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+                    csw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+                    csw[8..12].copy_from_slice(&self.residue.to_le_bytes());
+                    csw[12] = self.failed as u8;
+                    match self.write_ep.write(&csw) {
+                        Ok(_) => self.state = State::Idle,
+                        Err(_) => return,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<B: UsbBus, D: BlockDevice> UsbClass<B> for MassStorage<'_, B, D> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        // Mass storage, SCSI transparent command set, Bulk-Only Transport
+        writer.interface(self.interface, 0x08, 0x06, 0x50)?;
+        writer.endpoint(&self.read_ep)?;
+        writer.endpoint(&self.write_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.state = State::Idle;
+    }
+
+    fn poll(&mut self) {
+        // The status can be sent once the host cleared the stalled endpoint
+        self.send();
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.interface) as u16
+            && req.request == REQ_GET_MAX_LUN
+        {
+            // A single logical unit
+            let _ = xfer.accept_with(&[0]);
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if req.request_type == control::RequestType::Class
+            && req.recipient == control::Recipient::Interface
+            && req.index == u8::from(self.interface) as u16
+            && req.request == REQ_RESET
+        {
+            self.state = State::Idle;
+            self.read_ep.unstall();
+            self.write_ep.unstall();
+            let _ = xfer.accept();
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr == self.read_ep.address() && self.state == State::Idle {
+            self.read_command();
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.send();
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Serial channel multiplexing
+//!
+//! Carries several logical streams over a single serial port, for hosts limited to one port.
+//! Each frame is SLIP encoded, and starts with the ID of its channel: `END id payload END`.
+
+use heapless::Vec;
+
+/// Largest payload of a frame, longer frames are dropped
+pub const MAX_FRAME: usize = 64;
+
+/// Frame delimiter
+const END: u8 = 0xC0;
+/// Escapes `END` and `ESC` in the frames
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// Logical streams
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Commands and their replies, and the echo
+    Console,
+    /// Data log
+    Log,
+    /// Binary transfers
+    Data,
+}
+
+impl Channel {
+    pub fn id(self) -> u8 {
+        match self {
+            Channel::Console => 0,
+            Channel::Log => 1,
+            Channel::Data => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Channel::Console),
+            1 => Some(Channel::Log),
+            2 => Some(Channel::Data),
+            _ => None,
+        }
+    }
+}
+
+/// Size of the frame for a payload of `len` bytes, in the worst case
+pub const fn max_encoded_len(len: usize) -> usize {
+    // Both delimiters, the channel ID, and every byte escaped
+    2 * (len + 1) + 2
+}
+
+/// Encode a frame of `data` on `channel` into `out`, returning its length
+///
+/// Returns `None` if `out` is too small.
+pub fn encode(channel: Channel, data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    let mut push = |byte: u8| {
+        *out.get_mut(len)? = byte;
+        len += 1;
+        Some(())
+    };
+
+    push(END)?;
+    for &byte in core::iter::once(&channel.id()).chain(data) {
+        match byte {
+            END => {
+                push(ESC)?;
+                push(ESC_END)?;
+            }
+            ESC => {
+                push(ESC)?;
+                push(ESC_ESC)?;
+            }
+            byte => push(byte)?,
+        }
+    }
+    push(END)?;
+    Some(len)
+}
+
+/// Frame received from the host
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub channel: Channel,
+    pub payload: &'a [u8],
+}
+
+/// Reassembles the frames received from the host
+pub struct Decoder {
+    /// Channel ID and payload of the current frame
+    buf: Vec<u8, { MAX_FRAME + 1 }>,
+    escaped: bool,
+    /// The current frame is dropped, because it is too long
+    overflow: bool,
+    /// The buffer holds the last frame returned, and is cleared on the next byte
+    done: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            escaped: false,
+            overflow: false,
+            done: false,
+        }
+    }
+
+    /// Handle a received byte, returning the frame it completes
+    ///
+    /// Empty frames, frames too long and frames for unknown channels are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<Frame<'_>> {
+        if self.done {
+            self.done = false;
+            self.buf.clear();
+        }
+
+        if byte == END {
+            let complete = !self.overflow && !self.buf.is_empty();
+            self.escaped = false;
+            self.overflow = false;
+            if !complete {
+                self.buf.clear();
+                return None;
+            }
+            self.done = true;
+            return Channel::from_id(self.buf[0]).map(|channel| Frame {
+                channel,
+                payload: &self.buf[1..],
+            });
+        }
+
+        let byte = match (self.escaped, byte) {
+            (false, ESC) => {
+                self.escaped = true;
+                return None;
+            }
+            (true, ESC_END) => END,
+            (true, ESC_ESC) => ESC,
+            (_, byte) => byte,
+        };
+        self.escaped = false;
+        if self.buf.push(byte).is_err() {
+            self.overflow = true;
+        }
+        None
+    }
+}
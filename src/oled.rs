@@ -0,0 +1,80 @@
+//! OLED status panel
+//!
+//! A 128x64 SSD1306 OLED on I2C1, showing the status segments one per line, for builds where the
+//! Pico Display is taken by something else, such as a dashboard.
+
+use crate::status::{StatusInfo, StatusLayout};
+use crate::{hal, pac};
+use display_interface::DisplayError;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use embedded_time::rate::*;
+use hal::gpio::pin::{bank0, FunctionI2C, Pin};
+use ssd1306::{prelude::*, I2CDisplayInterface, Ssd1306};
+
+pub type OledSdaPin = Pin<bank0::Gpio26, FunctionI2C>;
+pub type OledSclPin = Pin<bank0::Gpio27, FunctionI2C>;
+pub type OledI2c = hal::i2c::I2C<pac::I2C1, (OledSdaPin, OledSclPin)>;
+
+/// Driver for the OLED, drawing to a buffer sent by `StatusPanel::update`
+pub type Oled = Ssd1306<
+    I2CInterface<OledI2c>,
+    DisplaySize128x64,
+    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
+>;
+
+/// Number of text lines on the OLED, enough for every segment of a `StatusLayout`
+pub const LINES: usize = 6;
+
+/// Set up I2C1 at 400kHz and initialize the OLED, with the data and clock on GPIO26 and GPIO27
+///
+/// Fails if no OLED answers, so firmware can go on without it.
+pub fn init(
+    i2c: pac::I2C1,
+    sda: OledSdaPin,
+    scl: OledSclPin,
+    resets: &mut pac::RESETS,
+    system_clock: Hertz,
+) -> Result<Oled, DisplayError> {
+    let i2c = hal::i2c::I2C::i2c1(i2c, sda, scl, 400_000u32.Hz(), resets, system_clock);
+    let interface = I2CDisplayInterface::new(i2c);
+    let mut oled = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
+        .into_buffered_graphics_mode();
+    oled.init()?;
+    oled.flush()?;
+
+    Ok(oled)
+}
+
+/// Shows the segments of a `StatusLayout` on the OLED, each on its own line
+///
+/// The OLED only shows the state of the device, it has nothing to select or scroll.
+pub struct StatusPanel {
+    layout: StatusLayout,
+}
+
+impl StatusPanel {
+    pub fn new(layout: StatusLayout) -> Self {
+        Self { layout }
+    }
+
+    /// Draw the device state and send it to the OLED
+    ///
+    /// The whole buffer is sent over I2C, which takes about 25ms.
+    pub fn update(&self, oled: &mut Oled, info: &StatusInfo) -> Result<(), DisplayError> {
+        let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        oled.clear();
+        for (line, segment) in self.layout.segments.iter().flatten().take(LINES).enumerate() {
+            let mut text: heapless::String<24> = heapless::String::new();
+            // Text that doesn't fit in the buffer is cut, like text that doesn't fit on screen
+            let _ = segment.render(info, &mut text);
+            let pos = Point::new(0, line as i32 * 10);
+            Text::with_baseline(&text, pos, style, Baseline::Top).draw(oled)?;
+        }
+        oled.flush()
+    }
+}
@@ -0,0 +1,137 @@
+//! Terminal color palettes
+//!
+//! The 8 basic ANSI colors, with variants for color vision deficiencies, and a check of the
+//! contrast between the text and its background, to keep the text readable with low vision.
+
+use serde::{Deserialize, Serialize};
+
+/// Color as red, green and blue, from 0 to 255
+pub type Rgb = [u8; 3];
+
+/// Index of the ANSI colors in a palette
+pub mod ansi {
+    pub const BLACK: usize = 0;
+    pub const RED: usize = 1;
+    pub const GREEN: usize = 2;
+    pub const YELLOW: usize = 3;
+    pub const BLUE: usize = 4;
+    pub const MAGENTA: usize = 5;
+    pub const CYAN: usize = 6;
+    pub const WHITE: usize = 7;
+}
+
+/// Minimum contrast ratio, in hundredths, for text as recommended by WCAG
+pub const MIN_CONTRAST: u32 = 450;
+
+/// Sets of colors used for the ANSI colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    /// Fully saturated colors
+    Standard,
+    /// Colors that stay apart without green cones, from the Okabe-Ito palette
+    Deuteranopia,
+    /// Same as deuteranopia, with a brighter red as reds look dark without red cones
+    Protanopia,
+}
+
+impl Palette {
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Standard => "standard",
+            Palette::Deuteranopia => "deuteran",
+            Palette::Protanopia => "protan",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Palette::Standard => Palette::Deuteranopia,
+            Palette::Deuteranopia => Palette::Protanopia,
+            Palette::Protanopia => Palette::Standard,
+        }
+    }
+
+    /// Color at `index` in the palette, see `ansi`
+    pub fn color(self, index: usize) -> Rgb {
+        let colors = match self {
+            Palette::Standard => &STANDARD,
+            Palette::Deuteranopia => &DEUTERANOPIA,
+            Palette::Protanopia => &PROTANOPIA,
+        };
+        colors[index % colors.len()]
+    }
+}
+
+const STANDARD: [Rgb; 8] = [
+    [0, 0, 0],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [0, 0, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+const DEUTERANOPIA: [Rgb; 8] = [
+    [0, 0, 0],
+    [213, 94, 0],
+    [0, 158, 115],
+    [240, 228, 66],
+    [0, 114, 178],
+    [204, 121, 167],
+    [86, 180, 233],
+    [255, 255, 255],
+];
+
+const PROTANOPIA: [Rgb; 8] = [
+    [0, 0, 0],
+    [230, 159, 0],
+    [0, 158, 115],
+    [240, 228, 66],
+    [0, 114, 178],
+    [204, 121, 167],
+    [86, 180, 233],
+    [255, 255, 255],
+];
+
+/// Relative luminance of a color, from 0 to 1000
+///
+/// The sRGB curve is approximated with a gamma of 2, which is close enough to compare colors.
+fn luminance([r, g, b]: Rgb) -> u32 {
+    let (r, g, b) = (r as u32, g as u32, b as u32);
+    (2126 * r * r + 7152 * g * g + 722 * b * b) / (255 * 255 * 10)
+}
+
+/// Contrast ratio between two colors, in hundredths, from 100 to 2100
+pub fn contrast(a: Rgb, b: Rgb) -> u32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 50) * 100 / (a.min(b) + 50)
+}
+
+/// Closest color to `fg` with at least `min` contrast against `bg`
+///
+/// The color is moved towards black or white, whichever is further from the background.
+pub fn with_contrast(fg: Rgb, bg: Rgb, min: u32) -> Rgb {
+    if contrast(fg, bg) >= min {
+        return fg;
+    }
+
+    let target = if contrast([255; 3], bg) >= contrast([0; 3], bg) {
+        [255; 3]
+    } else {
+        [0; 3]
+    };
+    const STEPS: i32 = 8;
+    for step in 1..STEPS {
+        let mut color = fg;
+        for (channel, &target) in color.iter_mut().zip(target.iter()) {
+            let (from, to) = (*channel as i32, target as i32);
+            *channel = (from + (to - from) * step / STEPS) as u8;
+        }
+        if contrast(color, bg) >= min {
+            return color;
+        }
+    }
+    target
+}
@@ -0,0 +1,79 @@
+//! Panic screen
+//!
+//! Panic handler taking over the Pico Display to show the panic message and its location, then
+//! blinking SOS on the LED, so a panic can be read without a debug probe.
+
+use crate::display;
+use crate::dual_display::VISIBLE_OFFSET;
+use crate::{hal, pac, PicoDisplayTerminalBuilder, Pins};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder},
+    pixelcolor::Rgb565,
+    prelude::*,
+};
+use embedded_hal::digital::v2::OutputPin;
+
+/// System clock set up by `init_clocks_and_plls`, which the examples all use
+const SYSTEM_CLOCK_HZ: u32 = 125_000_000;
+
+/// Length of a dot of the SOS, in milliseconds. Dashes are 3 dots long.
+const DOT_MS: u32 = 200;
+
+/// Show the panic on the screen, then blink SOS forever
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    // Note (safety): interrupts are disabled and the code that panicked doesn't run anymore, so
+    // nothing else uses the peripherals. They are set up again from scratch, as they may be left
+    // halfway through an update.
+    let (mut pac, core) = unsafe { (pac::Peripherals::steal(), pac::CorePeripherals::steal()) };
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, SYSTEM_CLOCK_HZ);
+
+    let sio = hal::sio::Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+    let screen = display::init(
+        pac.SPI0,
+        pins.lcd_dc.into_push_pull_output(),
+        pins.lcd_cs.into_push_pull_output(),
+        pins.spi_sclk.into_mode(),
+        pins.spi_mosi.into_mode(),
+        pins.gpio0.into_mode(),
+        &mut pac.RESETS,
+        &mut delay,
+    );
+    let mut terminal = PicoDisplayTerminalBuilder::new(screen)
+        .with_offset(VISIBLE_OFFSET)
+        .with_size(Size::new(display::WIDTH as u32, display::HEIGHT as u32))
+        .with_style(
+            MonoTextStyleBuilder::new()
+                .font(&FONT_6X10)
+                .text_color(Rgb565::WHITE)
+                .background_color(Rgb565::RED)
+                .build(),
+        )
+        .build();
+    // Fills the screen with the background color
+    terminal.refresh();
+    // The message includes the file and line of the panic
+    terminal.write(b"*** PANIC ***\r\n\r\n");
+    let _ = write!(terminal, "{}", info);
+
+    let mut led = pins.led.into_push_pull_output();
+    loop {
+        for &units in [1, 1, 1, 3, 3, 3, 1, 1, 1].iter() {
+            led.set_high().unwrap();
+            delay.delay_ms(units * DOT_MS);
+            led.set_low().unwrap();
+            delay.delay_ms(DOT_MS);
+        }
+        // Between words
+        delay.delay_ms(6 * DOT_MS);
+    }
+}
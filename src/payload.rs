@@ -0,0 +1,238 @@
+//! Scripted HID keyboard payloads
+//!
+//! Payloads are small scripts typed on the host through the HID keyboard, for automating
+//! repetitive setup tasks. The script format is line based:
+//!
+//! ```text
+//! REM comments are ignored
+//! DELAY 500
+//! STRING text typed as is
+//! CTRL ALT t
+//! ENTER
+//! ```
+//!
+//! Any other line is a key combination: modifier names followed by at most one key name.
+//!
+//! Running a payload requires holding a button and then confirming on the display through the
+//! `Interlock`, so it can never be started from the serial port alone.
+
+use crate::events::MAX_INPUT;
+use crate::keyboard::{self, KeyPress};
+use heapless::String;
+use usbd_hid::descriptor::KeyboardReport;
+
+/// What the player wants to do next
+pub enum Step {
+    /// Send this report to the host
+    Report(KeyboardReport),
+    /// Nothing to send yet
+    Wait,
+    /// The script is over
+    Done,
+    /// The script has an invalid line, with its line number
+    Error(usize),
+}
+
+/// Plays a payload script
+pub struct ScriptPlayer<'s> {
+    lines: core::iter::Enumerate<core::str::Lines<'s>>,
+    /// Characters of a `STRING` command left to type
+    typing: &'s [u8],
+    /// A key is pressed and must be released
+    release: bool,
+    /// Timestamp in milliseconds until which the script is paused
+    resume_at: u64,
+}
+
+impl<'s> ScriptPlayer<'s> {
+    pub fn new(script: &'s str) -> Self {
+        Self {
+            lines: script.lines().enumerate(),
+            typing: &[],
+            release: false,
+            resume_at: 0,
+        }
+    }
+
+    /// Next step of the script at `now`, in milliseconds
+    pub fn poll(&mut self, now: u64) -> Step {
+        if self.release {
+            self.release = false;
+            return Step::Report(keyboard::release_report());
+        }
+        if now < self.resume_at {
+            return Step::Wait;
+        }
+
+        loop {
+            if let Some((&c, rest)) = self.typing.split_first() {
+                self.typing = rest;
+                // Characters that cannot be typed are skipped
+                if let Some(key) = KeyPress::from_ascii(c) {
+                    return self.press(key);
+                }
+                continue;
+            }
+
+            let (index, line) = match self.lines.next() {
+                Some(line) => line,
+                None => return Step::Done,
+            };
+            let line = line.trim_start();
+            let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+
+            match command {
+                "" | "REM" => (),
+                "DELAY" => {
+                    return match args.trim().parse::<u64>() {
+                        Ok(ms) => {
+                            self.resume_at = now + ms;
+                            Step::Wait
+                        }
+                        Err(_) => Step::Error(index + 1),
+                    }
+                }
+                "STRING" => self.typing = args.as_bytes(),
+                _ => {
+                    return match parse_combination(line) {
+                        Some(key) => self.press(key),
+                        None => Step::Error(index + 1),
+                    }
+                }
+            }
+        }
+    }
+
+    fn press(&mut self, key: KeyPress) -> Step {
+        self.release = true;
+        Step::Report(key.report())
+    }
+}
+
+/// Types a line of text composed on the device, such as with the on-screen keyboard
+pub struct TextTyper {
+    text: String<MAX_INPUT>,
+    /// Number of characters already typed
+    typed: usize,
+    /// A key is pressed and must be released
+    release: bool,
+}
+
+impl TextTyper {
+    pub fn new(text: String<MAX_INPUT>) -> Self {
+        Self {
+            text,
+            typed: 0,
+            release: false,
+        }
+    }
+
+    /// Next step, pressing or releasing a key on each call
+    pub fn poll(&mut self) -> Step {
+        if self.release {
+            self.release = false;
+            return Step::Report(keyboard::release_report());
+        }
+        while let Some(&c) = self.text.as_bytes().get(self.typed) {
+            self.typed += 1;
+            // Characters that cannot be typed are skipped
+            if let Some(key) = KeyPress::from_ascii(c) {
+                self.release = true;
+                return Step::Report(key.report());
+            }
+        }
+        Step::Done
+    }
+}
+
+/// Parse a key combination, such as `CTRL ALT DELETE`
+fn parse_combination(line: &str) -> Option<KeyPress> {
+    let mut key = KeyPress::default();
+    for name in line.split_whitespace() {
+        if let Some(modifier) = keyboard::modifier_from_name(name) {
+            key.modifier |= modifier;
+        } else if key.keycode == 0 {
+            key.keycode = keyboard::keycode_from_name(name)?;
+        } else {
+            // Only one key per combination
+            return None;
+        }
+    }
+    Some(key)
+}
+
+/// Number of ticks the trigger button must be held for
+const HOLD_TICKS: u32 = 200;
+/// Number of ticks to wait for a confirmation
+const CONFIRM_TICKS: u32 = 500;
+
+/// Event raised by the `Interlock`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterlockEvent {
+    /// The trigger was held long enough, the user must now confirm
+    Prompt,
+    /// The user confirmed, the payload can run
+    Confirmed,
+    /// The user cancelled or didn't confirm in time
+    Cancelled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InterlockState {
+    Idle,
+    Holding(u32),
+    Confirming(u32),
+    /// Waiting for all buttons to be released before going back to idle
+    Releasing,
+}
+
+/// Physical button interlock for running payloads
+///
+/// The trigger button must be held for a while, then the confirm button pressed while the
+/// prompt is shown.
+pub struct Interlock {
+    state: InterlockState,
+}
+
+impl Interlock {
+    pub const fn new() -> Self {
+        Self {
+            state: InterlockState::Idle,
+        }
+    }
+
+    /// Returns true when the interlock is not using the buttons
+    pub fn is_idle(&self) -> bool {
+        matches!(
+            self.state,
+            InterlockState::Idle | InterlockState::Holding(_)
+        )
+    }
+
+    /// Update the interlock with the state of its buttons, called once per tick
+    pub fn update(&mut self, trigger: bool, confirm: bool, cancel: bool) -> Option<InterlockEvent> {
+        let (state, event) = match self.state {
+            InterlockState::Idle if trigger => (InterlockState::Holding(0), None),
+            InterlockState::Idle => (InterlockState::Idle, None),
+            InterlockState::Holding(_) if !trigger => (InterlockState::Idle, None),
+            InterlockState::Holding(ticks) if ticks >= HOLD_TICKS => {
+                (InterlockState::Confirming(0), Some(InterlockEvent::Prompt))
+            }
+            InterlockState::Holding(ticks) => (InterlockState::Holding(ticks + 1), None),
+            InterlockState::Confirming(_) if confirm && !trigger => {
+                (InterlockState::Releasing, Some(InterlockEvent::Confirmed))
+            }
+            InterlockState::Confirming(ticks) if cancel || ticks >= CONFIRM_TICKS => {
+                (InterlockState::Releasing, Some(InterlockEvent::Cancelled))
+            }
+            InterlockState::Confirming(ticks) => (InterlockState::Confirming(ticks + 1), None),
+            InterlockState::Releasing if trigger || confirm || cancel => {
+                (InterlockState::Releasing, None)
+            }
+            InterlockState::Releasing => (InterlockState::Idle, None),
+        };
+
+        self.state = state;
+        event
+    }
+}
@@ -0,0 +1,114 @@
+//! GPIO diagnostics
+//!
+//! Reads the configuration of the pins straight from the IO and pads banks, to debug the wiring
+//! and pins claimed by more than one peripheral.
+
+use crate::pac;
+use core::fmt;
+
+/// Number of user GPIOs
+pub const GPIO_COUNT: usize = 30;
+
+/// Header of the table of pins, with columns matching the `Display` of `PinInfo`
+pub const HEADER: &str = "GPIO FUNC  PULL  IN OD DRIVE\r\n";
+
+/// Pull resistors of a pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+    /// Both, which keeps the last level driven on the pin
+    BusKeep,
+}
+
+impl Pull {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pull::None => "none",
+            Pull::Up => "up",
+            Pull::Down => "down",
+            Pull::BusKeep => "keep",
+        }
+    }
+}
+
+/// Configuration and state of a pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinInfo {
+    pub gpio: usize,
+    /// Function select, see section 2.19.2 of the RP2040 datasheet
+    pub function: u8,
+    pub pull: Pull,
+    /// Level read on the pad, `None` when its input is disabled
+    pub input: Option<bool>,
+    pub output_disabled: bool,
+    /// Drive strength, in milliamps
+    pub drive_ma: u8,
+}
+
+impl PinInfo {
+    /// Read the current configuration of `gpio`
+    pub fn read(gpio: usize) -> Self {
+        assert!(gpio < GPIO_COUNT);
+        // Note (safety): the registers are only read
+        let (io, pads) = unsafe { (&*pac::IO_BANK0::ptr(), &*pac::PADS_BANK0::ptr()) };
+        let ctrl = io.gpio[gpio].gpio_ctrl.read();
+        let status = io.gpio[gpio].gpio_status.read();
+        let pad = pads.gpio[gpio].read();
+
+        Self {
+            gpio,
+            function: ctrl.funcsel().bits(),
+            pull: match (pad.pue().bit(), pad.pde().bit()) {
+                (false, false) => Pull::None,
+                (true, false) => Pull::Up,
+                (false, true) => Pull::Down,
+                (true, true) => Pull::BusKeep,
+            },
+            input: if pad.ie().bit() {
+                Some(status.infrompad().bit())
+            } else {
+                None
+            },
+            output_disabled: pad.od().bit(),
+            drive_ma: [2, 4, 8, 12][pad.drive().bits() as usize & 0x3],
+        }
+    }
+
+    /// Name of the selected function
+    pub fn function_name(&self) -> &'static str {
+        match self.function {
+            1 => "spi",
+            2 => "uart",
+            3 => "i2c",
+            4 => "pwm",
+            5 => "sio",
+            6 => "pio0",
+            7 => "pio1",
+            8 if (20..=25).contains(&self.gpio) => "clock",
+            8 | 9 => "usb",
+            0x1F => "null",
+            _ => "?",
+        }
+    }
+}
+
+impl fmt::Display for PinInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>4} {:<5} {:<5} {:<2} {:<2} {}mA",
+            self.gpio,
+            self.function_name(),
+            self.pull.name(),
+            match self.input {
+                Some(true) => "1",
+                Some(false) => "0",
+                None => "-",
+            },
+            if self.output_disabled { "y" } else { "n" },
+            self.drive_ma
+        )
+    }
+}
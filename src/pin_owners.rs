@@ -0,0 +1,136 @@
+//! GPIO owners
+//!
+//! Records which part of the firmware uses each GPIO, so two features wired to the same pin are
+//! reported instead of fighting over it silently. The owners can be checked at compile time with
+//! `with`, or at boot with `claim`.
+
+use crate::pin_info::GPIO_COUNT;
+use core::fmt;
+
+/// Part of the firmware using a pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinOwner {
+    /// Pins of the Pico itself: SMPS mode, VBUS detect and VSYS monitor
+    Board,
+    Led,
+    /// RGB LED of the Pico Display
+    RgbLed,
+    Buttons,
+    /// Pico Display panel, with its backlight and data out
+    Display,
+    Switches,
+    StallPin,
+    Oled,
+    UartBridge,
+    /// UART of the data routes
+    Uart,
+    Tachometer,
+    PulseCapture,
+}
+
+impl PinOwner {
+    pub fn name(self) -> &'static str {
+        match self {
+            PinOwner::Board => "board",
+            PinOwner::Led => "LED",
+            PinOwner::RgbLed => "RGB LED",
+            PinOwner::Buttons => "buttons",
+            PinOwner::Display => "display",
+            PinOwner::Switches => "switches",
+            PinOwner::StallPin => "stall pin",
+            PinOwner::Oled => "OLED",
+            PinOwner::UartBridge => "UART bridge",
+            PinOwner::Uart => "UART",
+            PinOwner::Tachometer => "tachometer",
+            PinOwner::PulseCapture => "pulse capture",
+        }
+    }
+}
+
+/// Pin claimed by a second owner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinConflict {
+    pub gpio: u8,
+    /// Owner that claimed the pin first, and keeps it
+    pub owner: PinOwner,
+    pub claimed_by: PinOwner,
+}
+
+impl fmt::Display for PinConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GPIO{} is used by the {}, not given to the {}",
+            self.gpio,
+            self.owner.name(),
+            self.claimed_by.name()
+        )
+    }
+}
+
+/// Owner of each GPIO
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinOwners {
+    owners: [Option<PinOwner>; GPIO_COUNT],
+}
+
+impl PinOwners {
+    pub const fn new() -> Self {
+        Self {
+            owners: [None; GPIO_COUNT],
+        }
+    }
+
+    /// Give `gpios`, as bits by GPIO number, to `owner`, failing to compile if one has an owner
+    ///
+    /// This is for owners known at compile time, in a `const`.
+    pub const fn with(mut self, gpios: u32, owner: PinOwner) -> Self {
+        let mut gpio = 0;
+        while gpio < GPIO_COUNT {
+            if gpios & 1 << gpio != 0 {
+                if self.owners[gpio].is_some() {
+                    panic!("GPIO claimed twice");
+                }
+                self.owners[gpio] = Some(owner);
+            }
+            gpio += 1;
+        }
+        self
+    }
+
+    /// Give `gpios`, as bits by GPIO number, to `owner`
+    ///
+    /// If one of them already has an owner, none of them are given and the first conflict is
+    /// returned.
+    pub fn claim(&mut self, gpios: u32, owner: PinOwner) -> Result<(), PinConflict> {
+        let claimed = (0..GPIO_COUNT).filter(|gpio| gpios & 1 << gpio != 0);
+        for gpio in claimed.clone() {
+            if let Some(first) = self.owners[gpio] {
+                return Err(PinConflict {
+                    gpio: gpio as u8,
+                    owner: first,
+                    claimed_by: owner,
+                });
+            }
+        }
+        claimed.for_each(|gpio| self.owners[gpio] = Some(owner));
+        Ok(())
+    }
+
+    pub fn owner(&self, gpio: usize) -> Option<PinOwner> {
+        self.owners.get(gpio).copied().flatten()
+    }
+
+    /// Pins with an owner, as bits by GPIO number, such as for `PinProfile::with_used`
+    pub fn used(&self) -> u32 {
+        (0..GPIO_COUNT)
+            .filter(|&gpio| self.owners[gpio].is_some())
+            .fold(0, |used, gpio| used | 1 << gpio)
+    }
+}
+
+impl Default for PinOwners {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,144 @@
+//! Rolling plot of numeric samples
+//!
+//! Keeps the last samples received, for example from a host script sending `plot 23.4`, and
+//! draws them as a sparkline or a bar histogram scaled to fit its area.
+
+use embedded_graphics::{
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+use heapless::Deque;
+
+/// Maximum number of samples kept, one per pixel column at most
+pub const MAX_SAMPLES: usize = 240;
+
+/// How the samples are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlotMode {
+    /// Line through the samples
+    Sparkline,
+    /// One bar per sample
+    Bars,
+}
+
+/// Plot widget
+pub struct Plot<C> {
+    area: Rectangle,
+    mode: PlotMode,
+    color: C,
+    background_color: C,
+    samples: Deque<f32, MAX_SAMPLES>,
+}
+
+impl<C: PixelColor> Plot<C> {
+    pub fn new(area: Rectangle, color: C, background_color: C) -> Self {
+        Self {
+            area,
+            mode: PlotMode::Sparkline,
+            color,
+            background_color,
+            samples: Deque::new(),
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: PlotMode) {
+        self.mode = mode;
+    }
+
+    /// Number of samples shown, one per pixel column for sparklines and every 4 columns for
+    /// bars
+    fn capacity(&self) -> usize {
+        let width = self.area.size.width as usize;
+        match self.mode {
+            PlotMode::Sparkline => width,
+            PlotMode::Bars => width / 4,
+        }
+        .min(MAX_SAMPLES)
+    }
+
+    /// Add a sample, dropping the oldest ones that don't fit anymore
+    pub fn push(&mut self, sample: f32) {
+        while self.samples.len() >= self.capacity().max(1) {
+            self.samples.pop_front();
+        }
+        let _ = self.samples.push_back(sample);
+    }
+
+    /// Remove all samples
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Smallest and largest samples
+    pub fn range(&self) -> Option<(f32, f32)> {
+        self.samples
+            .iter()
+            .fold(None, |range, &sample| match range {
+                None => Some((sample, sample)),
+                Some((min, max)) => Some((min.min(sample), max.max(sample))),
+            })
+    }
+
+    /// Vertical position of `sample`, scaled so the samples fill the height of the area
+    fn y(&self, sample: f32, (min, max): (f32, f32)) -> i32 {
+        let height = self.area.size.height.saturating_sub(1) as f32;
+        // Constant samples are drawn in the middle
+        let ratio = if max > min {
+            (sample - min) / (max - min)
+        } else {
+            0.5
+        };
+        self.area.top_left.y + (height - ratio * height) as i32
+    }
+
+    /// Redraw the whole area
+    pub fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        self.area
+            .into_styled(PrimitiveStyle::with_fill(self.background_color))
+            .draw(target)?;
+
+        let range = match self.range() {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        let left = self.area.top_left.x;
+        // Samples pushed before switching to bars may not fit anymore
+        let skip = self.samples.len().saturating_sub(self.capacity());
+        let bottom = self.area.top_left.y + self.area.size.height as i32 - 1;
+
+        match self.mode {
+            PlotMode::Sparkline => {
+                let style = PrimitiveStyle::with_stroke(self.color, 1);
+                let mut points = self
+                    .samples
+                    .iter()
+                    .skip(skip)
+                    .enumerate()
+                    .map(|(x, &sample)| Point::new(left + x as i32, self.y(sample, range)));
+                if let Some(mut previous) = points.next() {
+                    Pixel(previous, self.color).draw(target)?;
+                    for point in points {
+                        Line::new(previous, point).into_styled(style).draw(target)?;
+                        previous = point;
+                    }
+                }
+            }
+            PlotMode::Bars => {
+                let style = PrimitiveStyle::with_fill(self.color);
+                for (i, &sample) in self.samples.iter().skip(skip).enumerate() {
+                    let top = self.y(sample, range);
+                    Rectangle::with_corners(
+                        Point::new(left + i as i32 * 4, top),
+                        Point::new(left + i as i32 * 4 + 2, bottom),
+                    )
+                    .into_styled(style)
+                    .draw(target)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
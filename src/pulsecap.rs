@@ -0,0 +1,128 @@
+//! Pulse capture
+//!
+//! Records how long a pin stays at each level with a state machine of PIO1, like a logic analyzer
+//! with a single channel. The protocols sending their bits as pulse widths, such as IR remotes,
+//! DHT sensors or 1-Wire, can be decoded from the durations.
+
+use crate::pac;
+
+/// State machine of PIO1 running the capture
+const SM: usize = 0;
+/// Function select of a GPIO for PIO1
+const FUNCSEL_PIO1: u8 = 7;
+/// Rate of the counting loop: 2 cycles per count, one count per microsecond
+const COUNT_HZ: u32 = 2_000_000;
+
+/// Counts down X while the pin stays at a level, then pushes X on each edge and starts over
+///
+/// ```text
+///  0: mov x, ~null
+///  1: jmp pin, 4       ; low: the pin rose
+///  2: jmp x--, 1
+///  3: jmp 1
+///  4: mov isr, x
+///  5: push block
+///  6: mov x, ~null
+///  7: jmp pin, 9       ; high: the pin is still high
+///  8: jmp 11
+///  9: jmp x--, 7
+/// 10: jmp 7
+/// 11: mov isr, x
+/// 12: push block
+/// 13: jmp 0
+/// ```
+const PROGRAM: [u16; 14] = [
+    0xA02B, 0x00C4, 0x0041, 0x0001, 0xA0C1, 0x8020, 0xA02B, 0x00C9, 0x000B, 0x0047, 0x0007,
+    0xA0C1, 0x8020, 0x0000,
+];
+
+/// Result of a capture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capture {
+    /// Durations recorded, in the buffer given to `capture`
+    pub len: usize,
+    /// The durations came faster than they were read, so some are longer than on the pin
+    pub overrun: bool,
+}
+
+/// Pulse recorder on one pin
+pub struct PulseCapture {
+    pio: pac::PIO1,
+}
+
+impl PulseCapture {
+    /// Load the capture program in PIO1, to record the levels of `gpio`
+    ///
+    /// The pin is pulled up, as the usual sources are open collector and idle high. PIO1 is
+    /// taken over, with all its instruction memory.
+    pub fn new(pio: pac::PIO1, resets: &mut pac::RESETS, gpio: usize, sys_hz: u32) -> Self {
+        resets.reset.modify(|_, w| w.pio1().clear_bit());
+        while resets.reset_done.read().pio1().bit_is_clear() {}
+
+        // Note (safety): only the registers of the capture pin are written
+        let (io, pads) = unsafe { (&*pac::IO_BANK0::ptr(), &*pac::PADS_BANK0::ptr()) };
+        pads.gpio[gpio].write(|w| w.ie().set_bit().od().set_bit().pue().set_bit());
+        io.gpio[gpio]
+            .gpio_ctrl
+            .write(|w| unsafe { w.funcsel().bits(FUNCSEL_PIO1) });
+
+        for (slot, &instruction) in pio.instr_mem.iter().zip(PROGRAM.iter()) {
+            slot.write(|w| unsafe { w.bits(instruction as u32) });
+        }
+        let sm = &pio.sm[SM];
+        let (int, frac) = (sys_hz / COUNT_HZ, (sys_hz % COUNT_HZ) as u64 * 256 / COUNT_HZ as u64);
+        sm.sm_clkdiv
+            .write(|w| unsafe { w.int().bits(int as u16).frac().bits(frac as u8) });
+        sm.sm_execctrl.write(|w| unsafe {
+            w.jmp_pin()
+                .bits(gpio as u8)
+                .wrap_top()
+                .bits(PROGRAM.len() as u8 - 1)
+                .wrap_bottom()
+                .bits(0)
+        });
+        // The 8 entries of both FIFOs hold the durations
+        sm.sm_shiftctrl.write(|w| w.fjoin_rx().set_bit());
+
+        Self { pio }
+    }
+
+    /// Record the durations of the levels on the pin, in microseconds, for up to `timeout_us`
+    ///
+    /// The levels alternate from low, so the first duration is 0 if the pin was high. The level
+    /// the pin is at when the capture ends isn't recorded. This waits until `durations` is full
+    /// or the time is up, reading the time with `now_us`.
+    pub fn capture(
+        &mut self,
+        durations: &mut [u32],
+        timeout_us: u32,
+        now_us: impl Fn() -> u64,
+    ) -> Capture {
+        let mask = 1 << SM;
+        let pio = &self.pio;
+        pio.ctrl
+            .write(|w| unsafe { w.sm_restart().bits(mask).clkdiv_restart().bits(mask) });
+        // Clear the stall flag of an earlier capture
+        pio.fdebug.write(|w| unsafe { w.rxstall().bits(mask) });
+        // Start from the first instruction
+        pio.sm[SM].sm_instr.write(|w| unsafe { w.sm0_instr().bits(0x0000) });
+        pio.ctrl.write(|w| unsafe { w.sm_enable().bits(mask) });
+
+        let start_us = now_us();
+        let mut len = 0;
+        while len < durations.len() && now_us().wrapping_sub(start_us) < timeout_us as u64 {
+            if pio.fstat.read().rxempty().bits() & mask == 0 {
+                durations[len] = !pio.rxf[SM].read().bits();
+                len += 1;
+            }
+        }
+
+        pio.ctrl.write(|w| unsafe { w.sm_enable().bits(0) });
+        let overrun = pio.fdebug.read().rxstall().bits() & mask != 0;
+        // Drop what came after the buffer was full
+        while pio.fstat.read().rxempty().bits() & mask == 0 {
+            let _ = pio.rxf[SM].read();
+        }
+        Capture { len, overrun }
+    }
+}
@@ -0,0 +1,99 @@
+//! Rendering on core 1
+//!
+//! Hands a terminal to the second core, which draws the text core 0 queues for it. Core 0 keeps
+//! the USB device and the application, so a slow redraw of the panel doesn't hold up the host.
+
+use crate::sync::SpinMutex;
+use crate::{hal, pac, PicoDisplayTerminal};
+use hal::multicore::{Multicore, Stack};
+use hal::sio::{Sio, SioFifo};
+use heapless::Deque;
+
+/// Bytes queued for core 1 before `Renderer::write` refuses more
+pub const QUEUE_SIZE: usize = 2048;
+/// Bytes drawn per lock of the queue, so core 0 isn't kept waiting during a redraw
+const CHUNK: usize = 32;
+/// Hardware spinlock of the queue, away from the last ones taken by the HAL
+const QUEUE_LOCK: usize = 8;
+
+/// Text for the terminal, written by core 0 and drawn by core 1
+static QUEUE: SpinMutex<Deque<u8, QUEUE_SIZE>, QUEUE_LOCK> = SpinMutex::new(Deque::new());
+
+/// Stack of core 1, in words
+static mut CORE1_STACK: Stack<4096> = Stack::new();
+
+/// Core 0 side of the renderer, queuing the text for the terminal on core 1
+pub struct Renderer {
+    fifo: SioFifo,
+}
+
+impl Renderer {
+    /// Start core 1, which owns `terminal` from now on
+    ///
+    /// Returns `None` if core 1 didn't start.
+    pub fn spawn(
+        terminal: PicoDisplayTerminal<'static>,
+        psm: &mut pac::PSM,
+        ppb: &mut pac::PPB,
+        mut fifo: SioFifo,
+    ) -> Option<Self> {
+        let mut multicore = Multicore::new(psm, ppb, &mut fifo);
+        let core1 = &mut multicore.cores()[1];
+        // Note (safety): the stack is only given to core 1, and `fifo` is taken so core 1 can
+        // only be started once
+        let stack = unsafe { &mut CORE1_STACK.mem };
+        core1.spawn(stack, move || render(terminal)).ok()?;
+        Some(Self { fifo })
+    }
+
+    /// Queue bytes for the terminal, returning how many fit
+    ///
+    /// The rest should be kept and written again, as core 1 frees the queue while drawing.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let written = {
+            let mut queue = QUEUE.lock();
+            data.iter()
+                .take_while(|&&byte| queue.push_back(byte).is_ok())
+                .count()
+        };
+        // A doorbell already waiting in the FIFO wakes core 1 just as well
+        if written > 0 && self.fifo.is_write_ready() {
+            self.fifo.write(0);
+        }
+        written
+    }
+
+    /// Bytes that can be queued right now
+    pub fn space(&self) -> usize {
+        QUEUE_SIZE - QUEUE.lock().len()
+    }
+
+    /// Locks of the queue that waited for the other core, and that waited suspiciously long
+    pub fn contention(&self) -> (u32, u32) {
+        QUEUE.contention()
+    }
+}
+
+/// Main loop of core 1: sleep until core 0 rings, then draw the queue
+fn render(mut terminal: PicoDisplayTerminal<'static>) -> ! {
+    // Note (safety): core 1 only uses its own FIFO, which the SIO keeps apart for each core
+    let pac = unsafe { pac::Peripherals::steal() };
+    let mut fifo = Sio::new(pac.SIO).fifo;
+    loop {
+        let _ = fifo.read_blocking();
+        loop {
+            let mut chunk = [0; CHUNK];
+            let len = {
+                let mut queue = QUEUE.lock();
+                chunk
+                    .iter_mut()
+                    .map_while(|slot| queue.pop_front().map(|byte| *slot = byte))
+                    .count()
+            };
+            if len == 0 {
+                break;
+            }
+            terminal.write(&chunk[..len]);
+        }
+    }
+}
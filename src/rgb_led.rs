@@ -0,0 +1,131 @@
+//! RGB LED of the Pico Display
+//!
+//! Drives the red, green and blue LEDs on GPIO6, GPIO7 and GPIO8 with PWM, from slices 3 and 4,
+//! so any color can be shown and dimmed, steady or with a breathing or blinking effect.
+
+use crate::pac;
+use embedded_graphics::pixelcolor::{Rgb565, Rgb888, RgbColor};
+
+/// GPIOs of the red, green and blue LEDs
+pub const RGB_GPIOS: [usize; 3] = [6, 7, 8];
+
+/// Function select of a GPIO for its PWM channel
+const FUNCSEL_PWM: u8 = 4;
+
+/// Counter wrap of the slices, giving 16 bits of levels at about 1.9kHz
+const TOP: u16 = 0xFFFF;
+
+/// How the color is shown over time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    Solid,
+    /// Fade in and out over `period_ms`
+    Breathe { period_ms: u32 },
+    /// On for the first half of `period_ms`
+    Blink { period_ms: u32 },
+}
+
+/// Driver of the RGB LED
+pub struct RgbLed {
+    color: Rgb888,
+    brightness: u8,
+    effect: Effect,
+}
+
+impl RgbLed {
+    /// Set up the PWM slices of the LED, turned off
+    ///
+    /// Only slices 3 and 4 of `pwm` are used, the other slices are left alone. Slice 4 also
+    /// drives GPIO9, which is left as it is.
+    pub fn new(pwm: &pac::PWM, resets: &mut pac::RESETS) -> Self {
+        resets.reset.modify(|_, w| w.pwm().clear_bit());
+        while resets.reset_done.read().pwm().bit_is_clear() {}
+
+        // The LEDs are active low, so the outputs are inverted
+        for slice in [3, 4] {
+            let ch = &pwm.ch[slice];
+            ch.top.write(|w| unsafe { w.top().bits(TOP) });
+            ch.cc.write(|w| unsafe { w.a().bits(0).b().bits(0) });
+            ch.csr.write(|w| w.a_inv().set_bit().b_inv().set_bit().en().set_bit());
+        }
+
+        // Note (safety): only the registers of the LED pins are written
+        let io = unsafe { &*pac::IO_BANK0::ptr() };
+        for gpio in RGB_GPIOS {
+            io.gpio[gpio]
+                .gpio_ctrl
+                .write(|w| unsafe { w.funcsel().bits(FUNCSEL_PWM) });
+        }
+
+        Self {
+            color: Rgb888::BLACK,
+            brightness: 255,
+            effect: Effect::Solid,
+        }
+    }
+
+    pub fn set_color(&mut self, color: Rgb565) {
+        self.color = color.into();
+    }
+
+    /// Set the color with 8 bits per channel, as sent by a host
+    pub fn set_rgb888(&mut self, color: Rgb888) {
+        self.color = color;
+    }
+
+    pub fn color(&self) -> Rgb888 {
+        self.color
+    }
+
+    /// Scale the color, from 0 for off to 255 for full brightness
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub fn set_effect(&mut self, effect: Effect) {
+        self.effect = effect;
+    }
+
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    /// Update the LED for the time `now_ms`, in any time base
+    ///
+    /// With an effect, this must be called often, such as every 10ms.
+    pub fn update(&self, now_ms: u32) {
+        // Brightness scale for the effect, from 0 to 256
+        let scale = match self.effect {
+            Effect::Solid => 256,
+            Effect::Breathe { period_ms } => {
+                let period_ms = period_ms.max(2);
+                let phase = now_ms % period_ms;
+                // Up for the first half, down for the second
+                let ramp = phase.min(period_ms - phase);
+                ramp * 512 / period_ms
+            }
+            Effect::Blink { period_ms } => {
+                if now_ms % period_ms.max(1) < period_ms / 2 {
+                    256
+                } else {
+                    0
+                }
+            }
+        };
+        let level = |channel: u8| {
+            let level = channel as u32 * self.brightness as u32 * scale / 256;
+            // From 0..=65025 to the whole counter range
+            (level * TOP as u32 / (255 * 255)) as u16
+        };
+        let (r, g, b) = (level(self.color.r()), level(self.color.g()), level(self.color.b()));
+
+        // Note (safety): slices 3 and 4 were given to the LED in `new`
+        let pwm = unsafe { &*pac::PWM::ptr() };
+        pwm.ch[3].cc.write(|w| unsafe { w.a().bits(r).b().bits(g) });
+        pwm.ch[4].cc.modify(|_, w| unsafe { w.a().bits(b) });
+    }
+}
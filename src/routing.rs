@@ -0,0 +1,212 @@
+//! Data routing
+//!
+//! Decides, for each source of data, which sinks receive it. Routes can be changed at runtime,
+//! from the main loop or from an interrupt, such as with the `route` command of `serial_echo`.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+use heapless::Deque;
+
+/// Where data comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// Data received from the host over USB serial
+    UsbSerial,
+    /// Key sequences generated by the buttons
+    Buttons,
+    /// Records of the `log` crate, see the `logger` module
+    Log,
+    /// Data received on the UART
+    Uart,
+}
+
+const SOURCES: usize = 4;
+
+impl Source {
+    pub const ALL: [Source; SOURCES] = [
+        Source::UsbSerial,
+        Source::Buttons,
+        Source::Log,
+        Source::Uart,
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            Source::UsbSerial => 0,
+            Source::Buttons => 1,
+            Source::Log => 2,
+            Source::Uart => 3,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::UsbSerial => "usb",
+            Source::Buttons => "buttons",
+            Source::Log => "log",
+            Source::Uart => "uart",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|source| source.name() == name)
+    }
+}
+
+/// Where data goes to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sink {
+    /// The terminal on the display
+    Display,
+    /// The host, over USB serial
+    Usb,
+    /// The command line, see the `shell` module
+    Shell,
+    /// The page shown over the terminal
+    Page,
+    /// The UART
+    Uart,
+    /// The most recent data, kept in RAM, see `LogBuffer`
+    LogBuffer,
+}
+
+impl Sink {
+    pub const ALL: [Sink; 6] = [
+        Sink::Display,
+        Sink::Usb,
+        Sink::Shell,
+        Sink::Page,
+        Sink::Uart,
+        Sink::LogBuffer,
+    ];
+
+    fn mask(self) -> u8 {
+        match self {
+            Sink::Display => 1 << 0,
+            Sink::Usb => 1 << 1,
+            Sink::Shell => 1 << 2,
+            Sink::Page => 1 << 3,
+            Sink::Uart => 1 << 4,
+            Sink::LogBuffer => 1 << 5,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sink::Display => "display",
+            Sink::Usb => "usb",
+            Sink::Shell => "shell",
+            Sink::Page => "page",
+            Sink::Uart => "uart",
+            Sink::LogBuffer => "buffer",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|sink| sink.name() == name)
+    }
+}
+
+/// Routing matrix between sources and sinks
+pub struct Routes {
+    sinks: [AtomicU8; SOURCES],
+}
+
+impl Routes {
+    /// Create the default routes: USB serial data is shown on the display, echoed back and
+    /// checked for commands, and the logs go to the host
+    pub const fn new() -> Self {
+        Self {
+            sinks: [
+                // Source::UsbSerial
+                AtomicU8::new(1 << 0 | 1 << 1 | 1 << 2),
+                // Source::Buttons
+                AtomicU8::new(0),
+                // Source::Log
+                AtomicU8::new(1 << 1),
+                // Source::Uart
+                AtomicU8::new(0),
+            ],
+        }
+    }
+
+    /// Returns true if data from `source` goes to `sink`
+    pub fn is_routed(&self, source: Source, sink: Sink) -> bool {
+        self.sinks[source.index()].load(Ordering::Relaxed) & sink.mask() != 0
+    }
+
+    /// Sinks receiving the data from `source`
+    pub fn sinks(&self, source: Source) -> impl Iterator<Item = Sink> + '_ {
+        Sink::ALL
+            .iter()
+            .copied()
+            .filter(move |&sink| self.is_routed(source, sink))
+    }
+
+    /// Enable or disable the route from `source` to `sink`
+    pub fn set(&self, source: Source, sink: Sink, enabled: bool) {
+        let sinks = &self.sinks[source.index()];
+        // The Cortex-M0+ has no atomic read-modify-write instructions
+        cortex_m::interrupt::free(|_| {
+            let value = sinks.load(Ordering::Relaxed);
+            let value = if enabled {
+                value | sink.mask()
+            } else {
+                value & !sink.mask()
+            };
+            sinks.store(value, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Routes of the device
+pub static ROUTES: Routes = Routes::new();
+
+/// Most recent data routed to `Sink::LogBuffer`, the oldest bytes make room for the new ones
+pub struct LogBuffer<const N: usize> {
+    bytes: Deque<u8, N>,
+}
+
+impl<const N: usize> LogBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            bytes: Deque::new(),
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        for &byte in data {
+            if self.bytes.is_full() {
+                self.bytes.pop_front();
+            }
+            let _ = self.bytes.push_back(byte);
+        }
+    }
+
+    /// Move the oldest bytes to `buf`, returning how many were moved
+    pub fn take(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        for byte in buf.iter_mut() {
+            match self.bytes.pop_front() {
+                Some(kept) => *byte = kept,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+}
@@ -0,0 +1,144 @@
+//! Terminal history
+//!
+//! Keeps the rows that leave the terminal, compressed so more of them fit in the same memory:
+//! trailing spaces are dropped, and runs of the same character take two bytes.
+
+/// Marks a run: the byte holds the length of the run, and the next one the repeated character
+const RUN: u8 = 0x80;
+/// Shortest run worth encoding
+const MIN_RUN: usize = 3;
+/// Longest run, to fit in the run byte
+const MAX_RUN: usize = 0x7F;
+
+/// Compressed rows, from the oldest to the newest
+///
+/// Each row is stored as its encoded length, then the encoded bytes, in a ring buffer of `N`
+/// bytes. The oldest rows are dropped to make room for new ones.
+pub struct Scrollback<const N: usize> {
+    buf: [u8; N],
+    /// Index of the first byte of the oldest row
+    start: usize,
+    /// Bytes in use
+    len: usize,
+    /// Number of rows
+    rows: usize,
+}
+
+impl<const N: usize> Scrollback<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            start: 0,
+            len: 0,
+            rows: 0,
+        }
+    }
+
+    /// Number of rows kept
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.rows = 0;
+    }
+
+    /// Add a row of printable characters
+    pub fn push(&mut self, row: &[u8]) {
+        let mut encoded = [0; 255];
+        let len = encode(row, &mut encoded);
+        if len + 1 > N {
+            return;
+        }
+        while N - self.len < len + 1 {
+            self.drop_oldest();
+        }
+        self.write_byte(len as u8);
+        for &byte in &encoded[..len] {
+            self.write_byte(byte);
+        }
+        self.rows += 1;
+    }
+
+    /// Decode the row `age` rows back from the newest one into `out`, returning its length
+    ///
+    /// The row is cut if `out` is too small.
+    pub fn row(&self, age: usize, out: &mut [u8]) -> Option<usize> {
+        let index = self.rows.checked_sub(age + 1)?;
+        let mut offset = 0;
+        for _ in 0..index {
+            offset += 1 + self.byte(offset) as usize;
+        }
+        let encoded_len = self.byte(offset) as usize;
+        let encoded = (0..encoded_len).map(|i| self.byte(offset + 1 + i));
+        Some(decode(encoded, out))
+    }
+
+    fn byte(&self, offset: usize) -> u8 {
+        self.buf[(self.start + offset) % N]
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.buf[(self.start + self.len) % N] = byte;
+        self.len += 1;
+    }
+
+    fn drop_oldest(&mut self) {
+        let size = 1 + self.buf[self.start] as usize;
+        self.start = (self.start + size) % N;
+        self.len -= size;
+        self.rows -= 1;
+    }
+}
+
+/// Encode a row into `out`, which must hold at least twice the length of the row
+fn encode(row: &[u8], out: &mut [u8]) -> usize {
+    let row = &row[..row.iter().rposition(|&c| c != b' ').map_or(0, |end| end + 1)];
+    let mut len = 0;
+    let mut i = 0;
+    while i < row.len() && len + 2 <= out.len() {
+        let c = row[i];
+        let run = row[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&next| next == c)
+            .count();
+        // Bytes outside of ASCII, such as the custom glyphs, are always in a run, so they aren't
+        // taken for its marker
+        if run >= MIN_RUN || c & RUN != 0 {
+            out[len] = RUN | run as u8;
+            out[len + 1] = c;
+            len += 2;
+            i += run;
+        } else {
+            out[len] = c;
+            len += 1;
+            i += 1;
+        }
+    }
+    len
+}
+
+fn decode<I: Iterator<Item = u8>>(mut encoded: I, out: &mut [u8]) -> usize {
+    let mut len = 0;
+    while let Some(byte) = encoded.next() {
+        let (c, count) = if byte & RUN != 0 {
+            match encoded.next() {
+                Some(c) => (c, (byte & !RUN) as usize),
+                None => break,
+            }
+        } else {
+            (byte, 1)
+        };
+        let count = count.min(out.len() - len);
+        out[len..len + count].fill(c);
+        len += count;
+    }
+    len
+}
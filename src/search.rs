@@ -0,0 +1,117 @@
+//! Search of the terminal text
+//!
+//! Finds a substring in the rows of the terminal and its history, ignoring case, and keeps the
+//! match being shown so the matches can be stepped through.
+
+use core::fmt;
+use heapless::{String, Vec};
+
+/// Longest text searched for
+pub const MAX_QUERY: usize = 24;
+/// Matches kept, the older ones are only counted as more
+pub const MAX_MATCHES: usize = 32;
+
+/// Place of a match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Rows up from the bottom row of the screen, going on into the history
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Position in the matches, for the status bar
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchStatus {
+    /// Match being shown, counted from 1, or 0 without matches
+    pub current: usize,
+    pub count: usize,
+    /// Some older matches weren't kept
+    pub more: bool,
+}
+
+impl fmt::Display for SearchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.current, self.count)?;
+        if self.more {
+            f.write_str("+")?;
+        }
+        Ok(())
+    }
+}
+
+/// Matches of a search, from the newest
+pub struct Search {
+    query: String<MAX_QUERY>,
+    matches: Vec<Match, MAX_MATCHES>,
+    more: bool,
+    current: usize,
+}
+
+impl Search {
+    /// Start a search for `query`, returning `None` if it is empty or too long
+    pub fn new(query: &str) -> Option<Self> {
+        let mut text = String::new();
+        if query.is_empty() || text.push_str(query).is_err() {
+            return None;
+        }
+        Some(Self {
+            query: text,
+            matches: Vec::new(),
+            more: false,
+            current: 0,
+        })
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Look for the query in `text`, the row `line` rows up from the bottom of the screen
+    ///
+    /// The rows must be given from the bottom up, so the matches go from the newest.
+    pub fn find_in_line(&mut self, line: usize, text: &[u8]) {
+        let query = self.query.as_bytes();
+        if text.len() < query.len() {
+            return;
+        }
+        for col in 0..=text.len() - query.len() {
+            if text[col..col + query.len()].eq_ignore_ascii_case(query)
+                && self.matches.push(Match { line, col }).is_err()
+            {
+                self.more = true;
+                return;
+            }
+        }
+    }
+
+    pub fn matches(&self) -> &[Match] {
+        &self.matches
+    }
+
+    /// Match being shown, `None` without matches
+    pub fn current(&self) -> Option<Match> {
+        self.matches.get(self.current).copied()
+    }
+
+    /// Go to the next older match, wrapping around to the newest one
+    pub fn older(&mut self) {
+        if !self.matches.is_empty() {
+            self.current = (self.current + 1) % self.matches.len();
+        }
+    }
+
+    /// Go to the next newer match, wrapping around to the oldest one
+    pub fn newer(&mut self) {
+        if !self.matches.is_empty() {
+            self.current = (self.current + self.matches.len() - 1) % self.matches.len();
+        }
+    }
+
+    pub fn status(&self) -> SearchStatus {
+        SearchStatus {
+            current: if self.matches.is_empty() { 0 } else { self.current + 1 },
+            count: self.matches.len(),
+            more: self.more,
+        }
+    }
+}
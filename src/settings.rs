@@ -0,0 +1,154 @@
+//! Device settings
+//!
+//! Options changed on the device itself, from the settings page, and kept in the configuration
+//! sectors of the flash so they survive a reboot.
+
+use crate::config;
+use crate::confirm::ConfirmMode;
+use crate::device_name::DeviceName;
+use crate::palette::Palette;
+#[cfg(all(feature = "display", feature = "usb"))]
+use crate::status::StatusLayout;
+use serde::{Deserialize, Serialize};
+
+/// Colors of the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    /// Red on black
+    Dark,
+    /// Black on white
+    Light,
+    /// Green on black
+    Green,
+}
+
+/// Orientation of the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    /// Buttons on the left
+    Normal,
+    /// Buttons on the right
+    Flipped,
+}
+
+/// How the data received from the host is echoed back
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EchoMode {
+    /// Converted to lower case
+    Lower,
+    /// As received
+    Plain,
+    /// Not echoed
+    Off,
+}
+
+impl Theme {
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Green => "green",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Theme::Dark => Theme::Light,
+            Theme::Light => Theme::Green,
+            Theme::Green => Theme::Dark,
+        }
+    }
+}
+
+impl Rotation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rotation::Normal => "normal",
+            Rotation::Flipped => "flipped",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Rotation::Normal => Rotation::Flipped,
+            Rotation::Flipped => Rotation::Normal,
+        }
+    }
+}
+
+impl EchoMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            EchoMode::Lower => "lower",
+            EchoMode::Plain => "plain",
+            EchoMode::Off => "off",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            EchoMode::Lower => EchoMode::Plain,
+            EchoMode::Plain => EchoMode::Off,
+            EchoMode::Off => EchoMode::Lower,
+        }
+    }
+}
+
+/// Options of the device
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    pub theme: Theme,
+    pub rotation: Rotation,
+    /// Backlight brightness, in percent
+    pub brightness: u8,
+    pub echo: EchoMode,
+    pub palette: Palette,
+    /// Lighten or darken the text colors too close to their background
+    pub min_contrast: bool,
+    /// How the commands taking the device offline are confirmed
+    pub confirm: ConfirmMode,
+    /// Name replacing the one derived from the unique ID
+    pub name: Option<DeviceName>,
+    /// Segments of the status bar, in order
+    #[cfg(all(feature = "display", feature = "usb"))]
+    pub status: StatusLayout,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark,
+            rotation: Rotation::Normal,
+            brightness: 100,
+            echo: EchoMode::Lower,
+            palette: Palette::Standard,
+            min_contrast: false,
+            confirm: ConfirmMode::Token,
+            name: None,
+            #[cfg(all(feature = "display", feature = "usb"))]
+            status: StatusLayout::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Returns true if the settings last saved don't match their CRC-32
+    ///
+    /// `load` then gives the ones saved before, or the defaults.
+    pub fn is_corrupt() -> bool {
+        config::is_corrupt()
+    }
+
+    /// Load the settings stored in flash, or the defaults if there are none
+    pub fn load() -> Self {
+        let mut settings: Self = config::load().unwrap_or_default();
+        settings.brightness = settings.brightness.min(100);
+        settings
+    }
+
+    /// Store the settings in flash, returning the number of records in the configuration sector
+    pub fn save(&self) -> usize {
+        // The settings always fit in a record
+        config::save(self).unwrap_or(0)
+    }
+}
@@ -0,0 +1,180 @@
+//! Large 7-segment digits
+//!
+//! Draws numbers of any size with filled rectangles, for dashboard values, without storing
+//! large bitmap fonts. Unlit segments are drawn in the background color, so a new value
+//! replaces the previous one without clearing the area first.
+
+use embedded_graphics::{
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+/// Segments lit for a character, as bits `abcdefg` from bit 0 to 6
+fn segments(c: char) -> Option<u8> {
+    Some(match c {
+        '0' => 0b0111111,
+        '1' => 0b0000110,
+        '2' => 0b1011011,
+        '3' => 0b1001111,
+        '4' => 0b1100110,
+        '5' => 0b1101101,
+        '6' => 0b1111101,
+        '7' => 0b0000111,
+        '8' => 0b1111111,
+        '9' => 0b1101111,
+        '-' => 0b1000000,
+        ' ' => 0b0000000,
+        'C' => 0b0111001,
+        'E' => 0b1111001,
+        'F' => 0b1110001,
+        'H' => 0b1110110,
+        'L' => 0b0111000,
+        'P' => 0b1110011,
+        _ => return None,
+    })
+}
+
+/// Style of 7-segment text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SevenSegmentStyle<C> {
+    /// Size of a digit
+    pub digit_size: Size,
+    /// Thickness of the segments
+    pub thickness: u32,
+    /// Space between characters
+    pub spacing: u32,
+    /// Color of the lit segments
+    pub color: C,
+    /// Color of the unlit segments and the space around them
+    pub background_color: C,
+}
+
+impl<C: PixelColor> SevenSegmentStyle<C> {
+    /// Width of `c` when drawn, without spacing
+    fn char_width(&self, c: char) -> u32 {
+        match c {
+            ':' | '.' => self.thickness * 2,
+            _ => self.digit_size.width,
+        }
+    }
+
+    /// Width of `text` when drawn
+    pub fn text_width(&self, text: &str) -> u32 {
+        let width: u32 = text
+            .chars()
+            .map(|c| self.char_width(c) + self.spacing)
+            .sum();
+        width.saturating_sub(self.spacing)
+    }
+}
+
+/// Text drawn with 7-segment digits
+///
+/// Supports the digits, `-`, `:`, `.`, space, and the letters `C`, `E`, `F`, `H`, `L` and
+/// `P`. Other characters are drawn blank.
+pub struct SevenSegment<'a, C> {
+    text: &'a str,
+    /// Top-left corner of the text
+    position: Point,
+    style: SevenSegmentStyle<C>,
+}
+
+impl<'a, C: PixelColor> SevenSegment<'a, C> {
+    pub fn new(text: &'a str, position: Point, style: SevenSegmentStyle<C>) -> Self {
+        Self {
+            text,
+            position,
+            style,
+        }
+    }
+
+    /// Draw a digit at `pos`
+    fn draw_digit<D>(&self, lit: u8, pos: Point, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let Size { width, height } = self.style.digit_size;
+        let t = self.style.thickness;
+        let half = (height - t) / 2;
+        let (w, t_i, half_i) = (width as i32, t as i32, half as i32);
+        let h_size = Size::new(width - 2 * t, t);
+        let upper_size = Size::new(t, half - t);
+        let lower_size = Size::new(t, height - half - 2 * t);
+
+        let rects = [
+            // a
+            Rectangle::new(pos + Point::new(t_i, 0), h_size),
+            // b
+            Rectangle::new(pos + Point::new(w - t_i, t_i), upper_size),
+            // c
+            Rectangle::new(pos + Point::new(w - t_i, half_i + t_i), lower_size),
+            // d
+            Rectangle::new(pos + Point::new(t_i, height as i32 - t_i), h_size),
+            // e
+            Rectangle::new(pos + Point::new(0, half_i + t_i), lower_size),
+            // f
+            Rectangle::new(pos + Point::new(0, t_i), upper_size),
+            // g
+            Rectangle::new(pos + Point::new(t_i, half_i), h_size),
+        ];
+
+        // Clear the whole cell first, to erase the corners between segments
+        Rectangle::new(pos, self.style.digit_size)
+            .into_styled(PrimitiveStyle::with_fill(self.style.background_color))
+            .draw(target)?;
+        for (i, rect) in rects.iter().enumerate() {
+            if lit & (1 << i) != 0 {
+                rect.into_styled(PrimitiveStyle::with_fill(self.style.color))
+                    .draw(target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the dots of `:` or `.` at `pos`
+    fn draw_dots<D>(&self, c: char, pos: Point, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let t = self.style.thickness;
+        let height = self.style.digit_size.height;
+        let dot = Size::new(t * 2, t * 2);
+
+        Rectangle::new(pos, Size::new(t * 2, height))
+            .into_styled(PrimitiveStyle::with_fill(self.style.background_color))
+            .draw(target)?;
+
+        let dots: &[i32] = if c == ':' {
+            &[(height / 3 - t) as i32, (height * 2 / 3 - t) as i32]
+        } else {
+            &[(height - 2 * t) as i32]
+        };
+        for &y in dots {
+            Rectangle::new(pos + Point::new(0, y), dot)
+                .into_styled(PrimitiveStyle::with_fill(self.style.color))
+                .draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, C: PixelColor> Drawable for SevenSegment<'a, C> {
+    type Color = C;
+    /// Position right after the text, to continue drawing from there
+    type Output = Point;
+
+    fn draw<D>(&self, target: &mut D) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let mut pos = self.position;
+        for c in self.text.chars() {
+            match c {
+                ':' | '.' => self.draw_dots(c, pos, target)?,
+                _ => self.draw_digit(segments(c).unwrap_or(0), pos, target)?,
+            }
+            pos.x += (self.style.char_width(c) + self.style.spacing) as i32;
+        }
+        Ok(pos)
+    }
+}
@@ -0,0 +1,98 @@
+//! State shared with interrupts
+//!
+//! Values set up by `main`, then used by both the main loop and the interrupt handlers. They are
+//! reached through a critical section and a `RefCell`, rather than a `static mut`.
+
+use core::cell::{RefCell, RefMut};
+use core::ops::Deref;
+use cortex_m::interrupt::{self, CriticalSection, Mutex};
+
+/// Value shared between the main loop and the interrupt handlers, empty until `put`
+///
+/// Like `cortex_m::interrupt::Mutex`, the critical sections only keep out the interrupts of the
+/// core taking them. Data reached from core 1 goes in a `sync::SpinMutex` instead.
+pub struct Shared<T> {
+    value: Mutex<RefCell<Option<T>>>,
+}
+
+// Note (safety): the value is only reached inside critical sections, so the interrupts of the
+// core can't reach it at the same time, and it is `Send` as any of them may take it
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    pub const fn new() -> Self {
+        Self {
+            value: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Store the value, replacing the previous one
+    pub fn put(&self, value: T) {
+        interrupt::free(|cs| *self.value.borrow(cs).borrow_mut() = Some(value));
+    }
+
+    /// Borrow the value until the end of the critical section, `None` until it is set
+    ///
+    /// Panics if it is already borrowed, such as by a caller up the stack.
+    pub fn borrow<'cs>(&'cs self, cs: &'cs CriticalSection) -> RefMut<'cs, Option<T>> {
+        self.value.borrow(cs).borrow_mut()
+    }
+
+    /// Run `f` on the value inside a critical section, if it is set
+    pub fn with<R, F>(&self, cs: &CriticalSection, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        self.borrow(cs).as_mut().map(f)
+    }
+
+    /// Run `f` on the value with the interrupts disabled, if it is set
+    pub fn lock<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        interrupt::free(|cs| self.with(cs, f))
+    }
+
+    /// Read the value even while it is borrowed, such as from a panic handler
+    ///
+    /// # Safety
+    ///
+    /// Nothing may change the value while the reference is used. This holds once the interrupts
+    /// are disabled for good and the code holding a borrow will never resume.
+    pub unsafe fn peek<'cs>(&'cs self, cs: &'cs CriticalSection) -> Option<&'cs T> {
+        (*self.value.borrow(cs).as_ptr()).as_ref()
+    }
+}
+
+/// `Shared` value that isn't `Send`, such as the USB types, kept on core 0
+///
+/// The USB classes and device hold a reference to the bus allocator, which isn't `Sync`, so they
+/// can't be in a `Shared`. This gives the same methods, for values only used on core 0.
+pub struct Core0Shared<T> {
+    shared: Shared<T>,
+}
+
+// Note (safety): `Core0Shared::new` requires the value to only be used from core 0, where the
+// critical sections of `Shared` keep the main loop and the interrupt handlers apart
+unsafe impl<T> Sync for Core0Shared<T> {}
+
+impl<T> Core0Shared<T> {
+    /// # Safety
+    ///
+    /// The value, and what it borrows such as the USB bus allocator, must only be reached from
+    /// core 0: no code running on core 1, nor anything it calls, may use this `Core0Shared`.
+    pub const unsafe fn new() -> Self {
+        Self {
+            shared: Shared::new(),
+        }
+    }
+}
+
+impl<T> Deref for Core0Shared<T> {
+    type Target = Shared<T>;
+
+    fn deref(&self) -> &Shared<T> {
+        &self.shared
+    }
+}
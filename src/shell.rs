@@ -0,0 +1,312 @@
+//! Line-oriented commands
+//!
+//! Collects the bytes received from the host into lines, which the application splits into
+//! words to run commands such as `time sync <unix_ms>`, or hands to a `Shell` of registered
+//! commands. Long command output is paged, see `Pager`.
+
+use core::cell::Cell;
+use core::fmt::Write;
+use heapless::{String, Vec};
+
+/// Maximum length of a command line
+pub const MAX_LINE: usize = 64;
+/// Time to send `reboot confirm` after `reboot`
+pub const REBOOT_CONFIRM_US: u64 = 10_000_000;
+
+/// Assembles incoming bytes into lines
+pub struct LineBuffer {
+    line: String<MAX_LINE>,
+    /// The current line is too long, and is dropped up to its end
+    overflow: bool,
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            line: String::new(),
+            overflow: false,
+        }
+    }
+
+    /// Add a byte, returning the line once it is complete
+    ///
+    /// Backspace and delete remove the last character. Empty lines, lines longer than
+    /// `MAX_LINE` and non-ASCII bytes are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<String<MAX_LINE>> {
+        match byte {
+            0x08 | 0x7F if !self.overflow => {
+                self.line.pop();
+                None
+            }
+            b'\r' | b'\n' => {
+                let line = core::mem::replace(&mut self.line, String::new());
+                let overflow = core::mem::replace(&mut self.overflow, false);
+                if overflow || line.is_empty() {
+                    None
+                } else {
+                    Some(line)
+                }
+            }
+            0x20..=0x7E if !self.overflow => {
+                if self.line.push(byte as char).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Prompt shown when a page of command output is full
+pub const MORE_PROMPT: &[u8] = b"-- more --";
+/// Erases the prompt, so the output continues on the same line
+pub const CLEAR_PROMPT: &[u8] = b"\r          \r";
+
+/// Pauses long command output after each page of lines
+pub struct Pager {
+    /// Lines per page, `None` when paging is disabled
+    rows: Option<usize>,
+    /// Lines sent on the current page
+    lines: usize,
+    waiting: bool,
+}
+
+impl Pager {
+    pub const fn new(rows: Option<usize>) -> Self {
+        Self {
+            rows,
+            lines: 0,
+            waiting: false,
+        }
+    }
+
+    /// Change the number of lines per page, or disable paging with `None`
+    pub fn set_rows(&mut self, rows: Option<usize>) {
+        self.rows = rows.filter(|&rows| rows > 0);
+        self.reset();
+    }
+
+    /// Start a new command output, on a new page
+    pub fn reset(&mut self) {
+        self.lines = 0;
+        self.waiting = false;
+    }
+
+    /// Returns true while the output waits for a key before the next page
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Count a line sent, returning true when the page is full and `MORE_PROMPT` should be shown
+    pub fn line_sent(&mut self) -> bool {
+        let rows = match self.rows {
+            Some(rows) => rows,
+            None => return false,
+        };
+        self.lines += 1;
+        if self.lines < rows {
+            return false;
+        }
+        self.lines = 0;
+        self.waiting = true;
+        true
+    }
+
+    /// Continue with the next page
+    pub fn resume(&mut self) {
+        self.waiting = false;
+    }
+}
+
+/// Prompt sent after a command, when the host can type the next one
+pub const PROMPT: &[u8] = b"> ";
+
+/// Append the echo of a byte typed at the prompt to `out`, returning false if it doesn't fit
+///
+/// Backspace and delete erase the previous character on the host terminal, and a carriage
+/// return also moves to a new line.
+pub fn push_echo<const N: usize>(out: &mut Vec<u8, N>, byte: u8) -> bool {
+    let echo: &[u8] = match byte {
+        0x08 | 0x7F => b"\x08 \x08",
+        b'\r' => b"\r\n",
+        _ => core::slice::from_ref(&byte),
+    };
+    out.extend_from_slice(echo).is_ok()
+}
+
+/// What the built-in commands of the `Shell` need from the application
+pub trait ShellContext {
+    /// Send command output to the host
+    fn write(&mut self, data: &[u8]);
+
+    /// Time since boot, in microseconds
+    fn uptime_us(&self) -> u64;
+
+    /// Clear the screens of the device, the host screen is cleared with an escape sequence
+    fn clear_screen(&mut self) {}
+
+    /// Take over the built-in `reboot`, with the words after it, returning true if handled
+    ///
+    /// By default the shell handles it, and only restarts once `reboot confirm` follows within
+    /// `REBOOT_CONFIRM_US`, so a stray line can't restart the device. An application with its
+    /// own confirmation runs it from here instead.
+    fn reboot(&mut self, _args: Args<'_>) -> bool {
+        false
+    }
+}
+
+/// Command run by a `Shell`, with the words after its name
+pub type Handler<C> = fn(&mut C, Args<'_>);
+
+/// Words of a command line, split at whitespace like `str::split_whitespace`
+///
+/// Commands taking free text, such as a search, get the rest of the line as it was sent with
+/// `rest`.
+#[derive(Clone, Debug)]
+pub struct Args<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self { rest: line }
+    }
+
+    /// Part of the line after the words already taken, without its leading whitespace
+    pub fn rest(&self) -> &'a str {
+        self.rest.trim_start()
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, rest) = rest.split_at(end);
+        self.rest = rest;
+        if word.is_empty() {
+            None
+        } else {
+            Some(word)
+        }
+    }
+}
+
+/// Command registered in a `Shell`
+pub struct Command<C> {
+    pub name: &'static str,
+    /// One line description, shown by `help`
+    pub help: &'static str,
+    pub handler: Handler<C>,
+}
+
+/// Commands built in every `Shell`, in the order `help` shows them
+const BUILT_IN: [(&str, &str); 4] = [
+    ("help", "show the commands"),
+    ("clear", "clear the screen"),
+    ("uptime", "show the time since boot"),
+    ("reboot", "restart the device, once confirmed"),
+];
+
+/// Runs the command lines received from the host
+///
+/// Besides the built-in `help`, `clear`, `uptime` and `reboot`, it runs up to `N` commands
+/// registered by the application, with `C` as context.
+pub struct Shell<C, const N: usize> {
+    commands: Vec<Command<C>, N>,
+    /// Time of the last `reboot`, waiting for `reboot confirm`
+    reboot_asked_us: Cell<Option<u64>>,
+}
+
+impl<C: ShellContext, const N: usize> Shell<C, N> {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            reboot_asked_us: Cell::new(None),
+        }
+    }
+
+    /// Add a command, returning it back if there are already `N`
+    ///
+    /// A command with the name of a built-in one is never run.
+    pub fn register(&mut self, command: Command<C>) -> Result<(), Command<C>> {
+        self.commands.push(command)
+    }
+
+    /// Returns true if `name` is a built-in or registered command
+    pub fn has_command(&self, name: &str) -> bool {
+        BUILT_IN.iter().any(|&(built_in, _)| built_in == name)
+            || self.commands.iter().any(|command| command.name == name)
+    }
+
+    /// Run a command line, returning false if it isn't a command of the shell
+    pub fn run(&self, context: &mut C, line: &str) -> bool {
+        let mut words = Args::new(line);
+        let name = match words.next() {
+            Some(name) => name,
+            None => return false,
+        };
+        match name {
+            "help" => self.help(context),
+            "clear" => {
+                context.write(b"\x1b[2J\x1b[H");
+                context.clear_screen();
+            }
+            "uptime" => {
+                let seconds = context.uptime_us() / 1_000_000;
+                let mut text: String<32> = String::new();
+                let _ = write!(
+                    text,
+                    "uptime {:02}:{:02}:{:02}\r\n",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                );
+                context.write(text.as_bytes());
+            }
+            "reboot" => {
+                if !context.reboot(words.clone()) {
+                    self.reboot(context, words);
+                }
+            }
+            _ => match self.commands.iter().find(|command| command.name == name) {
+                Some(command) => (command.handler)(context, words),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    /// Ask for `reboot confirm`, and restart once it comes in time
+    fn reboot(&self, context: &mut C, mut words: Args<'_>) {
+        let now = context.uptime_us();
+        let asked = self.reboot_asked_us.take();
+        match (words.next(), words.next()) {
+            (None, None) => {
+                self.reboot_asked_us.set(Some(now));
+                context.write(b"reboot: send `reboot confirm` within 10 seconds\r\n");
+            }
+            (Some("confirm"), None) => match asked {
+                Some(asked) if now.saturating_sub(asked) <= REBOOT_CONFIRM_US => {
+                    cortex_m::peripheral::SCB::sys_reset()
+                }
+                _ => context.write(b"reboot: send `reboot` first\r\n"),
+            },
+            _ => context.write(b"reboot: use `reboot`, then `reboot confirm`\r\n"),
+        }
+    }
+
+    /// Send the name and description of each command
+    fn help(&self, context: &mut C) {
+        let registered = self.commands.iter().map(|command| (command.name, command.help));
+        for (name, help) in BUILT_IN.iter().copied().chain(registered) {
+            let mut line: String<80> = String::new();
+            // A description too long for the line is cut
+            let _ = write!(line, "{:<10}{}\r\n", name, help);
+            context.write(line.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Low power waits
+//!
+//! Sleeps the core until a time of the 1MHz timer, instead of spinning in a delay, for main loops
+//! with nothing to do between their ticks. Alarm 1 of the timer is taken for it. Its interrupt
+//! stays masked: with SEVONPEND, the alarm turning pending is enough to end `wfe`.
+
+use crate::pac;
+use cortex_m::peripheral::SCB;
+
+/// Let alarm 1 wake the core, once the timer is out of reset such as after `Timer::new`
+pub fn init() {
+    // Note (safety): only the SEVONPEND bit is set, which nothing else changes
+    unsafe { (*SCB::ptr()).scr.modify(|scr| scr | 1 << 4) };
+    timer().inte.modify(|_, w| w.alarm_1().set_bit());
+}
+
+/// Sleep until the counter of the timer reaches `deadline_us`
+///
+/// The other interrupts still run, and the core goes back to sleep after each of them until the
+/// deadline.
+pub fn until(deadline_us: u64) {
+    let timer = timer();
+    clear_alarm(timer);
+    // Only the low word is compared, the deadline being a few milliseconds away
+    timer
+        .alarm1
+        .write(|w| unsafe { w.bits(deadline_us as u32) });
+    while now_us(timer) < deadline_us {
+        cortex_m::asm::wfe();
+    }
+    // A deadline passed before the alarm was set would only fire once the low word wraps
+    timer.armed.write(|w| unsafe { w.bits(1 << 1) });
+    clear_alarm(timer);
+}
+
+fn timer() -> &'static pac::timer::RegisterBlock {
+    // Note (safety): the counter is only read, and alarm 1 only used here
+    unsafe { &*pac::TIMER::ptr() }
+}
+
+fn clear_alarm(timer: &pac::timer::RegisterBlock) {
+    timer.intr.write(|w| w.alarm_1().set_bit());
+    pac::NVIC::unpend(pac::Interrupt::TIMER_IRQ_1);
+}
+
+fn now_us(timer: &pac::timer::RegisterBlock) -> u64 {
+    // The high word is read again, in case the low word wrapped in between
+    loop {
+        let high = timer.timerawh.read().bits();
+        let low = timer.timerawl.read().bits();
+        if timer.timerawh.read().bits() == high {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
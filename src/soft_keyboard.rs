@@ -0,0 +1,183 @@
+//! On-screen keyboard widget
+//!
+//! Grid of characters for entering text without a host, one key at a time with the buttons.
+//! Like the menu, it only handles the keys: the page decides what to do with the text.
+
+use crate::events::MAX_INPUT;
+use crate::keys::Key;
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, MonoTextStyleBuilder},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use heapless::String;
+
+/// Number of characters on a row of the grid
+const COLUMNS: usize = 10;
+
+/// Rows of characters, lower case then with shift
+const ROWS: [&[u8; COLUMNS]; 4] = [b"abcdefghij", b"klmnopqrst", b"uvwxyz.,-'", b"0123456789"];
+const SHIFTED_ROWS: [&[u8; COLUMNS]; 4] =
+    [b"ABCDEFGHIJ", b"KLMNOPQRST", b"UVWXYZ!?:/", b"@#()_+=&*;"];
+
+/// Keys on the last row, after the characters
+const SPECIAL_KEYS: [&str; 4] = ["Shift", "Space", "Del", "Done"];
+
+/// Number of keys, characters and special keys
+const KEY_COUNT: usize = ROWS.len() * COLUMNS + SPECIAL_KEYS.len();
+
+/// What the keyboard asks the page to do
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SoftKeyAction {
+    /// Done was pressed with this text
+    Done(String<MAX_INPUT>),
+    /// Leave the keyboard, dropping the text
+    Cancel,
+}
+
+/// On-screen keyboard widget
+pub struct SoftKeyboard<'f, C> {
+    area: Rectangle,
+    title: &'static str,
+    style: MonoTextStyle<'f, C>,
+    background_color: C,
+    /// Background of the selected key
+    highlight_color: C,
+    value: String<MAX_INPUT>,
+    /// Index of the selected key, the characters row by row, then the special keys
+    selected: usize,
+    shift: bool,
+}
+
+impl<'f, C: PixelColor> SoftKeyboard<'f, C> {
+    pub fn new(
+        area: Rectangle,
+        title: &'static str,
+        style: MonoTextStyle<'f, C>,
+        background_color: C,
+        highlight_color: C,
+    ) -> Self {
+        Self {
+            area,
+            title,
+            style,
+            background_color,
+            highlight_color,
+            value: String::new(),
+            selected: 0,
+            shift: false,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Handle a key
+    ///
+    /// Up and down move to the previous and next key, wrapping around, so the buttons reach all
+    /// of them. Left and right do the same, and page up and down move a row at a time. Enter
+    /// presses the selected key and escape cancels.
+    pub fn handle_key(&mut self, key: Key) -> Option<SoftKeyAction> {
+        match key {
+            Key::Up | Key::Left => self.selected = (self.selected + KEY_COUNT - 1) % KEY_COUNT,
+            Key::Down | Key::Right => self.selected = (self.selected + 1) % KEY_COUNT,
+            Key::PageUp => self.selected = self.selected.saturating_sub(COLUMNS),
+            Key::PageDown => self.selected = (self.selected + COLUMNS).min(KEY_COUNT - 1),
+            Key::Enter => return self.press(),
+            Key::Escape => return Some(SoftKeyAction::Cancel),
+            Key::Function(_) => (),
+        }
+        None
+    }
+
+    fn press(&mut self) -> Option<SoftKeyAction> {
+        let chars = ROWS.len() * COLUMNS;
+        if self.selected < chars {
+            let rows = if self.shift { SHIFTED_ROWS } else { ROWS };
+            let c = rows[self.selected / COLUMNS][self.selected % COLUMNS];
+            // Text past `MAX_INPUT` is dropped
+            let _ = self.value.push(c as char);
+            // Shift only applies to one character
+            self.shift = false;
+            return None;
+        }
+        // In the order of `SPECIAL_KEYS`
+        match self.selected - chars {
+            0 => self.shift = !self.shift,
+            1 => {
+                let _ = self.value.push(' ');
+            }
+            2 => {
+                self.value.pop();
+            }
+            _ => return Some(SoftKeyAction::Done(self.value.clone())),
+        }
+        None
+    }
+
+    /// Height of a row, in pixels
+    fn row_height(&self) -> i32 {
+        self.style.font.character_size.height as i32 + 2
+    }
+
+    /// Draw the title, the text entered so far, then the keys
+    pub fn draw<D: DrawTarget<Color = C>>(&self, target: &mut D) -> Result<(), D::Error> {
+        self.area
+            .into_styled(PrimitiveStyle::with_fill(self.background_color))
+            .draw(target)?;
+
+        let char_width = self.style.font.character_size.width as i32;
+        let row_height = self.row_height();
+        let origin = self.area.top_left;
+        Text::with_baseline(self.title, origin, self.style, Baseline::Top).draw(target)?;
+        // The end of the text stays in view
+        let visible = (self.area.size.width as usize / char_width as usize).saturating_sub(2);
+        let start = self.value.len().saturating_sub(visible);
+        let text_pos = origin + Point::new(0, row_height);
+        Text::with_baseline(">", text_pos, self.style, Baseline::Top).draw(target)?;
+        Text::with_baseline(
+            &self.value[start..],
+            text_pos + Point::new(2 * char_width, 0),
+            self.style,
+            Baseline::Top,
+        )
+        .draw(target)?;
+
+        let highlight_style = MonoTextStyleBuilder::from(&self.style)
+            .background_color(self.highlight_color)
+            .build();
+        let key_width = self.area.size.width as i32 / COLUMNS as i32;
+        let rows = if self.shift { SHIFTED_ROWS } else { ROWS };
+        let mut buf = [0u8; 4];
+        for index in 0..KEY_COUNT {
+            let (row, column) = ((index / COLUMNS) as i32, (index % COLUMNS) as i32);
+            let (label, width) = match rows.get(row as usize) {
+                Some(chars) => {
+                    let c = chars[column as usize] as char;
+                    (&*c.encode_utf8(&mut buf), key_width)
+                }
+                // The special keys are wider, sharing the last row
+                None => (
+                    SPECIAL_KEYS[index - ROWS.len() * COLUMNS],
+                    self.area.size.width as i32 / SPECIAL_KEYS.len() as i32,
+                ),
+            };
+            let top_left = origin + Point::new(column * width, row_height * (row + 2));
+            let style = if index == self.selected {
+                Rectangle::new(top_left, Size::new(width as u32, row_height as u32))
+                    .into_styled(PrimitiveStyle::with_fill(self.highlight_color))
+                    .draw(target)?;
+                highlight_style
+            } else {
+                self.style
+            };
+            // Centered in the key
+            let label_width = label.len() as i32 * char_width;
+            let label_pos = top_left + Point::new((width - label_width) / 2, 1);
+            Text::with_baseline(label, label_pos, style, Baseline::Top).draw(target)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+//! Startup steps
+//!
+//! Parts of the firmware start as named steps, each declaring the steps it runs after and what a
+//! failure does. They run in the order they are declared, except that a step waits for its
+//! dependencies, so the boot order is the same on every boot and doesn't depend on `main`.
+
+use heapless::Vec;
+
+/// Most steps in a startup
+pub const MAX_STEPS: usize = 24;
+
+/// What happens when a step fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnFailure {
+    /// Stop the startup, the firmware can't run without this step
+    Abort,
+    /// Carry on without this step, skipping the steps that run after it
+    Skip,
+}
+
+/// Part of the firmware to start, with a context `C` shared by all the steps
+pub struct Step<C, E> {
+    pub name: &'static str,
+    /// Steps that must have run before this one
+    pub after: &'static [&'static str],
+    pub on_failure: OnFailure,
+    pub run: fn(&mut C) -> Result<(), E>,
+}
+
+impl<C, E> Step<C, E> {
+    /// Step without dependencies, stopping the startup if it fails
+    pub const fn new(name: &'static str, run: fn(&mut C) -> Result<(), E>) -> Self {
+        Self {
+            name,
+            after: &[],
+            on_failure: OnFailure::Abort,
+            run,
+        }
+    }
+
+    pub const fn after(mut self, steps: &'static [&'static str]) -> Self {
+        self.after = steps;
+        self
+    }
+
+    /// Carry on without this step if it fails
+    pub const fn optional(mut self) -> Self {
+        self.on_failure = OnFailure::Skip;
+        self
+    }
+}
+
+/// How a step went
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome<E> {
+    Done,
+    Failed(E),
+    /// Not run, as a step it runs after failed or was skipped
+    Skipped,
+}
+
+/// Step that just ended, reported to show the progress of the startup
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress<E> {
+    pub name: &'static str,
+    pub outcome: Outcome<E>,
+    /// Steps ended, this one included, out of `total`
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Reason the startup stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartupError<E> {
+    /// More than `MAX_STEPS` steps
+    TooManySteps,
+    /// A step runs after a step that isn't declared
+    UnknownDependency {
+        step: &'static str,
+        dependency: &'static str,
+    },
+    /// The steps left all wait for each other
+    Cycle { step: &'static str },
+    /// A step that can't fail was skipped, as a step it runs after failed
+    Blocked { step: &'static str },
+    /// A step that can't fail failed
+    Failed { step: &'static str, error: E },
+}
+
+/// Outcome of each step, in the order they ran
+pub type Outcomes<E> = Vec<(&'static str, Outcome<E>), MAX_STEPS>;
+
+/// Run the steps in order, calling `progress` after each one
+///
+/// The next step to run is the first declared one whose dependencies have all ended. Nothing
+/// runs if the steps are inconsistent, and the steps left are dropped once one that can't fail
+/// fails.
+pub fn run<C, E: Copy>(
+    steps: &[Step<C, E>],
+    context: &mut C,
+    mut progress: impl FnMut(&Progress<E>),
+) -> Result<Outcomes<E>, StartupError<E>> {
+    if steps.len() > MAX_STEPS {
+        return Err(StartupError::TooManySteps);
+    }
+    let index_of = |name: &str| steps.iter().position(|step| step.name == name);
+    for step in steps {
+        if let Some(&dependency) = step.after.iter().find(|&&name| index_of(name).is_none()) {
+            return Err(StartupError::UnknownDependency {
+                step: step.name,
+                dependency,
+            });
+        }
+    }
+
+    let mut ended: [Option<Outcome<E>>; MAX_STEPS] = [None; MAX_STEPS];
+    let mut outcomes = Outcomes::new();
+    for done in 1..=steps.len() {
+        let ready = |index: usize| {
+            ended[index].is_none()
+                && steps[index]
+                    .after
+                    .iter()
+                    .all(|&name| index_of(name).map_or(false, |dep| ended[dep].is_some()))
+        };
+        let index = match (0..steps.len()).find(|&index| ready(index)) {
+            Some(index) => index,
+            None => {
+                let pending = (0..steps.len()).find(|&index| ended[index].is_none());
+                let step = pending.map_or("", |index| steps[index].name);
+                return Err(StartupError::Cycle { step });
+            }
+        };
+
+        let step = &steps[index];
+        let blocked = step.after.iter().any(|&name| {
+            index_of(name).map_or(true, |dep| !matches!(ended[dep], Some(Outcome::Done)))
+        });
+        let outcome = if blocked {
+            Outcome::Skipped
+        } else {
+            match (step.run)(context) {
+                Ok(()) => Outcome::Done,
+                Err(error) => Outcome::Failed(error),
+            }
+        };
+        ended[index] = Some(outcome);
+        let _ = outcomes.push((step.name, outcome));
+        progress(&Progress {
+            name: step.name,
+            outcome,
+            done,
+            total: steps.len(),
+        });
+
+        if step.on_failure == OnFailure::Abort {
+            match outcome {
+                Outcome::Done => {}
+                Outcome::Failed(error) => {
+                    return Err(StartupError::Failed {
+                        step: step.name,
+                        error,
+                    })
+                }
+                Outcome::Skipped => return Err(StartupError::Blocked { step: step.name }),
+            }
+        }
+    }
+    Ok(outcomes)
+}
@@ -0,0 +1,244 @@
+//! Status bar
+//!
+//! A single line of text at the top of the screen, showing the state of the device. What is
+//! shown is decided by a `StatusBarRenderer`, such as a `SegmentRenderer` with a custom
+//! `StatusLayout`, which can be kept in the settings.
+
+use crate::search::SearchStatus;
+use core::fmt::{self, Write};
+use embedded_graphics::{mono_font::MonoTextStyle, prelude::*, text::Text};
+use serde::{Deserialize, Serialize};
+use usb_device::device::UsbDeviceState;
+
+// 64 character long string
+static FILLER_STRING: &str = "                                                                ";
+
+/// Snapshot of the device state shown in the status bar
+pub struct StatusInfo {
+    /// Time since boot, in seconds
+    pub uptime: u32,
+    /// Chip temperature, in tenths of degrees Celsius
+    pub temperature: Option<i32>,
+    pub usb_state: UsbDeviceState,
+    /// Bytes received over USB serial during the last second
+    pub rx_rate: u32,
+    /// Baud rate set by the host
+    pub baud_rate: u32,
+    /// Number of bytes lost
+    pub errors: u32,
+    /// Matches of the search of the terminal, `None` when not searching
+    pub search: Option<SearchStatus>,
+}
+
+/// Turns the device state into the text of the status bar
+pub trait StatusBarRenderer {
+    fn render(&self, info: &StatusInfo, out: &mut dyn Write) -> fmt::Result;
+}
+
+/// Piece of information that can be shown in the status bar
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Segment {
+    /// Uptime as `hh:mm:ss`
+    Clock,
+    /// Chip temperature
+    Temperature,
+    /// USB device state
+    UsbState,
+    /// Received bytes per second
+    RxRate,
+    /// Baud rate set by the host
+    BaudRate,
+    /// Number of bytes lost
+    Errors,
+    /// Match shown and number of matches, while searching the terminal
+    Search,
+}
+
+impl Segment {
+    pub const ALL: [Segment; 7] = [
+        Segment::Clock,
+        Segment::Temperature,
+        Segment::UsbState,
+        Segment::RxRate,
+        Segment::BaudRate,
+        Segment::Errors,
+        Segment::Search,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Segment::Clock => "clock",
+            Segment::Temperature => "temp",
+            Segment::UsbState => "usb",
+            Segment::RxRate => "rx",
+            Segment::BaudRate => "baud",
+            Segment::Errors => "errors",
+            Segment::Search => "search",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|segment| segment.name() == name)
+    }
+
+    /// Write the text of the segment for `info`
+    pub fn render(self, info: &StatusInfo, out: &mut dyn Write) -> fmt::Result {
+        match self {
+            Segment::Clock => write!(
+                out,
+                "{:02}:{:02}:{:02}",
+                info.uptime / 3600,
+                info.uptime / 60 % 60,
+                info.uptime % 60
+            ),
+            Segment::Temperature => match info.temperature {
+                Some(t) => write!(out, "{}.{}C", t / 10, (t % 10).abs()),
+                None => out.write_str("--.-C"),
+            },
+            Segment::UsbState => out.write_str(match info.usb_state {
+                UsbDeviceState::Default => "USB:--",
+                UsbDeviceState::Addressed => "USB:ADDR",
+                UsbDeviceState::Configured => "USB:OK",
+                UsbDeviceState::Suspend => "USB:SUSP",
+            }),
+            Segment::RxRate => write!(out, "{}B/s", info.rx_rate),
+            Segment::BaudRate => write!(out, "{}bd", info.baud_rate),
+            Segment::Errors => write!(out, "E:{}", info.errors),
+            Segment::Search => match info.search {
+                Some(search) => write!(out, "find {}", search),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// Maximum number of segments in a `StatusLayout`
+pub const MAX_SEGMENTS: usize = 6;
+
+/// Ordered list of the segments shown in the status bar
+///
+/// This is a plain constant-constructible value, so a layout can be kept in flash as a
+/// `static`, or serialized with the settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusLayout {
+    pub segments: [Option<Segment>; MAX_SEGMENTS],
+}
+
+impl StatusLayout {
+    pub const fn new(segments: [Option<Segment>; MAX_SEGMENTS]) -> Self {
+        Self { segments }
+    }
+
+    /// Layout of segment names separated by commas, such as `clock,temp,usb`
+    ///
+    /// Returns `None` for an unknown name, or more than `MAX_SEGMENTS` of them.
+    pub fn parse(names: &str) -> Option<Self> {
+        let mut segments = [None; MAX_SEGMENTS];
+        let mut count = 0;
+        for name in names.split(',') {
+            *segments.get_mut(count)? = Some(Segment::from_name(name)?);
+            count += 1;
+        }
+        Some(Self::new(segments))
+    }
+}
+
+impl Default for StatusLayout {
+    fn default() -> Self {
+        Self::new([
+            Some(Segment::Clock),
+            Some(Segment::Temperature),
+            Some(Segment::UsbState),
+            Some(Segment::RxRate),
+            Some(Segment::BaudRate),
+            Some(Segment::Errors),
+        ])
+    }
+}
+
+/// Renders the segments of a `StatusLayout`, separated by spaces
+pub struct SegmentRenderer {
+    layout: StatusLayout,
+}
+
+impl SegmentRenderer {
+    pub fn new(layout: StatusLayout) -> Self {
+        Self { layout }
+    }
+}
+
+impl StatusBarRenderer for SegmentRenderer {
+    fn render(&self, info: &StatusInfo, out: &mut dyn Write) -> fmt::Result {
+        let mut first = true;
+        for segment in self.layout.segments.iter().flatten() {
+            if !first {
+                out.write_char(' ')?;
+            }
+            first = false;
+            segment.render(info, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Single line status bar
+pub struct StatusBar<'f, C> {
+    pos: Point,
+    columns: usize,
+    style: MonoTextStyle<'f, C>,
+}
+
+impl<'f, C> StatusBar<'f, C>
+where
+    C: PixelColor,
+{
+    /// Create a status bar whose text baseline starts at `pos`, `columns` characters wide
+    pub fn new(pos: Point, columns: usize, style: MonoTextStyle<'f, C>) -> Self {
+        Self {
+            pos,
+            columns,
+            style,
+        }
+    }
+
+    /// Render the device state with `renderer` and draw it
+    pub fn update<S, R>(
+        &self,
+        screen: &mut S,
+        renderer: &R,
+        info: &StatusInfo,
+    ) -> Result<(), S::Error>
+    where
+        S: DrawTarget<Color = C>,
+        R: StatusBarRenderer,
+    {
+        let mut text: heapless::String<64> = heapless::String::new();
+        // Text that doesn't fit in the buffer is cut, like text that doesn't fit on screen
+        let _ = renderer.render(info, &mut text);
+        self.draw(screen, &text)
+    }
+
+    /// Draw the status bar, replacing its previous content
+    pub fn draw<S>(&self, screen: &mut S, text: &str) -> Result<(), S::Error>
+    where
+        S: DrawTarget<Color = C>,
+    {
+        let text = match text.char_indices().nth(self.columns) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+        let next = Text::new(text, self.pos, self.style).draw(screen)?;
+
+        // Pad the rest of the line with spaces to erase the previous content
+        let padding = self
+            .columns
+            .saturating_sub(text.chars().count())
+            .min(FILLER_STRING.len());
+        Text::new(&FILLER_STRING[..padding], next, self.style).draw(screen)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Relay outputs
+//!
+//! Keeps the state of the outputs driving relays or loads, and turns them on and off at set
+//! times of the day. Outputs are off at boot, unless they are set to restore their last state.
+
+use crate::flash::{self, checksum, PAGE_SIZE, SECTOR_SIZE};
+use core::fmt;
+
+/// Number of outputs
+pub const MAX_SWITCHES: usize = 4;
+
+/// Sector where the switches are stored, from the start of the flash
+pub const SWITCHES_OFFSET: u32 = 0x1F_3000;
+
+const MAGIC: [u8; 4] = *b"SWT1";
+
+/// Minutes in a day
+const DAY_MINUTES: u16 = 24 * 60;
+
+/// Daily times to turn an output on and off, in minutes since midnight UTC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub on: u16,
+    pub off: u16,
+}
+
+impl Schedule {
+    /// Parse a time of the day as `HH:MM`
+    pub fn parse_time(text: &str) -> Option<u16> {
+        let (hours, minutes) = text.split_once(':')?;
+        let (hours, minutes): (u16, u16) = (hours.parse().ok()?, minutes.parse().ok()?);
+        if hours < 24 && minutes < 60 {
+            Some(hours * 60 + minutes)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.on / 60,
+            self.on % 60,
+            self.off / 60,
+            self.off % 60
+        )
+    }
+}
+
+/// State and schedules of the outputs
+pub struct Switches {
+    on: [bool; MAX_SWITCHES],
+    schedules: [Option<Schedule>; MAX_SWITCHES],
+    /// Outputs that restore their last state at boot, as bits
+    restore: u8,
+    /// Minute of the day of the last update
+    last_minute: Option<u16>,
+}
+
+impl Switches {
+    /// All outputs off, without schedules
+    pub const fn new() -> Self {
+        Self {
+            on: [false; MAX_SWITCHES],
+            schedules: [None; MAX_SWITCHES],
+            restore: 0,
+            last_minute: None,
+        }
+    }
+
+    pub fn is_on(&self, index: usize) -> bool {
+        self.on.get(index).copied().unwrap_or(false)
+    }
+
+    /// Turn an output on or off, returning false if there is no such output
+    pub fn set(&mut self, index: usize, on: bool) -> bool {
+        match self.on.get_mut(index) {
+            Some(state) => {
+                *state = on;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn toggle(&mut self, index: usize) -> bool {
+        self.set(index, !self.is_on(index))
+    }
+
+    pub fn schedule(&self, index: usize) -> Option<Schedule> {
+        self.schedules.get(index).copied().flatten()
+    }
+
+    /// Set or remove the schedule of an output, returning false if there is no such output
+    pub fn set_schedule(&mut self, index: usize, schedule: Option<Schedule>) -> bool {
+        match self.schedules.get_mut(index) {
+            Some(stored) => {
+                *stored = schedule;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn restores(&self, index: usize) -> bool {
+        self.restore & 1 << index != 0
+    }
+
+    /// Keep the state of an output across reboots, instead of turning it off at boot
+    pub fn set_restore(&mut self, index: usize, restore: bool) -> bool {
+        if index >= MAX_SWITCHES {
+            return false;
+        }
+        if restore {
+            self.restore |= 1 << index;
+        } else {
+            self.restore &= !(1 << index);
+        }
+        true
+    }
+
+    /// Run the schedules for the time `unix_ms`, returning true if an output changed
+    ///
+    /// Outputs only change when their on or off time is reached, so an output set by hand
+    /// stays that way until its next scheduled time.
+    pub fn update(&mut self, unix_ms: u64) -> bool {
+        let minute = ((unix_ms / 60_000) % DAY_MINUTES as u64) as u16;
+        if self.last_minute == Some(minute) {
+            return false;
+        }
+        self.last_minute = Some(minute);
+
+        let mut changed = false;
+        for (on, schedule) in self.on.iter_mut().zip(self.schedules.iter()) {
+            let state = match schedule {
+                Some(schedule) if schedule.on == minute => true,
+                Some(schedule) if schedule.off == minute => false,
+                _ => continue,
+            };
+            changed |= *on != state;
+            *on = state;
+        }
+        changed
+    }
+
+    /// Load the schedules stored in flash, and the state of the outputs that restore it
+    pub fn load(&mut self) {
+        // Magic, states, restore bits, schedules and checksum
+        const LEN: usize = 6 + 4 * MAX_SWITCHES;
+        let page = flash::read(SWITCHES_OFFSET, LEN + 2);
+        let sum = u16::from_le_bytes([page[LEN], page[LEN + 1]]);
+        if page[..4] != MAGIC || checksum(&page[4..LEN]) != sum {
+            return;
+        }
+
+        let (states, restore) = (page[4], page[5]);
+        self.restore = restore;
+        for index in 0..MAX_SWITCHES {
+            self.on[index] = restore & states & 1 << index != 0;
+            let times = &page[6 + 4 * index..][..4];
+            let (on, off) = (
+                u16::from_le_bytes([times[0], times[1]]),
+                u16::from_le_bytes([times[2], times[3]]),
+            );
+            self.schedules[index] = if on < DAY_MINUTES && off < DAY_MINUTES {
+                Some(Schedule { on, off })
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Store the schedules and the state of the outputs in flash
+    pub fn save(&self) {
+        const LEN: usize = 6 + 4 * MAX_SWITCHES;
+        let mut page = [0xFF; PAGE_SIZE as usize];
+        page[..4].copy_from_slice(&MAGIC);
+        page[4] = self
+            .on
+            .iter()
+            .enumerate()
+            .fold(0, |states, (index, &on)| states | (on as u8) << index);
+        page[5] = self.restore;
+        for (index, schedule) in self.schedules.iter().enumerate() {
+            // Schedules that aren't set are left erased
+            if let Some(schedule) = schedule {
+                page[6 + 4 * index..][..2].copy_from_slice(&schedule.on.to_le_bytes());
+                page[8 + 4 * index..][..2].copy_from_slice(&schedule.off.to_le_bytes());
+            }
+        }
+        let sum = checksum(&page[4..LEN]);
+        page[LEN..LEN + 2].copy_from_slice(&sum.to_le_bytes());
+
+        // Note (safety): the sector is reserved for the switches
+        unsafe {
+            flash::erase(SWITCHES_OFFSET, SECTOR_SIZE);
+            flash::program(SWITCHES_OFFSET, &page);
+        }
+    }
+}
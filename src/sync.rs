@@ -0,0 +1,147 @@
+//! Cross-core locks
+//!
+//! Mutexes on the SIO hardware spinlocks, shared by both cores without disabling the
+//! interrupts. Each lock counts how often it was contended, to spot lock ordering problems.
+
+use crate::pac;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+
+/// Number of hardware spinlocks
+pub const SPINLOCK_COUNT: usize = 32;
+/// Spinlock of the HAL's critical sections, which a `SpinMutex` can't take
+pub const RESERVED_SPINLOCK: usize = 31;
+
+/// Spins after which a lock attempt counts as a possible deadlock
+const DEADLOCK_SPINS: u32 = 1_000_000;
+
+/// Data protected by hardware spinlock `N`, from 0 to 30
+///
+/// Each spinlock must back a single `SpinMutex`, and a core must not lock it again while
+/// holding it, or it waits forever. Spinlock 31 is `RESERVED_SPINLOCK`, and it or one past the
+/// last spinlock fails to build.
+pub struct SpinMutex<T, const N: usize> {
+    data: UnsafeCell<T>,
+    /// Locks that had to wait for the other core
+    contended: AtomicU32,
+    /// Locks that waited longer than `DEADLOCK_SPINS`
+    stalled: AtomicU32,
+}
+
+// Note (safety): access to the data is serialized by the spinlock
+unsafe impl<T: Send, const N: usize> Sync for SpinMutex<T, N> {}
+
+impl<T, const N: usize> SpinMutex<T, N> {
+    /// Evaluated by `new`, so an invalid `N` is a build error rather than a panic
+    const VALID_SPINLOCK: () = assert!(
+        N < SPINLOCK_COUNT && N != RESERVED_SPINLOCK,
+        "SpinMutex needs a spinlock from 0 to 30"
+    );
+
+    pub const fn new(data: T) -> Self {
+        let () = Self::VALID_SPINLOCK;
+        Self {
+            data: UnsafeCell::new(data),
+            contended: AtomicU32::new(0),
+            stalled: AtomicU32::new(0),
+        }
+    }
+
+    /// Wait for the lock, spinning while the other core holds it
+    ///
+    /// The interrupts stay enabled, so an interrupt handler locking the same mutex on the core
+    /// holding it waits forever. Use `try_lock` from interrupt handlers.
+    pub fn lock(&self) -> SpinGuard<'_, T, N> {
+        let mut spins = 0u32;
+        loop {
+            if let Some(guard) = self.try_lock() {
+                if spins > 0 {
+                    increment(&self.contended);
+                }
+                return guard;
+            }
+            spins = spins.wrapping_add(1);
+            if spins == DEADLOCK_SPINS {
+                increment(&self.stalled);
+            }
+        }
+    }
+
+    /// Take the lock if it is free
+    pub fn try_lock(&self) -> Option<SpinGuard<'_, T, N>> {
+        if claim::<N>() {
+            Some(SpinGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    /// Number of locks that waited for the other core, and that waited suspiciously long
+    pub fn contention(&self) -> (u32, u32) {
+        (
+            self.contended.load(Ordering::Relaxed),
+            self.stalled.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Access to the data of a locked `SpinMutex`, which is unlocked when this is dropped
+pub struct SpinGuard<'a, T, const N: usize> {
+    mutex: &'a SpinMutex<T, N>,
+}
+
+impl<T, const N: usize> Deref for SpinGuard<'_, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Note (safety): the guard holds the spinlock
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T, const N: usize> DerefMut for SpinGuard<'_, T, N> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Note (safety): the guard holds the spinlock
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T, const N: usize> Drop for SpinGuard<'_, T, N> {
+    fn drop(&mut self) {
+        release::<N>();
+    }
+}
+
+/// Claim spinlock `N`, returning false if it is already claimed
+fn claim<const N: usize>() -> bool {
+    // Note (safety): the spinlocks are only claimed and released by `SpinMutex`
+    let sio = unsafe { &*pac::SIO::ptr() };
+    // Reading claims the spinlock, and reads as zero if it was already claimed
+    let claimed = sio.spinlock[N].read().bits() != 0;
+    // Keeps the accesses to the data after the claim
+    compiler_fence(Ordering::Acquire);
+    claimed
+}
+
+fn release<const N: usize>() {
+    // Note (safety): the spinlocks are only claimed and released by `SpinMutex`
+    let sio = unsafe { &*pac::SIO::ptr() };
+    // Keeps the accesses to the data before the release
+    compiler_fence(Ordering::Release);
+    // Writing any value releases the spinlock
+    sio.spinlock[N].write(|w| unsafe { w.bits(1) });
+}
+
+/// Add one to a counter
+///
+/// The Cortex-M0+ has no atomic read-modify-write instructions, and disabling the interrupts
+/// doesn't stop the other core, so counts from both cores at the same time may be lost.
+fn increment(counter: &AtomicU32) {
+    cortex_m::interrupt::free(|_| {
+        counter.store(
+            counter.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Relaxed,
+        );
+    });
+}
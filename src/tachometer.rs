@@ -0,0 +1,77 @@
+//! Tachometer input
+//!
+//! Counts the pulses of a fan or motor on GPIO11 with the counter of PWM slice 5, which counts
+//! rising edges without the CPU, and turns the count over each gate interval into RPM.
+
+use crate::pac;
+
+/// GPIO of the tachometer input, the B channel of PWM slice 5
+pub const TACH_GPIO: usize = 11;
+const SLICE: usize = 5;
+
+/// Function select of a GPIO for its PWM channel
+const FUNCSEL_PWM: u8 = 4;
+
+/// Pulse counter turning pulses into RPM
+pub struct Tachometer {
+    pulses_per_rev: u32,
+    last_count: u16,
+    last_us: u64,
+    rpm: Option<u32>,
+}
+
+impl Tachometer {
+    /// Start counting the rising edges on GPIO11
+    ///
+    /// Most PC fans send 2 pulses per revolution. Their output is open collector, so the pin is
+    /// pulled up. Only slice 5 of `pwm` is used, the other slices are left alone.
+    pub fn new(
+        pwm: &pac::PWM,
+        resets: &mut pac::RESETS,
+        pulses_per_rev: u32,
+        now_us: u64,
+    ) -> Self {
+        resets.reset.modify(|_, w| w.pwm().clear_bit());
+        while resets.reset_done.read().pwm().bit_is_clear() {}
+
+        // Note (safety): only the registers of the tachometer pin are written
+        let (io, pads) = unsafe { (&*pac::IO_BANK0::ptr(), &*pac::PADS_BANK0::ptr()) };
+        pads.gpio[TACH_GPIO].write(|w| w.ie().set_bit().od().clear_bit().pue().set_bit());
+        io.gpio[TACH_GPIO]
+            .gpio_ctrl
+            .write(|w| unsafe { w.funcsel().bits(FUNCSEL_PWM) });
+
+        let ch = &pwm.ch[SLICE];
+        ch.div.write(|w| unsafe { w.int().bits(1) });
+        ch.ctr.write(|w| unsafe { w.ctr().bits(0) });
+        ch.csr.write(|w| w.divmode().rise().en().set_bit());
+
+        Self {
+            pulses_per_rev: pulses_per_rev.max(1),
+            last_count: 0,
+            last_us: now_us,
+            rpm: None,
+        }
+    }
+
+    /// End the gate interval started by the previous call, returning the RPM over it
+    ///
+    /// The counter wraps after 65536 pulses, so the interval must be short enough to count
+    /// fewer than that, such as half a second for up to 3.9 million RPM at 2 pulses.
+    pub fn sample(&mut self, pwm: &pac::PWM, now_us: u64) -> u32 {
+        let count = pwm.ch[SLICE].ctr.read().ctr().bits();
+        let pulses = count.wrapping_sub(self.last_count) as u64;
+        let elapsed_us = now_us.wrapping_sub(self.last_us).max(1);
+        self.last_count = count;
+        self.last_us = now_us;
+
+        let rpm = (pulses * 60_000_000 / (elapsed_us * self.pulses_per_rev as u64)) as u32;
+        self.rpm = Some(rpm);
+        rpm
+    }
+
+    /// RPM over the last gate interval, `None` before the first one ends
+    pub fn rpm(&self) -> Option<u32> {
+        self.rpm
+    }
+}
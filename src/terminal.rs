@@ -1,15 +1,133 @@
+use crate::ansi::{Action, Csi, Parser};
+use crate::custom_glyph::{self, Bitmap, CustomGlyphs};
+use crate::glyph_cache::{CacheStats, GlyphCache};
+use crate::scrollback::Scrollback;
+use crate::search::{Match, Search, SearchStatus, MAX_MATCHES};
 use embedded_graphics::{
     mono_font::{ascii::FONT_6X10, MonoTextStyle, MonoTextStyleBuilder},
     prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
     text::Text,
 };
 
-// 64 character long string
-static FILLER_STRING: &str = "                                                            ";
+/// Spaces erasing a whole row, as many as `MAX_COLUMNS`
+const FILLER_STRING: &str = "                                                                ";
+
+/// Maximum number of columns kept in the cell buffer
+pub const MAX_COLUMNS: usize = 64;
+// Rows wider than the filler wouldn't be erased to the end
+const _: () = assert!(FILLER_STRING.len() == MAX_COLUMNS);
+/// Maximum number of rows kept in the cell buffer
+pub const MAX_ROWS: usize = 32;
+/// Bytes of compressed rows kept after they leave the screen
+pub const HISTORY_SIZE: usize = 2048;
+/// Columns between tab stops
+pub const TAB_WIDTH: usize = 8;
+/// Colors a cell can take besides the ones of the style, see `palette::ansi`
+pub const ANSI_COLORS: usize = 8;
+
+/// Colors of a cell, as indexes in the ANSI colors of the terminal
+///
+/// The low 4 bits are the foreground and the high 4 bits the background, so a screen of colors
+/// takes a byte per cell. All bits set is the color of the style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellColors(u8);
+
+impl CellColors {
+    /// The text and background colors of the style
+    pub const DEFAULT: Self = Self(0xFF);
+    const STYLE: u8 = 0xF;
+
+    pub fn fg(self) -> Option<usize> {
+        Self::index(self.0 & 0xF)
+    }
+
+    pub fn bg(self) -> Option<usize> {
+        Self::index(self.0 >> 4)
+    }
+
+    fn with_fg(self, color: Option<usize>) -> Self {
+        Self(self.0 & 0xF0 | Self::nibble(color))
+    }
+
+    fn with_bg(self, color: Option<usize>) -> Self {
+        Self(self.0 & 0x0F | Self::nibble(color) << 4)
+    }
+
+    fn index(nibble: u8) -> Option<usize> {
+        match nibble {
+            Self::STYLE => None,
+            index => Some(index as usize),
+        }
+    }
+
+    fn nibble(color: Option<usize>) -> u8 {
+        color.map_or(Self::STYLE, |index| (index % ANSI_COLORS) as u8)
+    }
+}
+
+/// What happens to the text going past the last column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Continue on the next row, breaking words anywhere
+    Character,
+    /// Continue on the next row, moving the last word of the row down with it
+    ///
+    /// Words longer than a row still break where the row ends.
+    Word,
+    /// Stay on the last column, each character overwriting the previous one
+    Truncate,
+}
 
 pub struct Terminal<'f, C, S> {
     config: TerminalConfig<'f, C, S>,
     pos: Point,
+    /// Bit `n` is set when row `n` continues the logical line of the previous row
+    continued_rows: u32,
+    /// Characters on screen
+    cells: [[u8; MAX_COLUMNS]; MAX_ROWS],
+    /// Colors of the characters on screen
+    cell_colors: [[CellColors; MAX_COLUMNS]; MAX_ROWS],
+    /// Colors of the next characters printed
+    colors: CellColors,
+    /// Colors the indexes of `CellColors` stand for
+    ansi_colors: [C; ANSI_COLORS],
+    /// Characters are inserted rather than overwriting the existing ones (IRM)
+    insert_mode: bool,
+    /// The cursor is drawn, unless hidden by the host (DECTCEM)
+    cursor_visible: bool,
+    /// Text going past the last column continues on the next row, rather than overwriting the
+    /// last column (DECAWM)
+    autowrap: bool,
+    /// The cursor just wrapped onto the next row, and hasn't moved since
+    just_wrapped: bool,
+    parser: Parser,
+    /// Primary screen, saved while the alternate screen is shown
+    primary: Option<SavedScreen>,
+    /// Scale of the glyphs
+    zoom: u32,
+    /// Rows that left the screen
+    history: Scrollback<HISTORY_SIZE>,
+    glyphs: GlyphCache<C>,
+    /// Search shown instead of the live rows, with the background of its matches
+    search: Option<(Search, C)>,
+    /// Rows the view is scrolled back from the live rows, by a search or `scroll_back`
+    view_back: usize,
+    /// Glyphs sent by the host for the private use code points
+    custom_glyphs: CustomGlyphs,
+    /// Code point of the UTF-8 sequence being received, and its bytes left
+    utf8: Option<(u32, u8)>,
+    /// Scrolling moves the pixels with the function of the builder, rather than drawing the rows
+    /// again
+    pixel_scroll: bool,
+}
+
+/// Content of the primary screen, kept while the alternate screen is active
+struct SavedScreen {
+    cells: [[u8; MAX_COLUMNS]; MAX_ROWS],
+    cell_colors: [[CellColors; MAX_COLUMNS]; MAX_ROWS],
+    continued_rows: u32,
+    pos: Option<Point>,
 }
 
 impl<'f, C, S> Terminal<'f, C, S>
@@ -25,51 +143,606 @@ where
 
     /// Handle a single ASCII character
     pub fn write_char(&mut self, c: u8) {
+        // New data brings the history view back to the live rows, except while searching
+        if self.search.is_none() && self.view_back > 0 {
+            self.view_back = 0;
+            self.refresh();
+        }
+
         // Erase the cursor
         if self.config.cursor_color.is_some() {
-            self.erase_chars(1);
+            self.draw_cell(self.col(), self.row() as usize);
+        }
+
+        match self.parser.advance(c) {
+            Action::Print(c) => self.handle_char(c),
+            // Anything but a character moves the cursor or changes the screen
+            Action::Control(c) => {
+                self.just_wrapped = false;
+                self.handle_char(c);
+            }
+            Action::Csi(csi) => {
+                self.just_wrapped = false;
+                self.handle_csi(&csi);
+            }
+            Action::Escape(_) => self.just_wrapped = false,
+            Action::None => (),
+        }
+
+        // Redraw the cursor
+        if self.config.cursor_color.is_some() {
+            self.draw_cursor();
         }
+    }
 
+    /// Handle a printable or control character
+    fn handle_char(&mut self, c: u8) {
+        // An unfinished UTF-8 sequence is dropped
+        if c < 0x80 {
+            self.utf8 = None;
+        }
         match c {
             0x00..=0x07 => (),
             // Backspace
             0x08 => self.move_backward(1),
             // Tab
-            0x09 => self.move_forward(4),
+            0x09 => self.move_to_tab_stop(),
             // New line
             0x0A => self.move_next_line(),
             0x0B..=0x0C => (),
             // Carriage return
-            0x0D => self.move_next_line(),
+            0x0D => self.move_start_line(),
             0x0E..=0x1F => (),
             // Delete
             0x7F => self.move_backward(1),
+            // Bytes of the characters outside of ASCII
+            0x80..=0xFF => self.decode_utf8(c),
             // Characters
             _ => self.print_char(c),
         }
+    }
 
-        // Redraw the cursor
-        if self.config.cursor_color.is_some() {
-            self.draw_cursor();
+    /// Decode a byte of a UTF-8 sequence, printing the custom glyphs and `?` for the other
+    /// characters outside of ASCII
+    fn decode_utf8(&mut self, c: u8) {
+        let (code_point, left) = match (c, self.utf8) {
+            (0x80..=0xBF, Some((code_point, left))) => {
+                (code_point << 6 | (c & 0x3F) as u32, left - 1)
+            }
+            (0xC0..=0xDF, _) => ((c & 0x1F) as u32, 1),
+            (0xE0..=0xEF, _) => ((c & 0x0F) as u32, 2),
+            (0xF0..=0xF7, _) => ((c & 0x07) as u32, 3),
+            // A continuation byte out of a sequence, or a byte UTF-8 never uses
+            _ => (0, 0),
+        };
+        if left > 0 {
+            self.utf8 = Some((code_point, left));
+            return;
+        }
+        self.utf8 = None;
+        self.print_char(custom_glyph::cell_byte(code_point).unwrap_or(b'?'));
+    }
+
+    /// Handle a control sequence
+    fn handle_csi(&mut self, csi: &Csi) {
+        match (csi.private, csi.action) {
+            // Insert characters (ICH)
+            (false, b'@') => self.insert_chars(csi.param(0, 1) as usize),
+            // Delete characters (DCH)
+            (false, b'P') => self.delete_chars(csi.param(0, 1) as usize),
+            // Set and reset insert mode (IRM)
+            (false, b'h') | (false, b'l') if csi.params().contains(&4) => {
+                self.insert_mode = csi.action == b'h';
+            }
+            // DEC private modes
+            (true, b'h') | (true, b'l') => {
+                let set = csi.action == b'h';
+                for &mode in csi.params() {
+                    match mode {
+                        // Autowrap (DECAWM)
+                        7 => self.autowrap = set,
+                        // Show and hide the cursor (DECTCEM)
+                        25 => self.cursor_visible = set,
+                        // Switch to and from the alternate screen (smcup/rmcup)
+                        47 | 1047 | 1049 if set => self.enter_alternate_screen(mode == 1049),
+                        47 | 1047 | 1049 => self.leave_alternate_screen(),
+                        _ => (),
+                    }
+                }
+            }
+            // Cursor up, down, forward and back (CUU, CUD, CUF, CUB)
+            (false, b'A') => self.move_by(0, -(csi.param(0, 1) as i32)),
+            (false, b'B') => self.move_by(0, csi.param(0, 1) as i32),
+            (false, b'C') => self.move_by(csi.param(0, 1) as i32, 0),
+            (false, b'D') => self.move_by(-(csi.param(0, 1) as i32), 0),
+            // Cursor position (CUP, HVP), counted from 1
+            (false, b'H') | (false, b'f') => {
+                let (row, col) = (csi.param(0, 1) as usize, csi.param(1, 1) as usize);
+                self.move_to(col - 1, row - 1);
+            }
+            // Cursor horizontal absolute (CHA)
+            (false, b'G') => self.move_to(csi.param(0, 1) as usize - 1, self.row() as usize),
+            // Erase in display (ED) and in line (EL)
+            (false, b'J') => self.erase_display(csi.param(0, 0)),
+            (false, b'K') => self.erase_line(csi.param(0, 0)),
+            // Colors and attributes (SGR)
+            (false, b'm') => self.select_graphic_rendition(csi),
+            // Unsupported sequences are dropped
+            _ => (),
+        }
+    }
+
+    /// Change the colors of the next characters, dropping the other attributes
+    fn select_graphic_rendition(&mut self, csi: &Csi) {
+        // Without parameters, the attributes are reset
+        if csi.params().is_empty() {
+            self.colors = CellColors::DEFAULT;
+        }
+        for &param in csi.params() {
+            match param {
+                0 => self.colors = CellColors::DEFAULT,
+                30..=37 => self.set_fg(Some((param - 30) as usize)),
+                39 => self.set_fg(None),
+                40..=47 => self.set_bg(Some((param - 40) as usize)),
+                49 => self.set_bg(None),
+                // The bright colors are shown as the normal ones
+                90..=97 => self.set_fg(Some((param - 90) as usize)),
+                100..=107 => self.set_bg(Some((param - 100) as usize)),
+                // The parameters after 256 and RGB colors aren't attributes
+                38 | 48 => break,
+                _ => (),
+            }
+        }
+    }
+
+    /// Color of the next characters, an index of `palette::ansi`, or the text color of the style
+    /// with `None`
+    pub fn set_fg(&mut self, color: Option<usize>) {
+        self.colors = self.colors.with_fg(color);
+    }
+
+    /// Background of the next characters, an index of `palette::ansi`, or the background of the
+    /// style with `None`
+    pub fn set_bg(&mut self, color: Option<usize>) {
+        self.colors = self.colors.with_bg(color);
+    }
+
+    /// Change the colors of the ANSI indexes, such as for another `Palette`, redrawing the cells
+    pub fn set_ansi_colors(&mut self, colors: [C; ANSI_COLORS]) {
+        if colors != self.ansi_colors {
+            self.ansi_colors = colors;
+            self.refresh();
+        }
+    }
+
+    /// Style of the characters with `colors`
+    fn cell_style(&self, colors: CellColors) -> MonoTextStyle<'f, C> {
+        let mut style = self.config.style;
+        if let Some(fg) = colors.fg() {
+            style.text_color = Some(self.ansi_colors[fg]);
+        }
+        if let Some(bg) = colors.bg() {
+            style.background_color = Some(self.ansi_colors[bg]);
+        }
+        style
+    }
+
+    /// Move the cursor to a cell, staying on the screen
+    fn move_to(&mut self, col: usize, row: usize) {
+        let col = col.min(self.columns().saturating_sub(1));
+        let row = row.min(self.rows().saturating_sub(1));
+        self.pos = self.cell_position(col, row);
+    }
+
+    /// Move the cursor by a number of columns and rows, staying on the screen
+    fn move_by(&mut self, cols: i32, rows: i32) {
+        let col = (self.col() as i32 + cols).max(0) as usize;
+        let row = (self.row() as i32 + rows).max(0) as usize;
+        self.move_to(col, row);
+    }
+
+    /// Erase from the cursor to the end of the screen (0), from the start of the screen to the
+    /// cursor (1), or the whole screen (2 and 3)
+    fn erase_display(&mut self, mode: u16) {
+        let row = self.row() as usize;
+        let rows = match mode {
+            0 => row + 1..self.rows(),
+            1 => 0..row,
+            _ => 0..self.rows(),
+        };
+        for row in rows {
+            self.clear_cells(row, 0, MAX_COLUMNS);
+            if row < 32 {
+                self.continued_rows &= !(1 << row);
+            }
+        }
+        if mode < 2 {
+            self.erase_line(mode);
+        }
+    }
+
+    /// Erase from the cursor to the end of the row (0), from the start of the row to the
+    /// cursor (1), or the whole row (2)
+    fn erase_line(&mut self, mode: u16) {
+        let (col, row) = (self.col(), self.row() as usize);
+        match mode {
+            0 => self.clear_cells(row, col, MAX_COLUMNS),
+            1 => self.clear_cells(row, 0, col + 1),
+            _ => self.clear_cells(row, 0, MAX_COLUMNS),
+        }
+    }
+
+    /// Blank the cells of `row` from `start` to `end`, excluded
+    fn clear_cells(&mut self, row: usize, start: usize, end: usize) {
+        if row >= MAX_ROWS {
+            return;
+        }
+        for col in start..end.min(self.columns()) {
+            if self.cells[row][col] != b' ' || self.cell_colors[row][col] != CellColors::DEFAULT {
+                self.cells[row][col] = b' ';
+                self.cell_colors[row][col] = CellColors::DEFAULT;
+                self.draw_cell(col, row);
+            }
         }
     }
 
     /// Print a single ASCII character
     fn print_char(&mut self, c: u8) {
+        let just_wrapped = core::mem::replace(&mut self.just_wrapped, false);
+        if just_wrapped && self.wraps_words() {
+            // The space the row broke at isn't kept at the start of the next one
+            if c == b' ' {
+                return;
+            }
+            self.move_word_down();
+        }
+
+        if self.insert_mode {
+            self.insert_chars(1);
+        }
+
+        let (col, row) = (self.col(), self.row() as usize);
+        if col < MAX_COLUMNS && row < MAX_ROWS {
+            self.cells[row][col] = c;
+            self.cell_colors[row][col] = self.colors;
+        }
+        self.draw_cell(col, row);
+
+        self.move_forward(1);
+    }
+
+    /// Whether long lines break at the last space rather than at the last column
+    fn wraps_words(&self) -> bool {
+        self.autowrap && self.config.wrap == WrapMode::Word
+    }
+
+    /// Move the word cut at the end of the previous row to the start of the cursor row, after the
+    /// cursor wrapped onto it in the middle of the word
+    ///
+    /// Only right after a wrap, as a host placing the cursor on a continued row has its own
+    /// content there.
+    fn move_word_down(&mut self) {
+        let row = self.row() as usize;
+        let columns = self.columns();
+        if row == 0 || row >= MAX_ROWS || columns == 0 {
+            return;
+        }
+        let above = row - 1;
+        if self.cells[above][columns - 1] == b' ' {
+            return;
+        }
+        // Words as long as the row stay cut
+        let start = match self.cells[above][..columns].iter().rposition(|&c| c == b' ') {
+            Some(space) => space + 1,
+            None => return,
+        };
+
+        let len = columns - start;
+        let (cells, colors) = (self.cells[above], self.cell_colors[above]);
+        self.cells[row][..len].copy_from_slice(&cells[start..columns]);
+        self.cell_colors[row][..len].copy_from_slice(&colors[start..columns]);
+        self.cells[above][start..columns].fill(b' ');
+        self.cell_colors[above][start..columns].fill(CellColors::DEFAULT);
+        self.redraw_row_from(start, above);
+        self.draw_wrap_marker(above);
+        for col in 0..len {
+            self.draw_cell(col, row);
+        }
+        self.move_to(len, row);
+    }
+
+    /// Insert `n` blank characters at the cursor, shifting the rest of the row right
+    fn insert_chars(&mut self, n: usize) {
+        let (col, row) = (self.col(), self.row() as usize);
+        let columns = self.columns();
+        if col >= columns || row >= MAX_ROWS {
+            return;
+        }
+
+        let n = n.min(columns - col);
+        let cells = &mut self.cells[row];
+        cells.copy_within(col..columns - n, col + n);
+        cells[col..col + n].fill(b' ');
+        let colors = &mut self.cell_colors[row];
+        colors.copy_within(col..columns - n, col + n);
+        colors[col..col + n].fill(CellColors::DEFAULT);
+        self.redraw_row_from(col, row);
+    }
+
+    /// Delete `n` characters at the cursor, shifting the rest of the row left
+    fn delete_chars(&mut self, n: usize) {
+        let (col, row) = (self.col(), self.row() as usize);
+        let columns = self.columns();
+        if col >= columns || row >= MAX_ROWS {
+            return;
+        }
+
+        let n = n.min(columns - col);
+        let cells = &mut self.cells[row];
+        cells.copy_within(col + n..columns, col);
+        cells[columns - n..columns].fill(b' ');
+        let colors = &mut self.cell_colors[row];
+        colors.copy_within(col + n..columns, col);
+        colors[columns - n..columns].fill(CellColors::DEFAULT);
+        self.redraw_row_from(col, row);
+    }
+
+    /// Save the primary screen and show a blank alternate screen
+    ///
+    /// With `save_cursor`, the cursor position is restored when leaving the alternate screen.
+    fn enter_alternate_screen(&mut self, save_cursor: bool) {
+        if self.primary.is_some() {
+            return;
+        }
+
+        self.primary = Some(SavedScreen {
+            cells: self.cells,
+            cell_colors: self.cell_colors,
+            continued_rows: self.continued_rows,
+            pos: if save_cursor { Some(self.pos) } else { None },
+        });
+        self.cells = [[b' '; MAX_COLUMNS]; MAX_ROWS];
+        self.cell_colors = [[CellColors::DEFAULT; MAX_COLUMNS]; MAX_ROWS];
+        self.continued_rows = 0;
+        self.redraw();
+    }
+
+    /// Restore the primary screen saved by `enter_alternate_screen`
+    fn leave_alternate_screen(&mut self) {
+        if let Some(primary) = self.primary.take() {
+            self.cells = primary.cells;
+            self.cell_colors = primary.cell_colors;
+            self.continued_rows = primary.continued_rows;
+            if let Some(pos) = primary.pos {
+                self.pos = pos;
+            }
+            self.redraw();
+        }
+    }
+
+    /// Redraw all cells of the terminal
+    fn redraw(&mut self) {
+        for row in 0..self.rows() {
+            self.redraw_row_from(0, row);
+        }
+    }
+
+    /// Redraw the cells of `row`, from `col` to the end of the row
+    fn redraw_row_from(&mut self, col: usize, row: usize) {
+        for col in col..self.columns() {
+            self.draw_cell(col, row);
+        }
+    }
+
+    /// Draw the character of a cell on the screen
+    fn draw_cell(&mut self, col: usize, row: usize) {
+        // The history view stays on screen, the cells are drawn once it ends
+        if self.viewing_history() {
+            return;
+        }
+        let c = match self.cells.get(row).and_then(|cells| cells.get(col)) {
+            Some(&c) => c,
+            None => return,
+        };
+        let pos = self.cell_position(col, row);
+        let style = self.cell_style(self.cell_colors[row][col]);
+
+        // So do the custom glyphs, which aren't cached
+        if let Some(bitmap) = self.custom_glyphs.get(c).copied() {
+            self.draw_bitmap(&bitmap, pos, style);
+            return;
+        }
+
+        // The cached glyph covers the whole cell, background included
+        if self.zoom == 1 {
+            if let Some(pixels) = self.glyphs.get(c, &style) {
+                let top_left = pos - Point::new(0, style.font.baseline as i32);
+                let area = Rectangle::new(top_left, style.font.character_size);
+                self.config
+                    .screen
+                    .fill_contiguous(&area, pixels.iter().copied())
+                    .unwrap();
+                return;
+            }
+        }
+
+        // Erase the previous content of the cell first, in case the style has no background
+        let mut erase = self.erase_style();
+        erase.background_color = style.background_color.or(erase.background_color);
+        self.draw_text(" ", pos, erase);
+        if c != b' ' {
+            self.draw_text(&core::str::from_utf8(&[c]).unwrap_or("?"), pos, style);
+        }
+    }
+
+    /// Draw the character of a cell with `style`, a custom glyph or a character of the font
+    fn draw_char(&mut self, c: u8, pos: Point, style: MonoTextStyle<'f, C>) {
+        match self.custom_glyphs.get(c).copied() {
+            Some(bitmap) => self.draw_bitmap(&bitmap, pos, style),
+            None => self.draw_text(core::str::from_utf8(&[c]).unwrap_or("?"), pos, style),
+        }
+    }
+
+    /// Draw a custom glyph over its whole cell, in the colors of `style`
+    fn draw_bitmap(&mut self, bitmap: &Bitmap, pos: Point, style: MonoTextStyle<'f, C>) {
+        let size = style.font.character_size;
+        let on = style.text_color.unwrap_or(C::WHITE);
+        let off = style
+            .background_color
+            .unwrap_or_else(|| self.erase_style().background_color.unwrap_or(C::BLACK));
+        let bitmap = *bitmap;
+        let colors = (0..size.height as usize).flat_map(move |y| {
+            (0..size.width as usize).map(move |x| if bitmap.is_set(x, y) { on } else { off })
+        });
+        let area = Rectangle::new(pos - Point::new(0, style.font.baseline as i32), size);
+        if self.zoom == 1 {
+            self.config.screen.fill_contiguous(&area, colors).unwrap();
+        } else {
+            Scaled {
+                target: &mut self.config.screen,
+                origin: pos,
+                zoom: self.zoom,
+            }
+            .fill_contiguous(&area, colors)
+            .unwrap();
+        }
+    }
+
+    /// Draw text on the screen, scaled by the zoom level
+    fn draw_text(&mut self, text: &str, pos: Point, style: MonoTextStyle<'f, C>) {
+        let text = Text::new(text, pos, style);
         // TODO: remove unwraps
-        Text::new(
-            &core::str::from_utf8(&[c]).unwrap_or("?"),
-            self.pos,
-            self.config.style,
+        if self.zoom == 1 {
+            text.draw(&mut self.config.screen).unwrap();
+        } else {
+            text.draw(&mut Scaled {
+                target: &mut self.config.screen,
+                origin: pos,
+                zoom: self.zoom,
+            })
+            .unwrap();
+        }
+    }
+
+    /// Position of the text in a cell
+    fn cell_position(&self, col: usize, row: usize) -> Point {
+        let char_size = self.char_size();
+        Point::new(
+            self.min_x() + (col as u32 * char_size.width) as i32,
+            self.min_y() + (row as u32 * char_size.height) as i32,
         )
-        .draw(&mut self.config.screen)
-        .unwrap();
+    }
 
-        self.move_forward(1);
+    /// Redraw the row of the cursor with a `color` background, to make it stand out
+    ///
+    /// The row goes back to normal once it is written to or erased.
+    pub fn highlight_row(&mut self, color: C) {
+        if self.viewing_history() {
+            return;
+        }
+        let row = self.row() as usize;
+        let cells = match self.cells.get(row) {
+            Some(cells) => *cells,
+            None => return,
+        };
+        let colors = self.cell_colors[row];
+
+        for col in 0..self.columns() {
+            // The text keeps its color
+            let mut style = self.cell_style(colors[col]);
+            style.background_color = Some(color);
+            let pos = self.cell_position(col, row);
+            self.draw_char(cells[col], pos, style);
+        }
+    }
+
+    /// Set the cells of `row` from `col` to `text`, only drawing the ones that change
+    ///
+    /// The cursor doesn't move, so a host keeping a copy of the cells can send only what
+    /// changed. The cells take the colors of the next characters. Text past the end of the row is
+    /// dropped, and characters that aren't printable are shown as `?`.
+    pub fn set_cells(&mut self, col: usize, row: usize, text: &[u8]) {
+        if row >= self.rows() {
+            return;
+        }
+        for (col, &c) in (col..self.columns()).zip(text) {
+            let c = if (0x20..0x7F).contains(&c) { c } else { b'?' };
+            if self.cells[row][col] != c || self.cell_colors[row][col] != self.colors {
+                self.cells[row][col] = c;
+                self.cell_colors[row][col] = self.colors;
+                self.draw_cell(col, row);
+            }
+        }
+        // The cursor may have been drawn over
+        if row == self.row() as usize {
+            self.draw_cursor();
+        }
+    }
+
+    /// Render the glyphs `zoom` times larger, for readability at a distance
+    ///
+    /// This divides the number of columns and rows by `zoom`. The cells are kept, but only the
+    /// ones that fit at the new size are shown.
+    pub fn set_zoom(&mut self, zoom: u32) {
+        let zoom = zoom.max(1);
+        if zoom == self.zoom {
+            return;
+        }
+
+        let (col, row) = (self.col(), self.row() as usize);
+        self.zoom = zoom;
+        self.just_wrapped = false;
+        // Keep the cursor in the same cell, if it still fits
+        let col = col.min(self.columns().saturating_sub(1));
+        let row = row.min(self.rows().saturating_sub(1));
+        self.pos = self.cell_position(col, row);
+        self.refresh();
+    }
+
+    /// Change the font and colors, redrawing the terminal with them
+    ///
+    /// The font should have the same character size, or the cells won't fit the area anymore.
+    pub fn set_style(&mut self, style: MonoTextStyle<'f, C>) {
+        self.config.style = style;
+        self.glyphs.clear();
+        self.refresh();
     }
 
-    /// Draw the cursor on the screen
+    /// Clear the terminal area and draw the cells again
+    ///
+    /// This restores the terminal after something else was drawn over it.
+    pub fn refresh(&mut self) {
+        let font = self.config.style.font;
+        let top_left = Point::new(self.min_x(), self.config.offset.y - font.baseline as i32);
+        Rectangle::new(top_left, self.size())
+            .into_styled(PrimitiveStyle::with_fill(
+                self.erase_style().background_color.unwrap_or(C::BLACK),
+            ))
+            .draw(&mut self.config.screen)
+            .unwrap();
+        if self.viewing_history() {
+            self.draw_history_view();
+            return;
+        }
+        self.redraw();
+        self.draw_cursor();
+    }
+
+    /// Size of a cell on the screen
+    fn char_size(&self) -> Size {
+        self.cell_size() * self.zoom
+    }
+
+    /// Size of a cell before the zoom, the line spacing included
+    fn cell_size(&self) -> Size {
+        self.config.style.font.character_size + Size::new(0, self.config.line_spacing)
+    }
+
+    /// Draw the cursor on the screen, unless it is hidden
     fn draw_cursor(&mut self) {
+        if !self.cursor_visible || self.viewing_history() {
+            return;
+        }
         if let Some(color) = self.config.cursor_color {
             let style_builder = MonoTextStyleBuilder::new()
                 .font(self.config.style.font)
@@ -79,82 +752,437 @@ where
             }
             let style = style_builder.build();
 
-            Text::new("_", self.pos, style)
-                .draw(&mut self.config.screen)
-                .unwrap();
+            self.draw_text("_", self.pos, style);
         }
     }
 
     /// Move the cursor backwards
+    ///
+    /// The characters moved over are erased. At the left edge, nothing happens.
     fn move_backward(&mut self, n: i32) {
-        // TODO: clear characters
-        let new_x = self.pos.x - n * self.config.style.font.character_size.width as i32;
-        // Only move backwards if we're not at the left edge, otherwise, do nothing (for now).
-        // TODO: Move to previous line if we're at the left edge
-        if new_x >= self.min_x() {
-            self.pos.x = new_x;
+        let new_x = self.pos.x - n * self.char_size().width as i32;
+        if new_x < self.min_x() {
+            return;
         }
-
+        self.pos.x = new_x;
         self.erase_chars(n);
     }
 
     /// Move the cursor by `count` characters
     ///
-    /// If the cursor ends up outside the bounds of the screen, it will be moved to the next line.
+    /// If the cursor ends up outside the bounds of the screen, it will be moved to the next line,
+    /// or stay on the last column with autowrap off.
     fn move_forward(&mut self, n: i32) {
-        let char_width = self.config.style.font.character_size.width as i32;
+        let char_width = self.char_size().width as i32;
 
         let new_x = self.pos.x + n * char_width;
         if new_x + char_width > self.max_x() {
-            // Going to the next line
-            self.move_next_line();
+            if self.autowrap {
+                // Going to the next line
+                self.wrap_line();
+            } else {
+                self.pos.x = self.min_x() + (self.columns() as i32 - 1).max(0) * char_width;
+            }
         } else {
             self.pos.x = new_x;
         }
     }
 
-    // /// Move to the beginning of the line
-    // fn move_start_line(&mut self) {
-    //     self.pos.x = self.min_x();
-    // }
+    /// Move to the beginning of the row
+    fn move_start_line(&mut self) {
+        self.pos.x = self.min_x();
+    }
+
+    /// Move to the next tab stop, or to the last column if there is none left on the row
+    fn move_to_tab_stop(&mut self) {
+        let col = self.col();
+        let stop = (col / TAB_WIDTH + 1) * TAB_WIDTH;
+        self.move_to(stop, self.row() as usize);
+    }
+
+    /// Returns true if `row` continues the logical line of the previous row
+    ///
+    /// Long lines are wrapped over multiple rows on screen, but still form a single logical
+    /// line.
+    pub fn is_continued_row(&self, row: u32) -> bool {
+        row < 32 && self.continued_rows & (1 << row) != 0
+    }
+
+    /// Current row of the cursor
+    fn row(&self) -> u32 {
+        let char_height = self.char_size().height as i32;
+        ((self.pos.y - self.min_y()) / char_height) as u32
+    }
+
+    /// Current column of the cursor
+    fn col(&self) -> usize {
+        let char_width = self.char_size().width as i32;
+        ((self.pos.x - self.min_x()) / char_width) as usize
+    }
+
+    /// Number of columns of the terminal
+    fn columns(&self) -> usize {
+        let char_width = self.char_size().width;
+        ((self.size().width / char_width) as usize).min(MAX_COLUMNS)
+    }
+
+    /// Number of rows of the terminal
+    fn rows(&self) -> usize {
+        let char_height = self.char_size().height;
+        ((self.size().height / char_height) as usize).min(MAX_ROWS)
+    }
+
+    /// Move to the next line because the current one is full
+    fn wrap_line(&mut self) {
+        self.move_next_line();
+        self.just_wrapped = true;
+
+        let row = self.row();
+        if row < 32 {
+            self.continued_rows |= 1 << row;
+        }
+        // Mark the end of the previous row, to show that the line continues below
+        if row > 0 {
+            self.draw_wrap_marker(row as usize - 1);
+        }
+    }
+
+    /// Draw the wrap marker at the end of `row`, if there is one
+    fn draw_wrap_marker(&mut self, row: usize) {
+        if self.viewing_history() {
+            return;
+        }
+        if let Some(color) = self.config.wrap_marker_color {
+            let baseline = self.config.style.font.baseline * self.zoom;
+            let y = self.cell_position(0, row).y;
+            Rectangle::new(
+                Point::new(self.max_x() - 1, y - baseline as i32),
+                Size::new(1, self.char_size().height),
+            )
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(&mut self.config.screen)
+            .unwrap();
+        }
+    }
 
     /// Move to the next line
     fn move_next_line(&mut self) {
-        let char_height = self.config.style.font.character_size.height as i32;
+        let char_height = self.char_size().height as i32;
 
         // Reset x position to the beginning of the line
         self.pos.x = self.min_x();
 
         let new_y = self.pos.y + char_height;
         if new_y + char_height > self.max_y() {
-            // Looping to the beginning of the screen
-            // TODO: Clear the display or scroll the screen
-            self.pos.y = self.config.offset.y;
+            // The cursor stays on the last row, and the rows above move up
+            self.scroll_up();
         } else {
             self.pos.y = new_y;
+            self.save_row(self.row() as usize);
+            self.erase_chars(FILLER_STRING.len() as i32);
         }
 
-        self.erase_chars(FILLER_STRING.len() as i32);
+        let row = self.row();
+        if row < 32 {
+            self.continued_rows &= !(1 << row);
+        }
+    }
+
+    /// Move the rows up by one, the top row going to the history and the bottom one blank
+    ///
+    /// With a function from `with_pixel_scroll`, the pixels move with the cells. Otherwise the
+    /// rows are drawn again from the cells, which the glyph cache keeps fast.
+    fn scroll_up(&mut self) {
+        let rows = self.rows();
+        if rows == 0 {
+            return;
+        }
+        self.save_row(0);
+        self.cells.copy_within(1..rows, 0);
+        self.cells[rows - 1] = [b' '; MAX_COLUMNS];
+        self.cell_colors.copy_within(1..rows, 0);
+        self.cell_colors[rows - 1] = [CellColors::DEFAULT; MAX_COLUMNS];
+        self.continued_rows >>= 1;
+
+        let scroll = self.config.scroll.filter(|_| self.pixel_scroll);
+        match (scroll, self.config.style.background_color) {
+            // The wrap markers and the highlights move with their rows
+            (Some(scroll), Some(background)) if !self.viewing_history() => {
+                let char_size = self.char_size();
+                let baseline = self.config.style.font.baseline * self.zoom;
+                let area = Rectangle::new(
+                    self.cell_position(0, 0) - Point::new(0, baseline as i32),
+                    Size::new(self.size().width, rows as u32 * char_size.height),
+                );
+                scroll(&mut self.config.screen, &area, char_size.height, background);
+            }
+            _ => {
+                self.redraw();
+                for row in 1..rows {
+                    if self.is_continued_row(row as u32) {
+                        self.draw_wrap_marker(row - 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move the pixels when scrolling, if the builder was given a function for it, or draw the
+    /// rows again
+    ///
+    /// This is on by default, and turning it off compares both with `bench::compare_scroll`.
+    pub fn set_pixel_scroll(&mut self, enabled: bool) {
+        self.pixel_scroll = enabled;
+    }
+
+    /// Keep `row` in the history, before it is erased or leaves the screen
+    ///
+    /// Blank rows and the rows of the alternate screen aren't kept.
+    fn save_row(&mut self, row: usize) {
+        if self.primary.is_some() || row >= MAX_ROWS {
+            return;
+        }
+        let cells = &self.cells[row][..self.columns()];
+        if cells.iter().any(|&c| c != b' ') {
+            self.history.push(cells);
+        }
+    }
+
+    /// Cells of the rows shown on the screen, from the top
+    pub fn visible_rows(&self) -> impl Iterator<Item = &[u8]> {
+        let columns = self.columns();
+        self.cells[..self.rows()].iter().map(move |row| &row[..columns])
+    }
+
+    /// Define the custom glyph `index`, or remove it with `None`, drawing the cells showing it
+    /// again
+    ///
+    /// Returns false if the index isn't below `custom_glyph::MAX_GLYPHS`.
+    pub fn set_custom_glyph(&mut self, index: usize, bitmap: Option<Bitmap>) -> bool {
+        if !self.custom_glyphs.set(index, bitmap) {
+            return false;
+        }
+        let byte = custom_glyph::FIRST_BYTE + index as u8;
+        for row in 0..self.rows() {
+            for col in 0..self.columns() {
+                if self.cells[row][col] == byte {
+                    self.draw_cell(col, row);
+                }
+            }
+        }
+        // The cursor may have been drawn over
+        self.draw_cursor();
+        true
+    }
+
+    pub fn custom_glyphs(&self) -> &CustomGlyphs {
+        &self.custom_glyphs
+    }
+
+    /// Hits and misses of the cache of rendered glyphs
+    pub fn glyph_stats(&self) -> CacheStats {
+        self.glyphs.stats()
+    }
+
+    /// Rows that left the screen, see `Scrollback::row`
+    pub fn history(&self) -> &Scrollback<HISTORY_SIZE> {
+        &self.history
+    }
+
+    /// Search the rows on screen and in the history for `query`, showing the newest match
+    ///
+    /// The matches get a `color` background, and the one shown is inverted. Until `end_search`,
+    /// the screen shows the search instead of following the output, which still updates the
+    /// cells. Returns `None` if the query is empty or too long.
+    pub fn search(&mut self, query: &str, color: C) -> Option<SearchStatus> {
+        let mut search = Search::new(query)?;
+        let (rows, columns) = (self.rows(), self.columns());
+        // From the bottom up, so the matches go from the newest
+        for line in 0..rows {
+            search.find_in_line(line, &self.cells[rows - 1 - line][..columns]);
+        }
+        let mut text = [b' '; MAX_COLUMNS];
+        for age in 0..self.history.len() {
+            if let Some(len) = self.history.row(age, &mut text) {
+                search.find_in_line(rows + age, &text[..len]);
+            }
+        }
+
+        let status = search.status();
+        self.search = Some((search, color));
+        self.view_back = 0;
+        self.show_match();
+        Some(status)
+    }
+
+    /// Show the next older match, wrapping around to the newest one
+    pub fn older_match(&mut self) {
+        if let Some((search, _)) = self.search.as_mut() {
+            search.older();
+            self.show_match();
+        }
+    }
+
+    /// Show the next newer match, wrapping around to the oldest one
+    pub fn newer_match(&mut self) {
+        if let Some((search, _)) = self.search.as_mut() {
+            search.newer();
+            self.show_match();
+        }
+    }
+
+    /// Position in the matches, `None` when not searching
+    pub fn search_status(&self) -> Option<SearchStatus> {
+        self.search.as_ref().map(|(search, _)| search.status())
+    }
+
+    /// Go back to the live rows, which are drawn again with `refresh`
+    pub fn end_search(&mut self) {
+        self.search = None;
+        self.view_back = 0;
+    }
+
+    /// Scroll the search view so the current match is on screen, and draw it
+    fn show_match(&mut self) {
+        let rows = self.rows();
+        let current = self.search.as_ref().and_then(|(search, _)| search.current());
+        if let Some(current) = current {
+            if current.line < self.view_back {
+                self.view_back = current.line;
+            } else if current.line >= self.view_back + rows {
+                self.view_back = current.line + 1 - rows;
+            }
+        }
+        self.draw_history_view();
+    }
+
+    /// Scroll the view `rows` rows up into the history, as far as the oldest row kept
+    ///
+    /// The screen stops following the output until `scroll_forward` gets back to the live rows,
+    /// or new data comes. The cells are still updated meanwhile.
+    pub fn scroll_back(&mut self, rows: usize) {
+        let view_back = (self.view_back + rows).min(self.history.len());
+        if view_back != self.view_back {
+            self.view_back = view_back;
+            self.draw_history_view();
+        }
+    }
+
+    /// Scroll the view `rows` rows down, back to the live rows at the bottom
+    pub fn scroll_forward(&mut self, rows: usize) {
+        if self.view_back == 0 {
+            return;
+        }
+        self.view_back = self.view_back.saturating_sub(rows);
+        self.refresh();
+    }
+
+    /// Rows the view is scrolled back into the history, 0 while showing the live rows
+    pub fn scrolled_back(&self) -> usize {
+        self.view_back
+    }
+
+    /// The screen shows a search or the history instead of following the output
+    fn viewing_history(&self) -> bool {
+        self.search.is_some() || self.view_back > 0
+    }
+
+    /// Draw the rows `view_back` rows up from the live ones, with the matches of the search
+    /// highlighted
+    fn draw_history_view(&mut self) {
+        let (matches, current, len, color) = match self.search.as_ref() {
+            Some((search, color)) => {
+                let matches: heapless::Vec<Match, MAX_MATCHES> =
+                    search.matches().iter().copied().collect();
+                (matches, search.current(), search.query().len(), *color)
+            }
+            None => (heapless::Vec::new(), None, 0, C::BLACK),
+        };
+        let background = self.erase_style().background_color.unwrap_or(C::BLACK);
+        let mut normal = self.config.style;
+        normal.background_color = Some(background);
+        let mut found = normal;
+        found.background_color = Some(color);
+        let mut shown = normal;
+        shown.text_color = Some(background);
+        shown.background_color = Some(normal.text_color.unwrap_or(C::WHITE));
+
+        let (rows, columns) = (self.rows(), self.columns());
+        let mut text = [b' '; MAX_COLUMNS];
+        for row in 0..rows {
+            let line = self.view_back + rows - 1 - row;
+            text.fill(b' ');
+            if line < rows {
+                text = self.cells[rows - 1 - line];
+            } else {
+                self.history.row(line - rows, &mut text);
+            }
+
+            for col in 0..columns {
+                let in_match = |m: &Match| m.line == line && (m.col..m.col + len).contains(&col);
+                let style = if current.as_ref().map_or(false, in_match) {
+                    shown
+                } else if matches.iter().any(in_match) {
+                    found
+                } else {
+                    normal
+                };
+                let pos = self.cell_position(col, row);
+                self.draw_char(text[col], pos, style);
+            }
+        }
     }
 
     fn erase_chars(&mut self, n: i32) {
-        // Erase characters
+        // Clear the cells
+        let (col, row) = (self.col(), self.row() as usize);
+        if row < MAX_ROWS {
+            let end = (col + n as usize).min(MAX_COLUMNS);
+            if col < end {
+                self.cells[row][col..end].fill(b' ');
+                self.cell_colors[row][col..end].fill(CellColors::DEFAULT);
+            }
+        }
+
+        // Erase characters, up to the end of the row
+        if self.viewing_history() {
+            return;
+        }
+        let n = (n as usize).min(self.columns().saturating_sub(col).max(1));
+        self.draw_text(&FILLER_STRING[..n], self.pos, self.erase_style());
+    }
+
+    /// Style used to erase characters
+    fn erase_style(&self) -> MonoTextStyle<'f, C> {
         let color = match self.config.style.background_color {
             Some(color) => color,
             None => C::BLACK,
         };
-        let style = MonoTextStyleBuilder::new()
+        MonoTextStyleBuilder::new()
             .font(self.config.style.font)
             .background_color(color)
-            .build();
-        Text::new(&FILLER_STRING[..n as usize], self.pos, style)
-            .draw(&mut self.config.screen)
-            .unwrap();
+            .build()
+    }
+
+    /// Mutable access to the underlying screen, for drawing outside of the terminal area
+    pub fn screen_mut(&mut self) -> &mut S {
+        &mut self.config.screen
+    }
+
+    /// Size of the terminal area, in pixels
+    fn size(&self) -> Size {
+        if let Some((columns, rows)) = self.config.dimensions {
+            let cell = self.cell_size();
+            return Size::new(columns as u32 * cell.width, rows as u32 * cell.height);
+        }
+        self.config
+            .size
+            .unwrap_or_else(|| self.config.screen.size())
     }
 
     /// Maximum X coordinate for the screen
     fn max_x(&self) -> i32 {
-        self.config.offset.x + self.config.screen.size().width as i32
+        self.config.offset.x + self.size().width as i32
     }
     /// Minimum X coordinate for the screen
     fn min_x(&self) -> i32 {
@@ -163,20 +1191,86 @@ where
 
     /// Maximum Y coordinate for the screen
     fn max_y(&self) -> i32 {
-        self.config.offset.y + self.config.screen.size().height as i32
+        self.min_y() + self.size().height as i32
+    }
+    /// Baseline of the first row
+    ///
+    /// Zoomed glyphs grow around their baseline, so it moves down to keep them in the area.
+    fn min_y(&self) -> i32 {
+        let baseline = self.config.style.font.baseline as i32;
+        self.config.offset.y + baseline * (self.zoom as i32 - 1)
+    }
+}
+
+/// Formatted text, such as with `write!(terminal, "temp: {}", x)`
+///
+/// Characters outside of ASCII are shown as `?`, as the cells only hold ASCII and the custom
+/// glyphs.
+impl<'f, C, S> core::fmt::Write for Terminal<'f, C, S>
+where
+    C: RgbColor,
+    S: DrawTarget<Color = C> + OriginDimensions,
+    <S as embedded_graphics::draw_target::DrawTarget>::Error: core::fmt::Debug,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // Like the data of the host, the characters outside of ASCII go through the decoder
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Draw target that scales everything drawn around `origin`
+struct Scaled<'a, T> {
+    target: &'a mut T,
+    origin: Point,
+    zoom: u32,
+}
+
+impl<T: OriginDimensions> OriginDimensions for Scaled<'_, T> {
+    fn size(&self) -> Size {
+        self.target.size()
+    }
+}
+
+impl<T: DrawTarget + OriginDimensions> DrawTarget for Scaled<'_, T> {
+    type Color = T::Color;
+    type Error = T::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let area = Rectangle::new(point, Size::new(1, 1));
+            self.fill_solid(&area, color)?;
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let top_left = self.origin + (area.top_left - self.origin) * self.zoom as i32;
+        self.target
+            .fill_solid(&Rectangle::new(top_left, area.size * self.zoom), color)
     }
-    // /// Minimum Y coordinate for the screen
-    // fn min_y(&self) -> i32 {
-    //     self.config.offset.y
-    // }
 }
 
 /// Configuration of a `Terminal`
 struct TerminalConfig<'f, C, S> {
     screen: S,
     offset: Point,
+    size: Option<Size>,
     cursor_color: Option<C>,
+    wrap_marker_color: Option<C>,
     style: MonoTextStyle<'f, C>,
+    /// Background given to the builder, set on the style by `build` whatever the order of the calls
+    background_color: Option<C>,
+    wrap: WrapMode,
+    /// Pixels between two rows, before the zoom
+    line_spacing: u32,
+    /// Columns and rows, instead of as many as fit in the area
+    dimensions: Option<(usize, usize)>,
+    /// Moves the pixels of an area up, clearing the rows left at the bottom
+    scroll: Option<fn(&mut S, &Rectangle, u32, C)>,
 }
 
 /// Builder for the `Terminal`
@@ -194,12 +1288,19 @@ where
             config: TerminalConfig {
                 screen,
                 offset: Point::new(0, 0),
+                size: None,
                 cursor_color: None,
+                wrap_marker_color: None,
                 style: MonoTextStyleBuilder::new()
                     .font(&FONT_6X10)
                     .text_color(C::RED)
                     .background_color(C::BLACK)
                     .build(),
+                background_color: None,
+                wrap: WrapMode::Character,
+                line_spacing: 0,
+                dimensions: None,
+                scroll: None,
             },
         }
     }
@@ -209,20 +1310,101 @@ where
         self
     }
 
+    /// Restrict the terminal to an area of `size` pixels, instead of the whole screen
+    pub fn with_size(mut self, size: Size) -> Self {
+        self.config.size = Some(size);
+        self
+    }
+
     pub fn with_cursor(mut self, color: C) -> Self {
         self.config.cursor_color = Some(color);
         self
     }
 
+    /// Mark the right edge of rows that wrap onto the next one with a line of `color`
+    pub fn with_wrap_marker(mut self, color: C) -> Self {
+        self.config.wrap_marker_color = Some(color);
+        self
+    }
+
     pub fn with_style(mut self, style: MonoTextStyle<'f, C>) -> Self {
         self.config.style = style;
         self
     }
 
-    pub fn build(self) -> Terminal<'f, C, S> {
+    /// Fill the terminal with `color`, instead of the background of the style
+    pub fn with_background(mut self, color: C) -> Self {
+        self.config.background_color = Some(color);
+        self
+    }
+
+    /// Choose what happens to the text going past the last column, `WrapMode::Character` by
+    /// default
+    ///
+    /// The host can still turn the wrapping off and on with DECAWM, `\x1b[?7l` and `\x1b[?7h`.
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.config.wrap = wrap;
+        self
+    }
+
+    /// Leave `pixels` between two rows, scaled with the zoom
+    pub fn with_line_spacing(mut self, pixels: u32) -> Self {
+        self.config.line_spacing = pixels;
+        self
+    }
+
+    /// Size the terminal in cells rather than from the area, up to `MAX_COLUMNS` and `MAX_ROWS`
+    ///
+    /// This takes the place of `with_size`, the area being as large as the cells.
+    pub fn with_dimensions(mut self, columns: usize, rows: usize) -> Self {
+        self.config.dimensions = Some((columns.min(MAX_COLUMNS), rows.min(MAX_ROWS)));
+        self
+    }
+
+    /// Scroll by moving the pixels of the screen with `scroll`, such as `Framebuffer::scroll_up`,
+    /// instead of drawing all the rows again
+    ///
+    /// `scroll` gets the area of the rows, the pixels to move it up by and the background color.
+    pub fn with_pixel_scroll(mut self, scroll: fn(&mut S, &Rectangle, u32, C)) -> Self {
+        self.config.scroll = Some(scroll);
+        self
+    }
+
+    pub fn build(mut self) -> Terminal<'f, C, S> {
+        if let Some(color) = self.config.background_color {
+            self.config.style.background_color = Some(color);
+        }
         Terminal {
             pos: self.config.offset.clone(),
+            autowrap: self.config.wrap != WrapMode::Truncate,
             config: self.config,
+            continued_rows: 0,
+            cells: [[b' '; MAX_COLUMNS]; MAX_ROWS],
+            cell_colors: [[CellColors::DEFAULT; MAX_COLUMNS]; MAX_ROWS],
+            colors: CellColors::DEFAULT,
+            ansi_colors: [
+                C::BLACK,
+                C::RED,
+                C::GREEN,
+                C::YELLOW,
+                C::BLUE,
+                C::MAGENTA,
+                C::CYAN,
+                C::WHITE,
+            ],
+            insert_mode: false,
+            cursor_visible: true,
+            just_wrapped: false,
+            parser: Parser::new(),
+            primary: None,
+            zoom: 1,
+            history: Scrollback::new(),
+            glyphs: GlyphCache::new(),
+            search: None,
+            view_back: 0,
+            custom_glyphs: CustomGlyphs::new(),
+            utf8: None,
+            pixel_scroll: true,
         }
     }
 }
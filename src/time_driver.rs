@@ -0,0 +1,112 @@
+//! Embassy time driver
+//!
+//! Runs the timers of `embassy-time` on the 1MHz counter of the RP2040 timer, so async tasks can
+//! wait with `Timer::after` next to the rp2040-hal drivers. Alarm 0 of the timer is taken for it.
+
+use crate::pac::{self, interrupt};
+use core::cell::Cell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::interrupt::Mutex;
+use embassy_time_driver::{AlarmHandle, Driver};
+
+/// Callback of the alarm, and when it should run
+struct Alarm {
+    timestamp: Cell<u64>,
+    callback: Cell<Option<(fn(*mut ()), *mut ())>>,
+}
+
+// Note (safety): the alarm is only reached in critical sections, and the context pointer is only
+// given back to the executor which set it
+unsafe impl Send for Alarm {}
+
+struct TimerDriver {
+    alarm: Mutex<Alarm>,
+    allocated: AtomicBool,
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: TimerDriver = TimerDriver {
+    alarm: Mutex::new(Alarm {
+        timestamp: Cell::new(u64::MAX),
+        callback: Cell::new(None),
+    }),
+    allocated: AtomicBool::new(false),
+});
+
+/// Start the alarm interrupt, once the timer is out of reset such as after `Timer::new`
+pub fn init() {
+    // Note (safety): only the alarm 0 bits are changed, and its interrupt is handled here
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    timer.inte.modify(|_, w| w.alarm_0().set_bit());
+    unsafe { pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_0) };
+}
+
+fn timer() -> &'static pac::timer::RegisterBlock {
+    // Note (safety): the counter is only read, and alarm 0 only used by this driver
+    unsafe { &*pac::TIMER::ptr() }
+}
+
+impl Driver for TimerDriver {
+    fn now(&self) -> u64 {
+        let timer = timer();
+        // The high word is read again, in case the low word wrapped in between
+        loop {
+            let high = timer.timerawh.read().bits();
+            let low = timer.timerawl.read().bits();
+            if timer.timerawh.read().bits() == high {
+                return (high as u64) << 32 | low as u64;
+            }
+        }
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        // The Cortex-M0+ has no atomic swap
+        cortex_m::interrupt::free(|_| {
+            if self.allocated.load(Ordering::Relaxed) {
+                None
+            } else {
+                self.allocated.store(true, Ordering::Relaxed);
+                Some(AlarmHandle::new(0))
+            }
+        })
+    }
+
+    fn set_alarm_callback(&self, _alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        cortex_m::interrupt::free(|cs| self.alarm.borrow(cs).callback.set(Some((callback, ctx))));
+    }
+
+    fn set_alarm(&self, _alarm: AlarmHandle, timestamp: u64) -> bool {
+        cortex_m::interrupt::free(|cs| {
+            self.alarm.borrow(cs).timestamp.set(timestamp);
+            let timer = timer();
+            // Only the low word is compared, the interrupt arms it again until the time is due
+            timer.alarm0.write(|w| unsafe { w.bits(timestamp as u32) });
+            if timestamp <= self.now() {
+                // Too late, the executor runs the callback itself
+                timer.armed.write(|w| unsafe { w.bits(1) });
+                self.alarm.borrow(cs).timestamp.set(u64::MAX);
+                false
+            } else {
+                true
+            }
+        })
+    }
+}
+
+#[allow(non_snake_case)]
+#[interrupt]
+fn TIMER_IRQ_0() {
+    let timer = timer();
+    timer.intr.write(|w| w.alarm_0().set_bit());
+    cortex_m::interrupt::free(|cs| {
+        let alarm = DRIVER.alarm.borrow(cs);
+        let timestamp = alarm.timestamp.get();
+        if timestamp <= DRIVER.now() {
+            alarm.timestamp.set(u64::MAX);
+            if let Some((callback, ctx)) = alarm.callback.get() {
+                callback(ctx);
+            }
+        } else {
+            timer.alarm0.write(|w| unsafe { w.bits(timestamp as u32) });
+        }
+    });
+}
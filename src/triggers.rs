@@ -0,0 +1,215 @@
+//! Pattern triggers on the serial data
+//!
+//! Watches the text received from the host for patterns, and reports which triggers fired so
+//! the application can act on them. Patterns are substrings, where `?` matches any character
+//! and a leading `^` only matches at the start of a line.
+
+use crate::flash::{self, checksum, PAGE_SIZE, SECTOR_SIZE};
+use heapless::{String, Vec};
+
+/// Maximum number of triggers
+pub const MAX_TRIGGERS: usize = 8;
+/// Maximum length of a pattern
+pub const MAX_PATTERN: usize = 24;
+
+/// Sector where the triggers are stored, from the start of the flash
+pub const TRIGGERS_OFFSET: u32 = 0x1F_0000;
+
+const MAGIC: [u8; 4] = *b"TRG1";
+
+/// What happens when a pattern matches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerAction {
+    /// Mark the line on the display
+    Highlight,
+    /// Light the LED for a moment
+    Led,
+    /// Send a `!TRG <index>` line to the host
+    Marker,
+}
+
+impl TriggerAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "highlight" => Some(TriggerAction::Highlight),
+            "led" => Some(TriggerAction::Led),
+            "marker" => Some(TriggerAction::Marker),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TriggerAction::Highlight => "highlight",
+            TriggerAction::Led => "led",
+            TriggerAction::Marker => "marker",
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(TriggerAction::Highlight),
+            1 => Some(TriggerAction::Led),
+            2 => Some(TriggerAction::Marker),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            TriggerAction::Highlight => 0,
+            TriggerAction::Led => 1,
+            TriggerAction::Marker => 2,
+        }
+    }
+}
+
+/// Pattern and its action
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trigger {
+    pub pattern: String<MAX_PATTERN>,
+    pub action: TriggerAction,
+}
+
+impl Trigger {
+    /// Returns true if `line` ends with the pattern
+    fn matches_end(&self, line: &[u8]) -> bool {
+        let (anchored, pattern) = match self.pattern.as_bytes() {
+            [b'^', rest @ ..] => (true, rest),
+            pattern => (false, pattern),
+        };
+        if pattern.is_empty() || line.len() < pattern.len() {
+            return false;
+        }
+        if anchored && line.len() != pattern.len() {
+            return false;
+        }
+
+        let end = &line[line.len() - pattern.len()..];
+        pattern
+            .iter()
+            .zip(end.iter())
+            .all(|(&p, &c)| p == b'?' || p == c)
+    }
+}
+
+/// Set of triggers, and the line they are matched against
+pub struct Triggers {
+    triggers: Vec<Trigger, MAX_TRIGGERS>,
+    line: Vec<u8, 64>,
+}
+
+impl Triggers {
+    pub const fn new() -> Self {
+        Self {
+            triggers: Vec::new(),
+            line: Vec::new(),
+        }
+    }
+
+    /// Add a trigger, returning false if there are too many or the pattern is too long
+    pub fn add(&mut self, pattern: &str, action: TriggerAction) -> bool {
+        let mut trigger = Trigger {
+            pattern: String::new(),
+            action,
+        };
+        !pattern.is_empty()
+            && trigger.pattern.push_str(pattern).is_ok()
+            && self.triggers.push(trigger).is_ok()
+    }
+
+    /// Remove the trigger at `index`
+    pub fn remove(&mut self, index: usize) -> Option<Trigger> {
+        let removed = self.triggers.get(index)?.clone();
+        self.triggers = self
+            .triggers
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != index)
+            .map(|(_, trigger)| trigger.clone())
+            .collect();
+        Some(removed)
+    }
+
+    pub fn clear(&mut self) {
+        self.triggers.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trigger> {
+        self.triggers.iter()
+    }
+
+    /// Handle a received byte, returning the indices of the triggers that fired as bits
+    ///
+    /// A pattern fires once, on the byte that completes it.
+    pub fn feed(&mut self, byte: u8) -> u8 {
+        if byte == b'\r' || byte == b'\n' {
+            self.line.clear();
+            return 0;
+        }
+        if self.line.is_full() {
+            // Keep the end of long lines, anchored patterns can't match anymore
+            self.line.copy_within(1.., 0);
+            self.line.pop();
+        }
+        let _ = self.line.push(byte);
+
+        self.triggers
+            .iter()
+            .enumerate()
+            .filter(|(_, trigger)| trigger.matches_end(&self.line))
+            .fold(0, |fired, (index, _)| fired | 1 << index)
+    }
+
+    /// Load the triggers stored in flash, if any
+    pub fn load(&mut self) {
+        self.triggers.clear();
+        let sector = flash::read(TRIGGERS_OFFSET, PAGE_SIZE as usize);
+        if sector[..4] != MAGIC {
+            return;
+        }
+        let len = u16::from_le_bytes([sector[4], sector[5]]) as usize;
+        let sum = u16::from_le_bytes([sector[6], sector[7]]);
+        let data = match sector.get(8..8 + len) {
+            Some(data) if checksum(data) == sum => data,
+            _ => return,
+        };
+
+        // Each trigger is its action, the length of its pattern, and its pattern
+        let mut data = data;
+        while let [action, len, rest @ ..] = data {
+            let len = *len as usize;
+            let (pattern, action) = match (rest.get(..len), TriggerAction::from_byte(*action)) {
+                (Some(pattern), Some(action)) => (pattern, action),
+                _ => return,
+            };
+            if let Ok(pattern) = core::str::from_utf8(pattern) {
+                self.add(pattern, action);
+            }
+            data = &rest[len..];
+        }
+    }
+
+    /// Store the triggers in flash
+    pub fn save(&self) {
+        let mut page = [0xFF; PAGE_SIZE as usize];
+        let mut len = 0;
+        for trigger in &self.triggers {
+            let pattern = trigger.pattern.as_bytes();
+            page[8 + len] = trigger.action.to_byte();
+            page[9 + len] = pattern.len() as u8;
+            page[10 + len..][..pattern.len()].copy_from_slice(pattern);
+            len += 2 + pattern.len();
+        }
+        page[..4].copy_from_slice(&MAGIC);
+        page[4..6].copy_from_slice(&(len as u16).to_le_bytes());
+        let sum = checksum(&page[8..8 + len]);
+        page[6..8].copy_from_slice(&sum.to_le_bytes());
+
+        // Note (safety): the sector is reserved for the triggers
+        unsafe {
+            flash::erase(TRIGGERS_OFFSET, SECTOR_SIZE);
+            flash::program(TRIGGERS_OFFSET, &page);
+        }
+    }
+}
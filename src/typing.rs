@@ -0,0 +1,119 @@
+//! Typing speed test
+//!
+//! Compares the characters typed on the host with a prompt, and measures the speed in words
+//! per minute and the accuracy. A word is 5 characters, as usual for typing tests.
+
+use heapless::Vec;
+
+/// Longest prompt, longer prompts are cut
+pub const MAX_PROMPT: usize = 120;
+
+/// State of a character of the prompt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharState {
+    /// Not typed yet
+    Pending,
+    Correct,
+    Wrong,
+}
+
+/// Typing test against a prompt
+pub struct TypingTest {
+    prompt: &'static [u8],
+    /// Whether each character typed so far matches the prompt
+    typed: Vec<bool, MAX_PROMPT>,
+    /// Characters typed, including the ones erased since
+    keystrokes: u32,
+    /// Characters typed that didn't match the prompt, including the ones corrected since
+    errors: u32,
+    /// Time of the first character, in microseconds
+    start_us: Option<u64>,
+    /// Time of the last character of the prompt, in microseconds
+    end_us: Option<u64>,
+}
+
+impl TypingTest {
+    pub fn new(prompt: &'static [u8]) -> Self {
+        Self {
+            prompt: &prompt[..prompt.len().min(MAX_PROMPT)],
+            typed: Vec::new(),
+            keystrokes: 0,
+            errors: 0,
+            start_us: None,
+            end_us: None,
+        }
+    }
+
+    pub fn prompt(&self) -> &'static [u8] {
+        self.prompt
+    }
+
+    /// Index of the next character to type
+    pub fn position(&self) -> usize {
+        self.typed.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.end_us.is_some()
+    }
+
+    pub fn state(&self, index: usize) -> CharState {
+        match self.typed.get(index) {
+            Some(true) => CharState::Correct,
+            Some(false) => CharState::Wrong,
+            None => CharState::Pending,
+        }
+    }
+
+    /// Handle a byte received from the host
+    ///
+    /// Backspace and delete erase the last character, other control characters are ignored.
+    /// The clock starts on the first character and stops on the last one of the prompt.
+    pub fn push(&mut self, byte: u8, now_us: u64) {
+        if self.is_done() {
+            return;
+        }
+        match byte {
+            0x08 | 0x7F => {
+                self.typed.pop();
+            }
+            0x20..=0x7E => {
+                let correct = self.prompt.get(self.typed.len()) == Some(&byte);
+                if self.typed.push(correct).is_err() {
+                    return;
+                }
+                self.start_us.get_or_insert(now_us);
+                self.keystrokes += 1;
+                if !correct {
+                    self.errors += 1;
+                }
+                if self.typed.len() == self.prompt.len() {
+                    self.end_us = Some(now_us);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Speed at `now_us`, in words per minute of correct characters
+    pub fn wpm(&self, now_us: u64) -> u32 {
+        let start_us = match self.start_us {
+            Some(start_us) => start_us,
+            None => return 0,
+        };
+        let elapsed_us = self.end_us.unwrap_or(now_us).saturating_sub(start_us);
+        if elapsed_us == 0 {
+            return 0;
+        }
+        let correct = self.typed.iter().filter(|&&correct| correct).count() as u64;
+        (correct * 60_000_000 / (5 * elapsed_us)) as u32
+    }
+
+    /// Share of the characters typed that matched the prompt, in percent
+    pub fn accuracy(&self) -> u32 {
+        if self.keystrokes == 0 {
+            return 100;
+        }
+        (self.keystrokes - self.errors) * 100 / self.keystrokes
+    }
+}
@@ -0,0 +1,169 @@
+//! USB to UART bridge
+//!
+//! Drives UART0 with the line coding set by the host on the USB serial port, so the board
+//! behaves like a USB serial adapter: changing the baud rate, parity or stop bits on the host
+//! changes them on the wire. It can also run at a fixed rate, for a UART used by the firmware
+//! itself.
+
+use crate::{hal, pac};
+use hal::gpio::pin::{bank0, FunctionUart, Pin, PinId};
+use usbd_serial::{LineCoding, ParityType, StopBits};
+
+pub type TxPin = Pin<bank0::Gpio0, FunctionUart>;
+pub type RxPin = Pin<bank0::Gpio1, FunctionUart>;
+
+/// GPIOs UART0 can send on: GPIO0, or GPIO28 for boards using GPIO0 for something else
+pub trait Uart0Tx: PinId {}
+
+impl Uart0Tx for bank0::Gpio0 {}
+impl Uart0Tx for bank0::Gpio28 {}
+
+/// Line coding applied to the UART
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Coding {
+    data_rate: u32,
+    data_bits: u8,
+    parity: ParityType,
+    stop_bits: StopBits,
+}
+
+/// UART0 following the line coding of the host
+pub struct UartBridge {
+    uart: pac::UART0,
+    /// Frequency of the peripheral clock, in Hz
+    clock_freq: u32,
+    coding: Option<Coding>,
+}
+
+impl UartBridge {
+    /// Take UART0 out of reset, it stays disabled until the first `apply` or
+    /// `set_data_rate`
+    ///
+    /// The pins are only taken to make sure they are configured for the UART.
+    pub fn new<Tx: Uart0Tx>(
+        uart: pac::UART0,
+        _tx: Pin<Tx, FunctionUart>,
+        _rx: RxPin,
+        resets: &mut pac::RESETS,
+        clock_freq: u32,
+    ) -> Self {
+        resets.reset.modify(|_, w| w.uart0().set_bit());
+        resets.reset.modify(|_, w| w.uart0().clear_bit());
+        while resets.reset_done.read().uart0().bit_is_clear() {}
+
+        Self {
+            uart,
+            clock_freq,
+            coding: None,
+        }
+    }
+
+    /// Apply the line coding set by the host, if it changed
+    ///
+    /// 1.5 stop bits aren't supported by the UART and become 2, and data bits outside of 5 to 8
+    /// become 8.
+    pub fn apply(&mut self, line_coding: &LineCoding) {
+        let coding = Coding {
+            data_rate: line_coding.data_rate().max(1),
+            data_bits: line_coding.data_bits(),
+            parity: line_coding.parity_type(),
+            stop_bits: line_coding.stop_bits(),
+        };
+        self.set_coding(coding);
+    }
+
+    /// Send and receive at `data_rate`, with 8 data bits, no parity and 1 stop bit
+    pub fn set_data_rate(&mut self, data_rate: u32) {
+        self.set_coding(Coding {
+            data_rate: data_rate.max(1),
+            data_bits: 8,
+            parity: ParityType::None,
+            stop_bits: StopBits::One,
+        });
+    }
+
+    fn set_coding(&mut self, coding: Coding) {
+        if self.coding == Some(coding) {
+            return;
+        }
+        self.coding = Some(coding);
+
+        // See section 4.2.7.1 of the RP2040 datasheet
+        let divisor = 8 * self.clock_freq as u64 / coding.data_rate as u64;
+        let (integer, fraction) = match divisor >> 7 {
+            0 => (1, 0),
+            integer if integer >= 0xFFFF => (0xFFFF, 0),
+            integer => (integer as u16, ((divisor & 0x7F) as u8 + 1) / 2),
+        };
+        // The stick parity bit turns odd and even into mark and space
+        let (parity, even, stick) = match coding.parity {
+            ParityType::None => (false, false, false),
+            ParityType::Odd => (true, false, false),
+            ParityType::Event => (true, true, false),
+            ParityType::Mark => (true, false, true),
+            ParityType::Space => (true, true, true),
+        };
+        let word_length = match coding.data_bits {
+            bits @ 5..=8 => bits - 5,
+            _ => 3,
+        };
+
+        let uart = &self.uart;
+        // The UART must be disabled and idle while its format changes
+        uart.uartcr.write(|w| unsafe { w.bits(0) });
+        while uart.uartfr.read().busy().bit_is_set() {}
+        uart.uartibrd
+            .write(|w| unsafe { w.baud_divint().bits(integer) });
+        uart.uartfbrd
+            .write(|w| unsafe { w.baud_divfrac().bits(fraction) });
+        // Writing the line control register also latches the divisor
+        uart.uartlcr_h.write(|w| unsafe {
+            w.wlen()
+                .bits(word_length)
+                .fen()
+                .set_bit()
+                .stp2()
+                .bit(coding.stop_bits != StopBits::One)
+                .pen()
+                .bit(parity)
+                .eps()
+                .bit(even)
+                .sps()
+                .bit(stick)
+        });
+        uart.uartcr
+            .write(|w| w.uarten().set_bit().txe().set_bit().rxe().set_bit());
+    }
+
+    /// Read the bytes received on the UART, returning how many were read
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let uart = &self.uart;
+        let mut count = 0;
+        for byte in buf.iter_mut() {
+            if uart.uartfr.read().rxfe().bit_is_set() {
+                break;
+            }
+            // Bytes with framing or parity errors are passed on, as an adapter would
+            *byte = uart.uartdr.read().data().bits();
+            count += 1;
+        }
+        count
+    }
+
+    /// Queue bytes to send on the UART, returning how many fit in its FIFO
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        if self.coding.is_none() {
+            return 0;
+        }
+        let uart = &self.uart;
+        let mut count = 0;
+        for &byte in data {
+            if uart.uartfr.read().txff().bit_is_set() {
+                break;
+            }
+            uart.uartdr.write(|w| unsafe { w.data().bits(byte) });
+            count += 1;
+        }
+        count
+    }
+}
@@ -0,0 +1,64 @@
+//! Low power state for unused pins
+//!
+//! Floating inputs draw current and pick up noise, so the pins that aren't used are
+//! disconnected from the peripherals, with their output and input buffer disabled and a pull
+//! resistor holding them at a defined level.
+
+use crate::pac;
+use crate::pin_info::GPIO_COUNT;
+
+/// Pins of a board and application, as bits by GPIO number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinProfile {
+    /// Pins in use or wired to something on the board, which are left alone
+    pub used: u32,
+    /// Unused pins pulled up instead of down, for active low parts wired to them
+    pub pull_up: u32,
+}
+
+impl PinProfile {
+    /// Pico board: SMPS mode, VBUS detect, LED and VSYS monitor
+    pub const PICO: Self = Self {
+        used: 1 << 23 | 1 << 24 | 1 << 25 | 1 << 29,
+        pull_up: 0,
+    };
+
+    /// Pico with the Pico Display: buttons, panel and backlight
+    ///
+    /// The RGB LED of the display is active low, so its pins are pulled up to keep it off.
+    pub const PICO_DISPLAY: Self = Self {
+        used: Self::PICO.used | 0xF << 12 | 0xF << 16 | 1 << 20,
+        pull_up: 0x7 << 6,
+    };
+
+    /// Mark `gpios` as used by the application
+    pub const fn with_used(self, gpios: u32) -> Self {
+        Self {
+            used: self.used | gpios,
+            pull_up: self.pull_up & !gpios,
+        }
+    }
+
+    /// Put the unused pins in a low power state
+    pub fn park_unused(&self) {
+        // Note (safety): only the registers of unused pins are written, which no driver owns
+        let (io, pads) = unsafe { (&*pac::IO_BANK0::ptr(), &*pac::PADS_BANK0::ptr()) };
+        for gpio in (0..GPIO_COUNT).filter(|gpio| self.used & 1 << gpio == 0) {
+            let pull_up = self.pull_up & 1 << gpio != 0;
+            // No function, so the pin isn't driven
+            io.gpio[gpio]
+                .gpio_ctrl
+                .write(|w| unsafe { w.funcsel().bits(0x1F) });
+            pads.gpio[gpio].write(|w| {
+                w.ie()
+                    .clear_bit()
+                    .od()
+                    .set_bit()
+                    .pue()
+                    .bit(pull_up)
+                    .pde()
+                    .bit(!pull_up)
+            });
+        }
+    }
+}
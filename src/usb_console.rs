@@ -0,0 +1,163 @@
+//! USB serial console
+//!
+//! Wraps the USB serial port with non-blocking writes, see `PendingTx`. Small writes can be
+//! held back and sent together, see `FlushPolicy`, so they take fewer USB packets.
+
+use crate::usb_tx::PendingTx;
+use usb_device::{bus::UsbBus, UsbError};
+use usbd_serial::{LineCoding, SerialPort};
+
+/// Number of bytes that can wait to be sent to the host
+pub const TX_BUFFER_SIZE: usize = 256;
+
+/// Time after which unsent bytes are dropped if the host doesn't read them, in microseconds
+const TX_TIMEOUT: u64 = 100_000;
+
+/// Longest time bytes are held back by the flush policy, in microseconds
+const MAX_HOLD: u64 = 5_000;
+
+/// Baud rate of the port when it is closed to ask for the bootloader, as with Arduino boards
+pub const TOUCH_BAUD_RATE: u32 = 1200;
+
+/// When the bytes written to the console are sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// On every write
+    Immediate,
+    /// When a new line is written
+    Newline,
+    /// Once this many bytes are waiting
+    Size(usize),
+    /// Only on `flush`
+    Manual,
+}
+
+/// Console over a USB serial port
+pub struct UsbConsole<'a, B: UsbBus> {
+    serial: SerialPort<'a, B>,
+    tx: PendingTx<TX_BUFFER_SIZE>,
+    policy: FlushPolicy,
+    /// Time the oldest pending byte was written, in microseconds
+    pending_since: u64,
+    /// A flush is in progress, and continues on the next polls
+    flushing: bool,
+    /// The host had the port open (DTR set) on the previous check
+    dtr: bool,
+}
+
+impl<'a, B: UsbBus> UsbConsole<'a, B> {
+    pub fn new(serial: SerialPort<'a, B>) -> Self {
+        Self {
+            serial,
+            tx: PendingTx::new(TX_TIMEOUT),
+            policy: FlushPolicy::Immediate,
+            pending_since: 0,
+            flushing: false,
+            dtr: false,
+        }
+    }
+
+    /// The underlying serial port, to poll it with the USB device
+    pub fn port(&mut self) -> &mut SerialPort<'a, B> {
+        &mut self.serial
+    }
+
+    /// Line coding set by the host
+    pub fn line_coding(&self) -> &LineCoding {
+        self.serial.line_coding()
+    }
+
+    /// Returns true once the host closes the port while it is set to `TOUCH_BAUD_RATE`
+    ///
+    /// This is how the Arduino tools ask a board to reboot into its bootloader before flashing
+    /// it. Call it after each poll of the USB device.
+    pub fn bootloader_touched(&mut self) -> bool {
+        let dtr = self.serial.dtr();
+        let closed = self.dtr && !dtr;
+        self.dtr = dtr;
+        closed && self.serial.line_coding().data_rate() == TOUCH_BAUD_RATE
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+    }
+
+    /// Read bytes received from the host
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, UsbError> {
+        self.serial.read(buf)
+    }
+
+    /// Queue bytes for the host, sending them as the flush policy says
+    ///
+    /// Returns the number of bytes accepted, see `PendingTx::write`.
+    pub fn write(&mut self, data: &[u8], now: u64) -> Result<usize, UsbError> {
+        if self.policy == FlushPolicy::Immediate {
+            return self.tx.write(&mut self.serial, data, now);
+        }
+
+        // Make room first, so the new bytes stay behind the pending ones
+        if self.tx.len() + data.len() > TX_BUFFER_SIZE {
+            match self.flush(now) {
+                Ok(()) | Err(UsbError::WouldBlock) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        if self.tx.is_empty() {
+            self.pending_since = now;
+        }
+        let count = self.tx.queue(data, now);
+        if count == 0 && !data.is_empty() {
+            return Err(UsbError::WouldBlock);
+        }
+
+        let flush = match self.policy {
+            FlushPolicy::Newline => data[..count].contains(&b'\n'),
+            FlushPolicy::Size(size) => self.tx.len() >= size,
+            FlushPolicy::Immediate | FlushPolicy::Manual => false,
+        };
+        if flush {
+            match self.flush(now) {
+                Ok(()) | Err(UsbError::WouldBlock) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Send the pending bytes, see `PendingTx::flush`
+    ///
+    /// The bytes that don't fit in the endpoint are sent by the next calls to `poll`.
+    pub fn flush(&mut self, now: u64) -> Result<(), UsbError> {
+        let result = self.tx.flush(&mut self.serial, now);
+        self.flushing = !self.tx.is_empty();
+        result
+    }
+
+    /// Continue sending, to call regularly
+    ///
+    /// Bytes held back by the flush policy are sent once they waited for a few milliseconds,
+    /// except with `FlushPolicy::Manual`.
+    pub fn poll(&mut self, now: u64) -> Result<(), UsbError> {
+        let due = match self.policy {
+            FlushPolicy::Immediate => true,
+            FlushPolicy::Manual => self.flushing,
+            FlushPolicy::Newline | FlushPolicy::Size(_) => {
+                self.flushing || now.saturating_sub(self.pending_since) >= MAX_HOLD
+            }
+        };
+        if due && !self.tx.is_empty() {
+            self.flush(now)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Number of bytes that can be queued without dropping any
+    pub fn available(&self) -> usize {
+        TX_BUFFER_SIZE - self.tx.len()
+    }
+}
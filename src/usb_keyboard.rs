@@ -0,0 +1,101 @@
+//! USB HID keyboard
+//!
+//! Wraps a HID keyboard interface, which sits next to the serial console on the same USB
+//! device, so the board can be a macro pad and keep its console. Keys are held with `press`, or
+//! queued with `tap` and `type_text` and sent as the host reads the reports.
+
+use crate::keyboard::KeyPress;
+use heapless::Deque;
+use usb_device::{bus::UsbBus, class_prelude::UsbBusAllocator, UsbError};
+use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
+use usbd_hid::hid_class::HIDClass;
+
+/// Reports that can wait to be sent, two for each key tapped
+pub const QUEUE_SIZE: usize = 64;
+
+/// Interval at which the host reads the reports, in milliseconds
+const POLL_MS: u8 = 10;
+
+/// Keyboard over a USB HID interface
+pub struct UsbKeyboard<'a, B: UsbBus> {
+    hid: HIDClass<'a, B>,
+    /// Key presses to send, the default one releasing the keys
+    queue: Deque<KeyPress, QUEUE_SIZE>,
+    /// Key held with `press`, sent once the queue is empty
+    held: KeyPress,
+    /// Last report the host accepted
+    sent: Option<KeyPress>,
+}
+
+impl<'a, B: UsbBus> UsbKeyboard<'a, B> {
+    /// Add the keyboard interface to the USB device of `bus`
+    ///
+    /// Like the other classes, it has to be created before the device is built.
+    pub fn new(bus: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            hid: HIDClass::new(bus, KeyboardReport::desc(), POLL_MS),
+            queue: Deque::new(),
+            held: KeyPress::default(),
+            sent: None,
+        }
+    }
+
+    /// The underlying HID class, to poll it with the USB device
+    pub fn class(&mut self) -> &mut HIDClass<'a, B> {
+        &mut self.hid
+    }
+
+    /// Hold `key` until `release`, such as while a button is down
+    pub fn press(&mut self, key: KeyPress) {
+        self.held = key;
+    }
+
+    /// Release the key held with `press`
+    pub fn release(&mut self) {
+        self.held = KeyPress::default();
+    }
+
+    /// Queue a press and release of `key`, returning false if the queue is full
+    pub fn tap(&mut self, key: KeyPress) -> bool {
+        if self.queue.capacity() - self.queue.len() < 2 {
+            return false;
+        }
+        let _ = self.queue.push_back(key);
+        let _ = self.queue.push_back(KeyPress::default());
+        true
+    }
+
+    /// Queue the taps typing `text` on a US layout, returning the number of bytes queued
+    ///
+    /// Characters without a key are skipped. Typing stops at the first byte that doesn't fit.
+    pub fn type_text(&mut self, text: &[u8]) -> usize {
+        for (count, &c) in text.iter().enumerate() {
+            match KeyPress::from_ascii(c) {
+                // A repeated key has to be released in between, which `tap` does
+                Some(key) if !self.tap(key) => return count,
+                _ => (),
+            }
+        }
+        text.len()
+    }
+
+    /// Number of reports waiting to be sent
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Send the next report, to call after each poll of the USB device
+    ///
+    /// The queued taps go first, then the held key. A report the host hasn't made room for is
+    /// sent again on the next call.
+    pub fn poll(&mut self) -> Result<(), UsbError> {
+        let key = self.queue.front().copied().unwrap_or(self.held);
+        if self.queue.is_empty() && self.sent == Some(key) {
+            return Ok(());
+        }
+        self.hid.push_input(&key.report())?;
+        self.queue.pop_front();
+        self.sent = Some(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+//! USB link activity
+//!
+//! Watches the start of frame packets the host sends every millisecond while the bus is active,
+//! so the firmware can do less work while no host is listening. The state only changes after a
+//! few checks agree, so a host pausing briefly doesn't switch it back and forth. A hook set with
+//! `with_hook` runs on each change, for the parts of the firmware that don't see the monitor.
+
+use crate::pac;
+
+/// Checks without a new frame before the link is idle
+const IDLE_AFTER: u32 = 100;
+/// Checks with new frames before the link is active again
+const ACTIVE_AFTER: u32 = 3;
+
+/// Whether a host is listening
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    /// Configured by a host sending frames
+    Active,
+    /// Unplugged, suspended or not configured
+    Idle,
+}
+
+/// Watchdog of the USB frames
+pub struct LinkMonitor {
+    state: LinkState,
+    last_frame: Option<u16>,
+    /// Checks in a row that disagreed with the state
+    count: u32,
+    /// Called with the new state when it changes
+    hook: Option<fn(LinkState)>,
+}
+
+impl LinkMonitor {
+    /// Start as active, so nothing is held back until the host is known to be gone
+    pub const fn new() -> Self {
+        Self {
+            state: LinkState::Active,
+            last_frame: None,
+            count: 0,
+            hook: None,
+        }
+    }
+
+    /// Call `hook` from `update` each time the state changes
+    pub const fn with_hook(mut self, hook: fn(LinkState)) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Check for new frames, returning the new state when it changes, after calling the hook
+    ///
+    /// This should be called at a steady rate slower than the frames, such as every tick of
+    /// the main loop.
+    pub fn update(&mut self, configured: bool) -> Option<LinkState> {
+        let frame = frame_number();
+        let active = configured && self.last_frame.map_or(false, |last| last != frame);
+        self.last_frame = Some(frame);
+
+        if active == (self.state == LinkState::Active) {
+            self.count = 0;
+            return None;
+        }
+        self.count += 1;
+        let (threshold, next) = match self.state {
+            LinkState::Active => (IDLE_AFTER, LinkState::Idle),
+            LinkState::Idle => (ACTIVE_AFTER, LinkState::Active),
+        };
+        if self.count < threshold {
+            return None;
+        }
+        self.count = 0;
+        self.state = next;
+        if let Some(hook) = self.hook {
+            hook(next);
+        }
+        Some(next)
+    }
+}
+
+/// Number of the last frame received, which wraps around after 2047
+fn frame_number() -> u16 {
+    // Note (safety): the register is only read, and reading it has no side effect
+    let usb = unsafe { &*pac::USBCTRL_REGS::ptr() };
+    usb.sof_rd.read().count().bits()
+}
@@ -0,0 +1,116 @@
+//! USB log port
+//!
+//! Second USB serial port next to the console, carrying the debug logs so they don't mix with the
+//! data of the application. `usb_log!` queues a line from anywhere, starting with its time, and
+//! the USB interrupt sends the queue with `LogPort::poll`.
+
+use crate::clock::Timestamp;
+use crate::metrics::Metric;
+use crate::shared::Shared;
+use core::fmt::{self, Write};
+use cortex_m::interrupt;
+use heapless::{Deque, String};
+use usb_device::{bus::UsbBus, class_prelude::UsbBusAllocator};
+use usbd_serial::SerialPort;
+
+/// Bytes of log lines that can wait for the host
+pub const QUEUE_SIZE: usize = 512;
+/// Longest line, the rest is cut
+pub const MAX_LINE: usize = 96;
+
+/// Lines waiting for the host, empty until a `LogPort` is set up
+static QUEUE: Shared<LogQueue> = Shared::new();
+
+/// Lines that didn't fit in the queue
+static DROPPED: Metric = Metric::counter("log_dropped");
+
+struct LogQueue {
+    bytes: Deque<u8, QUEUE_SIZE>,
+    /// Time of the lines of `usb_log!`
+    now: fn() -> Timestamp,
+}
+
+/// Queue a line for the log port, formatted like `format!` after the time
+///
+/// Lines are dropped while the queue is full, and when there is no log port.
+#[macro_export]
+macro_rules! usb_log {
+    ($($arg:tt)*) => {
+        $crate::usb_log::log_line(core::format_args!($($arg)*))
+    };
+}
+
+/// Queue a line for the log port, see `usb_log!`
+pub fn log_line(args: fmt::Arguments) {
+    let now = match interrupt::free(|cs| QUEUE.with(cs, |queue| queue.now)) {
+        Some(now) => now,
+        None => return,
+    };
+    let mut line: String<MAX_LINE> = String::new();
+    let _ = write!(line, "{} {}", now(), args);
+    queue_line(line.as_bytes());
+}
+
+/// Queue a line that is already formatted, without its line ending nor a time added
+pub fn queue_line(line: &[u8]) {
+    interrupt::free(|cs| {
+        QUEUE.with(cs, |queue| {
+            if queue.bytes.capacity() - queue.bytes.len() < line.len() + 2 {
+                DROPPED.add(1);
+                return;
+            }
+            for &byte in line.iter().chain(b"\r\n") {
+                let _ = queue.bytes.push_back(byte);
+            }
+        });
+    });
+}
+
+/// Serial port sending the lines of `usb_log!`
+pub struct LogPort<'a, B: UsbBus> {
+    serial: SerialPort<'a, B>,
+}
+
+impl<'a, B: UsbBus> LogPort<'a, B> {
+    /// Add the log port to the USB device of `bus`, and start queueing the lines
+    ///
+    /// Like the other classes, it has to be created before the device is built. `now` gives the
+    /// time of the lines, such as `CLOCK.timestamp` of the timer.
+    pub fn new(bus: &'a UsbBusAllocator<B>, now: fn() -> Timestamp) -> Self {
+        QUEUE.put(LogQueue {
+            bytes: Deque::new(),
+            now,
+        });
+        DROPPED.register();
+        Self {
+            serial: SerialPort::new(bus),
+        }
+    }
+
+    /// The underlying serial port, to poll it with the USB device
+    pub fn port(&mut self) -> &mut SerialPort<'a, B> {
+        &mut self.serial
+    }
+
+    /// Send the queued lines, to call after each poll of the USB device
+    ///
+    /// The lines wait in the queue until the host opens the port. Anything the host sends on it
+    /// is ignored.
+    pub fn poll(&mut self) {
+        let mut discard = [0u8; 64];
+        let _ = self.serial.read(&mut discard);
+        if !self.serial.dtr() {
+            return;
+        }
+        interrupt::free(|cs| {
+            QUEUE.with(cs, |queue| {
+                let (front, _) = queue.bytes.as_slices();
+                if let Ok(sent) = self.serial.write(front) {
+                    for _ in 0..sent {
+                        queue.bytes.pop_front();
+                    }
+                }
+            });
+        });
+    }
+}
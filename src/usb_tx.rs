@@ -0,0 +1,122 @@
+//! Non-blocking USB serial writes
+//!
+//! The USB serial port only accepts as many bytes as fit in its endpoint buffer. Instead of
+//! spinning until the host reads everything, `PendingTx` keeps the bytes that could not be
+//! sent yet and retries on the next poll, giving up after a timeout.
+
+use crate::errors::{ErrorKind, ERRORS};
+use usb_device::{bus::UsbBus, UsbError};
+use usbd_serial::SerialPort;
+
+/// Bytes waiting to be sent over USB serial
+pub struct PendingTx<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// Timestamp of the last successful write, in microseconds
+    last_progress: u64,
+    /// Time after which pending bytes are dropped if the host doesn't read them, in microseconds
+    timeout: u64,
+}
+
+impl<const N: usize> PendingTx<N> {
+    pub const fn new(timeout: u64) -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            last_progress: 0,
+            timeout,
+        }
+    }
+
+    /// Number of bytes waiting to be sent
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Send as many pending bytes as possible
+    ///
+    /// Returns `Err(UsbError::WouldBlock)` while bytes remain. Pending bytes that made no
+    /// progress for longer than the timeout are dropped.
+    pub fn flush<B: UsbBus>(
+        &mut self,
+        serial: &mut SerialPort<B>,
+        now: u64,
+    ) -> Result<(), UsbError> {
+        while self.len > 0 {
+            match serial.write(&self.buf[..self.len]) {
+                Ok(0) | Err(UsbError::WouldBlock) => break,
+                Ok(count) => {
+                    self.buf.copy_within(count..self.len, 0);
+                    self.len -= count;
+                    self.last_progress = now;
+                }
+                Err(err) => {
+                    self.drop_pending();
+                    return Err(err);
+                }
+            }
+        }
+
+        if self.len == 0 {
+            self.last_progress = now;
+            return Ok(());
+        }
+
+        // The host stopped reading
+        if now.saturating_sub(self.last_progress) > self.timeout {
+            self.drop_pending();
+        }
+        Err(UsbError::WouldBlock)
+    }
+
+    /// Queue `data` after the pending bytes without sending them, returning how many fit
+    pub fn queue(&mut self, data: &[u8], now: u64) -> usize {
+        // The timeout starts when the first byte is queued
+        if self.len == 0 {
+            self.last_progress = now;
+        }
+        let count = data.len().min(N - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&data[..count]);
+        self.len += count;
+        count
+    }
+
+    /// Queue `data` after the pending bytes and try to send them
+    ///
+    /// Returns the number of bytes accepted, or `Err(UsbError::WouldBlock)` if there was no
+    /// room for any of them. Bytes that are accepted but not sent yet are sent by later calls
+    /// to `flush` or `write`.
+    pub fn write<B: UsbBus>(
+        &mut self,
+        serial: &mut SerialPort<B>,
+        data: &[u8],
+        now: u64,
+    ) -> Result<usize, UsbError> {
+        // Make room first, so the new bytes stay behind the pending ones
+        match self.flush(serial, now) {
+            Ok(()) | Err(UsbError::WouldBlock) => (),
+            Err(err) => return Err(err),
+        }
+
+        let count = data.len().min(N - self.len);
+        if count == 0 && !data.is_empty() {
+            return Err(UsbError::WouldBlock);
+        }
+        self.buf[self.len..self.len + count].copy_from_slice(&data[..count]);
+        self.len += count;
+
+        match self.flush(serial, now) {
+            Ok(()) | Err(UsbError::WouldBlock) => Ok(count),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn drop_pending(&mut self) {
+        ERRORS.record(ErrorKind::TxDrop, self.len as u32);
+        self.len = 0;
+    }
+}
@@ -0,0 +1,183 @@
+//! Virtual FAT12 volume
+//!
+//! Presents a set of read-only files as a FAT12 disk, with every block generated when the host
+//! reads it, so files such as logs are never copied to a disk image.
+
+use crate::msc::{BlockDevice, BLOCK_SIZE};
+use heapless::Vec;
+
+/// Maximum number of files, one root directory block minus the volume label
+pub const MAX_FILES: usize = 15;
+
+const SECTORS_PER_CLUSTER: u32 = 8;
+const CLUSTER_SIZE: u32 = SECTORS_PER_CLUSTER * BLOCK_SIZE as u32;
+const CLUSTERS: u32 = 512;
+const FAT_SECTORS: u32 = 2;
+const FAT_LBA: u32 = 1;
+const ROOT_LBA: u32 = FAT_LBA + FAT_SECTORS;
+const DATA_LBA: u32 = ROOT_LBA + 1;
+const TOTAL_SECTORS: u32 = DATA_LBA + CLUSTERS * SECTORS_PER_CLUSTER;
+
+/// Largest size of all files together
+pub const MAX_TOTAL_SIZE: u32 = CLUSTERS * CLUSTER_SIZE;
+
+/// File of the volume, whose content is generated when it is read
+pub trait VirtualFile: Sync {
+    /// Name in 8.3 format, padded with spaces, such as `b"LOG     TXT"`
+    fn name(&self) -> &[u8; 11];
+    /// Current size, in bytes
+    fn size(&self) -> u32;
+    /// Fill `buf` with the content at `offset`
+    fn read(&self, offset: u32, buf: &mut [u8]);
+}
+
+/// Volume made of virtual files
+pub struct VirtualFat {
+    label: [u8; 11],
+    files: &'static [&'static dyn VirtualFile],
+}
+
+impl VirtualFat {
+    /// Only the first `MAX_FILES` files are shown
+    pub fn new(label: [u8; 11], files: &'static [&'static dyn VirtualFile]) -> Self {
+        Self { label, files }
+    }
+
+    /// First cluster and size of each file, in the same order as the files
+    ///
+    /// Files are stored one after the other, and cut when the volume is full.
+    fn layout(&self) -> Vec<(u32, u32), MAX_FILES> {
+        let mut layout = Vec::new();
+        let mut next_cluster = 2;
+        for file in self.files.iter().take(MAX_FILES) {
+            let available = (CLUSTERS + 2 - next_cluster) * CLUSTER_SIZE;
+            let size = file.size().min(available);
+            let start = if size == 0 { 0 } else { next_cluster };
+            next_cluster += (size + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+            let _ = layout.push((start, size));
+        }
+        layout
+    }
+
+    /// Value of the FAT entry of `cluster`
+    fn fat_entry(layout: &[(u32, u32)], cluster: u32) -> u16 {
+        match cluster {
+            // Media descriptor and end of chain marker
+            0 => 0xFF8,
+            1 => 0xFFF,
+            _ => {
+                for &(start, size) in layout {
+                    let clusters = (size + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+                    if start != 0 && cluster >= start && cluster < start + clusters {
+                        return if cluster + 1 == start + clusters {
+                            0xFFF
+                        } else {
+                            (cluster + 1) as u16
+                        };
+                    }
+                }
+                // Free
+                0
+            }
+        }
+    }
+
+    fn boot_sector(&self, block: &mut [u8; BLOCK_SIZE]) {
+        block[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        block[3..11].copy_from_slice(b"MSWIN4.1");
+        block[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+        block[13] = SECTORS_PER_CLUSTER as u8;
+        // Reserved sectors
+        block[14..16].copy_from_slice(&(FAT_LBA as u16).to_le_bytes());
+        // Number of FATs
+        block[16] = 1;
+        // Root directory entries
+        block[17..19].copy_from_slice(&16u16.to_le_bytes());
+        block[19..21].copy_from_slice(&(TOTAL_SECTORS as u16).to_le_bytes());
+        // Fixed disk
+        block[21] = 0xF8;
+        block[22..24].copy_from_slice(&(FAT_SECTORS as u16).to_le_bytes());
+        // Sectors per track and heads
+        block[24..26].copy_from_slice(&1u16.to_le_bytes());
+        block[26..28].copy_from_slice(&1u16.to_le_bytes());
+        // Drive number, extended boot signature, volume ID and label
+        block[36] = 0x80;
+        block[38] = 0x29;
+        block[39..43].copy_from_slice(&0x2040_2040u32.to_le_bytes());
+        block[43..54].copy_from_slice(&self.label);
+        block[54..62].copy_from_slice(b"FAT12   ");
+        block[510..512].copy_from_slice(&[0x55, 0xAA]);
+    }
+
+    fn fat_sector(&self, index: u32, block: &mut [u8; BLOCK_SIZE], layout: &[(u32, u32)]) {
+        // Entries are 12 bits, so two entries are packed in three bytes
+        for (i, byte) in block.iter_mut().enumerate() {
+            let offset = index * BLOCK_SIZE as u32 + i as u32;
+            let pair = offset / 3;
+            let first = Self::fat_entry(layout, pair * 2);
+            let second = Self::fat_entry(layout, pair * 2 + 1);
+            *byte = match offset % 3 {
+                0 => first as u8,
+                1 => (first >> 8) as u8 | (second << 4) as u8,
+                _ => (second >> 4) as u8,
+            };
+        }
+    }
+
+    fn root_directory(&self, block: &mut [u8; BLOCK_SIZE], layout: &[(u32, u32)]) {
+        let (label, entries) = block.split_at_mut(32);
+        label[..11].copy_from_slice(&self.label);
+        // Volume label
+        label[11] = 0x08;
+
+        for ((file, &(start, size)), entry) in self
+            .files
+            .iter()
+            .zip(layout.iter())
+            .zip(entries.chunks_exact_mut(32))
+        {
+            entry[..11].copy_from_slice(file.name());
+            // Read-only
+            entry[11] = 0x01;
+            entry[26..28].copy_from_slice(&(start as u16).to_le_bytes());
+            entry[28..32].copy_from_slice(&size.to_le_bytes());
+        }
+    }
+
+    fn data_sector(&self, lba: u32, block: &mut [u8; BLOCK_SIZE], layout: &[(u32, u32)]) {
+        let cluster = (lba - DATA_LBA) / SECTORS_PER_CLUSTER + 2;
+        let sector = (lba - DATA_LBA) % SECTORS_PER_CLUSTER;
+
+        for (file, &(start, size)) in self.files.iter().zip(layout.iter()) {
+            let clusters = (size + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+            if start == 0 || cluster < start || cluster >= start + clusters {
+                continue;
+            }
+            let offset = (cluster - start) * CLUSTER_SIZE + sector * BLOCK_SIZE as u32;
+            let len = (size.saturating_sub(offset) as usize).min(BLOCK_SIZE);
+            file.read(offset, &mut block[..len]);
+            return;
+        }
+    }
+}
+
+impl BlockDevice for VirtualFat {
+    fn block_count(&self) -> u32 {
+        TOTAL_SECTORS
+    }
+
+    fn read_block(&self, lba: u32, block: &mut [u8; BLOCK_SIZE]) {
+        block.iter_mut().for_each(|byte| *byte = 0);
+        if lba == 0 {
+            return self.boot_sector(block);
+        }
+
+        // The files are sized once per block, as sizing some of them takes a while
+        let layout = self.layout();
+        match lba {
+            ROOT_LBA => self.root_directory(block, &layout),
+            _ if lba < ROOT_LBA => self.fat_sector(lba - FAT_LBA, block, &layout),
+            _ => self.data_sector(lba, block, &layout),
+        }
+    }
+}